@@ -0,0 +1,314 @@
+//! `execve` target translation and `#!` interpreter handling.
+//!
+//! The exec target is resolved inside the chroot like any other path. If it
+//! is a script, the host would read its `#!` line and look the interpreter up
+//! from the host root, outside the jail. The grate therefore parses the `#!`
+//! line itself, resolves the interpreter inside the chroot, and execs the
+//! interpreter directly with the argv Linux would build:
+//!
+//! ```text
+//! <interpreter> [<optional arg>] <script path> <argv[1]> ...
+//! ```
+//!
+//! Interpreters that are scripts themselves are followed the same way, up to
+//! `MAX_INTERPRETER_DEPTH` levels.
+//!
+//! `PATH` lookups (`execvp`, `execlp`, ...) are done by the cage's libc, which
+//! tries `execve` on each `PATH` entry in turn. Each attempt is translated
+//! here and fails with the errno Linux would return, so the search moves on
+//! to the next entry as it would outside the chroot.
+
+use crate::paths::{host_path, resolve_path};
+use grate_rs::constants::lind::GRATE_MEMORY_FLAG;
+use grate_rs::constants::{SYS_ACCESS, SYS_CLOSE, SYS_OPEN, SYS_READ};
+use grate_rs::{GrateError, copy_data_between_cages, getcageid, make_threei_call};
+use std::ffi::{CString, c_char};
+
+/// Bytes of a file Linux reads to find a `#!` line.
+const BINPRM_BUF_SIZE: usize = 256;
+
+/// How many nested script interpreters Linux follows before `ELOOP`.
+const MAX_INTERPRETER_DEPTH: usize = 4;
+
+/// Longest single argument Linux accepts.
+const MAX_ARG_STRLEN: usize = 131072;
+
+/// Most `argv` entries read from a cage.
+const MAX_ARGS: usize = 65536;
+
+/// What to pass to the real `execve`.
+pub struct ExecTarget {
+    /// Host path of the program to run.
+    pub path: CString,
+    /// Replacement `argv` when the target was a script, `None` to keep the
+    /// cage's own.
+    pub argv: Option<Vec<CString>>,
+}
+
+impl ExecTarget {
+    /// A NULL-terminated pointer array for `argv`, pointing into `self`.
+    pub fn argv_pointers(&self) -> Option<Vec<*const c_char>> {
+        self.argv.as_ref().map(|argv| {
+            argv.iter()
+                .map(|arg| arg.as_ptr())
+                .chain(std::iter::once(std::ptr::null()))
+                .collect()
+        })
+    }
+}
+
+/// Work out what to exec for `execve(path, argv, ...)` issued by `cageid`.
+pub fn resolve_exec(
+    cageid: u64,
+    path: &str,
+    argv_ptr: u64,
+    argv_cage: u64,
+) -> Result<ExecTarget, i32> {
+    let mut filename = path.to_string();
+    let mut argv: Option<Vec<Vec<u8>>> = None;
+
+    for _ in 0..=MAX_INTERPRETER_DEPTH {
+        let resolved = host_path(cageid, &resolve_path(&filename, cageid, true)?);
+
+        let Some(shebang) = read_shebang(cageid, &resolved)? else {
+            let path = CString::new(resolved).map_err(|_| -libc::EINVAL)?;
+            let argv = match argv {
+                Some(argv) => Some(
+                    argv.into_iter()
+                        .map(|arg| CString::new(arg).map_err(|_| -libc::EINVAL))
+                        .collect::<Result<_, _>>()?,
+                ),
+                None => None,
+            };
+            return Ok(ExecTarget { path, argv });
+        };
+
+        // Running the interpreter directly skips the host's permission check
+        // on the script, so make it here.
+        check_executable(cageid, &resolved)?;
+
+        let old_argv = match argv.take() {
+            Some(argv) => argv,
+            None => read_argv(argv_ptr, argv_cage)?,
+        };
+        let mut new_argv = vec![shebang.interpreter.clone().into_bytes()];
+        if let Some(arg) = shebang.arg {
+            new_argv.push(arg.into_bytes());
+        }
+        new_argv.push(filename.into_bytes());
+        new_argv.extend(old_argv.into_iter().skip(1));
+
+        argv = Some(new_argv);
+        filename = shebang.interpreter;
+    }
+
+    Err(-libc::ELOOP)
+}
+
+/// A parsed `#!` line.
+struct Shebang {
+    interpreter: String,
+    arg: Option<String>,
+}
+
+/// Read the `#!` line of the file at `host_path`, if it has one, as
+/// `cageid`.
+///
+/// A file the cage may execute but not read (mode `--x`) goes to the real
+/// `execve` as is. Any other file that cannot be read fails the exec with
+/// the error: handing it to the real `execve` would let the host look up a
+/// `#!` interpreter outside the chroot.
+fn read_shebang(cageid: u64, host_path: &str) -> Result<Option<Shebang>, i32> {
+    let c_path = CString::new(host_path).map_err(|_| -libc::EINVAL)?;
+
+    let fd = cage_syscall(
+        cageid,
+        SYS_OPEN,
+        [c_path.as_ptr() as u64, libc::O_RDONLY as u64, 0, 0, 0, 0],
+        &[0],
+    );
+    if fd == -libc::EACCES {
+        check_executable(cageid, host_path)?;
+        return Ok(None);
+    }
+    if fd < 0 {
+        return Err(fd);
+    }
+
+    let mut buf = [0u8; BINPRM_BUF_SIZE];
+    let len = cage_syscall(
+        cageid,
+        SYS_READ,
+        [
+            fd as u64,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+            0,
+            0,
+            0,
+        ],
+        &[1],
+    );
+    cage_syscall(cageid, SYS_CLOSE, [fd as u64, 0, 0, 0, 0, 0], &[]);
+    if len < 0 {
+        // execve(2) reports a directory as EACCES.
+        return Err(if len == -libc::EISDIR {
+            -libc::EACCES
+        } else {
+            len
+        });
+    }
+    if len < 2 {
+        return Ok(None);
+    }
+
+    parse_shebang(&buf[..len as usize])
+}
+
+/// Parse a `#!` line the way Linux does: the interpreter is the first word,
+/// and everything after it up to the end of the line, trimmed, is a single
+/// optional argument.
+fn parse_shebang(header: &[u8]) -> Result<Option<Shebang>, i32> {
+    let Some(line) = header.strip_prefix(b"#!") else {
+        return Ok(None);
+    };
+    let line = match line.iter().position(|&b| b == b'\n') {
+        Some(end) => &line[..end],
+        None => line,
+    };
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_matches([' ', '\t']);
+
+    let (interpreter, arg) = match line.split_once([' ', '\t']) {
+        Some((interpreter, arg)) => (interpreter, arg.trim_matches([' ', '\t'])),
+        None => (line, ""),
+    };
+    if interpreter.is_empty() {
+        return Err(-libc::ENOEXEC);
+    }
+
+    Ok(Some(Shebang {
+        interpreter: interpreter.to_string(),
+        arg: (!arg.is_empty()).then(|| arg.to_string()),
+    }))
+}
+
+/// Fail unless `cageid` may execute the file at `host_path`.
+fn check_executable(cageid: u64, host_path: &str) -> Result<(), i32> {
+    let thiscage = getcageid();
+    let c_path = CString::new(host_path).map_err(|_| -libc::EINVAL)?;
+
+    match make_threei_call(
+        SYS_ACCESS as u32,
+        0,
+        thiscage,
+        cageid,
+        c_path.as_ptr() as u64,
+        thiscage | GRATE_MEMORY_FLAG,
+        libc::X_OK as u64,
+        cageid,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ) {
+        Ok(ret) if ret < 0 => Err(ret),
+        Ok(_) => Ok(()),
+        Err(grate_rs::GrateError::MakeSyscallError(n)) => Err(n),
+        Err(_) => Err(-libc::EACCES),
+    }
+}
+
+/// Issue a syscall as `cageid`. `buffers` lists the arguments that point
+/// into grate memory.
+fn cage_syscall(cageid: u64, syscall_no: u64, args: [u64; 6], buffers: &[usize]) -> i32 {
+    let thiscage = getcageid();
+    let mut cages = [cageid; 6];
+    for &i in buffers {
+        cages[i] = thiscage | GRATE_MEMORY_FLAG;
+    }
+
+    match make_threei_call(
+        syscall_no as u32,
+        0,
+        thiscage,
+        cageid,
+        args[0],
+        cages[0],
+        args[1],
+        cages[1],
+        args[2],
+        cages[2],
+        args[3],
+        cages[3],
+        args[4],
+        cages[4],
+        args[5],
+        cages[5],
+        0,
+    ) {
+        Ok(ret) => ret,
+        Err(GrateError::MakeSyscallError(n)) => n,
+        Err(_) => -libc::EIO,
+    }
+}
+
+/// Read a NULL-terminated `argv` array of 32-bit pointers from a cage.
+fn read_argv(argv_ptr: u64, argv_cage: u64) -> Result<Vec<Vec<u8>>, i32> {
+    let thiscage = getcageid();
+    let mut argv = Vec::new();
+    if argv_ptr == 0 {
+        return Ok(argv);
+    }
+
+    for i in 0..MAX_ARGS as u64 {
+        let mut entry = [0u8; 4];
+        copy_data_between_cages(
+            thiscage,
+            argv_cage,
+            argv_ptr + i * 4,
+            argv_cage,
+            entry.as_mut_ptr() as u64,
+            thiscage,
+            entry.len() as u64,
+            0,
+        )
+        .map_err(|_| -libc::EFAULT)?;
+
+        let arg_ptr = u32::from_ne_bytes(entry) as u64;
+        if arg_ptr == 0 {
+            return Ok(argv);
+        }
+        argv.push(read_arg(arg_ptr, argv_cage)?);
+    }
+
+    Err(-libc::E2BIG)
+}
+
+/// Read one NUL-terminated argument from a cage.
+fn read_arg(ptr: u64, cage: u64) -> Result<Vec<u8>, i32> {
+    let thiscage = getcageid();
+    let mut buf = vec![0u8; MAX_ARG_STRLEN];
+
+    copy_data_between_cages(
+        thiscage,
+        cage,
+        ptr,
+        cage,
+        buf.as_mut_ptr() as u64,
+        thiscage,
+        buf.len() as u64,
+        1, // Strncpy: stop at NUL
+    )
+    .map_err(|_| -libc::EFAULT)?;
+
+    match buf.iter().position(|&b| b == 0) {
+        Some(len) => Ok(buf[..len].to_vec()),
+        None => Err(-libc::E2BIG),
+    }
+}
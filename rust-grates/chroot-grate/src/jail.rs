@@ -0,0 +1,394 @@
+//! Per-cage jails for a `--chroot-dir` containing `{cageid}`.
+//!
+//! A cage's jail is set up before the cage runs in it: the initial cage's in
+//! `preexec`, a forked cage's in `fork_handler`. If that fails, the grate
+//! exits or the fork fails. A jail that does not exist yet is created along
+//! with any missing parents and, with `--chroot-template`, filled with a copy
+//! of the template directory. A jail that already exists is used as it is,
+//! so jails can be prepared in advance and are kept after the grate exits.
+//!
+//! The template copy keeps directories, regular files and symlinks, with their
+//! permission bits; other file types are skipped. Symlink targets are copied
+//! verbatim. Host files are accessed through 3i as the grate itself rather
+//! than through `std::fs`, which does not work reliably inside Lind.
+
+use grate_rs::constants::fs::{O_CREAT, O_DIRECTORY, O_EXCL, O_RDONLY, O_WRONLY, S_IFDIR};
+use grate_rs::constants::lind::GRATE_MEMORY_FLAG;
+use grate_rs::constants::{
+    SYS_CHMOD, SYS_CLOSE, SYS_GETDENTS, SYS_MKDIR, SYS_OPEN, SYS_READ, SYS_READLINK, SYS_RENAME,
+    SYS_SYMLINK, SYS_WRITE,
+};
+use grate_rs::ffi::stat;
+use grate_rs::{GrateError, getcageid, make_threei_call};
+use std::collections::HashSet;
+use std::ffi::{CString, c_char};
+use std::sync::Mutex;
+
+/// Size of a `linux_dirent64` record before the name.
+const DIRENT64_FIXED_SIZE: usize = 19;
+
+/// File type bits of a regular file in `st_mode`.
+const S_IFREG: u32 = 0o10_0000;
+
+/// Buffer size for directory listings and file copies.
+const COPY_BUF_SIZE: usize = 4096;
+
+/// Serializes jail setups, so two threads never fill the same jail. Held
+/// while a jail is created, unlike `PREPARED_JAILS`, which every path mapping
+/// checks.
+static JAIL_SETUP: Mutex<()> = Mutex::new(());
+
+/// Suffixes tried for the directory a jail is filled in before it is renamed
+/// into place.
+const PARTIAL_JAIL_ATTEMPTS: usize = 100;
+
+/// Make sure the jail at `root` exists, creating it on first use.
+///
+/// A jail is recorded in `PREPARED_JAILS` only once it is ready. A new jail
+/// is filled in a sibling directory and renamed to `root` when complete, so a
+/// setup that fails never leaves a partly copied jail at `root`, and is tried
+/// again the next time.
+pub fn prepare_jail(cageid: u64, root: &str) -> Result<(), i32> {
+    if jail_prepared(root) {
+        return Ok(());
+    }
+
+    let _setup = JAIL_SETUP.lock().unwrap();
+    if jail_prepared(root) {
+        return Ok(());
+    }
+
+    if host_mode(root).is_some() {
+        crate::log!("Cage {}: using existing jail {}", cageid, root);
+    } else {
+        match create_jail(root) {
+            Ok(()) => crate::log!("Cage {}: created jail {}", cageid, root),
+            Err(e) => {
+                crate::log_error!("Cage {}: cannot create jail {}: errno {}", cageid, root, -e);
+                return Err(e);
+            }
+        }
+    }
+
+    crate::PREPARED_JAILS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashSet::new)
+        .insert(root.to_string());
+    Ok(())
+}
+
+fn jail_prepared(root: &str) -> bool {
+    crate::PREPARED_JAILS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|jails| jails.contains(root))
+}
+
+/// Create the jail `root`, with a copy of the template if one is set.
+fn create_jail(root: &str) -> Result<(), i32> {
+    let template = crate::CHROOT_TEMPLATE.lock().unwrap().clone();
+    let Some((parent, _)) = root.trim_end_matches('/').rsplit_once('/') else {
+        return Err(-libc::EINVAL);
+    };
+    create_dir_all(parent)?;
+    if template.is_empty() {
+        let c_root = cstring(root)?;
+        return check(grate_syscall(
+            SYS_MKDIR,
+            [c_root.as_ptr() as u64, 0o755, 0, 0, 0, 0],
+            &[0],
+        ));
+    }
+
+    let partial = create_partial_dir(root)?;
+    copy_dir_contents(&template, &partial)?;
+    let c_partial = cstring(&partial)?;
+    let c_root = cstring(root)?;
+    check(grate_syscall(
+        SYS_RENAME,
+        [
+            c_partial.as_ptr() as u64,
+            c_root.as_ptr() as u64,
+            0,
+            0,
+            0,
+            0,
+        ],
+        &[0, 1],
+    ))
+}
+
+/// Create a new, empty directory next to `root` to fill the jail in.
+fn create_partial_dir(root: &str) -> Result<String, i32> {
+    for attempt in 0..PARTIAL_JAIL_ATTEMPTS {
+        let dir = format!("{}.partial{}", root.trim_end_matches('/'), attempt);
+        let c_dir = cstring(&dir)?;
+        match grate_syscall(SYS_MKDIR, [c_dir.as_ptr() as u64, 0o755, 0, 0, 0, 0], &[0]) {
+            ret if ret == -libc::EEXIST => continue,
+            ret => return check(ret).map(|()| dir),
+        }
+    }
+    Err(-libc::EEXIST)
+}
+
+/// Create `path` and any missing parent directories.
+fn create_dir_all(path: &str) -> Result<(), i32> {
+    let mut dir = String::new();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        dir.push('/');
+        dir.push_str(component);
+        let c_dir = cstring(&dir)?;
+        let ret = grate_syscall(SYS_MKDIR, [c_dir.as_ptr() as u64, 0o755, 0, 0, 0, 0], &[0]);
+        if ret < 0 && ret != -libc::EEXIST {
+            return Err(ret);
+        }
+    }
+    Ok(())
+}
+
+/// Copy everything in the directory `src` into the existing directory `dst`.
+fn copy_dir_contents(src: &str, dst: &str) -> Result<(), i32> {
+    for name in list_dir(src)? {
+        copy_entry(&format!("{}/{}", src, name), &format!("{}/{}", dst, name))?;
+    }
+    Ok(())
+}
+
+/// Copy one directory entry: a symlink, a directory with its contents, or a
+/// regular file.
+fn copy_entry(src: &str, dst: &str) -> Result<(), i32> {
+    let c_dst = cstring(dst)?;
+
+    if let Some(target) = host_readlink(src) {
+        let c_target = cstring(&target)?;
+        return check(grate_syscall(
+            SYS_SYMLINK,
+            [c_target.as_ptr() as u64, c_dst.as_ptr() as u64, 0, 0, 0, 0],
+            &[0, 1],
+        ));
+    }
+
+    let Some(mode) = host_mode(src) else {
+        return Ok(());
+    };
+    match mode & crate::S_IFMT {
+        S_IFDIR => {
+            check(grate_syscall(
+                SYS_MKDIR,
+                [c_dst.as_ptr() as u64, 0o700, 0, 0, 0, 0],
+                &[0],
+            ))?;
+            copy_dir_contents(src, dst)?;
+        }
+        S_IFREG => copy_file(src, &c_dst)?,
+        _ => return Ok(()),
+    }
+
+    // Set the mode last, so read-only directories can still be filled.
+    check(grate_syscall(
+        SYS_CHMOD,
+        [c_dst.as_ptr() as u64, (mode & 0o7777) as u64, 0, 0, 0, 0],
+        &[0],
+    ))
+}
+
+/// Copy the contents of the regular file `src` to a new file `dst`.
+fn copy_file(src: &str, dst: &CString) -> Result<(), i32> {
+    let c_src = cstring(src)?;
+    let in_fd = grate_syscall(
+        SYS_OPEN,
+        [c_src.as_ptr() as u64, O_RDONLY as u64, 0, 0, 0, 0],
+        &[0],
+    );
+    check(in_fd)?;
+    let out_fd = grate_syscall(
+        SYS_OPEN,
+        [
+            dst.as_ptr() as u64,
+            (O_WRONLY | O_CREAT | O_EXCL) as u64,
+            0o600,
+            0,
+            0,
+            0,
+        ],
+        &[0],
+    );
+    if out_fd < 0 {
+        grate_syscall(SYS_CLOSE, [in_fd as u64, 0, 0, 0, 0, 0], &[]);
+        return Err(out_fd);
+    }
+
+    let mut buf = vec![0u8; COPY_BUF_SIZE];
+    let result = loop {
+        let len = grate_syscall(
+            SYS_READ,
+            [
+                in_fd as u64,
+                buf.as_mut_ptr() as u64,
+                buf.len() as u64,
+                0,
+                0,
+                0,
+            ],
+            &[1],
+        );
+        if len <= 0 {
+            break check(len);
+        }
+        if let Err(e) = write_all(out_fd, &buf[..len as usize]) {
+            break Err(e);
+        }
+    };
+
+    grate_syscall(SYS_CLOSE, [in_fd as u64, 0, 0, 0, 0, 0], &[]);
+    grate_syscall(SYS_CLOSE, [out_fd as u64, 0, 0, 0, 0, 0], &[]);
+    result
+}
+
+fn write_all(fd: i32, mut data: &[u8]) -> Result<(), i32> {
+    while !data.is_empty() {
+        let len = grate_syscall(
+            SYS_WRITE,
+            [fd as u64, data.as_ptr() as u64, data.len() as u64, 0, 0, 0],
+            &[1],
+        );
+        if len <= 0 {
+            return Err(if len < 0 { len } else { -libc::EIO });
+        }
+        data = &data[len as usize..];
+    }
+    Ok(())
+}
+
+/// Names in a host directory, without `.` and `..`.
+fn list_dir(path: &str) -> Result<Vec<String>, i32> {
+    let c_path = cstring(path)?;
+    let fd = grate_syscall(
+        SYS_OPEN,
+        [
+            c_path.as_ptr() as u64,
+            (O_RDONLY | O_DIRECTORY) as u64,
+            0,
+            0,
+            0,
+            0,
+        ],
+        &[0],
+    );
+    check(fd)?;
+
+    let mut buf = vec![0u8; COPY_BUF_SIZE];
+    let mut entries = Vec::new();
+    loop {
+        let ret = grate_syscall(
+            SYS_GETDENTS,
+            [
+                fd as u64,
+                buf.as_mut_ptr() as u64,
+                buf.len() as u64,
+                0,
+                0,
+                0,
+            ],
+            &[1],
+        );
+        if ret < 0 {
+            grate_syscall(SYS_CLOSE, [fd as u64, 0, 0, 0, 0, 0], &[]);
+            return Err(ret);
+        }
+        if ret == 0 {
+            break;
+        }
+
+        // linux_dirent64: d_ino, d_off, d_reclen, d_type, then the
+        // NUL-terminated name.
+        let mut pos = 0;
+        while pos + DIRENT64_FIXED_SIZE <= ret as usize {
+            let reclen = u16::from_ne_bytes([buf[pos + 16], buf[pos + 17]]) as usize;
+            if reclen == 0 {
+                break;
+            }
+            let name = &buf[pos + DIRENT64_FIXED_SIZE..pos + reclen];
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            let name = String::from_utf8_lossy(&name[..len]).into_owned();
+            if name != "." && name != ".." {
+                entries.push(name);
+            }
+            pos += reclen;
+        }
+    }
+
+    grate_syscall(SYS_CLOSE, [fd as u64, 0, 0, 0, 0, 0], &[]);
+    Ok(entries)
+}
+
+/// `st_mode` of the host file at `path`, following symlinks.
+fn host_mode(path: &str) -> Option<u32> {
+    let c_path = CString::new(path).ok()?;
+    let mut st: stat = unsafe { std::mem::zeroed() };
+    let ret = unsafe { stat(c_path.as_ptr() as *const c_char, &mut st) };
+    (ret == 0).then_some(st.st_mode)
+}
+
+/// Target of the host symlink at `path`, or `None` if it is not a symlink.
+fn host_readlink(path: &str) -> Option<String> {
+    let c_path = CString::new(path).ok()?;
+    let mut buf = vec![0u8; COPY_BUF_SIZE];
+    let len = grate_syscall(
+        SYS_READLINK,
+        [
+            c_path.as_ptr() as u64,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+            0,
+            0,
+            0,
+        ],
+        &[0, 1],
+    );
+    (len >= 0).then(|| String::from_utf8_lossy(&buf[..len as usize]).into_owned())
+}
+
+/// Issue a syscall as the grate itself. `buffers` lists the arguments that
+/// point into grate memory.
+fn grate_syscall(syscall_no: u64, args: [u64; 6], buffers: &[usize]) -> i32 {
+    let thiscage = getcageid();
+    let mut cages = [thiscage; 6];
+    for &i in buffers {
+        cages[i] |= GRATE_MEMORY_FLAG;
+    }
+
+    match make_threei_call(
+        syscall_no as u32,
+        0,
+        thiscage,
+        thiscage,
+        args[0],
+        cages[0],
+        args[1],
+        cages[1],
+        args[2],
+        cages[2],
+        args[3],
+        cages[3],
+        args[4],
+        cages[4],
+        args[5],
+        cages[5],
+        0,
+    ) {
+        Ok(ret) => ret,
+        Err(GrateError::MakeSyscallError(n)) => n,
+        Err(_) => -libc::EIO,
+    }
+}
+
+fn check(ret: i32) -> Result<(), i32> {
+    if ret < 0 { Err(ret) } else { Ok(()) }
+}
+
+fn cstring(path: &str) -> Result<CString, i32> {
+    CString::new(path).map_err(|_| -libc::EINVAL)
+}
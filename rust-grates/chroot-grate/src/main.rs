@@ -5,36 +5,70 @@ use grate_rs::constants::lind::GRATE_MEMORY_FLAG;
 use grate_rs::constants::{
     SYS_ACCEPT, SYS_ACCESS, SYS_BIND, SYS_CHDIR, SYS_CHMOD, SYS_CHOWN, SYS_CHROOT, SYS_CLONE,
     SYS_CLOSE, SYS_CONNECT, SYS_DUP, SYS_DUP2, SYS_DUP3, SYS_EXECVE, SYS_FACCESSAT, SYS_FCHDIR,
-    SYS_FCHMODAT, SYS_FCHOWNAT, SYS_FCNTL, SYS_GETCWD, SYS_GETPEERNAME, SYS_GETSOCKNAME,
-    SYS_LCHOWN, SYS_LINK, SYS_LISTXATTR, SYS_MKDIR, SYS_NEWFSTATAT, SYS_OPEN, SYS_OPENAT,
-    SYS_READLINK, SYS_READLINKAT, SYS_RECVFROM, SYS_RENAME, SYS_RENAMEAT, SYS_RENAMEAT2, SYS_RMDIR,
-    SYS_SENDTO, SYS_SETXATTR, SYS_STATFS, SYS_STATX, SYS_SYMLINK, SYS_SYMLINKAT, SYS_TRUNCATE,
-    SYS_UNLINK, SYS_UNLINKAT, SYS_UTIMENSAT, SYS_XSTAT,
+    SYS_FCHMODAT, SYS_FCHOWNAT, SYS_FCNTL, SYS_FXSTAT, SYS_GETCWD, SYS_GETPEERNAME,
+    SYS_GETSOCKNAME, SYS_KILL, SYS_LCHOWN, SYS_LINK, SYS_LISTXATTR, SYS_MKDIR, SYS_MKDIRAT,
+    SYS_MKNOD, SYS_NEWFSTATAT, SYS_OPEN, SYS_OPENAT, SYS_READLINK, SYS_READLINKAT, SYS_RECVFROM,
+    SYS_RENAME, SYS_RENAMEAT, SYS_RENAMEAT2, SYS_RMDIR, SYS_SENDTO, SYS_SETXATTR, SYS_STATFS,
+    SYS_STATX, SYS_SYMLINK, SYS_SYMLINKAT, SYS_TRUNCATE, SYS_UNLINK, SYS_UNLINKAT, SYS_UTIMENSAT,
+    SYS_XSTAT,
 };
 use grate_rs::ffi::stat;
 use grate_rs::{
     GrateBuilder, GrateError, copy_data_between_cages, copy_handler_table_to_cage, getcageid,
     is_thread_clone, make_threei_call,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::ffi::c_char;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+mod exec;
+mod jail;
 mod logging;
 mod paths;
 mod sockets;
 
 use crate::paths::{
-    chroot_path, get_cage_cwd, init_cwd, normalize_path, read_path_from_cage, register_cage,
-    set_cage_cwd,
+    BindMount, CAGEID_PLACEHOLDER, check_read_only_mode, check_writable, chroot_path, get_cage_cwd,
+    host_path, init_cwd, normalize_path, parse_bind, per_cage_roots, prepare_cage_root,
+    read_path_from_cage, register_cage, relative_target_is_contained, resolve_path,
+    resolve_path_in_root, set_cage_cwd, strip_chroot_prefix,
 };
 
-use crate::sockets::{translate_sockaddr, untranslate_sockaddr_in_cage};
+use crate::exec::resolve_exec;
+use crate::sockets::{
+    AbstractSockets, SocketAliases, parse_abstract_sockets, remove_socket_aliases,
+    translate_sockaddr, untranslate_sockaddr_in_cage,
+};
 
-/// Host-side absolute directory used as the cage's chroot prefix.
+/// Host-side absolute directory used as the cage's chroot prefix. A
+/// `{cageid}` in it gives each cage its own root; see `paths::cage_root`.
 pub static CHROOT_DIR: Mutex<String> = Mutex::new(String::new());
 
+/// Set by `--chroot-template`: host directory copied into each new per-cage
+/// root. Empty if per-cage roots start out empty.
+pub static CHROOT_TEMPLATE: Mutex<String> = Mutex::new(String::new());
+
+/// Per-cage roots that are ready, see `jail::prepare_jail`.
+pub static PREPARED_JAILS: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// Additional host directories mapped into the virtual tree, longest virtual
+/// path first.
+pub static BIND_MOUNTS: Mutex<Vec<BindMount>> = Mutex::new(Vec::new());
+
+/// Set by `--read-only`: every syscall that would modify the filesystem fails
+/// with `EROFS`.
+pub static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--abstract-sockets`: what to do with abstract-namespace AF_UNIX
+/// addresses.
+pub static ABSTRACT_SOCKETS: Mutex<AbstractSockets> = Mutex::new(AbstractSockets::Isolate);
+
+/// Short symlinks for the host directories of AF_UNIX paths that overflow
+/// `sun_path`, see `sockets::SocketAliases`.
+pub static SOCKET_ALIASES: Mutex<SocketAliases> = Mutex::new(SocketAliases::new());
+
 /// Per-cage virtual current working directory (cwd) tracking.
 pub static CAGE_CWDS: Mutex<Option<HashMap<u64, String>>> = Mutex::new(None);
 
@@ -61,11 +95,27 @@ fn check_dir(dir: String) -> bool {
 }
 
 /// Initialize chroot state for this grate process.
-pub fn init_state(chroot_dir: String) {
-    match check_dir(chroot_dir.clone()) {
+pub fn init_state(chroot_dir: String, mut binds: Vec<BindMount>) {
+    if binds.iter().any(|bind| !check_dir(bind.host_dir.clone())) {
+        panic!("Invalid host path for --bind");
+    }
+    binds.sort_by_key(|bind| std::cmp::Reverse(bind.virtual_path.len()));
+
+    // Per-cage roots are created on demand; only the directory holding them
+    // has to exist.
+    let existing_dir = match chroot_dir.split_once(CAGEID_PLACEHOLDER) {
+        Some((prefix, _)) => match prefix.rsplit_once('/') {
+            Some(("", _)) | None => "/".to_string(),
+            Some((parent, _)) => parent.to_string(),
+        },
+        None => chroot_dir.clone(),
+    };
+
+    match check_dir(existing_dir) {
         false => panic!("Invalid path for --chroot-dir"),
         true => {
             *CHROOT_DIR.lock().unwrap() = chroot_dir;
+            *BIND_MOUNTS.lock().unwrap() = binds;
             *CAGE_CWDS.lock().unwrap() = Some(HashMap::new());
             *CAGE_DIR_FDS.lock().unwrap() = Some(HashMap::new());
         }
@@ -148,20 +198,6 @@ fn call_with_rewrites(
     }
 }
 
-fn strip_chroot_prefix(path: &str) -> String {
-    let chroot_dir = CHROOT_DIR.lock().unwrap().clone();
-    if path.starts_with(&chroot_dir) {
-        let stripped = &path[chroot_dir.len()..];
-        if stripped.is_empty() {
-            "/".to_string()
-        } else {
-            stripped.to_string()
-        }
-    } else {
-        path.to_string()
-    }
-}
-
 fn write_bytes_to_cage(
     thiscage: u64,
     buf_ptr: u64,
@@ -186,6 +222,25 @@ fn write_bytes_to_cage(
 }
 
 const AT_FDCWD: i64 = -100;
+const AT_SYMLINK_NOFOLLOW: u64 = 0x100;
+const AT_SYMLINK_FOLLOW: u64 = 0x400;
+
+// `*at` syscalls missing from grate_rs::constants.
+const SYS_MKNODAT: u64 = 259;
+const SYS_LINKAT: u64 = 265;
+const SYS_OPENAT2: u64 = 437;
+const SYS_FACCESSAT2: u64 = 439;
+const SYS_FCHMODAT2: u64 = 452;
+
+// `open_how.resolve` flags for openat2.
+const RESOLVE_NO_SYMLINKS: u64 = 0x04;
+const RESOLVE_BENEATH: u64 = 0x08;
+const RESOLVE_IN_ROOT: u64 = 0x10;
+
+/// Size of `struct open_how` as of Linux 5.6.
+const OPEN_HOW_SIZE_VER0: u64 = 24;
+
+const S_IFMT: u32 = 0o17_0000;
 
 fn make_syscall_from_grate(
     syscall_no: u32,
@@ -224,24 +279,35 @@ fn rewrite_at_path(
     dirfd: u64,
     path_ptr: u64,
     path_cage: u64,
+    follow: bool,
+    write: bool,
 ) -> Result<(u64, CString), i32> {
     let path = match read_path_from_cage(path_ptr, path_cage) {
         Some(p) => p,
         None => return Err(-(libc::EFAULT as i32)),
     };
 
-    let (rewritten_dirfd, rewritten_path) = if path.starts_with('/') || dirfd as i64 == AT_FDCWD {
-        (AT_FDCWD as u64, chroot_path(&path, cageid))
-    } else if !path.is_empty() {
-        match fd_dir_path(cageid, dirfd) {
-            Some(dir) => (
-                AT_FDCWD as u64,
-                chroot_path(&normalize_path(&path, &dir), cageid),
-            ),
-            None => (dirfd, path),
+    let resolved = resolve_virtual_at_path(cageid, dirfd, &path, follow)?;
+    if write {
+        match &resolved {
+            Some(virtual_path) => check_writable(virtual_path)?,
+            None => check_read_only_mode()?,
         }
-    } else {
-        (dirfd, path)
+    }
+    at_path_args(cageid, dirfd, path, resolved)
+}
+
+/// Build the `(dirfd, path)` pair to dispatch: the host path of `resolved`
+/// against `AT_FDCWD`, or the original pair if the path was not resolved.
+fn at_path_args(
+    cageid: u64,
+    dirfd: u64,
+    path: String,
+    resolved: Option<String>,
+) -> Result<(u64, CString), i32> {
+    let (rewritten_dirfd, rewritten_path) = match resolved {
+        Some(virtual_path) => (AT_FDCWD as u64, host_path(cageid, &virtual_path)),
+        None => (dirfd, path),
     };
 
     match CString::new(rewritten_path) {
@@ -250,21 +316,31 @@ fn rewrite_at_path(
     }
 }
 
+/// Dispatch a `*at` syscall with its path rewritten. `follow` says whether
+/// the final symlink is followed; `write` marks syscalls that modify the
+/// filesystem.
 fn call_with_at_path(
     syscall_no: u32,
-    cageid: u64,
     mut args: [u64; 6],
     mut arg_cages: [u64; 6],
     dirfd_idx: usize,
     path_idx: usize,
+    follow: bool,
+    write: bool,
 ) -> i32 {
     let thiscage = getcageid();
     let target_cageid = arg_cages[path_idx];
-    let (dirfd, c_path) =
-        match rewrite_at_path(cageid, args[dirfd_idx], args[path_idx], arg_cages[path_idx]) {
-            Ok(v) => v,
-            Err(e) => return e,
-        };
+    let (dirfd, c_path) = match rewrite_at_path(
+        target_cageid,
+        args[dirfd_idx],
+        args[path_idx],
+        arg_cages[path_idx],
+        follow,
+        write,
+    ) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
 
     args[dirfd_idx] = dirfd;
     args[path_idx] = c_path.as_ptr() as u64;
@@ -273,16 +349,33 @@ fn call_with_at_path(
     make_syscall_from_grate(syscall_no, target_cageid, args, arg_cages)
 }
 
-fn rewrite_symlink_target(cageid: u64, target_ptr: u64, target_cage: u64) -> Result<CString, i32> {
+/// Rewrite a symlink target for storage on the host. `link_path` is the
+/// resolved virtual path of the link, if it has one.
+fn rewrite_symlink_target(
+    cageid: u64,
+    target_ptr: u64,
+    target_cage: u64,
+    link_path: Option<&str>,
+) -> Result<CString, i32> {
     let target = match read_path_from_cage(target_ptr, target_cage) {
         Some(p) => p,
         None => return Err(-(libc::EFAULT as i32)),
     };
 
+    // The target is stored, not resolved: prefix it lexically so that host
+    // lookups of the link stay inside the chroot too. A relative target is
+    // resolved by the host from the link's directory, so one whose `..`
+    // could climb out of the jail is stored as the host path it leads to.
+    let link_dir = link_path.map(|path| normalize_path("..", path));
     let rewritten = if target.starts_with('/') {
-        chroot_path(&target, cageid)
+        host_path(cageid, &normalize_path(&target, "/"))
     } else {
-        target
+        match link_dir {
+            Some(dir) if relative_target_is_contained(&dir, &target) => target,
+            Some(dir) => host_path(cageid, &normalize_path(&target, &dir)),
+            None if relative_target_is_contained("/", &target) => target,
+            None => return Err(-libc::EPERM),
+        }
     };
 
     CString::new(rewritten).map_err(|_| -(libc::EINVAL as i32))
@@ -323,15 +416,25 @@ fn copy_fd_dir_path(cageid: u64, oldfd: u64, newfd: u64) {
 }
 
 fn register_dir_fd_if_directory(cageid: u64, fd: u64, virtual_path: String) {
-    let host_path = chroot_path(&virtual_path, cageid);
-    if check_dir(host_path) {
-        set_fd_dir_path(cageid, fd, virtual_path);
+    if check_dir(host_path(cageid, &virtual_path)) {
+        // Drop a trailing slash kept by resolve_path.
+        set_fd_dir_path(cageid, fd, normalize_path(&virtual_path, "/"));
     } else {
         clear_fd_dir_path(cageid, fd);
     }
 }
 
+/// Copy the parent's tracked directory fds to a forked child. With per-cage
+/// roots the paths name directories in the parent's jail, so the child's
+/// inherited directory fds are left untracked instead.
 fn register_child_fd_paths(parent_cageid: u64, child_cageid: u64) {
+    if per_cage_roots() {
+        if let Some(ref mut cages) = *CAGE_DIR_FDS.lock().unwrap() {
+            cages.insert(child_cageid, HashMap::new());
+        }
+        return;
+    }
+
     let parent_fds = CAGE_DIR_FDS
         .lock()
         .unwrap()
@@ -357,12 +460,79 @@ fn dev_fd_target(path: &str) -> Option<u64> {
     fd.parse().ok()
 }
 
-fn resolve_virtual_at_path(cageid: u64, dirfd: u64, path: &str) -> Option<String> {
+/// Resolve a `*at` path to a virtual path inside the chroot.
+///
+/// A relative path is resolved against the virtual path recorded when
+/// `dirfd` was opened, never against the host fd. Returns `Ok(None)` for an
+/// empty path, which names `dirfd` itself (`AT_EMPTY_PATH`) and is passed
+/// through with it.
+fn resolve_virtual_at_path(
+    cageid: u64,
+    dirfd: u64,
+    path: &str,
+    follow: bool,
+) -> Result<Option<String>, i32> {
+    if path.is_empty() {
+        return Ok(None);
+    }
+
     if path.starts_with('/') || dirfd as i64 == AT_FDCWD {
-        Some(normalize_path(path, &get_cage_cwd(cageid)))
+        resolve_path(path, cageid, follow).map(Some)
     } else {
-        fd_dir_path(cageid, dirfd).map(|dir| normalize_path(path, &dir))
+        let dir = at_dir(cageid, dirfd)?;
+        let joined = format!("{}/{}", dir.trim_end_matches('/'), path);
+        resolve_path(&joined, cageid, follow).map(Some)
+    }
+}
+
+/// The virtual directory a relative `*at` path starts from: the cwd for
+/// `AT_FDCWD`, otherwise the path `dirfd` was opened with.
+fn at_dir(cageid: u64, dirfd: u64) -> Result<String, i32> {
+    if dirfd as i64 == AT_FDCWD {
+        return Ok(get_cage_cwd(cageid));
     }
+    fd_dir_path(cageid, dirfd).ok_or_else(|| untracked_dirfd_error(cageid, dirfd))
+}
+
+/// The error for a relative path against a dirfd with no recorded virtual
+/// path: what the kernel would report for a bad fd or a non-directory, and
+/// `EACCES` for a directory the grate did not see opened (e.g. one received
+/// over a socket), which cannot be placed in the virtual tree.
+fn untracked_dirfd_error(cageid: u64, dirfd: u64) -> i32 {
+    let thiscage = getcageid();
+    let mut st: stat = unsafe { std::mem::zeroed() };
+    let ret = make_syscall_from_grate(
+        SYS_FXSTAT as u32,
+        cageid,
+        [dirfd, &mut st as *mut stat as u64, 0, 0, 0, 0],
+        [cageid, thiscage | GRATE_MEMORY_FLAG, 0, 0, 0, 0],
+    );
+
+    if ret < 0 {
+        ret
+    } else if st.st_mode & S_IFMT != S_IFDIR {
+        -libc::ENOTDIR
+    } else {
+        -libc::EACCES
+    }
+}
+
+/// Whether a `*at` call with these flags follows a final symlink.
+fn at_follow(flags: u64) -> bool {
+    flags & AT_SYMLINK_NOFOLLOW == 0
+}
+
+/// Whether `open`/`openat` flags may create or modify the file.
+fn open_writes(flags: u64) -> bool {
+    let flags = flags as i32;
+    flags & libc::O_ACCMODE != libc::O_RDONLY || flags & (libc::O_CREAT | libc::O_TRUNC) != 0
+}
+
+/// Whether `open`/`openat` flags ask not to follow a final symlink.
+fn open_nofollow(flags: u64) -> bool {
+    let flags = flags as i32;
+    flags & libc::O_NOFOLLOW != 0
+        || flags & (libc::O_CREAT | libc::O_EXCL) == libc::O_CREAT | libc::O_EXCL
 }
 
 // -----------------------------------------------------------------------------
@@ -371,27 +541,27 @@ fn resolve_virtual_at_path(cageid: u64, dirfd: u64, path: &str) -> Option<String
 
 // "Path input" syscalls: read the path argument(s), chroot them, and dispatch
 // the real syscall with rewritten pointers.
-// input_path_handler!(execve_handler, SYS_EXECVE, 0);
 input_path_handler!(stat_handler, SYS_XSTAT, 0);
 input_path_handler!(access_handler, SYS_ACCESS, 0);
 input_path_handler!(statfs_handler, SYS_STATFS, 0);
-input_path_handler!(mkdir_handler, SYS_MKDIR, 0);
-input_path_handler!(rmdir_handler, SYS_RMDIR, 0);
-input_path_handler!(unlink_handler, SYS_UNLINK, 0);
-input_path_handler!(chmod_handler, SYS_CHMOD, 0);
-input_path_handler!(chown_handler, SYS_CHOWN, 0);
-input_path_handler!(lchown_handler, SYS_LCHOWN, 0);
-input_path_handler!(truncate_handler, SYS_TRUNCATE, 0);
-input_path_handler!(setxattr_handler, SYS_SETXATTR, 0);
+input_path_handler!(write nofollow mkdir_handler, SYS_MKDIR, 0);
+input_path_handler!(write nofollow mknod_handler, SYS_MKNOD, 0);
+input_path_handler!(write nofollow rmdir_handler, SYS_RMDIR, 0);
+input_path_handler!(write nofollow unlink_handler, SYS_UNLINK, 0);
+input_path_handler!(write chmod_handler, SYS_CHMOD, 0);
+input_path_handler!(write chown_handler, SYS_CHOWN, 0);
+input_path_handler!(write nofollow lchown_handler, SYS_LCHOWN, 0);
+input_path_handler!(write truncate_handler, SYS_TRUNCATE, 0);
+input_path_handler!(write setxattr_handler, SYS_SETXATTR, 0);
 input_path_handler!(listxattr_handler, SYS_LISTXATTR, 0);
 
 // Two-path syscalls: chroot both path arguments.
-input_path_handler!(rename_handler, SYS_RENAME, 0, 1);
-input_path_handler!(link_handler, SYS_LINK, 0, 1);
+input_path_handler!(write nofollow rename_handler, SYS_RENAME, 0, 1);
+input_path_handler!(write nofollow link_handler, SYS_LINK, 0, 1);
 
 // Socket syscalls that take an AF_UNIX sockaddr: chroot `sun_path` before
 // dispatch.
-socket_translate_handler!(bind_handler, SYS_BIND, 1, 2);
+socket_translate_handler!(write bind_handler, SYS_BIND, 1, 2);
 socket_translate_handler!(connect_handler, SYS_CONNECT, 1, 2);
 socket_translate_handler!(sendto_handler, SYS_SENDTO, 4, 5);
 
@@ -444,8 +614,16 @@ extern "C" fn open_handler(
         }
         return ret;
     }
-    let virtual_path = normalize_path(&path, &get_cage_cwd(cageid));
-    let c_path = match CString::new(chroot_path(&path, cageid)) {
+    let virtual_path = match resolve_path(&path, cageid, !open_nofollow(flags)) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    if open_writes(flags)
+        && let Err(e) = check_writable(&virtual_path)
+    {
+        return e;
+    }
+    let c_path = match CString::new(host_path(cageid, &virtual_path)) {
         Ok(p) => p,
         Err(_) => return -(libc::EINVAL as i32),
     };
@@ -511,8 +689,20 @@ extern "C" fn openat_handler(
             return ret;
         }
     }
-    let virtual_path = resolve_virtual_at_path(cageid, dirfd, &path);
-    let (rewritten_dirfd, c_path) = match rewrite_at_path(cageid, dirfd, path_ptr, path_cage) {
+    let virtual_path = match resolve_virtual_at_path(cageid, dirfd, &path, !open_nofollow(flags)) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    if open_writes(flags) {
+        let writable = match &virtual_path {
+            Some(virtual_path) => check_writable(virtual_path),
+            None => check_read_only_mode(),
+        };
+        if let Err(e) = writable {
+            return e;
+        }
+    }
+    let (rewritten_dirfd, c_path) = match at_path_args(cageid, dirfd, path, virtual_path.clone()) {
         Ok(v) => v,
         Err(e) => return e,
     };
@@ -547,6 +737,192 @@ extern "C" fn openat_handler(
     ret
 }
 
+/// `openat2(2)` handler.
+///
+/// Like `openat_handler`, with the flags read from `struct open_how`. The
+/// host gets an absolute path against `AT_FDCWD`, so the `resolve`
+/// restrictions that refer to `dirfd` are applied here; see
+/// [`resolve_openat2_path`].
+extern "C" fn openat2_handler(
+    _cageid: u64,
+    dirfd: u64,
+    dirfd_cage: u64,
+    path_ptr: u64,
+    path_cage: u64,
+    how_ptr: u64,
+    how_cage: u64,
+    size: u64,
+    size_cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    let thiscage = getcageid();
+    let cageid = path_cage;
+
+    if size < OPEN_HOW_SIZE_VER0 {
+        return -libc::EINVAL;
+    }
+    if size > 4096 {
+        return -libc::E2BIG;
+    }
+    let mut how = vec![0u8; size as usize];
+    if copy_data_between_cages(
+        thiscage,
+        how_cage,
+        how_ptr,
+        how_cage,
+        how.as_mut_ptr() as u64,
+        thiscage,
+        size,
+        0,
+    )
+    .is_err()
+    {
+        return -libc::EFAULT;
+    }
+    let flags = u64::from_ne_bytes(how[0..8].try_into().unwrap());
+    let resolve = u64::from_ne_bytes(how[16..24].try_into().unwrap());
+
+    let path = match read_path_from_cage(path_ptr, path_cage) {
+        Some(p) => p,
+        None => return -libc::EFAULT,
+    };
+    if path.is_empty() {
+        return -libc::ENOENT;
+    }
+    let virtual_path = match resolve_openat2_path(cageid, dirfd, &path, flags, resolve) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    if open_writes(flags)
+        && let Err(e) = check_writable(&virtual_path)
+    {
+        return e;
+    }
+    let c_path = match CString::new(host_path(cageid, &virtual_path)) {
+        Ok(p) => p,
+        Err(_) => return -libc::EINVAL,
+    };
+
+    // The restrictions relative to dirfd have been applied already.
+    let resolve = resolve & !(RESOLVE_BENEATH | RESOLVE_IN_ROOT);
+    how[16..24].copy_from_slice(&resolve.to_ne_bytes());
+
+    let ret = make_syscall_from_grate(
+        SYS_OPENAT2 as u32,
+        path_cage,
+        [
+            AT_FDCWD as u64,
+            c_path.as_ptr() as u64,
+            how.as_ptr() as u64,
+            size,
+            arg5,
+            arg6,
+        ],
+        [
+            dirfd_cage,
+            thiscage | GRATE_MEMORY_FLAG,
+            thiscage | GRATE_MEMORY_FLAG,
+            size_cage,
+            arg5cage,
+            arg6cage,
+        ],
+    );
+
+    if ret >= 0 {
+        register_dir_fd_if_directory(cageid, ret as u64, virtual_path);
+    }
+
+    ret
+}
+
+/// Resolve an `openat2` path, applying the `resolve` flags that the host
+/// cannot check on the rewritten path:
+/// - `RESOLVE_BENEATH`: absolute paths, and paths that lead outside `dirfd`,
+///   fail with `EXDEV`;
+/// - `RESOLVE_IN_ROOT`: `dirfd` is the root for the path, so an absolute path
+///   or link target starts there and `..` does not climb above it;
+/// - `RESOLVE_NO_SYMLINKS`: following any symlink fails with `ELOOP`. The
+///   final component is left to the host, which checks it too.
+fn resolve_openat2_path(
+    cageid: u64,
+    dirfd: u64,
+    path: &str,
+    flags: u64,
+    resolve: u64,
+) -> Result<String, i32> {
+    let beneath = resolve & RESOLVE_BENEATH != 0;
+    let in_root = resolve & RESOLVE_IN_ROOT != 0;
+    if beneath && in_root {
+        return Err(-libc::EINVAL);
+    }
+    if beneath && path.starts_with('/') {
+        return Err(-libc::EXDEV);
+    }
+
+    let dir = if beneath || in_root || !path.starts_with('/') {
+        Some(normalize_path(&at_dir(cageid, dirfd)?, "/"))
+    } else {
+        None
+    };
+
+    let no_symlinks = resolve & RESOLVE_NO_SYMLINKS != 0;
+    let follow = !open_nofollow(flags) && !no_symlinks;
+    let (virtual_path, lexical_path) = match &dir {
+        Some(dir) if in_root => {
+            // Normalizing against "/" keeps `..` from leaving the root.
+            let below = normalize_path(path, "/");
+            let lexical_path = match (dir.as_str(), below.as_str()) {
+                (_, "/") => dir.clone(),
+                ("/", _) => below,
+                _ => format!("{}{}", dir, below),
+            };
+            (
+                resolve_path_in_root(dir, path, cageid, follow)?,
+                lexical_path,
+            )
+        }
+        _ => {
+            let joined = match &dir {
+                Some(dir) if !path.starts_with('/') => {
+                    format!("{}/{}", dir.trim_end_matches('/'), path)
+                }
+                _ => path.to_string(),
+            };
+            (
+                resolve_path(&joined, cageid, follow)?,
+                normalize_path(&joined, "/"),
+            )
+        }
+    };
+
+    // Without symlinks, resolving the path and normalizing it lexically
+    // agree.
+    if no_symlinks && normalize_path(&virtual_path, "/") != lexical_path {
+        return Err(-libc::ELOOP);
+    }
+    if beneath
+        && let Some(dir) = &dir
+        && (!within_dir(&lexical_path, dir)
+            || !within_dir(&normalize_path(&virtual_path, "/"), dir))
+    {
+        return Err(-libc::EXDEV);
+    }
+
+    Ok(virtual_path)
+}
+
+/// Whether the normalized `path` is `dir` or below it.
+fn within_dir(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
 extern "C" fn close_handler(
     _cageid: u64,
     fd: u64,
@@ -734,7 +1110,10 @@ extern "C" fn readlink_handler(
         Some(p) => p,
         None => return -14, // EFAULT
     };
-    let chrooted_path = chroot_path(&path, cageid);
+    let chrooted_path = match chroot_path(&path, cageid, false) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
     let c_path = match CString::new(chrooted_path) {
         Ok(p) => p,
         Err(_) => return -1,
@@ -772,16 +1151,15 @@ extern "C" fn readlink_handler(
 
     let result_len = ret as usize;
     let result = String::from_utf8_lossy(&result_buf[..result_len]).to_string();
-    let final_result = strip_chroot_prefix(&result);
+    let final_result = strip_chroot_prefix(cageid, &result);
     write_bytes_to_cage(thiscage, buf, buf_cage, bufsiz, final_result.as_bytes())
 }
 
 /// `readlinkat(2)` handler.
 ///
-/// This mirrors `readlink_handler`, but handles `dirfd`:
-/// - If `path` is absolute or `dirfd == AT_FDCWD`, the input path is chrooted.
-/// - Otherwise, the relative path is passed through (it is interpreted relative
-///   to `dirfd`, which is expected to already refer to a chrooted directory).
+/// This mirrors `readlink_handler`, but finds the link like any other `*at`
+/// path: relative to the cwd for `AT_FDCWD`, and otherwise relative to the
+/// virtual path `dirfd` was opened with.
 ///
 /// The returned symlink target is always un-chrooted (prefix stripped) so the
 /// cage sees a virtual path.
@@ -802,27 +1180,12 @@ extern "C" fn readlinkat_handler(
 ) -> i32 {
     let thiscage = getcageid();
     let cageid = path_cage;
-    const AT_FDCWD: i64 = -100;
 
-    // Read the input path.
-    let path = match read_path_from_cage(path_ptr, path_cage) {
-        Some(p) => p,
-        None => return -14, // EFAULT
-    };
-
-    // If path is absolute or `dirfd` is AT_FDCWD, we can chroot it; otherwise
-    // pass through (relative to `dirfd`).
-    let (c_path, use_chrooted) = if path.starts_with('/') || dirfd as i64 == AT_FDCWD {
-        let chrooted = chroot_path(&path, cageid);
-        (CString::new(chrooted).ok(), true)
-    } else {
-        (CString::new(path).ok(), false)
-    };
-
-    let c_path = match c_path {
-        Some(p) => p,
-        None => return -1,
-    };
+    let (rewritten_dirfd, c_path) =
+        match rewrite_at_path(cageid, dirfd, path_ptr, path_cage, false, false) {
+            Ok(v) => v,
+            Err(e) => return e,
+        };
 
     // Call real readlinkat into a buffer owned by this grate.
     let mut result_buf = vec![0u8; bufsiz as usize];
@@ -831,7 +1194,7 @@ extern "C" fn readlinkat_handler(
         0,
         thiscage,
         path_cage,
-        if use_chrooted { AT_FDCWD as u64 } else { dirfd },
+        rewritten_dirfd,
         dirfd_cage,
         c_path.as_ptr() as u64,
         thiscage | GRATE_MEMORY_FLAG,
@@ -856,22 +1219,14 @@ extern "C" fn readlinkat_handler(
 
     let result_len = ret as usize;
     let result = String::from_utf8_lossy(&result_buf[..result_len]).to_string();
-    let final_result = strip_chroot_prefix(&result);
+    let final_result = strip_chroot_prefix(cageid, &result);
     write_bytes_to_cage(thiscage, buf, buf_cage, bufsiz, final_result.as_bytes())
 }
 
 /// `unlinkat(2)` handler.
 ///
-/// Mirrors `readlinkat_handler`'s dirfd handling:
-/// - If `path` is absolute or `dirfd == AT_FDCWD`, chroot the path and pass
-///   `AT_FDCWD` through.
-/// - Otherwise, pass the relative path and the original `dirfd` through
-///   unchanged (the dirfd already refers to an open directory inside the
-///   chrooted tree at the kernel level).
-///
-/// Without this dirfd-aware logic, a relative path like `"file.txt"` against an
-/// `open(2)`-returned dirfd would be normalized against the cage's cwd and
-/// chrooted into an absolute path, causing the kernel to ignore `dirfd`.
+/// A relative path is resolved against the virtual path `dirfd` was opened
+/// with rather than the host fd, so `..` cannot climb out of the chroot.
 extern "C" fn unlinkat_handler(
     _cageid: u64,
     dirfd: u64,
@@ -889,34 +1244,19 @@ extern "C" fn unlinkat_handler(
 ) -> i32 {
     let thiscage = getcageid();
     let cageid = path_cage;
-    const AT_FDCWD: i64 = -100;
 
-    let path = match read_path_from_cage(path_ptr, path_cage) {
-        Some(p) => p,
-        None => return -14, // EFAULT
-    };
-    if path.is_empty() {
-        return -(grate_rs::constants::error::ENOENT as i32);
-    }
-
-    let (c_path, use_chrooted) = if path.starts_with('/') || dirfd as i64 == AT_FDCWD {
-        let chrooted = chroot_path(&path, cageid);
-        (CString::new(chrooted).ok(), true)
-    } else {
-        (CString::new(path).ok(), false)
-    };
-
-    let c_path = match c_path {
-        Some(p) => p,
-        None => return -1,
-    };
+    let (rewritten_dirfd, c_path) =
+        match rewrite_at_path(cageid, dirfd, path_ptr, path_cage, false, true) {
+            Ok(v) => v,
+            Err(e) => return e,
+        };
 
     match make_threei_call(
         SYS_UNLINKAT as u32,
         0,
         thiscage,
         path_cage,
-        if use_chrooted { AT_FDCWD as u64 } else { dirfd },
+        rewritten_dirfd,
         dirfd_cage,
         c_path.as_ptr() as u64,
         thiscage | GRATE_MEMORY_FLAG,
@@ -954,15 +1294,22 @@ extern "C" fn symlink_handler(
     let thiscage = getcageid();
     let cageid = linkpath_cage;
 
-    let target = match rewrite_symlink_target(cageid, target_ptr, target_cage) {
-        Ok(v) => v,
-        Err(e) => return e,
-    };
     let linkpath = match read_path_from_cage(linkpath_ptr, linkpath_cage) {
         Some(p) => p,
         None => return -(libc::EFAULT as i32),
     };
-    let linkpath = match CString::new(chroot_path(&linkpath, cageid)) {
+    let linkpath = match resolve_path(&linkpath, cageid, false) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    if let Err(e) = check_writable(&linkpath) {
+        return e;
+    }
+    let target = match rewrite_symlink_target(cageid, target_ptr, target_cage, Some(&linkpath)) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+    let linkpath = match CString::new(host_path(cageid, &linkpath)) {
         Ok(v) => v,
         Err(_) => return -(libc::EINVAL as i32),
     };
@@ -1007,15 +1354,30 @@ extern "C" fn symlinkat_handler(
     let thiscage = getcageid();
     let cageid = linkpath_cage;
 
-    let target = match rewrite_symlink_target(cageid, target_ptr, target_cage) {
+    let linkpath = match read_path_from_cage(linkpath_ptr, linkpath_cage) {
+        Some(p) => p,
+        None => return -libc::EFAULT,
+    };
+    let resolved = match resolve_virtual_at_path(cageid, dirfd, &linkpath, false) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+    let writable = match &resolved {
+        Some(virtual_path) => check_writable(virtual_path),
+        None => check_read_only_mode(),
+    };
+    if let Err(e) = writable {
+        return e;
+    }
+    let link_path = resolved.as_deref();
+    let target = match rewrite_symlink_target(cageid, target_ptr, target_cage, link_path) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+    let (rewritten_dirfd, linkpath) = match at_path_args(cageid, dirfd, linkpath, resolved) {
         Ok(v) => v,
         Err(e) => return e,
     };
-    let (rewritten_dirfd, linkpath) =
-        match rewrite_at_path(cageid, dirfd, linkpath_ptr, linkpath_cage) {
-            Ok(v) => v,
-            Err(e) => return e,
-        };
 
     make_syscall_from_grate(
         SYS_SYMLINKAT as u32,
@@ -1039,6 +1401,62 @@ extern "C" fn symlinkat_handler(
     )
 }
 
+extern "C" fn mkdirat_handler(
+    _cageid: u64,
+    dirfd: u64,
+    dirfd_cage: u64,
+    path_ptr: u64,
+    path_cage: u64,
+    mode: u64,
+    mode_cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    call_with_at_path(
+        SYS_MKDIRAT as u32,
+        [dirfd, path_ptr, mode, arg4, arg5, arg6],
+        [
+            dirfd_cage, path_cage, mode_cage, arg4cage, arg5cage, arg6cage,
+        ],
+        0,
+        1,
+        false,
+        true,
+    )
+}
+
+extern "C" fn mknodat_handler(
+    _cageid: u64,
+    dirfd: u64,
+    dirfd_cage: u64,
+    path_ptr: u64,
+    path_cage: u64,
+    mode: u64,
+    mode_cage: u64,
+    dev: u64,
+    dev_cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    call_with_at_path(
+        SYS_MKNODAT as u32,
+        [dirfd, path_ptr, mode, dev, arg5, arg6],
+        [
+            dirfd_cage, path_cage, mode_cage, dev_cage, arg5cage, arg6cage,
+        ],
+        0,
+        1,
+        false,
+        true,
+    )
+}
+
 extern "C" fn faccessat_handler(
     _cageid: u64,
     dirfd: u64,
@@ -1056,13 +1474,42 @@ extern "C" fn faccessat_handler(
 ) -> i32 {
     call_with_at_path(
         SYS_FACCESSAT as u32,
-        path_cage,
         [dirfd, path_ptr, mode, flags, arg5, arg6],
         [
             dirfd_cage, path_cage, mode_cage, flags_cage, arg5cage, arg6cage,
         ],
         0,
         1,
+        at_follow(flags),
+        false,
+    )
+}
+
+extern "C" fn faccessat2_handler(
+    _cageid: u64,
+    dirfd: u64,
+    dirfd_cage: u64,
+    path_ptr: u64,
+    path_cage: u64,
+    mode: u64,
+    mode_cage: u64,
+    flags: u64,
+    flags_cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    call_with_at_path(
+        SYS_FACCESSAT2 as u32,
+        [dirfd, path_ptr, mode, flags, arg5, arg6],
+        [
+            dirfd_cage, path_cage, mode_cage, flags_cage, arg5cage, arg6cage,
+        ],
+        0,
+        1,
+        at_follow(flags),
+        false,
     )
 }
 
@@ -1083,13 +1530,42 @@ extern "C" fn fchmodat_handler(
 ) -> i32 {
     call_with_at_path(
         SYS_FCHMODAT as u32,
-        path_cage,
         [dirfd, path_ptr, mode, flags, arg5, arg6],
         [
             dirfd_cage, path_cage, mode_cage, flags_cage, arg5cage, arg6cage,
         ],
         0,
         1,
+        at_follow(flags),
+        true,
+    )
+}
+
+extern "C" fn fchmodat2_handler(
+    _cageid: u64,
+    dirfd: u64,
+    dirfd_cage: u64,
+    path_ptr: u64,
+    path_cage: u64,
+    mode: u64,
+    mode_cage: u64,
+    flags: u64,
+    flags_cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    call_with_at_path(
+        SYS_FCHMODAT2 as u32,
+        [dirfd, path_ptr, mode, flags, arg5, arg6],
+        [
+            dirfd_cage, path_cage, mode_cage, flags_cage, arg5cage, arg6cage,
+        ],
+        0,
+        1,
+        at_follow(flags),
+        true,
     )
 }
 
@@ -1110,13 +1586,14 @@ extern "C" fn fchownat_handler(
 ) -> i32 {
     call_with_at_path(
         SYS_FCHOWNAT as u32,
-        path_cage,
         [dirfd, path_ptr, owner, group, flags, arg6],
         [
             dirfd_cage, path_cage, owner_cage, group_cage, flags_cage, arg6cage,
         ],
         0,
         1,
+        at_follow(flags),
+        true,
     )
 }
 
@@ -1137,7 +1614,6 @@ extern "C" fn fstatat_handler(
 ) -> i32 {
     call_with_at_path(
         SYS_NEWFSTATAT as u32,
-        path_cage,
         [dirfd, path_ptr, statbuf, flags, arg5, arg6],
         [
             dirfd_cage,
@@ -1149,6 +1625,8 @@ extern "C" fn fstatat_handler(
         ],
         0,
         1,
+        at_follow(flags),
+        false,
     )
 }
 
@@ -1169,7 +1647,6 @@ extern "C" fn statx_handler(
 ) -> i32 {
     call_with_at_path(
         SYS_STATX as u32,
-        path_cage,
         [dirfd, path_ptr, flags, mask, statxbuf, arg6],
         [
             dirfd_cage,
@@ -1181,6 +1658,8 @@ extern "C" fn statx_handler(
         ],
         0,
         1,
+        at_follow(flags),
+        false,
     )
 }
 
@@ -1205,10 +1684,21 @@ extern "C" fn utimensat_handler(
     ];
 
     if path_ptr == 0 {
+        if let Err(e) = check_read_only_mode() {
+            return e;
+        }
         return make_syscall_from_grate(SYS_UTIMENSAT as u32, dirfd_cage, args, arg_cages);
     }
 
-    call_with_at_path(SYS_UTIMENSAT as u32, path_cage, args, arg_cages, 0, 1)
+    call_with_at_path(
+        SYS_UTIMENSAT as u32,
+        args,
+        arg_cages,
+        0,
+        1,
+        at_follow(flags),
+        true,
+    )
 }
 
 extern "C" fn renameat_handler(
@@ -1295,12 +1785,12 @@ fn renameat_common(
 ) -> i32 {
     let thiscage = getcageid();
     let (rewritten_olddirfd, oldpath) =
-        match rewrite_at_path(cageid, olddirfd, oldpath_ptr, oldpath_cage) {
+        match rewrite_at_path(cageid, olddirfd, oldpath_ptr, oldpath_cage, false, true) {
             Ok(v) => v,
             Err(e) => return e,
         };
     let (rewritten_newdirfd, newpath) =
-        match rewrite_at_path(cageid, newdirfd, newpath_ptr, newpath_cage) {
+        match rewrite_at_path(cageid, newdirfd, newpath_ptr, newpath_cage, false, true) {
             Ok(v) => v,
             Err(e) => return e,
         };
@@ -1327,6 +1817,60 @@ fn renameat_common(
     )
 }
 
+/// `linkat(2)` handler. The existing path's final symlink is followed only
+/// with `AT_SYMLINK_FOLLOW`, and the new path's never is.
+extern "C" fn linkat_handler(
+    _cageid: u64,
+    olddirfd: u64,
+    olddirfd_cage: u64,
+    oldpath_ptr: u64,
+    oldpath_cage: u64,
+    newdirfd: u64,
+    newdirfd_cage: u64,
+    newpath_ptr: u64,
+    newpath_cage: u64,
+    flags: u64,
+    flags_cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    let thiscage = getcageid();
+    let cageid = oldpath_cage;
+    let follow = flags & AT_SYMLINK_FOLLOW != 0;
+
+    let (rewritten_olddirfd, oldpath) =
+        match rewrite_at_path(cageid, olddirfd, oldpath_ptr, oldpath_cage, follow, true) {
+            Ok(v) => v,
+            Err(e) => return e,
+        };
+    let (rewritten_newdirfd, newpath) =
+        match rewrite_at_path(cageid, newdirfd, newpath_ptr, newpath_cage, false, true) {
+            Ok(v) => v,
+            Err(e) => return e,
+        };
+
+    make_syscall_from_grate(
+        SYS_LINKAT as u32,
+        oldpath_cage,
+        [
+            rewritten_olddirfd,
+            oldpath.as_ptr() as u64,
+            rewritten_newdirfd,
+            newpath.as_ptr() as u64,
+            flags,
+            arg6,
+        ],
+        [
+            olddirfd_cage,
+            thiscage | GRATE_MEMORY_FLAG,
+            newdirfd_cage,
+            thiscage | GRATE_MEMORY_FLAG,
+            flags_cage,
+            arg6cage,
+        ],
+    )
+}
+
 // -----------------------------------------------------------------------------
 // Process/cwd state tracking handlers
 // -----------------------------------------------------------------------------
@@ -1381,6 +1925,12 @@ extern "C" fn fork_handler(
 
     if ret > 0 && !is_thread {
         let child_cageid = ret as u64;
+        // Set up the child's own root, if it has one, before its cwd is
+        // checked against it. A child without its jail must not run.
+        if let Err(e) = prepare_cage_root(child_cageid) {
+            kill_cage(parent_cageid, child_cageid);
+            return e;
+        }
         register_cage(parent_cageid, child_cageid);
         register_child_fd_paths(parent_cageid, child_cageid);
         let _ = copy_handler_table_to_cage(thiscage, child_cageid);
@@ -1389,7 +1939,34 @@ extern "C" fn fork_handler(
     ret
 }
 
-/// execve handler.
+/// Kill a child forked by `parent_cageid` that cannot be run, as the parent.
+fn kill_cage(parent_cageid: u64, child_cageid: u64) {
+    let _ = make_threei_call(
+        SYS_KILL as u32,
+        0,
+        getcageid(),
+        parent_cageid,
+        child_cageid,
+        parent_cageid,
+        libc::SIGKILL as u64,
+        parent_cageid,
+        0,
+        parent_cageid,
+        0,
+        parent_cageid,
+        0,
+        parent_cageid,
+        0,
+        parent_cageid,
+        0,
+    );
+}
+
+/// `execve(2)` handler.
+///
+/// The target is resolved inside the chroot. Scripts are run through their
+/// `#!` interpreter, also resolved inside the chroot, with the rewritten
+/// `argv` held in grate memory; see [`exec`].
 extern "C" fn execve_handler(
     _cageid: u64,
     arg1: u64,
@@ -1405,7 +1982,6 @@ extern "C" fn execve_handler(
     arg6: u64,
     arg6cage: u64,
 ) -> i32 {
-    // Call the real fork syscall.
     let thiscage = getcageid();
     let cageid = arg1cage;
 
@@ -1414,23 +1990,27 @@ extern "C" fn execve_handler(
         None => return -(libc::EFAULT as i32),
     };
 
-    let transformed = chroot_path(&path, cageid);
-    let c_path = match CString::new(transformed) {
-        Ok(p) => p,
-        Err(_) => return -1,
+    let target = match resolve_exec(cageid, &path, arg2, arg2cage) {
+        Ok(t) => t,
+        Err(e) => return e,
     };
 
-    let rewritten_ptr = c_path.as_ptr() as u64;
+    // Keep the pointer array alive until the call returns.
+    let argv_ptrs = target.argv_pointers();
+    let (argv, argv_cage) = match &argv_ptrs {
+        Some(ptrs) => (ptrs.as_ptr() as u64, thiscage | GRATE_MEMORY_FLAG),
+        None => (arg2, arg2cage),
+    };
 
     match make_threei_call(
         SYS_EXECVE as u32,
         0,
         thiscage,
         arg1cage,
-        rewritten_ptr,
+        target.path.as_ptr() as u64,
         thiscage | GRATE_MEMORY_FLAG,
-        arg2,
-        arg2cage,
+        argv,
+        argv_cage,
         arg3,
         arg3cage,
         arg4,
@@ -1474,11 +2054,14 @@ extern "C" fn chdir_handler(
         None => return -14, // EFAULT
     };
 
-    // Normalize relative to the current virtual cwd.
-    let cwd = get_cage_cwd(cageid);
-    let new_cwd = normalize_path(&path, &cwd);
+    // Resolve relative to the current virtual cwd, so the tracked cwd never
+    // contains a symlink.
+    let new_cwd = match resolve_path(&path, cageid, true) {
+        Ok(p) => normalize_path(&p, "/"),
+        Err(e) => return e,
+    };
 
-    match check_dir(chroot_path(&new_cwd, cageid)) {
+    match check_dir(host_path(cageid, &new_cwd)) {
         false => return -20, // ENOTDIR
         true => {
             // Update the cwd of cage in our hashmap.
@@ -1599,6 +2182,11 @@ extern "C" fn chroot_handler(
 
 struct Config {
     chroot_dir: String,
+    chroot_template: Option<String>,
+    binds: Vec<BindMount>,
+    read_only: bool,
+    abstract_sockets: AbstractSockets,
+    socket_alias_dir: Option<String>,
     remaining_args: Vec<String>,
     log_enabled: bool,
 }
@@ -1607,6 +2195,11 @@ fn parse_args() -> Result<Config, &'static str> {
     let args: Vec<String> = std::env::args().skip(1).collect();
 
     let mut chroot_dir = String::new();
+    let mut chroot_template = None;
+    let mut binds = Vec::new();
+    let mut read_only = false;
+    let mut abstract_sockets = AbstractSockets::Isolate;
+    let mut socket_alias_dir = None;
     let mut remaining_args = Vec::new();
     let mut log_enabled = false;
     let mut i = 0;
@@ -1621,14 +2214,56 @@ fn parse_args() -> Result<Config, &'static str> {
             }
             chroot_dir = args[i + 1].clone();
             i += 2;
+        } else if args[i] == "--chroot-template" {
+            if i + 1 >= args.len() {
+                return Err("--chroot-template requires an argument");
+            }
+            if !args[i + 1].starts_with('/') {
+                return Err("--chroot-template must be an absolute path");
+            }
+            chroot_template = Some(normalize_path(&args[i + 1], "/"));
+            i += 2;
+        } else if args[i] == "--read-only" {
+            read_only = true;
+            i += 1;
+        } else if args[i] == "--bind" {
+            if i + 1 >= args.len() {
+                return Err("--bind requires an argument");
+            }
+            binds.push(parse_bind(&args[i + 1])?);
+            i += 2;
+        } else if args[i] == "--abstract-sockets" {
+            if i + 1 >= args.len() {
+                return Err("--abstract-sockets requires an argument");
+            }
+            abstract_sockets = parse_abstract_sockets(&args[i + 1])?;
+            i += 2;
+        } else if args[i] == "--socket-alias-dir" {
+            if i + 1 >= args.len() {
+                return Err("--socket-alias-dir requires an argument");
+            }
+            if !args[i + 1].starts_with('/') {
+                return Err("--socket-alias-dir must be an absolute path");
+            }
+            socket_alias_dir = Some(normalize_path(&args[i + 1], "/"));
+            i += 2;
         } else {
             remaining_args.push(args[i].clone());
             i += 1;
         }
     }
 
+    if chroot_template.is_some() && !chroot_dir.contains(CAGEID_PLACEHOLDER) {
+        return Err("--chroot-template requires {cageid} in --chroot-dir");
+    }
+
     Ok(Config {
         chroot_dir,
+        chroot_template,
+        binds,
+        read_only,
+        abstract_sockets,
+        socket_alias_dir,
         remaining_args,
         log_enabled,
     })
@@ -1639,20 +2274,55 @@ fn main() {
         Ok(config) => config,
         Err(err) => {
             eprintln!("argument error: {}", err);
-            eprintln!("Usage: chroot-grate [--log] --chroot-dir <path> <program> [args...]");
+            eprintln!(
+                "Usage: chroot-grate [--log] --chroot-dir <path> [--chroot-template <path>] [--read-only] [--bind <virtual>=<host>[:ro]]... [--abstract-sockets isolate|deny|allow] [--socket-alias-dir <path>] <program> [args...]"
+            );
             std::process::exit(1);
         }
     };
     logging::init(config.log_enabled);
 
     if config.chroot_dir.is_empty() {
-        eprintln!("Usage: chroot-grate [--log] --chroot-dir <path> <program> [args...]");
+        eprintln!(
+            "Usage: chroot-grate [--log] --chroot-dir <path> [--chroot-template <path>] [--read-only] [--bind <virtual>=<host>[:ro]]... [--abstract-sockets isolate|deny|allow] [--socket-alias-dir <path>] <program> [args...]"
+        );
         std::process::exit(1);
     }
 
     log!("Initializing with chroot dir: {}", config.chroot_dir);
+    if let Some(template) = &config.chroot_template {
+        log!("Per-cage root template: {}", template);
+    }
+    for bind in &config.binds {
+        log!(
+            "Bind mount: {} -> {}{}",
+            bind.virtual_path,
+            bind.host_dir,
+            if bind.read_only { " (read-only)" } else { "" }
+        );
+    }
+
+    if config.read_only {
+        log!("Read-only: refusing all writes");
+    }
 
-    init_state(config.chroot_dir);
+    log!("Abstract sockets: {:?}", config.abstract_sockets);
+
+    let socket_alias_dir = config.socket_alias_dir.unwrap_or_default();
+    if !socket_alias_dir.is_empty() {
+        log!("Socket alias dir: {}", socket_alias_dir);
+    }
+
+    init_state(config.chroot_dir, config.binds);
+    if let Some(template) = config.chroot_template {
+        if !check_dir(template.clone()) {
+            panic!("Invalid path for --chroot-template");
+        }
+        *CHROOT_TEMPLATE.lock().unwrap() = template;
+    }
+    READ_ONLY.store(config.read_only, Ordering::Relaxed);
+    *ABSTRACT_SOCKETS.lock().unwrap() = config.abstract_sockets;
+    SOCKET_ALIASES.lock().unwrap().dir = socket_alias_dir;
 
     // Get initial cwd via syscall and add to table
     let cageid = getcageid();
@@ -1669,6 +2339,7 @@ fn main() {
         // Filesystem syscalls
         .register(SYS_OPEN, open_handler)
         .register(SYS_OPENAT, openat_handler)
+        .register(SYS_OPENAT2, openat2_handler)
         .register(SYS_CLOSE, close_handler)
         .register(SYS_DUP, dup_handler)
         .register(SYS_DUP2, dup2_handler)
@@ -1680,8 +2351,12 @@ fn main() {
         .register(SYS_STATX, statx_handler)
         .register(SYS_ACCESS, access_handler)
         .register(SYS_FACCESSAT, faccessat_handler)
+        .register(SYS_FACCESSAT2, faccessat2_handler)
         .register(SYS_STATFS, statfs_handler)
         .register(SYS_MKDIR, mkdir_handler)
+        .register(SYS_MKDIRAT, mkdirat_handler)
+        .register(SYS_MKNOD, mknod_handler)
+        .register(SYS_MKNODAT, mknodat_handler)
         .register(SYS_RMDIR, rmdir_handler)
         .register(SYS_UNLINK, unlink_handler)
         .register(SYS_UNLINKAT, unlinkat_handler)
@@ -1689,10 +2364,12 @@ fn main() {
         .register(SYS_RENAMEAT, renameat_handler)
         .register(SYS_RENAMEAT2, renameat2_handler)
         .register(SYS_LINK, link_handler)
+        .register(SYS_LINKAT, linkat_handler)
         .register(SYS_SYMLINK, symlink_handler)
         .register(SYS_SYMLINKAT, symlinkat_handler)
         .register(SYS_CHMOD, chmod_handler)
         .register(SYS_FCHMODAT, fchmodat_handler)
+        .register(SYS_FCHMODAT2, fchmodat2_handler)
         .register(SYS_CHOWN, chown_handler)
         .register(SYS_LCHOWN, lchown_handler)
         .register(SYS_FCHOWNAT, fchownat_handler)
@@ -1716,12 +2393,20 @@ fn main() {
         .register(SYS_RECVFROM, recvfrom_handler)
         .preexec(move |child_cage| {
             let child_cage = child_cage as u64;
+            if let Err(e) = prepare_cage_root(child_cage) {
+                eprintln!(
+                    "cannot create the jail of cage {}: errno {}",
+                    child_cage, -e
+                );
+                std::process::exit(1);
+            }
             set_cage_cwd(child_cage, initial_cwd_for_child.clone());
             if let Some(ref mut cages) = *CAGE_DIR_FDS.lock().unwrap() {
                 cages.entry(child_cage).or_insert_with(HashMap::new);
             }
         })
         .teardown(|result| {
+            remove_socket_aliases();
             log!("Result: {:#?}", result);
         });
 
@@ -1,33 +1,105 @@
 //! Path utilities and path-rewriting syscall handler helpers.
 //!
 //! This module provides:
-//! - Virtual path resolution (`normalize_path`) using the cage's tracked cwd.
-//! - Chroot path mapping (`chroot_path`) that prepends the configured chroot directory.
+//! - Lexical path normalization (`normalize_path`) using the cage's tracked cwd.
+//! - Symlink-aware path resolution (`resolve_path`) that keeps every lookup
+//!   inside the chroot.
+//! - Chroot path mapping (`chroot_path`) that resolves a path and maps it to
+//!   the host, through the cage's root directory or a `--bind` mount.
+//! - The reverse mapping (`strip_chroot_prefix`) for paths returned to a cage.
+//! - Per-cage roots (`cage_root`): a `{cageid}` in `--chroot-dir` gives each
+//!   cage its own root directory.
 //! - Helpers for reading paths out of a cage address space.
 //! - The `input_path_handler!` macro used by `main.rs` to generate syscall
 //!   handlers for "path input" syscalls (e.g. `open`, `mkdir`, `unlink`).
 //!
-//! The chroot directory and per-cage cwd table live in the crate root:
-//! `crate::CHROOT_DIR` and `crate::CAGE_CWDS`.
+//! The chroot directory, bind mounts, read-only flag and per-cage cwd table
+//! live in the crate root: `crate::CHROOT_DIR`, `crate::BIND_MOUNTS`,
+//! `crate::READ_ONLY` and `crate::CAGE_CWDS`. Every mapping between virtual
+//! and host paths takes the cage id, since cages may have different roots.
 
-use grate_rs::{copy_data_between_cages, getcageid};
-use std::ffi::CStr;
+use grate_rs::constants::SYS_READLINK;
+use grate_rs::constants::lind::GRATE_MEMORY_FLAG;
+use grate_rs::{copy_data_between_cages, getcageid, make_threei_call};
+use std::ffi::{CStr, CString};
+use std::sync::atomic::Ordering;
+
+/// Number of symlinks a single lookup may follow before failing with `ELOOP`,
+/// the same limit Linux uses.
+pub const MAXSYMLINKS: usize = 40;
+
+/// Placeholder in `--chroot-dir` replaced by the cage id.
+pub const CAGEID_PLACEHOLDER: &str = "{cageid}";
+
+/// A host directory mapped into the cage's virtual tree with `--bind`.
+#[derive(Clone, Debug)]
+pub struct BindMount {
+    /// Where the directory appears in the virtual tree.
+    pub virtual_path: String,
+    /// The host directory shown there.
+    pub host_dir: String,
+    /// Whether writes below `virtual_path` fail with `EROFS`.
+    pub read_only: bool,
+}
+
+/// Parse a `--bind <virtual>=<host>[:ro]` argument.
+pub fn parse_bind(spec: &str) -> Result<BindMount, &'static str> {
+    let (virtual_path, host_dir) = spec
+        .split_once('=')
+        .ok_or("--bind expects <virtual>=<host>[:ro]")?;
+    let (host_dir, read_only) = match host_dir.strip_suffix(":ro") {
+        Some(dir) => (dir, true),
+        None => (host_dir, false),
+    };
+
+    if !virtual_path.starts_with('/') || !host_dir.starts_with('/') {
+        return Err("--bind paths must be absolute");
+    }
+    let virtual_path = normalize_path(virtual_path, "/");
+    if virtual_path == "/" {
+        return Err("--bind cannot replace /, use --chroot-dir");
+    }
+
+    Ok(BindMount {
+        virtual_path,
+        host_dir: normalize_path(host_dir, "/"),
+        read_only,
+    })
+}
 
 /// Generate a syscall handler that rewrites one or more path arguments.
 ///
 /// The generated handler:
 /// 1) reads each path argument from the calling cage's memory,
-/// 2) normalizes it relative to the cage's virtual cwd,
-/// 3) prepends the configured chroot directory,
+/// 2) resolves it inside the chroot relative to the cage's virtual cwd,
+/// 3) prepends the cage's root directory,
 /// 4) dispatches the real syscall via `call_with_rewrites`.
 ///
+/// A symlink in the final component is followed unless the handler is
+/// declared with `nofollow`, for syscalls that act on the link itself
+/// (e.g. `unlink`, `lchown`). Handlers declared with `write` modify the
+/// filesystem and fail with `EROFS` under `--read-only` or on a read-only
+/// bind mount.
+///
 /// # Parameters
 /// - `$name`: function name for the generated handler.
 /// - `$syscall_const`: syscall number constant (e.g. `SYS_OPEN`).
 /// - `$idx...`: argument indices (0..=5) that are path pointers.
 #[macro_export]
 macro_rules! input_path_handler {
+    (write nofollow $name:ident, $syscall_const:expr, $( $idx:expr ),+ $(,)?) => {
+        input_path_handler!(@path false, true, $name, $syscall_const, $( $idx ),+);
+    };
+    (write $name:ident, $syscall_const:expr, $( $idx:expr ),+ $(,)?) => {
+        input_path_handler!(@path true, true, $name, $syscall_const, $( $idx ),+);
+    };
+    (nofollow $name:ident, $syscall_const:expr, $( $idx:expr ),+ $(,)?) => {
+        input_path_handler!(@path false, false, $name, $syscall_const, $( $idx ),+);
+    };
     ($name:ident, $syscall_const:expr, $( $idx:expr ),+ $(,)?) => {
+        input_path_handler!(@path true, false, $name, $syscall_const, $( $idx ),+);
+    };
+    (@path $follow:expr, $write:expr, $name:ident, $syscall_const:expr, $( $idx:expr ),+) => {
         extern "C" fn $name(
             _cageid: u64,
             arg1: u64, arg1cage: u64,
@@ -62,8 +134,17 @@ macro_rules! input_path_handler {
                     return -(::grate_rs::constants::error::ENOENT as i32);
                 }
 
-                // Apply chroot transformation (normalize relative to cwd, prepend chroot dir).
-                let transformed = chroot_path(&path, cage);
+                // Apply chroot transformation (resolve relative to cwd, map to the host).
+                let resolved = match resolve_path(&path, cage, $follow) {
+                    Ok(p) => p,
+                    Err(e) => return e,
+                };
+                if $write {
+                    if let Err(e) = check_writable(&resolved) {
+                        return e;
+                    }
+                }
+                let transformed = host_path(cage, &resolved);
                 let c_path = match ::std::ffi::CString::new(transformed) {
                     Ok(p) => p,
                     Err(_) => return -1,
@@ -116,40 +197,330 @@ pub fn normalize_path(path: &str, cwd: &str) -> String {
     format!("/{}", components.join("/"))
 }
 
-/// Apply chroot mapping: normalize and prepend the configured chroot directory.
-pub fn chroot_path(path: &str, cageid: u64) -> String {
+/// Resolve `path` to a virtual path inside the chroot, following symlinks.
+///
+/// Each component is looked up on the host through `readlink(2)`, issued via
+/// 3i as the calling cage. Absolute link targets are re-anchored at the chroot
+/// root, and `..` never climbs above it, so neither can reach a host path
+/// outside the jail. A symlink in the final component is only followed when
+/// `follow_final` is set; a trailing slash always follows it.
+///
+/// Returns `-ELOOP` once more than `MAXSYMLINKS` links were followed.
+pub fn resolve_path(path: &str, cageid: u64, follow_final: bool) -> Result<String, i32> {
     if path.is_empty() {
-        return String::new();
+        return Ok(String::new());
     }
 
-    let chroot_dir = crate::CHROOT_DIR.lock().unwrap().clone();
-    let cwd = get_cage_cwd(cageid);
+    let mut start: Vec<String> = Vec::new();
+    if !path.starts_with('/') {
+        let cwd = get_cage_cwd(cageid);
+        start.extend(cwd.split('/').filter(|c| !c.is_empty()).map(String::from));
+    }
+    walk_path(0, start, path, cageid, follow_final)
+}
+
+/// Resolve `path` like `resolve_path`, but with the virtual directory `root`
+/// in place of the chroot root, as `openat2` does with `RESOLVE_IN_ROOT`: the
+/// path and absolute link targets start at `root`, and `..` stops there.
+pub fn resolve_path_in_root(
+    root: &str,
+    path: &str,
+    cageid: u64,
+    follow_final: bool,
+) -> Result<String, i32> {
+    if path.is_empty() {
+        return Ok(String::new());
+    }
+
+    let start: Vec<String> = root
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .map(String::from)
+        .collect();
+    walk_path(start.len(), start, path, cageid, follow_final)
+}
+
+/// Walk `path` from the components in `start`, following symlinks. The first
+/// `floor` components are the root: `..` does not pop them, and an absolute
+/// path or link target starts over from them.
+fn walk_path(
+    floor: usize,
+    start: Vec<String>,
+    path: &str,
+    cageid: u64,
+    follow_final: bool,
+) -> Result<String, i32> {
+    let mut resolved = start;
+
+    // Components still to walk, last one first.
+    let mut pending: Vec<String> = path.rsplit('/').map(String::from).collect();
+    let mut links = 0;
+
+    while let Some(component) = pending.pop() {
+        match component.as_str() {
+            "" | "." => continue,
+            ".." => {
+                if resolved.len() > floor {
+                    resolved.pop();
+                }
+                continue;
+            }
+            _ => resolved.push(component),
+        }
+
+        if pending.is_empty() && !follow_final {
+            break;
+        }
+
+        // A bind mount shows its host directory even if the path it is
+        // mounted over is a symlink.
+        let current = format!("/{}", resolved.join("/"));
+        if is_mount_point(&current) {
+            continue;
+        }
+        let Some(target) = host_readlink(cageid, &host_path(cageid, &current)) else {
+            continue;
+        };
+
+        links += 1;
+        if links > MAXSYMLINKS {
+            return Err(-libc::ELOOP);
+        }
+        if target.is_empty() {
+            return Err(-libc::ENOENT);
+        }
+
+        resolved.pop();
+        // Links created by this grate store their absolute target as a host
+        // path.
+        let target = virtual_path_of(cageid, &target).unwrap_or(target);
+        if target.starts_with('/') {
+            resolved.truncate(floor);
+        }
+        pending.extend(target.rsplit('/').map(String::from));
+    }
+
+    let mut resolved = format!("/{}", resolved.join("/"));
+    if path.ends_with('/') && resolved != "/" {
+        resolved.push('/');
+    }
+    Ok(resolved)
+}
+
+/// Whether the host, resolving the relative symlink `target` from the
+/// virtual directory `link_dir`, stays below the root holding the link: the
+/// bind mount it is in, or else the chroot root.
+///
+/// `..` is only trusted before any other component. After one, the host may
+/// have followed a link to anywhere, so a later `..` can climb out.
+pub fn relative_target_is_contained(link_dir: &str, target: &str) -> bool {
+    let root = bind_for(link_dir).map_or_else(|| "/".to_string(), |bind| bind.virtual_path);
+    let below_root = strip_dir_prefix(link_dir, &root).unwrap_or(link_dir);
+    let mut depth = below_root.split('/').filter(|c| !c.is_empty()).count();
+    let mut descended = false;
+
+    for component in target.split('/') {
+        match component {
+            "" | "." => {}
+            ".." if descended || depth == 0 => return false,
+            ".." => depth -= 1,
+            _ => descended = true,
+        }
+    }
+    true
+}
 
-    // Normalize the path relative to cage's cwd
-    let normalized = normalize_path(path, &cwd);
+/// Whether `--chroot-dir` gives each cage its own root.
+pub fn per_cage_roots() -> bool {
+    crate::CHROOT_DIR
+        .lock()
+        .unwrap()
+        .contains(CAGEID_PLACEHOLDER)
+}
+
+/// The host directory that is `/` for `cageid`: the chroot directory with
+/// `{cageid}` replaced by the cage id. A per-cage root is set up by
+/// `prepare_cage_root` before the cage runs.
+pub fn cage_root(cageid: u64) -> String {
+    let chroot_dir = crate::CHROOT_DIR.lock().unwrap().clone();
+    if !chroot_dir.contains(CAGEID_PLACEHOLDER) {
+        return chroot_dir;
+    }
 
-    // Prepend chroot directory
-    format!("{}{}", chroot_dir.trim_end_matches('/'), normalized)
+    let root = chroot_dir.replace(CAGEID_PLACEHOLDER, &cageid.to_string());
+    // fork_handler and preexec report a jail that cannot be created. This
+    // only covers a child that maps a path before fork_handler sets it up.
+    let _ = crate::jail::prepare_jail(cageid, &root);
+    root
 }
 
-fn virtualize_host_cwd(host_cwd: &str) -> String {
+/// Create the per-cage root of `cageid`, from the template if one is set,
+/// unless it is ready already. Does nothing without per-cage roots.
+pub fn prepare_cage_root(cageid: u64) -> Result<(), i32> {
     let chroot_dir = crate::CHROOT_DIR.lock().unwrap().clone();
-    let chroot_dir = chroot_dir.trim_end_matches('/');
+    if !chroot_dir.contains(CAGEID_PLACEHOLDER) {
+        return Ok(());
+    }
 
-    if chroot_dir.is_empty() || chroot_dir == "/" {
-        return normalize_path(host_cwd, "/");
+    let root = chroot_dir.replace(CAGEID_PLACEHOLDER, &cageid.to_string());
+    crate::jail::prepare_jail(cageid, &root)
+}
+
+/// Map a resolved virtual path to its host path for `cageid`: below the most
+/// specific bind mount holding it, or else under the cage's root.
+pub fn host_path(cageid: u64, virtual_path: &str) -> String {
+    if virtual_path.is_empty() {
+        return String::new();
     }
 
-    if host_cwd == chroot_dir {
-        return "/".to_string();
+    if let Some(bind) = bind_for(virtual_path) {
+        let rest = strip_dir_prefix(virtual_path, &bind.virtual_path).unwrap_or("/");
+        return format!("{}{}", bind.host_dir.trim_end_matches('/'), rest);
+    }
+
+    let root = cage_root(cageid);
+    format!("{}{}", root.trim_end_matches('/'), virtual_path)
+}
+
+/// Map a host path back to the virtual path it is visible at for `cageid`,
+/// using the cage's root or the bind mount with the longest matching prefix.
+/// `None` if the path is not below any of them.
+pub fn virtual_path_of(cageid: u64, host: &str) -> Option<String> {
+    let chroot_dir = cage_root(cageid);
+    let binds = crate::BIND_MOUNTS.lock().unwrap();
+
+    let mut best: Option<(usize, String)> = None;
+    let roots = binds
+        .iter()
+        .map(|bind| (bind.host_dir.as_str(), bind.virtual_path.as_str()))
+        .chain(std::iter::once((chroot_dir.as_str(), "/")));
+    for (host_dir, virtual_dir) in roots {
+        let Some(rest) = strip_dir_prefix(host, host_dir) else {
+            continue;
+        };
+        if best.as_ref().is_some_and(|(len, _)| *len >= host_dir.len()) {
+            continue;
+        }
+        let virtual_path = match (virtual_dir, rest) {
+            ("/", rest) => rest.to_string(),
+            (dir, "/") => dir.to_string(),
+            (dir, rest) => format!("{}{}", dir, rest),
+        };
+        best = Some((host_dir.len(), virtual_path));
+    }
+
+    best.map(|(_, virtual_path)| virtual_path)
+}
+
+/// Strip the cage's root or a bind mount's host directory from a host path
+/// returned by the kernel, so the cage sees a virtual path. Paths outside all
+/// of them are returned unchanged.
+pub fn strip_chroot_prefix(cageid: u64, path: &str) -> String {
+    virtual_path_of(cageid, path).unwrap_or_else(|| path.to_string())
+}
+
+/// Fail with `EROFS` if writes to `virtual_path` are refused: everywhere with
+/// `--read-only`, or below a read-only bind mount.
+pub fn check_writable(virtual_path: &str) -> Result<(), i32> {
+    check_read_only_mode()?;
+    match bind_for(virtual_path) {
+        Some(bind) if bind.read_only => Err(-libc::EROFS),
+        _ => Ok(()),
+    }
+}
+
+/// Fail with `EROFS` under `--read-only`. Used for writes whose path is not
+/// resolved by the grate (e.g. relative to an untracked dirfd).
+pub fn check_read_only_mode() -> Result<(), i32> {
+    if crate::READ_ONLY.load(Ordering::Relaxed) {
+        return Err(-libc::EROFS);
+    }
+    Ok(())
+}
+
+/// The most specific bind mount holding `virtual_path`.
+fn bind_for(virtual_path: &str) -> Option<BindMount> {
+    // BIND_MOUNTS is kept sorted with the longest virtual path first.
+    crate::BIND_MOUNTS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|bind| strip_dir_prefix(virtual_path, &bind.virtual_path).is_some())
+        .cloned()
+}
+
+/// Whether a bind mount is mounted exactly at `virtual_path`.
+fn is_mount_point(virtual_path: &str) -> bool {
+    crate::BIND_MOUNTS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|bind| bind.virtual_path == virtual_path)
+}
+
+/// Apply chroot mapping: resolve the path inside the chroot and prepend the
+/// cage's root directory.
+pub fn chroot_path(path: &str, cageid: u64, follow_final: bool) -> Result<String, i32> {
+    resolve_path(path, cageid, follow_final).map(|resolved| host_path(cageid, &resolved))
+}
+
+/// Strip `dir` from the front of `path` on a component boundary, returning
+/// the remainder as an absolute path.
+fn strip_dir_prefix<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    let dir = dir.trim_end_matches('/');
+    if dir.is_empty() {
+        return None;
     }
 
-    match host_cwd.strip_prefix(chroot_dir) {
-        Some(stripped) if stripped.starts_with('/') => normalize_path(stripped, "/"),
-        _ => normalize_path(host_cwd, "/"),
+    match path.strip_prefix(dir) {
+        Some("") => Some("/"),
+        Some(rest) if rest.starts_with('/') => Some(rest),
+        _ => None,
     }
 }
 
+/// The errno of the last failed libc call made by the grate itself.
+pub fn last_errno() -> i32 {
+    std::io::Error::last_os_error()
+        .raw_os_error()
+        .unwrap_or(libc::EIO)
+}
+
+/// Read the target of the symlink at `host_path`, or `None` if it is not a
+/// symlink (or does not exist).
+fn host_readlink(cageid: u64, host_path: &str) -> Option<String> {
+    let thiscage = getcageid();
+    let c_path = CString::new(host_path).ok()?;
+    let mut buf = vec![0u8; 4096];
+
+    match make_threei_call(
+        SYS_READLINK as u32,
+        0,
+        thiscage,
+        cageid,
+        c_path.as_ptr() as u64,
+        thiscage | GRATE_MEMORY_FLAG,
+        buf.as_mut_ptr() as u64,
+        thiscage | GRATE_MEMORY_FLAG,
+        buf.len() as u64,
+        thiscage,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ) {
+        Ok(len) if len >= 0 => Some(String::from_utf8_lossy(&buf[..len as usize]).to_string()),
+        _ => None,
+    }
+}
+
+fn virtualize_host_cwd(cageid: u64, host_cwd: &str) -> String {
+    normalize_path(&strip_chroot_prefix(cageid, host_cwd), "/")
+}
+
 /// Read a NUL-terminated C string from a cage's memory and return it as UTF-8.
 ///
 /// Uses `copytype=1` (Strncpy) so threei scans for the NUL byte in
@@ -181,8 +552,14 @@ pub fn read_path_from_cage(ptr: u64, src_cage: u64) -> Option<String> {
     }
 }
 
-/// Seed the per-cage cwd table using the host `getcwd(2)`.
+/// Seed the per-cage cwd table using the host `getcwd(2)`. With per-cage
+/// roots the host cwd means nothing inside a jail, so cages start in `/`.
 pub fn init_cwd(cageid: u64) -> String {
+    if per_cage_roots() {
+        set_cage_cwd(cageid, "/".to_string());
+        return "/".to_string();
+    }
+
     let mut buf = vec![0u8; 4096];
 
     let _ = unsafe { libc::getcwd(buf.as_mut_ptr() as *mut libc::c_char, 4096) };
@@ -192,7 +569,7 @@ pub fn init_cwd(cageid: u64) -> String {
             .to_string_lossy()
             .into_owned()
     };
-    let cwd = virtualize_host_cwd(&host_cwd);
+    let cwd = virtualize_host_cwd(cageid, &host_cwd);
 
     set_cage_cwd(cageid, cwd.clone());
     cwd
@@ -216,8 +593,12 @@ pub fn set_cage_cwd(cageid: u64, cwd: String) {
     }
 }
 
-/// Register a new cage by copying the parent's cwd.
+/// Register a new cage by copying the parent's cwd. A cage with its own
+/// root starts in `/` instead if that directory is missing from its jail.
 pub fn register_cage(parent_cageid: u64, child_cageid: u64) {
-    let parent_cwd = get_cage_cwd(parent_cageid);
-    set_cage_cwd(child_cageid, parent_cwd);
+    let mut cwd = get_cage_cwd(parent_cageid);
+    if per_cage_roots() && !crate::check_dir(host_path(child_cageid, &cwd)) {
+        cwd = "/".to_string();
+    }
+    set_cage_cwd(child_cageid, cwd);
 }
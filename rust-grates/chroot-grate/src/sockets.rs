@@ -7,12 +7,17 @@
 //! - **Output** (e.g. `accept`, `getsockname`, `getpeername`, `recvfrom`): strip
 //!   the chroot prefix from returned `sun_path` so the cage sees a virtual path.
 //!
-//! This module intentionally focuses on pathname sockets. Abstract-namespace
-//! AF_UNIX addresses (leading `\\0`) are treated as opaque bytes and are passed
-//! through unchanged.
+//! A host path that no longer fits in the 108-byte `sun_path` once the chroot
+//! directory is prepended is shortened through a symlink to its directory in
+//! the socket alias directory (`--socket-alias-dir`).
+//!
+//! Abstract-namespace addresses (leading `\\0`) are not files and are shared by
+//! the whole host. `--abstract-sockets` decides what happens to them; see
+//! [`AbstractSockets`].
 
 use crate::paths::*;
 use grate_rs::{copy_data_between_cages, getcageid};
+use std::ffi::CString;
 
 /// Generate a syscall handler that rewrites a `sockaddr` argument before calling
 /// the real syscall.
@@ -20,12 +25,21 @@ use grate_rs::{copy_data_between_cages, getcageid};
 /// Used for syscalls that **take** a sockaddr containing a path (AF_UNIX):
 /// `bind`, `connect`, and the destination address in `sendto`.
 ///
+/// Handlers declared with `write` create the socket file (`bind`) and fail
+/// with `EROFS` under `--read-only` or on a read-only bind mount.
+///
 /// # Parameters
 /// - `$idx`: index of the `sockaddr*` argument in the 6-arg syscall ABI.
 /// - `$idx_len`: index of the corresponding `socklen_t` argument.
 #[macro_export]
 macro_rules! socket_translate_handler {
+    (write $name: ident, $syscall_const:expr, $idx:expr, $idx_len:expr) => {
+        socket_translate_handler!(@sockaddr true, $name, $syscall_const, $idx, $idx_len);
+    };
     ($name: ident, $syscall_const:expr, $idx:expr, $idx_len:expr) => {
+        socket_translate_handler!(@sockaddr false, $name, $syscall_const, $idx, $idx_len);
+    };
+    (@sockaddr $write:expr, $name: ident, $syscall_const:expr, $idx:expr, $idx_len:expr) => {
         extern "C" fn $name(
             _cageid: u64,
             arg1: u64,
@@ -52,9 +66,9 @@ macro_rules! socket_translate_handler {
                 // practice this is the cage associated with the syscall's
                 // memory arguments (and therefore the cwd tracking entry).
                 let (sockaddr_buf, new_len) =
-                    match translate_sockaddr(arg1cage, args[$idx], cages[$idx], args[$idx_len]) {
-                        Some(v) => v,
-                        None => return -14, // EFAULT
+                    match translate_sockaddr(arg1cage, args[$idx], cages[$idx], args[$idx_len], $write) {
+                        Ok(v) => v,
+                        Err(e) => return e,
                     };
 
                 let rewrites = &[($idx as usize, sockaddr_buf.as_ptr() as u64)];
@@ -180,20 +194,73 @@ macro_rules! socket_untranslate_handler {
 /// Address family for Unix domain sockets (`sockaddr_un`).
 const AF_UNIX: u16 = 1;
 
+/// Size of `sockaddr_un.sun_path`.
+const SUN_PATH_MAX: usize = 108;
+
+/// Prefix of the default socket alias directory, followed by a random suffix.
+const DEFAULT_ALIAS_DIR_PREFIX: &str = "/tmp/chroot-grate-";
+
+/// Random names tried for the default socket alias directory.
+const ALIAS_DIR_ATTEMPTS: usize = 100;
+
+/// Policy for abstract-namespace AF_UNIX addresses, set by `--abstract-sockets`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbstractSockets {
+    /// Prefix every name with a tag derived from the chroot directory, so
+    /// only cages sharing the chroot directory reach each other's sockets.
+    Isolate,
+    /// Fail `bind`, `connect`, and `sendto` on abstract addresses with
+    /// `EPERM`.
+    Deny,
+    /// Pass abstract addresses through unchanged.
+    Allow,
+}
+
+/// Socket alias directory and the host directories aliased in it. Kept under
+/// one lock so the two are always read and changed together.
+pub struct SocketAliases {
+    /// Host directory holding the aliases. Set by `--socket-alias-dir`, or
+    /// else to a new directory under `/tmp` when the first alias is created.
+    pub dir: String,
+    /// Host directories aliased in `dir`, by alias number.
+    pub aliased: Vec<String>,
+}
+
+impl SocketAliases {
+    pub const fn new() -> Self {
+        Self {
+            dir: String::new(),
+            aliased: Vec::new(),
+        }
+    }
+}
+
+/// Parse the argument of `--abstract-sockets`.
+pub fn parse_abstract_sockets(policy: &str) -> Result<AbstractSockets, &'static str> {
+    match policy {
+        "isolate" => Ok(AbstractSockets::Isolate),
+        "deny" => Ok(AbstractSockets::Deny),
+        "allow" => Ok(AbstractSockets::Allow),
+        _ => Err("--abstract-sockets must be one of isolate, deny, allow"),
+    }
+}
+
 /// Translate a `sockaddr` buffer if it is AF_UNIX.
 ///
 /// Returns a freshly-allocated buffer containing the rewritten sockaddr bytes
-/// and the new length.
+/// and the new length, or a negative errno. `write` marks a syscall that
+/// creates the socket file.
 pub fn translate_sockaddr(
     cageid: u64,
     addr: u64,
     addr_cage: u64,
     addrlen: u64,
-) -> Option<(Vec<u8>, u64)> {
+    write: bool,
+) -> Result<(Vec<u8>, u64), i32> {
     let thiscage = getcageid();
 
     if addrlen < 2 {
-        return None;
+        return Err(-14); // EFAULT
     }
 
     let mut sockaddr_buf = vec![0u8; addrlen as usize];
@@ -209,19 +276,25 @@ pub fn translate_sockaddr(
     )
     .is_err()
     {
-        return None;
+        return Err(-14); // EFAULT
     }
 
     // Check `sa_family` (first 2 bytes).
     let sa_family = u16::from_ne_bytes([sockaddr_buf[0], sockaddr_buf[1]]);
 
-    if sa_family == AF_UNIX && addrlen > 2 {
+    if sa_family == AF_UNIX && addrlen == 2 {
+        // An address with no path makes `bind` pick an abstract name.
+        if abstract_sockets() == AbstractSockets::Deny {
+            return Err(-libc::EPERM);
+        }
+        Ok((sockaddr_buf, addrlen))
+    } else if sa_family == AF_UNIX {
         // Extract and chroot the path (starts at offset 2).
         let path_bytes = &sockaddr_buf[2..];
 
-        // Abstract socket paths start with \0. Skip these.
+        // Abstract socket paths start with \0.
         if path_bytes.first() == Some(&0) {
-            return Some((sockaddr_buf, addrlen));
+            return translate_abstract(cageid, sockaddr_buf);
         }
 
         let path_len = path_bytes
@@ -230,7 +303,14 @@ pub fn translate_sockaddr(
             .unwrap_or(path_bytes.len());
         let path = String::from_utf8_lossy(&path_bytes[..path_len]).to_string();
 
-        let chrooted = chroot_path(&path, cageid);
+        let resolved = resolve_path(&path, cageid, true)?;
+        if write {
+            check_writable(&resolved)?;
+        }
+        let mut chrooted = host_path(cageid, &resolved);
+        if chrooted.len() >= SUN_PATH_MAX {
+            chrooted = alias_socket_path(&chrooted)?;
+        }
 
         // Build a new sockaddr payload (sa_family + sun_path + trailing NUL).
         let mut new_sockaddr = vec![0u8; 2 + chrooted.len() + 1];
@@ -238,26 +318,32 @@ pub fn translate_sockaddr(
         new_sockaddr[1] = sockaddr_buf[1];
         new_sockaddr[2..2 + chrooted.len()].copy_from_slice(chrooted.as_bytes());
 
-        Some((new_sockaddr.clone(), new_sockaddr.len() as u64))
+        Ok((new_sockaddr.clone(), new_sockaddr.len() as u64))
     } else {
         // Not AF_UNIX, return original
-        Some((sockaddr_buf, addrlen))
+        Ok((sockaddr_buf, addrlen))
     }
 }
 
 /// Untranslate a sockaddr buffer in place (strip chroot prefix from AF_UNIX path)
-pub fn untranslate_sockaddr(sockaddr_buf: &mut [u8]) {
-    let chroot_dir = crate::CHROOT_DIR.lock().unwrap().clone();
-
+/// as seen by `cageid`.
+///
+/// Returns the address length to report to the cage, which is shorter than
+/// the buffer when an isolated abstract name lost its prefix.
+pub fn untranslate_sockaddr(cageid: u64, sockaddr_buf: &mut [u8]) -> usize {
     // Need at least 2 bytes for sa_family
     if sockaddr_buf.len() < 2 {
-        return;
+        return sockaddr_buf.len();
     }
 
     // Check if this is an AF_UNIX socket.
     let sa_family = u16::from_ne_bytes([sockaddr_buf[0], sockaddr_buf[1]]);
     if sa_family != AF_UNIX || sockaddr_buf.len() <= 2 {
-        return;
+        return sockaddr_buf.len();
+    }
+
+    if sockaddr_buf[2] == 0 {
+        return untranslate_abstract(cageid, sockaddr_buf);
     }
 
     // Extract the path (starts at offset 2, NUL-terminated).
@@ -269,16 +355,7 @@ pub fn untranslate_sockaddr(sockaddr_buf: &mut [u8]) {
     let path = String::from_utf8_lossy(&path_bytes[..path_len]).to_string();
 
     // Strip chroot prefix if present to get the virtual path.
-    let virtual_path = if path.starts_with(&chroot_dir) {
-        let stripped = &path[chroot_dir.len()..];
-        if stripped.is_empty() {
-            "/".to_string()
-        } else {
-            stripped.to_string()
-        }
-    } else {
-        path
-    };
+    let virtual_path = strip_chroot_prefix(cageid, &unalias_socket_path(&path));
 
     // Write the virtual path back to the buffer.
     let dest = &mut sockaddr_buf[2..];
@@ -287,6 +364,8 @@ pub fn untranslate_sockaddr(sockaddr_buf: &mut [u8]) {
     if copy_len < dest.len() {
         dest[copy_len] = 0; // null-terminate
     }
+
+    sockaddr_buf.len()
 }
 
 /// Read a returned sockaddr from cage memory, untranslate it, and write it back.
@@ -343,7 +422,7 @@ pub fn untranslate_sockaddr_in_cage(
     }
 
     // Step 3: Untranslate the sockaddr (strip chroot prefix if AF_UNIX)
-    untranslate_sockaddr(&mut sockaddr_buf);
+    let new_addrlen = untranslate_sockaddr(addr_cage, &mut sockaddr_buf);
 
     // Step 4: Write the untranslated sockaddr back to cage memory
     let _ = copy_data_between_cages(
@@ -356,4 +435,207 @@ pub fn untranslate_sockaddr_in_cage(
         addrlen as u64,
         0,
     );
+
+    // Step 5: Report the shorter length of an isolated abstract name
+    if new_addrlen != addrlen {
+        let new_addrlen_buf = (new_addrlen as u32).to_ne_bytes();
+        let _ = copy_data_between_cages(
+            thiscage,
+            addrlen_cage,
+            new_addrlen_buf.as_ptr() as u64,
+            thiscage,
+            addrlen_ptr,
+            addrlen_cage,
+            4,
+            0,
+        );
+    }
+}
+
+/// The `--abstract-sockets` policy in effect.
+fn abstract_sockets() -> AbstractSockets {
+    *crate::ABSTRACT_SOCKETS.lock().unwrap()
+}
+
+/// Name prefix isolating abstract sockets under `cageid`'s root directory.
+///
+/// The tag is a hash of the root directory so that it stays short, and so
+/// that cages sharing a root directory share abstract sockets.
+fn abstract_prefix(cageid: u64) -> String {
+    // 64-bit FNV-1a.
+    let hash = cage_root(cageid)
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("chroot-{:016x}:", hash)
+}
+
+/// Apply the `--abstract-sockets` policy to an abstract address.
+fn translate_abstract(cageid: u64, sockaddr_buf: Vec<u8>) -> Result<(Vec<u8>, u64), i32> {
+    match abstract_sockets() {
+        AbstractSockets::Allow => {
+            let len = sockaddr_buf.len() as u64;
+            Ok((sockaddr_buf, len))
+        }
+        AbstractSockets::Deny => Err(-libc::EPERM),
+        AbstractSockets::Isolate => {
+            // sa_family and the leading NUL, then the tag, then the name.
+            // Abstract names are not NUL-terminated: the length covers them.
+            let mut new_sockaddr = sockaddr_buf[..3].to_vec();
+            new_sockaddr.extend_from_slice(abstract_prefix(cageid).as_bytes());
+            new_sockaddr.extend_from_slice(&sockaddr_buf[3..]);
+            if new_sockaddr.len() > 2 + SUN_PATH_MAX {
+                return Err(-libc::EINVAL);
+            }
+
+            let len = new_sockaddr.len() as u64;
+            Ok((new_sockaddr, len))
+        }
+    }
+}
+
+/// Strip the isolation tag from a returned abstract address.
+fn untranslate_abstract(cageid: u64, sockaddr_buf: &mut [u8]) -> usize {
+    let len = sockaddr_buf.len();
+    if abstract_sockets() != AbstractSockets::Isolate {
+        return len;
+    }
+
+    let prefix = abstract_prefix(cageid);
+    if !sockaddr_buf[3..].starts_with(prefix.as_bytes()) {
+        return len;
+    }
+
+    sockaddr_buf.copy_within(3 + prefix.len().., 3);
+    sockaddr_buf[len - prefix.len()..].fill(0);
+    len - prefix.len()
+}
+
+/// Shorten a host socket path that does not fit in `sun_path`.
+///
+/// The socket's directory is reached through a symlink
+/// `<alias dir>/<n> -> <dir>`, created on first use, so the kernel binds or
+/// connects in the real directory through `<alias dir>/<n>/<name>`.
+fn alias_socket_path(host_path: &str) -> Result<String, i32> {
+    let Some((dir, name)) = host_path.rsplit_once('/') else {
+        return Err(-libc::ENAMETOOLONG);
+    };
+
+    let mut state = crate::SOCKET_ALIASES.lock().unwrap();
+    let SocketAliases {
+        dir: alias_dir,
+        aliased: aliases,
+    } = &mut *state;
+
+    let index = match aliases.iter().position(|aliased| aliased == dir) {
+        Some(index) => index,
+        None => {
+            if aliases.is_empty() {
+                // Keep the directory only once it holds an alias, so a failed
+                // first alias does not leave it behind to fail the next try.
+                let created = create_alias_dir(alias_dir)?;
+                if let Err(e) = create_alias(&format!("{}/0", created), dir) {
+                    if let Ok(c_dir) = CString::new(created) {
+                        unsafe { libc::rmdir(c_dir.as_ptr()) };
+                    }
+                    return Err(e);
+                }
+                *alias_dir = created;
+            } else {
+                create_alias(&format!("{}/{}", alias_dir, aliases.len()), dir)?;
+            }
+            aliases.push(dir.to_string());
+            aliases.len() - 1
+        }
+    };
+
+    let short_path = format!("{}/{}/{}", alias_dir, index, name);
+    if short_path.len() >= SUN_PATH_MAX {
+        return Err(-libc::ENAMETOOLONG);
+    }
+
+    Ok(short_path)
+}
+
+/// Map a path under the socket alias directory back to the host path.
+fn unalias_socket_path(path: &str) -> String {
+    let state = crate::SOCKET_ALIASES.lock().unwrap();
+    if state.aliased.is_empty() {
+        return path.to_string();
+    }
+
+    path.strip_prefix(state.dir.as_str())
+        .and_then(|rest| rest.strip_prefix('/'))
+        .and_then(|rest| rest.split_once('/'))
+        .and_then(|(index, name)| {
+            let dir = state.aliased.get(index.parse::<usize>().ok()?)?;
+            Some(format!("{}/{}", dir, name))
+        })
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Create the socket alias directory, mode 0700, and return its path.
+///
+/// The directory must be new, so that nobody else owns it or has planted
+/// links in it: an existing `--socket-alias-dir` fails with `EEXIST`, and
+/// without one a directory with a random name is made under `/tmp`, as
+/// mkdtemp(3) would.
+fn create_alias_dir(configured: &str) -> Result<String, i32> {
+    if !configured.is_empty() {
+        mkdir_private(configured)?;
+        return Ok(configured.to_string());
+    }
+
+    for _ in 0..ALIAS_DIR_ATTEMPTS {
+        let dir = format!("{}{:08x}", DEFAULT_ALIAS_DIR_PREFIX, random_u32());
+        match mkdir_private(&dir) {
+            Err(e) if e == -libc::EEXIST => continue,
+            result => return result.map(|_| dir),
+        }
+    }
+    Err(-libc::EEXIST)
+}
+
+fn mkdir_private(dir: &str) -> Result<(), i32> {
+    let c_dir = CString::new(dir).map_err(|_| -libc::EINVAL)?;
+    if unsafe { libc::mkdir(c_dir.as_ptr(), 0o700) } < 0 {
+        return Err(-last_errno());
+    }
+    Ok(())
+}
+
+/// A random number, from the OS-seeded keys of `RandomState`.
+fn random_u32() -> u32 {
+    use std::hash::{BuildHasher, RandomState};
+    RandomState::new().hash_one(std::process::id()) as u32
+}
+
+/// Point the alias `link` at `dir`.
+fn create_alias(link: &str, dir: &str) -> Result<(), i32> {
+    let c_link = CString::new(link).map_err(|_| -libc::EINVAL)?;
+    let c_dir = CString::new(if dir.is_empty() { "/" } else { dir }).map_err(|_| -libc::EINVAL)?;
+
+    if unsafe { libc::symlink(c_dir.as_ptr(), c_link.as_ptr()) } < 0 {
+        return Err(-last_errno());
+    }
+    Ok(())
+}
+
+/// Remove the socket aliases created by this grate.
+pub fn remove_socket_aliases() {
+    let mut state = crate::SOCKET_ALIASES.lock().unwrap();
+    if state.aliased.is_empty() {
+        return;
+    }
+
+    for index in 0..state.aliased.len() {
+        if let Ok(c_link) = CString::new(format!("{}/{}", state.dir, index)) {
+            unsafe { libc::unlink(c_link.as_ptr()) };
+        }
+    }
+    if let Ok(c_dir) = CString::new(state.dir.as_str()) {
+        unsafe { libc::rmdir(c_dir.as_ptr()) };
+    }
+    state.aliased.clear();
 }
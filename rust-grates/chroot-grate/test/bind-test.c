@@ -0,0 +1,149 @@
+#define _GNU_SOURCE
+
+/* bind-test.c — Test binary for chroot-grate's --bind mounts.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ *   --chroot-dir / --bind /ro-root=/:ro --bind /ro-root/rw=/
+ * so the host root is visible read-only at /ro-root, and writable again at
+ * /ro-root/rw, the more specific mount.
+ */
+
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+#include <sys/un.h>
+#include <unistd.h>
+
+static int failures = 0;
+static int total = 0;
+
+#define PASS(x) \
+    do { \
+        printf("PASS: %s\n", x); \
+    } while (0)
+
+#define FAIL(x) \
+    do { \
+        printf("FAIL: %s (%s)\n", x, strerror(errno)); \
+        failures++; \
+    } while (0)
+
+#define CHECK(x, expr) \
+    do { \
+        total++; \
+        if (expr) PASS(x); else FAIL(x); \
+    } while (0)
+
+#define CHECK_EROFS(x, expr) \
+    do { \
+        errno = 0; \
+        CHECK(x, (expr) == -1 && errno == EROFS); \
+    } while (0)
+
+int main(void) {
+    char base[PATH_MAX];
+    char file[64];
+    char link_name[64];
+    char ro_file[PATH_MAX + 64];
+    char ro_dir[PATH_MAX + 64];
+    char ro_new[PATH_MAX + 64];
+    char ro_link[PATH_MAX + 64];
+    char rw_file[PATH_MAX + 64];
+    char ro_base[PATH_MAX + 16];
+    char buf[PATH_MAX + 64];
+    struct stat st;
+
+    CHECK("getcwd: base directory", getcwd(base, sizeof(base)) != NULL);
+
+    snprintf(file, sizeof(file), "bind-%ld.txt", (long)getpid());
+    snprintf(link_name, sizeof(link_name), "bind-link-%ld", (long)getpid());
+    snprintf(ro_base, sizeof(ro_base), "/ro-root%s", base);
+    snprintf(ro_file, sizeof(ro_file), "%s/%s", ro_base, file);
+    snprintf(ro_dir, sizeof(ro_dir), "%s/bind-dir-%ld", ro_base, (long)getpid());
+    snprintf(ro_new, sizeof(ro_new), "%s/bind-new-%ld", ro_base, (long)getpid());
+    snprintf(ro_link, sizeof(ro_link), "%s/%s", ro_base, link_name);
+    snprintf(rw_file, sizeof(rw_file), "/ro-root/rw%s/bind-rw-%ld.txt", base, (long)getpid());
+
+    int fd = open(file, O_CREAT | O_RDWR | O_TRUNC, 0644);
+    CHECK("open: create file outside the mounts", fd >= 0);
+    if (fd >= 0) {
+        CHECK("write: populate it", write(fd, "data", 4) == 4);
+        close(fd);
+    }
+    CHECK("symlink: relative link next to it", symlink(file, link_name) == 0);
+
+    // ---- reads through the read-only mount ----
+    CHECK("stat: file through read-only mount", stat(ro_file, &st) == 0 && st.st_size == 4);
+    fd = open(ro_file, O_RDONLY);
+    CHECK("open: read-only open through read-only mount", fd >= 0);
+    if (fd >= 0) {
+        memset(buf, 0, sizeof(buf));
+        CHECK("read: sees the file's data", read(fd, buf, 4) == 4 && strcmp(buf, "data") == 0);
+        close(fd);
+    }
+    ssize_t len = readlink(ro_link, buf, sizeof(buf) - 1);
+    if (len >= 0) {
+        buf[len] = '\0';
+    }
+    CHECK("readlink: link target through read-only mount", len >= 0 && strcmp(buf, file) == 0);
+
+    // ---- writes through the read-only mount ----
+    CHECK_EROFS("open: O_WRONLY fails with EROFS", open(ro_file, O_WRONLY));
+    CHECK_EROFS("open: O_RDWR | O_TRUNC fails with EROFS", open(ro_file, O_RDWR | O_TRUNC));
+    CHECK_EROFS("open: O_CREAT fails with EROFS", open(ro_new, O_CREAT | O_WRONLY, 0644));
+    CHECK_EROFS("openat: O_WRONLY fails with EROFS", openat(AT_FDCWD, ro_file, O_WRONLY));
+    CHECK_EROFS("truncate: fails with EROFS", truncate(ro_file, 0));
+    CHECK_EROFS("chmod: fails with EROFS", chmod(ro_file, 0600));
+    CHECK_EROFS("fchmodat: fails with EROFS", fchmodat(AT_FDCWD, ro_file, 0600, 0));
+    CHECK_EROFS("utimensat: fails with EROFS", utimensat(AT_FDCWD, ro_file, NULL, 0));
+    CHECK_EROFS("unlink: fails with EROFS", unlink(ro_file));
+    CHECK_EROFS("unlinkat: fails with EROFS", unlinkat(AT_FDCWD, ro_file, 0));
+    CHECK_EROFS("rename: fails with EROFS", rename(ro_file, ro_new));
+    CHECK_EROFS("link: fails with EROFS", link(ro_file, ro_new));
+    CHECK_EROFS("symlink: fails with EROFS", symlink(file, ro_new));
+    CHECK_EROFS("mkdir: fails with EROFS", mkdir(ro_dir, 0755));
+    CHECK("stat: file is unchanged", stat(file, &st) == 0 && st.st_size == 4);
+
+    int s = socket(AF_UNIX, SOCK_STREAM, 0);
+    CHECK("socket: create AF_UNIX socket", s >= 0);
+    if (s >= 0) {
+        struct sockaddr_un un;
+        memset(&un, 0, sizeof(un));
+        un.sun_family = AF_UNIX;
+        snprintf(un.sun_path, sizeof(un.sun_path), "/ro-root/bind-sock-%ld", (long)getpid());
+        CHECK_EROFS("bind: socket in read-only mount fails with EROFS",
+                    bind(s, (struct sockaddr *)&un, sizeof(un)));
+        close(s);
+    }
+
+    // ---- the more specific writable mount ----
+    fd = open(rw_file, O_CREAT | O_RDWR, 0644);
+    CHECK("open: create through nested writable mount", fd >= 0);
+    if (fd >= 0) close(fd);
+    snprintf(buf, sizeof(buf), "bind-rw-%ld.txt", (long)getpid());
+    CHECK("access: created file is on the host", access(buf, F_OK) == 0);
+    CHECK("unlink: remove through nested writable mount", unlink(rw_file) == 0);
+
+    // ---- cwd inside the read-only mount ----
+    CHECK("chdir: into read-only mount", chdir(ro_base) == 0);
+    CHECK("getcwd: reports the virtual path",
+          getcwd(buf, sizeof(buf)) != NULL && strcmp(buf, ro_base) == 0);
+    fd = open(file, O_RDONLY);
+    CHECK("open: relative read inside read-only mount", fd >= 0);
+    if (fd >= 0) close(fd);
+    CHECK_EROFS("open: relative write inside read-only mount fails with EROFS", open(file, O_WRONLY));
+    CHECK("chdir: back to base", chdir(base) == 0);
+
+    unlink(link_name);
+    unlink(file);
+
+    printf("Result (%d/%d passed).\n", (total - failures), total);
+
+    return failures ? 1 : 0;
+}
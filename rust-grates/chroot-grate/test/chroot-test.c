@@ -199,6 +199,72 @@ int main(int argc, char *argv[]) {
     CHECK("readlink: absolute symlink target succeeds", linklen >= 0);
     CHECK("unlink: remove absolute symlink", unlink(sym) == 0);
 
+    // ---- symlink resolution ----
+    // The grate walks symlinks itself so that they cannot lead out of the
+    // chroot; with any chroot dir they must still behave as on Linux.
+    {
+        char base[PATH_MAX];
+        char real[64];
+        char inner[80];
+        char marker[80];
+        char via[PATH_MAX];
+        char up[64];
+        char loop_a[64];
+        char loop_b[64];
+        char target[PATH_MAX + 80];
+
+        snprintf(real, sizeof(real), "res-%s", seed);
+        snprintf(inner, sizeof(inner), "%s/inner", real);
+        snprintf(marker, sizeof(marker), "%s/marker", real);
+        snprintf(up, sizeof(up), "up-%s", seed);
+        snprintf(loop_a, sizeof(loop_a), "loop-a-%s", seed);
+        snprintf(loop_b, sizeof(loop_b), "loop-b-%s", seed);
+
+        CHECK("getcwd: base for absolute symlink", getcwd(base, sizeof(base)) != NULL);
+        CHECK("mkdir: symlink resolution directory", mkdir(real, 0755) == 0 || errno == EEXIST);
+        CHECK("mkdir: nested directory", mkdir(inner, 0755) == 0 || errno == EEXIST);
+        int mfd = open(marker, O_CREAT | O_RDWR, 0644);
+        CHECK("open: create marker file", mfd >= 0);
+        if (mfd >= 0) close(mfd);
+
+        // Absolute link to a directory, followed in the middle of a path.
+        snprintf(target, sizeof(target), "%s/%s", base, real);
+        CHECK("symlink: absolute link to directory", symlink(target, sym) == 0);
+        snprintf(via, sizeof(via), "%s/marker", sym);
+        CHECK("access: path through absolute link", access(via, F_OK) == 0);
+        CHECK("stat: follows the final link", stat(sym, &st) == 0 && S_ISDIR(st.st_mode));
+        CHECK("fstatat: AT_SYMLINK_NOFOLLOW sees the link",
+              fstatat(AT_FDCWD, sym, &st, AT_SYMLINK_NOFOLLOW) == 0 && S_ISLNK(st.st_mode));
+        errno = 0;
+        CHECK("open: O_NOFOLLOW on a link fails with ELOOP",
+              open(sym, O_RDONLY | O_NOFOLLOW) == -1 && errno == ELOOP);
+
+        // `..` after a symlinked directory leaves the link's target, not the
+        // directory holding the link.
+        snprintf(target, sizeof(target), "%s/%s", base, inner);
+        CHECK("symlink: link to nested directory", symlink(target, up) == 0);
+        snprintf(via, sizeof(via), "%s/../marker", up);
+        CHECK("access: .. through a symlinked directory", access(via, F_OK) == 0);
+
+        CHECK("chdir: through a link", chdir(up) == 0);
+        CHECK("getcwd: reports the resolved directory",
+              getcwd(via, sizeof(via)) != NULL && strcmp(via, target) == 0);
+        CHECK("chdir: back to base", chdir(base) == 0);
+
+        CHECK("symlink: first half of a loop", symlink(loop_b, loop_a) == 0);
+        CHECK("symlink: second half of a loop", symlink(loop_a, loop_b) == 0);
+        errno = 0;
+        CHECK("open: symlink loop fails with ELOOP", open(loop_a, O_RDONLY) == -1 && errno == ELOOP);
+
+        CHECK("unlink: removes the link, not its target", unlink(sym) == 0 && access(marker, F_OK) == 0);
+        unlink(up);
+        unlink(loop_a);
+        unlink(loop_b);
+        unlink(marker);
+        rmdir(inner);
+        rmdir(real);
+    }
+
     // ---- unlink ----
     CHECK("unlink: remove original file", unlink(a) == 0);
     errno = 0;
@@ -233,6 +299,56 @@ int main(int argc, char *argv[]) {
         close(dfd);
     }
 
+    // ---- *at relative to a subdirectory fd ----
+    {
+        char at_dir[64];
+        char at_back[80];
+        char target[16];
+        snprintf(at_dir, sizeof(at_dir), "at-dir-%s", seed);
+        snprintf(at_back, sizeof(at_back), "../%s/file", at_dir);
+
+        CHECK("mkdir: create *at directory", mkdir(at_dir, 0755) == 0);
+        int sfd = open(at_dir, O_RDONLY | O_DIRECTORY);
+        CHECK("open: *at directory fd", sfd >= 0);
+        if (sfd >= 0) {
+            CHECK("mkdirat: create directory relative to dirfd", mkdirat(sfd, "sub", 0755) == 0);
+            CHECK("fstatat: mkdirat result is a directory",
+                  fstatat(sfd, "sub", &st, 0) == 0 && S_ISDIR(st.st_mode));
+            CHECK("mknodat: create FIFO relative to dirfd", mknodat(sfd, "fifo", S_IFIFO | 0644, 0) == 0);
+            CHECK("fstatat: mknodat result is a FIFO",
+                  fstatat(sfd, "fifo", &st, 0) == 0 && S_ISFIFO(st.st_mode));
+
+            int ffd = openat(sfd, at_back, O_CREAT | O_RDWR, 0644);
+            CHECK("openat: create file through .. relative to dirfd", ffd >= 0);
+            CHECK("linkat: hard link relative to dirfd", linkat(sfd, "file", sfd, "hard", 0) == 0);
+            CHECK("fstatat: linkat raised the link count",
+                  fstatat(sfd, "file", &st, 0) == 0 && st.st_nlink == 2);
+            CHECK("symlinkat: link relative to dirfd", symlinkat("file", sfd, "link") == 0);
+            ssize_t tlen = readlinkat(sfd, "link", target, sizeof(target) - 1);
+            if (tlen >= 0) {
+                target[tlen] = '\0';
+            }
+            CHECK("readlinkat: read link relative to dirfd", tlen == 4 && strcmp(target, "file") == 0);
+
+            if (ffd >= 0) {
+                errno = 0;
+                CHECK("openat: regular file as dirfd fails with ENOTDIR",
+                      openat(ffd, "x", O_RDONLY) == -1 && errno == ENOTDIR);
+                close(ffd);
+            }
+            errno = 0;
+            CHECK("openat: closed dirfd fails with EBADF", openat(9999, "x", O_RDONLY) == -1 && errno == EBADF);
+
+            CHECK("unlinkat: remove link relative to dirfd", unlinkat(sfd, "link", 0) == 0);
+            CHECK("unlinkat: remove hard link relative to dirfd", unlinkat(sfd, "hard", 0) == 0);
+            CHECK("unlinkat: remove file relative to dirfd", unlinkat(sfd, "file", 0) == 0);
+            CHECK("unlinkat: remove FIFO relative to dirfd", unlinkat(sfd, "fifo", 0) == 0);
+            CHECK("unlinkat: remove directory relative to dirfd", unlinkat(sfd, "sub", AT_REMOVEDIR) == 0);
+            close(sfd);
+        }
+        CHECK("rmdir: remove *at directory", rmdir(at_dir) == 0);
+    }
+
     // ---- fchdir / fd inheritance ----
     char fd_dir_name[64];
     snprintf(fd_dir_name, sizeof(fd_dir_name), "fd-dir-%ld", (long)getpid());
@@ -0,0 +1,211 @@
+#define _GNU_SOURCE
+
+/* exec-test.c — Test binary for chroot-grate's execve and #! handling.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ *   --chroot-dir /
+ * The test writes scripts whose #! line names this binary, run with
+ * --as-interpreter. In that mode it checks that its argv matches
+ * EXEC_TEST_EXPECT and exits 0 if it does.
+ */
+
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int failures = 0;
+static int total = 0;
+
+#define PASS(x) \
+    do { \
+        printf("PASS: %s\n", x); \
+    } while (0)
+
+#define FAIL(x) \
+    do { \
+        printf("FAIL: %s (%s)\n", x, strerror(errno)); \
+        failures++; \
+    } while (0)
+
+#define CHECK(x, expr) \
+    do { \
+        total++; \
+        if (expr) PASS(x); else FAIL(x); \
+    } while (0)
+
+#define CHECK_CHILD_EXIT(x, status) \
+    do { \
+        total++; \
+        if (WIFEXITED(status) && WEXITSTATUS(status) == 0) { \
+            PASS(x); \
+        } else { \
+            printf("FAIL: %s (raw_status=%d exit_status=%d signal=%d)\n", \
+                   x, status, WIFEXITED(status) ? WEXITSTATUS(status) : -1, \
+                   WIFSIGNALED(status) ? WTERMSIG(status) : -1); \
+            failures++; \
+        } \
+    } while (0)
+
+// Interpreter mode: compare our argv, joined with spaces, to EXEC_TEST_EXPECT.
+static int run_as_interpreter(int argc, char *argv[]) {
+    const char *expect = getenv("EXEC_TEST_EXPECT");
+    char got[4 * PATH_MAX] = "";
+
+    for (int i = 0; i < argc; i++) {
+        if (i > 0) strncat(got, " ", sizeof(got) - strlen(got) - 1);
+        strncat(got, argv[i], sizeof(got) - strlen(got) - 1);
+    }
+    if (expect == NULL || strcmp(got, expect) != 0) {
+        printf("interpreter argv mismatch:\n  got:    %s\n  expect: %s\n", got, expect ? expect : "(unset)");
+        return 3;
+    }
+    return 0;
+}
+
+static int write_script(const char *path, const char *interpreter, mode_t mode) {
+    int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, mode);
+    if (fd < 0) return -1;
+    dprintf(fd, "#!%s\nthis line is never run\n", interpreter);
+    close(fd);
+    return chmod(path, mode);
+}
+
+// Fork, run exec_fn in the child, and return the child's wait status.
+static int run_child(void (*exec_fn)(void *), void *arg) {
+    pid_t pid = fork();
+    if (pid == 0) {
+        exec_fn(arg);
+        _exit(2);
+    }
+    int wstatus = -1;
+    if (pid > 0) waitpid(pid, &wstatus, 0);
+    return wstatus;
+}
+
+struct exec_case {
+    const char *path;
+    char *const *argv;
+    const char *expect;
+    int expect_errno;
+};
+
+// Expect the exec to succeed and the interpreter to see `expect`.
+static void do_execv(void *arg) {
+    struct exec_case *c = arg;
+    setenv("EXEC_TEST_EXPECT", c->expect, 1);
+    execv(c->path, c->argv);
+    printf("execv %s: %s\n", c->path, strerror(errno));
+}
+
+static void do_execvp(void *arg) {
+    struct exec_case *c = arg;
+    setenv("EXEC_TEST_EXPECT", c->expect, 1);
+    execvp(c->path, c->argv);
+    printf("execvp %s: %s\n", c->path, strerror(errno));
+}
+
+// Expect the exec to fail with `expect_errno`.
+static void do_execv_fails(void *arg) {
+    struct exec_case *c = arg;
+    execv(c->path, c->argv);
+    _exit(errno == c->expect_errno ? 0 : 4);
+}
+
+int main(int argc, char *argv[]) {
+    if (argc > 1 && strcmp(argv[1], "--as-interpreter") == 0) {
+        return run_as_interpreter(argc, argv);
+    }
+
+    char cwd[PATH_MAX];
+    char self[2 * PATH_MAX];
+    char interp[2 * PATH_MAX + 32];
+    char script[64];
+    char script_abs[PATH_MAX + 64];
+    char nested[64];
+    char missing[64];
+    char noexec[64];
+    char expect[4 * PATH_MAX];
+    int wstatus;
+
+    CHECK("getcwd: base directory", getcwd(cwd, sizeof(cwd)) != NULL);
+
+    // Absolute path of this binary, as seen inside the chroot.
+    if (argv[0][0] == '/') {
+        snprintf(self, sizeof(self), "%s", argv[0]);
+    } else {
+        snprintf(self, sizeof(self), "%s/%s", strcmp(cwd, "/") == 0 ? "" : cwd, argv[0]);
+    }
+    CHECK("access: test binary is executable", access(self, X_OK) == 0);
+    snprintf(interp, sizeof(interp), "%s --as-interpreter", self);
+
+    snprintf(script, sizeof(script), "exec-script-%ld", (long)getpid());
+    snprintf(script_abs, sizeof(script_abs), "%s/%s", strcmp(cwd, "/") == 0 ? "" : cwd, script);
+    snprintf(nested, sizeof(nested), "exec-nested-%ld", (long)getpid());
+    snprintf(missing, sizeof(missing), "exec-missing-%ld", (long)getpid());
+    snprintf(noexec, sizeof(noexec), "exec-noexec-%ld", (long)getpid());
+
+    CHECK("write: script run by this binary", write_script(script, interp, 0755) == 0);
+    CHECK("write: script run by the first script", write_script(nested, script_abs, 0755) == 0);
+    CHECK("write: script with a missing interpreter", write_script(missing, "/no-such-interpreter", 0755) == 0);
+    CHECK("write: script without exec permission", write_script(noexec, interp, 0644) == 0);
+
+    // ---- #! interpreter ----
+    {
+        char path[80];
+        snprintf(path, sizeof(path), "./%s", script);
+        char *args[] = {path, "one", "two", NULL};
+        snprintf(expect, sizeof(expect), "%s --as-interpreter %s one two", self, path);
+        struct exec_case c = {path, args, expect, 0};
+        wstatus = run_child(do_execv, &c);
+        CHECK_CHILD_EXIT("execv: script runs its #! interpreter", wstatus);
+    }
+
+    // ---- nested #! interpreter ----
+    {
+        char *args[] = {nested, "one", NULL};
+        snprintf(expect, sizeof(expect), "%s --as-interpreter %s %s one", self, script_abs, nested);
+        struct exec_case c = {nested, args, expect, 0};
+        wstatus = run_child(do_execv, &c);
+        CHECK_CHILD_EXIT("execv: interpreter that is itself a script", wstatus);
+    }
+
+    // ---- PATH lookup ----
+    {
+        char *args[] = {script, "one", NULL};
+        setenv("PATH", "/no-such-dir:.", 1);
+        snprintf(expect, sizeof(expect), "%s --as-interpreter ./%s one", self, script);
+        struct exec_case c = {script, args, expect, 0};
+        wstatus = run_child(do_execvp, &c);
+        CHECK_CHILD_EXIT("execvp: script found through PATH", wstatus);
+    }
+
+    // ---- failures ----
+    {
+        char *args[] = {missing, NULL};
+        struct exec_case c = {missing, args, NULL, ENOENT};
+        wstatus = run_child(do_execv_fails, &c);
+        CHECK_CHILD_EXIT("execv: missing interpreter fails with ENOENT", wstatus);
+    }
+    {
+        char *args[] = {noexec, NULL};
+        struct exec_case c = {noexec, args, NULL, EACCES};
+        wstatus = run_child(do_execv_fails, &c);
+        CHECK_CHILD_EXIT("execv: script without exec permission fails with EACCES", wstatus);
+    }
+
+    unlink(script);
+    unlink(nested);
+    unlink(missing);
+    unlink(noexec);
+
+    printf("Result (%d/%d passed).\n", (total - failures), total);
+
+    return failures ? 1 : 0;
+}
@@ -0,0 +1,142 @@
+#define _GNU_SOURCE
+
+/* per-cage-test.c — Test binary for chroot-grate's per-cage roots.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ *   --chroot-dir /per-cage-jail-{cageid} --chroot-template /per-cage-template
+ * and this binary copied into /per-cage-template. Each cage gets its own
+ * copy of the template as its root, so files the parent creates are not
+ * visible to a forked child, and the other way round.
+ */
+
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int failures = 0;
+static int total = 0;
+
+#define PASS(x) \
+    do { \
+        printf("PASS: %s\n", x); \
+    } while (0)
+
+#define FAIL(x) \
+    do { \
+        printf("FAIL: %s (%s)\n", x, strerror(errno)); \
+        failures++; \
+    } while (0)
+
+#define CHECK(x, expr) \
+    do { \
+        total++; \
+        if (expr) PASS(x); else FAIL(x); \
+    } while (0)
+
+#define CHECK_ERRNO(x, expr, err) \
+    do { \
+        errno = 0; \
+        CHECK(x, (expr) == -1 && errno == (err)); \
+    } while (0)
+
+#define CHECK_CHILD_EXIT(x, status) \
+    do { \
+        total++; \
+        if (WIFEXITED(status) && WEXITSTATUS(status) == 0) { \
+            PASS(x); \
+        } else { \
+            printf("FAIL: %s (raw_status=%d exit_status=%d signal=%d)\n", \
+                   x, status, WIFEXITED(status) ? WEXITSTATUS(status) : -1, \
+                   WIFSIGNALED(status) ? WTERMSIG(status) : -1); \
+            failures++; \
+        } \
+    } while (0)
+
+static int create_file(const char *path) {
+    int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+    if (fd < 0) return -1;
+    close(fd);
+    return 0;
+}
+
+int main(int argc, char *argv[]) {
+    char cwd[PATH_MAX];
+    char self[PATH_MAX];
+    char parent_marker[64];
+    char child_marker[64];
+    char subdir[64];
+    int to_parent[2], to_child[2];
+    char c = 0;
+
+    (void)argc;
+
+    CHECK("getcwd: cage starts at the root of its jail", getcwd(cwd, sizeof(cwd)) != NULL && strcmp(cwd, "/") == 0);
+
+    // This binary comes from the template, so every jail has it.
+    snprintf(self, sizeof(self), "%s%s", argv[0][0] == '/' ? "" : "/", argv[0]);
+    CHECK("access: template copied into the jail", access(self, R_OK) == 0);
+
+    snprintf(parent_marker, sizeof(parent_marker), "/per-cage-parent-%ld", (long)getpid());
+    snprintf(child_marker, sizeof(child_marker), "/per-cage-child-%ld", (long)getpid());
+    snprintf(subdir, sizeof(subdir), "/per-cage-dir-%ld", (long)getpid());
+
+    CHECK("create: marker in the parent's jail", create_file(parent_marker) == 0);
+    CHECK("mkdir: directory in the parent's jail", mkdir(subdir, 0755) == 0);
+
+    int dirfd = open("/", O_RDONLY | O_DIRECTORY);
+    CHECK("open: directory fd on /", dirfd >= 0);
+    CHECK("chdir: into the parent-only directory", chdir(subdir) == 0);
+
+    CHECK("pipe: to parent", pipe(to_parent) == 0);
+    CHECK("pipe: to child", pipe(to_child) == 0);
+
+    fflush(stdout);
+    pid_t pid = fork();
+    if (pid == 0) {
+        close(to_parent[0]);
+        close(to_child[1]);
+
+        CHECK("child getcwd: starts at / when the parent's cwd is not in its jail",
+              getcwd(cwd, sizeof(cwd)) != NULL && strcmp(cwd, "/") == 0);
+        CHECK("child access: template copied into the child's jail", access(self, R_OK) == 0);
+        CHECK_ERRNO("child access: parent's marker is not visible", access(parent_marker, F_OK), ENOENT);
+        CHECK_ERRNO("child openat: inherited directory fd is refused with EACCES",
+                    openat(dirfd, "anything", O_RDONLY), EACCES);
+        CHECK("child create: marker in the child's jail", create_file(child_marker) == 0);
+
+        // Let the parent look for the marker, then clean up.
+        if (write(to_parent[1], "x", 1) != 1 || read(to_child[0], &c, 1) != 1) failures++;
+        CHECK("child unlink: marker in the child's jail", unlink(child_marker) == 0);
+
+        fflush(stdout);
+        _exit(failures ? 1 : 0);
+    }
+    CHECK("fork: child created", pid > 0);
+    close(to_parent[1]);
+    close(to_child[0]);
+
+    CHECK("read: child created its marker", read(to_parent[0], &c, 1) == 1);
+    CHECK_ERRNO("access: child's marker is not visible", access(child_marker, F_OK), ENOENT);
+    CHECK("access: parent's marker is still visible", access(parent_marker, F_OK) == 0);
+    CHECK("write: let the child finish", write(to_child[1], "x", 1) == 1);
+
+    int wstatus = -1;
+    if (pid > 0) waitpid(pid, &wstatus, 0);
+    CHECK_CHILD_EXIT("child: checks in the child's jail", wstatus);
+
+    close(dirfd);
+    CHECK("chdir: back to /", chdir("/") == 0);
+    CHECK("rmdir: directory in the parent's jail", rmdir(subdir) == 0);
+    CHECK("unlink: marker in the parent's jail", unlink(parent_marker) == 0);
+
+    printf("Result (%d/%d passed).\n", (total - failures), total);
+
+    return failures ? 1 : 0;
+}
@@ -0,0 +1,124 @@
+#define _GNU_SOURCE
+
+/* read-only-test.c — Test binary for chroot-grate's --read-only mode.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ *   --chroot-dir / --read-only
+ * so reads work as usual and every write fails with EROFS. The suite copies
+ * read-only-fixture.txt next to the test binary.
+ */
+
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+#include <sys/un.h>
+#include <unistd.h>
+
+static int failures = 0;
+static int total = 0;
+
+#define PASS(x) \
+    do { \
+        printf("PASS: %s\n", x); \
+    } while (0)
+
+#define FAIL(x) \
+    do { \
+        printf("FAIL: %s (%s)\n", x, strerror(errno)); \
+        failures++; \
+    } while (0)
+
+#define CHECK(x, expr) \
+    do { \
+        total++; \
+        if (expr) PASS(x); else FAIL(x); \
+    } while (0)
+
+#define CHECK_EROFS(x, expr) \
+    do { \
+        errno = 0; \
+        CHECK(x, (expr) == -1 && errno == EROFS); \
+    } while (0)
+
+int main(void) {
+    const char *self = "read-only-fixture.txt";
+    char cwd[PATH_MAX];
+    char missing[64];
+    char other[64];
+    struct stat st;
+
+    snprintf(missing, sizeof(missing), "ro-missing-%ld", (long)getpid());
+    snprintf(other, sizeof(other), "ro-other-%ld", (long)getpid());
+
+    // ---- reads still work ----
+    CHECK("stat: fixture", stat(self, &st) == 0);
+    CHECK("access: fixture", access(self, R_OK) == 0);
+    int fd = open(self, O_RDONLY);
+    CHECK("open: read-only open", fd >= 0);
+    if (fd >= 0) {
+        char c;
+        CHECK("read: from read-only open", read(fd, &c, 1) == 1);
+        close(fd);
+    }
+    fd = openat(AT_FDCWD, self, O_RDONLY);
+    CHECK("openat: read-only open", fd >= 0);
+    if (fd >= 0) close(fd);
+    CHECK("getcwd: current directory", getcwd(cwd, sizeof(cwd)) != NULL);
+    CHECK("chdir: into /", chdir("/") == 0);
+    CHECK("chdir: back", chdir(cwd) == 0);
+
+    // ---- every write fails ----
+    CHECK_EROFS("open: O_WRONLY", open(self, O_WRONLY));
+    CHECK_EROFS("open: O_RDWR", open(self, O_RDWR));
+    CHECK_EROFS("open: O_RDONLY | O_TRUNC", open(self, O_RDONLY | O_TRUNC));
+    CHECK_EROFS("open: O_CREAT", open(missing, O_CREAT | O_WRONLY, 0644));
+    CHECK_EROFS("openat: O_CREAT", openat(AT_FDCWD, missing, O_CREAT | O_RDWR, 0644));
+    CHECK_EROFS("mkdir", mkdir(missing, 0755));
+    CHECK_EROFS("rmdir", rmdir(missing));
+    CHECK_EROFS("unlink", unlink(missing));
+    CHECK_EROFS("unlinkat", unlinkat(AT_FDCWD, missing, 0));
+    CHECK_EROFS("rename", rename(self, other));
+    CHECK_EROFS("renameat", renameat(AT_FDCWD, self, AT_FDCWD, other));
+    CHECK_EROFS("link", link(self, other));
+    CHECK_EROFS("symlink", symlink(self, other));
+    CHECK_EROFS("symlinkat", symlinkat(self, AT_FDCWD, other));
+    CHECK_EROFS("chmod", chmod(self, 0700));
+    CHECK_EROFS("fchmodat", fchmodat(AT_FDCWD, self, 0700, 0));
+    CHECK_EROFS("chown", chown(self, getuid(), getgid()));
+    CHECK_EROFS("lchown", lchown(self, getuid(), getgid()));
+    CHECK_EROFS("fchownat", fchownat(AT_FDCWD, self, getuid(), getgid(), 0));
+    CHECK_EROFS("truncate", truncate(self, 0));
+    CHECK_EROFS("utimensat", utimensat(AT_FDCWD, self, NULL, 0));
+    CHECK("stat: fixture is unchanged", stat(self, &st) == 0 && st.st_size > 0);
+
+    // Paths relative to a directory fd are refused as well.
+    int dfd = open(".", O_RDONLY | O_DIRECTORY);
+    CHECK("open: current directory fd", dfd >= 0);
+    if (dfd >= 0) {
+        CHECK_EROFS("openat: O_CREAT relative to dirfd", openat(dfd, missing, O_CREAT | O_WRONLY, 0644));
+        CHECK_EROFS("unlinkat: relative to dirfd", unlinkat(dfd, missing, 0));
+        CHECK_EROFS("fchmodat: relative to dirfd", fchmodat(dfd, missing, 0600, 0));
+        close(dfd);
+    }
+
+    int s = socket(AF_UNIX, SOCK_STREAM, 0);
+    CHECK("socket: create AF_UNIX socket", s >= 0);
+    if (s >= 0) {
+        struct sockaddr_un un;
+        memset(&un, 0, sizeof(un));
+        un.sun_family = AF_UNIX;
+        snprintf(un.sun_path, sizeof(un.sun_path), "%s", missing);
+        CHECK_EROFS("bind: AF_UNIX socket file", bind(s, (struct sockaddr *)&un, sizeof(un)));
+        close(s);
+    }
+
+    printf("Result (%d/%d passed).\n", (total - failures), total);
+
+    return failures ? 1 : 0;
+}
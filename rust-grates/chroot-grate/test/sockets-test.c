@@ -0,0 +1,135 @@
+#define _GNU_SOURCE
+
+/* sockets-test.c — Test binary for chroot-grate's AF_UNIX address handling.
+ *
+ * The test suite (see test/grates_test.toml) runs this test twice:
+ *   --chroot-dir /<long directory> --socket-alias-dir /chroot-sockets-alias
+ * where every socket path overflows sun_path on the host and goes through an
+ * alias, with abstract sockets isolated (the default); and
+ *   --chroot-dir / --abstract-sockets deny
+ * with ABSTRACT_SOCKETS=deny in the environment.
+ */
+
+#include <errno.h>
+#include <limits.h>
+#include <stddef.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+#include <sys/un.h>
+#include <unistd.h>
+
+static int failures = 0;
+static int total = 0;
+
+#define PASS(x) \
+    do { \
+        printf("PASS: %s\n", x); \
+    } while (0)
+
+#define FAIL(x) \
+    do { \
+        printf("FAIL: %s (%s)\n", x, strerror(errno)); \
+        failures++; \
+    } while (0)
+
+#define CHECK(x, expr) \
+    do { \
+        total++; \
+        if (expr) PASS(x); else FAIL(x); \
+    } while (0)
+
+#define CHECK_ERRNO(x, expr, err) \
+    do { \
+        errno = 0; \
+        CHECK(x, (expr) == -1 && errno == (err)); \
+    } while (0)
+
+// Build an abstract address; returns its length.
+static socklen_t mkabstract(struct sockaddr_un *un, const char *name) {
+    memset(un, 0, sizeof(*un));
+    un->sun_family = AF_UNIX;
+    memcpy(un->sun_path + 1, name, strlen(name));
+    return offsetof(struct sockaddr_un, sun_path) + 1 + strlen(name);
+}
+
+// Connect a new client to `addr`, accept it on `listener`, and pass a byte.
+static int round_trip(int listener, const struct sockaddr_un *addr, socklen_t len) {
+    int ok = 0;
+    int client = socket(AF_UNIX, SOCK_STREAM, 0);
+    if (client < 0) return 0;
+    if (connect(client, (const struct sockaddr *)addr, len) == 0) {
+        int conn = accept(listener, NULL, NULL);
+        if (conn >= 0) {
+            char c = 0;
+            ok = write(client, "x", 1) == 1 && read(conn, &c, 1) == 1 && c == 'x';
+            close(conn);
+        }
+    }
+    close(client);
+    return ok;
+}
+
+int main(void) {
+    const char *policy = getenv("ABSTRACT_SOCKETS");
+    int deny = policy != NULL && strcmp(policy, "deny") == 0;
+    char cwd[PATH_MAX];
+    char name[64];
+    char expect[PATH_MAX + 64];
+    struct sockaddr_un un, got;
+    socklen_t got_len;
+    struct stat st;
+
+    CHECK("getcwd: base directory", getcwd(cwd, sizeof(cwd)) != NULL);
+
+    // ---- pathname socket ----
+    snprintf(name, sizeof(name), "sock-%ld", (long)getpid());
+    snprintf(expect, sizeof(expect), "%s/%s", strcmp(cwd, "/") == 0 ? "" : cwd, name);
+    memset(&un, 0, sizeof(un));
+    un.sun_family = AF_UNIX;
+    snprintf(un.sun_path, sizeof(un.sun_path), "%s", name);
+
+    int s = socket(AF_UNIX, SOCK_STREAM, 0);
+    CHECK("socket: create listener", s >= 0);
+    CHECK("bind: pathname socket", bind(s, (struct sockaddr *)&un, sizeof(un)) == 0);
+    CHECK("stat: socket file is in the cage's directory", stat(name, &st) == 0 && S_ISSOCK(st.st_mode));
+    got_len = sizeof(got);
+    memset(&got, 0, sizeof(got));
+    CHECK("getsockname: reports the virtual path",
+          getsockname(s, (struct sockaddr *)&got, &got_len) == 0 && strcmp(got.sun_path, expect) == 0);
+    CHECK("listen: pathname socket", listen(s, 1) == 0);
+    CHECK("connect: round trip through pathname socket", round_trip(s, &un, sizeof(un)));
+    close(s);
+    CHECK("unlink: pathname socket", unlink(name) == 0);
+
+    // ---- abstract socket ----
+    snprintf(name, sizeof(name), "chroot-sockets-test-%ld", (long)getpid());
+    socklen_t len = mkabstract(&un, name);
+
+    s = socket(AF_UNIX, SOCK_STREAM, 0);
+    CHECK("socket: create abstract listener", s >= 0);
+    if (deny) {
+        CHECK_ERRNO("bind: abstract socket fails with EPERM", bind(s, (struct sockaddr *)&un, len), EPERM);
+        CHECK_ERRNO("connect: abstract socket fails with EPERM", connect(s, (struct sockaddr *)&un, len), EPERM);
+        struct sockaddr_un autobind = {.sun_family = AF_UNIX};
+        CHECK_ERRNO("bind: autobind fails with EPERM",
+                    bind(s, (struct sockaddr *)&autobind, sizeof(sa_family_t)), EPERM);
+    } else {
+        CHECK("bind: abstract socket", bind(s, (struct sockaddr *)&un, len) == 0);
+        got_len = sizeof(got);
+        memset(&got, 0, sizeof(got));
+        CHECK("getsockname: reports the abstract name as bound",
+              getsockname(s, (struct sockaddr *)&got, &got_len) == 0 && got_len == len &&
+                  memcmp(&got, &un, len) == 0);
+        CHECK("listen: abstract socket", listen(s, 1) == 0);
+        CHECK("connect: round trip through abstract socket", round_trip(s, &un, len));
+    }
+    close(s);
+
+    printf("Result (%d/%d passed).\n", (total - failures), total);
+
+    return failures ? 1 : 0;
+}
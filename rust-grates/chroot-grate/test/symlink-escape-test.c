@@ -0,0 +1,134 @@
+/* symlink-escape-test.c — Test binary for relative symlink targets in
+ * chroot-grate.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ *   --chroot-dir /symlink-escape-jail
+ * with this binary copied into /symlink-escape-jail and
+ * symlink-escape-fixture.txt placed next to the jail, outside it. A relative
+ * link whose `..` climbs above the jail root must lead to the path inside the
+ * jail, as it would under chroot(2), and never to the fixture.
+ */
+
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+static int failures = 0;
+static int total = 0;
+
+#define PASS(x) \
+    do { \
+        printf("PASS: %s\n", x); \
+    } while (0)
+
+#define FAIL(x) \
+    do { \
+        printf("FAIL: %s (%s)\n", x, strerror(errno)); \
+        failures++; \
+    } while (0)
+
+#define CHECK(x, expr) \
+    do { \
+        total++; \
+        if (expr) PASS(x); else FAIL(x); \
+    } while (0)
+
+#define CHECK_ERRNO(x, expr, err) \
+    do { \
+        errno = 0; \
+        CHECK(x, (expr) == -1 && errno == (err)); \
+    } while (0)
+
+#define FIXTURE "/symlink-escape-fixture.txt"
+
+static int write_file(const char *path, const char *data) {
+    int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+    if (fd < 0) return -1;
+    ssize_t n = write(fd, data, strlen(data));
+    close(fd);
+    return n == (ssize_t)strlen(data) ? 0 : -1;
+}
+
+static int file_has(const char *path, const char *data) {
+    char buf[64] = {0};
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) return 0;
+    ssize_t n = read(fd, buf, sizeof(buf) - 1);
+    close(fd);
+    return n == (ssize_t)strlen(data) && strcmp(buf, data) == 0;
+}
+
+static int link_is(const char *path, const char *target) {
+    char buf[PATH_MAX] = {0};
+    ssize_t n = readlink(path, buf, sizeof(buf) - 1);
+    return n == (ssize_t)strlen(target) && strcmp(buf, target) == 0;
+}
+
+int main(void) {
+    char dir[64];
+    char nested[96];
+    char up[128];
+    char up_at[128];
+    char beside[128];
+    char inner[128];
+
+    snprintf(dir, sizeof(dir), "/symlink-escape-%ld", (long)getpid());
+    snprintf(nested, sizeof(nested), "%s/nested", dir);
+    snprintf(up, sizeof(up), "%s/up", nested);
+    snprintf(up_at, sizeof(up_at), "%s/up-at", nested);
+    snprintf(beside, sizeof(beside), "%s/beside", nested);
+    snprintf(inner, sizeof(inner), "%s/inner", dir);
+
+    CHECK_ERRNO("access: fixture outside the jail is not visible", access(FIXTURE, F_OK), ENOENT);
+    CHECK("mkdir: link directory", mkdir(dir, 0755) == 0);
+    CHECK("mkdir: nested link directory", mkdir(nested, 0755) == 0);
+
+    // From <jail>/dir/nested, three `..` reach the directory holding the
+    // jail, where the fixture is.
+    CHECK("symlink: relative target climbing above the root",
+          symlink("../../../symlink-escape-fixture.txt", up) == 0);
+    CHECK("readlink: target is clamped to the root", link_is(up, FIXTURE));
+    CHECK_ERRNO("open: link does not reach the fixture", open(up, O_RDONLY), ENOENT);
+
+    int dirfd = open(nested, O_RDONLY | O_DIRECTORY);
+    CHECK("open: directory fd on the link directory", dirfd >= 0);
+    CHECK("symlinkat: relative target climbing above the root",
+          symlinkat("../../../symlink-escape-fixture.txt", dirfd, "up-at") == 0);
+    CHECK_ERRNO("open: symlinkat link does not reach the fixture", open(up_at, O_RDONLY), ENOENT);
+    close(dirfd);
+
+    // `..` after another component may follow a link first, so it is
+    // clamped too.
+    CHECK("symlink: `..` after a directory component",
+          symlink("../../x/../../../symlink-escape-fixture.txt", beside) == 0);
+    CHECK("readlink: target is clamped to the root", link_is(beside, FIXTURE));
+
+    CHECK("create: file at the clamped target inside the jail", write_file(FIXTURE, "inside") == 0);
+    CHECK("open: link reads the file inside the jail", file_has(up, "inside"));
+    CHECK("open: symlinkat link reads the file inside the jail", file_has(up_at, "inside"));
+    CHECK("open: link with a later `..` reads the file inside the jail", file_has(beside, "inside"));
+
+    // A target that stays below the root is stored as written.
+    CHECK("create: file next to the nested directory", write_file(inner, "inner") == 0);
+    CHECK("unlink: clamped link", unlink(up) == 0);
+    CHECK("symlink: relative target inside the root", symlink("../inner", up) == 0);
+    CHECK("readlink: target inside the root is preserved", link_is(up, "../inner"));
+    CHECK("open: link inside the root", file_has(up, "inner"));
+
+    CHECK("unlink: contained link", unlink(up) == 0);
+    CHECK("unlink: symlinkat link", unlink(up_at) == 0);
+    CHECK("unlink: link with a later `..`", unlink(beside) == 0);
+    CHECK("unlink: file inside the jail", unlink(FIXTURE) == 0);
+    CHECK("unlink: file next to the nested directory", unlink(inner) == 0);
+    CHECK("rmdir: nested link directory", rmdir(nested) == 0);
+    CHECK("rmdir: link directory", rmdir(dir) == 0);
+
+    printf("Result (%d/%d passed).\n", (total - failures), total);
+
+    return failures ? 1 : 0;
+}
@@ -48,7 +48,10 @@ pub extern "C" fn register_handler_handler(
     )
 }
 
-pub fn register_target_handlers(target_cage: u64) -> i32 {
+/// Wire `target_cage` up for routing: the handlers `group`'s stack registered
+/// on it become that group's routes, and its visible FS handlers point back at
+/// ns-grate.
+pub fn register_target_handlers(target_cage: u64, group: usize) -> i32 {
     let ns_cage = getcageid();
 
     // Reinstall namespace-grate handlers for the syscall set we clamp.
@@ -97,7 +100,7 @@ pub fn register_target_handlers(target_cage: u64) -> i32 {
             }
 
             // ... Add to routing table.
-            let _ = helpers::set_route(target_cage, fs_syscall, alt_nr);
+            let _ = helpers::set_route(group, target_cage, fs_syscall, alt_nr);
         }
         
         // The visible handler on the target cage always points at ns-grate.
@@ -135,9 +138,12 @@ pub fn register_target_handlers(target_cage: u64) -> i32 {
 /// When `%}` belongs to a nested inner grate, fs-routing-clamp decrements its nesting depth and
 /// forwards the exec unchanged.
 ///
-/// When `%}` closes fs-routing-clamp's own outer block, it:
-///   1. Ends the clamp phase
-///   2. Rewrites the exec to skip past `%}` and run the real program
+/// When `%}` closes one of fs-routing-clamp's own outer blocks, it:
+///   1. Records the routes of the group whose stack just finished
+///   2. Ends the clamp phase if that was the last group; otherwise the cage
+///      stays clamped and the next group's stack is set up on it
+///   3. Rewrites the exec to skip past `%}` and run the next group's first
+///      grate, or the real program
 ///
 /// For all other exec calls, passes through unchanged.
 ///
@@ -185,14 +191,21 @@ pub extern "C" fn exec_handler(
             }
 
             // This cage is going to be the target cage, register the fs-clamped routing syscalls
-            // to this cage_id.
-            register_target_handlers(arg1cage);
+            // of the current group to this cage_id. Later groups' stacks run on top of these
+            // routes, so their own file accesses are already dispatched by the earlier groups.
+            register_target_handlers(arg1cage, helpers::get_current_group());
 
-            helpers::deregister_clamped_cage(arg1cage);
-            helpers::exit_nested_clamp();
+            if helpers::advance_group() {
+                // The rewritten exec below does not pass through this handler, so record the
+                // cage as the next group's entry grate here.
+                helpers::set_clamp_entry(arg1cage);
+            } else {
+                helpers::deregister_clamped_cage(arg1cage);
+                helpers::exit_nested_clamp();
+            }
 
             // We've detected the clamp boundary, we need to left shift all argv[] and update the
-            // path to the binary to be argv[1].
+            // path to the binary to be argv[1]: the next group's first grate, or the program.
             //
             // Current state: { "%}", {...argv[]...} }
             // Desired state: { argv[1], {...argv[1]...} }
@@ -245,7 +258,7 @@ pub extern "C" fn exec_handler(
             // println!("[ns-grate] execing real binary: path={}, ret={}", path, ret);
             return ret;
         } else {
-            // Track the outer clamp's entry grate as the last non-marker exec seen at depth 1.
+            // Track the current group's entry grate as the last non-marker exec seen at depth 1.
             // Nested clamp bodies must not overwrite this, or routed calls would bypass the outer
             // grate and jump straight into an inner one.
             if helpers::get_clamp_depth() == 1 {
@@ -259,7 +272,7 @@ pub extern "C" fn exec_handler(
                 &[arg1, arg2, arg3, arg4, arg5, arg6],
                 &[arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage],
             );
-            // let path_print = helpers::resolve_path_from_cage(arg2cage, arg1, arg1cage).unwrap_or_default();
+            // let path_print = helpers::route_path_from_cage(arg2cage, arg1, arg1cage, true).ok().and_then(|p| p.path).unwrap_or_default();
             // println!("[ns-grate] execing binary: path={}, ret={}", path, ret);
             return ret;
         }
@@ -1,22 +1,32 @@
 use crate::helpers;
-use grate_rs::constants::error::{EBADF, EINVAL, EMFILE};
-use grate_rs::constants::fs::{F_DUPFD, F_DUPFD_CLOEXEC, F_SETFD, FD_CLOEXEC, O_CLOEXEC};
+use grate_rs::constants::error::{EBADF, EINVAL, EMFILE, ENOENT, EXDEV};
+use grate_rs::constants::fs::{
+    DT_DIR, F_DUPFD, F_DUPFD_CLOEXEC, F_SETFD, FD_CLOEXEC, O_CLOEXEC, O_CREAT, O_EXCL, S_IFDIR,
+};
 use grate_rs::constants::mman::MAP_ANON;
+use grate_rs::ffi::stat;
 use grate_rs::{SyscallHandler, constants::*, copy_data_between_cages, getcageid, is_thread_clone};
 
 const AT_FDCWD_I64: i64 = -100;
+const AT_SYMLINK_NOFOLLOW: u64 = 0x100;
+const AT_SYMLINK_FOLLOW: u64 = 0x400;
+const O_NOFOLLOW: u64 = 0o400000;
+
+// Whether a symlink in the final path component is followed.
+const FOLLOW: bool = true;
+const NOFOLLOW: bool = false;
 
 // =====================================================================
 //  PATH-BASED SYSCALL HANDLERS
 //
 //  These handle syscalls where arg1 is a pointer to a path string in the
-//  calling cage's memory. The handler reads the path, checks if it starts
-//  with the clamped prefix, and either:
-//    - Routes to the alt syscall (prefix matches → clamped grate handles it)
+//  calling cage's memory. The handler reads the path, looks up the group
+//  whose prefixes cover it, and either:
+//    - Routes to that group's alt syscall (its clamped grates handle it)
 //    - Passes through to kernel (no match → kernel handles it)
 // =====================================================================
 macro_rules! define_path_handler {
-    ($name:ident, $sysno:expr) => {
+    ($name:ident, $sysno:expr, $follow_final:expr) => {
         pub extern "C" fn $name(
             _cageid: u64,
             arg1: u64,
@@ -32,25 +42,31 @@ macro_rules! define_path_handler {
             arg6: u64,
             arg6cage: u64,
         ) -> i32 {
-            let args = [arg1, arg2, arg3, arg4, arg5, arg6];
-            let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+            let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
+            let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-            let nr = match helpers::get_route(arg1cage, $sysno) {
-                Some(alt) => match helpers::resolve_path_from_cage(arg1cage, arg1, arg1cage) {
-                    Some(path) if helpers::path_matches_prefix(&path) => alt,
-                    _ => $sysno,
-                },
-                None => $sysno,
+            let path = match helpers::route_path_from_cage(arg1cage, arg1, arg1cage, $follow_final)
+            {
+                Ok(path) => path,
+                Err(e) => return e,
             };
+            path.substitute(&mut args, &mut arg_cages, 0);
+
+            let nr = path
+                .group
+                .and_then(|group| helpers::get_route(group, arg1cage, $sysno))
+                .unwrap_or($sysno);
 
             let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
-            let path = helpers::read_path_from_cage(arg1, arg1cage).unwrap_or_default();
+            if $sysno == SYS_UNLINK {
+                helpers::forget_links();
+            }
 
             // println!(
-            //     "[ns_handlers|{}] cageid={} path={} routed_to={} ret={}",
+            //     "[ns_handlers|{}] cageid={} path={:?} routed_to={} ret={}",
             //     stringify!($name),
             //     arg1cage,
-            //     path,
+            //     path.path,
             //     if nr == $sysno { "kernel" } else { "clamped grate" },
             //     ret,
             // );
@@ -59,21 +75,151 @@ macro_rules! define_path_handler {
     };
 }
 
-define_path_handler!(ns_stat_handler, SYS_XSTAT);
-define_path_handler!(ns_lstat_handler, SYS_LSTAT);
-define_path_handler!(ns_access_handler, SYS_ACCESS);
-define_path_handler!(ns_unlink_handler, SYS_UNLINK);
-define_path_handler!(ns_link_handler, SYS_LINK);
-define_path_handler!(ns_mkdir_handler, SYS_MKDIR);
-define_path_handler!(ns_rmdir_handler, SYS_RMDIR);
-define_path_handler!(ns_rename_handler, SYS_RENAME);
-define_path_handler!(ns_truncate_handler, SYS_TRUNCATE);
-define_path_handler!(ns_chmod_handler, SYS_CHMOD);
-define_path_handler!(ns_chown_handler, SYS_CHOWN);
-define_path_handler!(ns_lchown_handler, SYS_LCHOWN);
-define_path_handler!(ns_mknod_handler, SYS_MKNOD);
-define_path_handler!(ns_readlink_handler, SYS_READLINK);
-define_path_handler!(ns_statfs_handler, SYS_STATFS);
+define_path_handler!(ns_unlink_handler, SYS_UNLINK, NOFOLLOW);
+define_path_handler!(ns_mkdir_handler, SYS_MKDIR, NOFOLLOW);
+define_path_handler!(ns_rmdir_handler, SYS_RMDIR, NOFOLLOW);
+define_path_handler!(ns_truncate_handler, SYS_TRUNCATE, FOLLOW);
+define_path_handler!(ns_chmod_handler, SYS_CHMOD, FOLLOW);
+define_path_handler!(ns_chown_handler, SYS_CHOWN, FOLLOW);
+define_path_handler!(ns_lchown_handler, SYS_LCHOWN, NOFOLLOW);
+define_path_handler!(ns_mknod_handler, SYS_MKNOD, NOFOLLOW);
+define_path_handler!(ns_readlink_handler, SYS_READLINK, NOFOLLOW);
+define_path_handler!(ns_statfs_handler, SYS_STATFS, FOLLOW);
+
+// Path handlers that must find routed prefixes and their ancestors even where
+// nothing exists there, as a mount point and the directories leading to it
+// always do. When the call fails with ENOENT on such a path,
+// `$fallback(arg2, arg2cage)` answers it instead.
+macro_rules! define_mount_point_handler {
+    ($name:ident, $sysno:expr, $follow_final:expr, $fallback:expr) => {
+        pub extern "C" fn $name(
+            _cageid: u64,
+            arg1: u64,
+            arg1cage: u64,
+            arg2: u64,
+            arg2cage: u64,
+            arg3: u64,
+            arg3cage: u64,
+            arg4: u64,
+            arg4cage: u64,
+            arg5: u64,
+            arg5cage: u64,
+            arg6: u64,
+            arg6cage: u64,
+        ) -> i32 {
+            let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
+            let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+
+            let path = match helpers::route_path_from_cage(arg1cage, arg1, arg1cage, $follow_final)
+            {
+                Ok(path) => path,
+                Err(e) => return e,
+            };
+            path.substitute(&mut args, &mut arg_cages, 0);
+
+            let nr = path
+                .group
+                .and_then(|group| helpers::get_route(group, arg1cage, $sysno))
+                .unwrap_or($sysno);
+
+            let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
+
+            if ret == -(ENOENT as i32) && path.path.as_deref().is_some_and(helpers::is_mount_path) {
+                return $fallback(arg2, arg2cage);
+            }
+            ret
+        }
+    };
+}
+
+define_mount_point_handler!(ns_stat_handler, SYS_XSTAT, FOLLOW, write_mount_point_stat);
+define_mount_point_handler!(
+    ns_lstat_handler,
+    SYS_LSTAT,
+    NOFOLLOW,
+    write_mount_point_stat
+);
+define_mount_point_handler!(ns_access_handler, SYS_ACCESS, FOLLOW, mount_point_access);
+
+/// Report a mount point, or a directory leading to one, as a directory.
+fn write_mount_point_stat(statbuf: u64, statbuf_cage: u64) -> i32 {
+    let st = stat {
+        st_mode: S_IFDIR | 0o755,
+        st_nlink: 2,
+        st_blksize: 4096,
+        ..Default::default()
+    };
+
+    let ns_cage = getcageid();
+    match copy_data_between_cages(
+        ns_cage,
+        statbuf_cage,
+        &st as *const stat as u64,
+        ns_cage,
+        statbuf,
+        statbuf_cage,
+        std::mem::size_of::<stat>() as u64,
+        0,
+    ) {
+        Ok(_) => 0,
+        Err(_) => -14,
+    }
+}
+
+/// A mount point, or a directory leading to one, is accessible in every mode.
+fn mount_point_access(_mode: u64, _mode_cage: u64) -> i32 {
+    0
+}
+
+// Two-path calls (rename, link): both paths must be routed to the same group.
+macro_rules! define_two_path_handler {
+    ($name:ident, $sysno:expr) => {
+        pub extern "C" fn $name(
+            _cageid: u64,
+            arg1: u64,
+            arg1cage: u64,
+            arg2: u64,
+            arg2cage: u64,
+            arg3: u64,
+            arg3cage: u64,
+            arg4: u64,
+            arg4cage: u64,
+            arg5: u64,
+            arg5cage: u64,
+            arg6: u64,
+            arg6cage: u64,
+        ) -> i32 {
+            let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
+            let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+
+            let old_path = match helpers::route_path_from_cage(arg1cage, arg1, arg1cage, NOFOLLOW) {
+                Ok(path) => path,
+                Err(e) => return e,
+            };
+            let new_path = match helpers::route_path_from_cage(arg1cage, arg2, arg2cage, NOFOLLOW) {
+                Ok(path) => path,
+                Err(e) => return e,
+            };
+            old_path.substitute(&mut args, &mut arg_cages, 0);
+            new_path.substitute(&mut args, &mut arg_cages, 1);
+
+            let group = match two_path_group(old_path.group, new_path.group) {
+                Ok(v) => v,
+                Err(e) => return e,
+            };
+            let nr = group
+                .and_then(|g| helpers::get_route(g, arg1cage, $sysno))
+                .unwrap_or($sysno);
+
+            let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
+            helpers::forget_links();
+            ret
+        }
+    };
+}
+
+define_two_path_handler!(ns_link_handler, SYS_LINK);
+define_two_path_handler!(ns_rename_handler, SYS_RENAME);
 
 // =====================================================================
 //  SPECIAL PATH-BASED HANDLERS
@@ -82,42 +228,55 @@ define_path_handler!(ns_statfs_handler, SYS_STATFS);
 //  management beyond the simple define_path_handler! passthrough.
 // =====================================================================
 
+/// Route the `(dirfd, path)` pair at `args[dirfd_arg_index]` and the argument
+/// after it.
+///
+/// An absolute path, or one relative to AT_FDCWD, is resolved with
+/// `helpers::route_path`, and replaced by the resolved path if a symlink was
+/// followed. A path relative to a real dirfd goes wherever the dirfd was
+/// opened; for an unrouted dirfd, the dirfd is replaced by the underlying fd.
 fn prepare_at_path_route(
     cage_id: u64,
-    dirfd: u64,
-    path_ptr: u64,
-    path_cage: u64,
     args: &mut [u64; 6],
+    arg_cages: &mut [u64; 6],
     dirfd_arg_index: usize,
-) -> Result<bool, i32> {
-    let is_at_fdcwd = (dirfd as i64) == AT_FDCWD_I64;
-    let path = helpers::read_path_from_cage(path_ptr, path_cage).unwrap_or_default();
-
-    if path.starts_with('/') {
-        return Ok(helpers::path_matches_prefix(&helpers::normalize_path(
-            &path,
-        )));
+    follow_final: bool,
+) -> Result<helpers::RoutedPath, i32> {
+    let path_arg_index = dirfd_arg_index + 1;
+    let is_at_fdcwd = (args[dirfd_arg_index] as i64) == AT_FDCWD_I64;
+    let path = helpers::read_path_from_cage(args[path_arg_index], arg_cages[path_arg_index])
+        .unwrap_or_default();
+
+    if path.starts_with('/') || (is_at_fdcwd && !path.is_empty()) {
+        let routed = helpers::route_path(cage_id, &path, follow_final)?;
+        routed.substitute(args, arg_cages, path_arg_index);
+        return Ok(routed);
     }
 
-    let mut dirfd_clamped = false;
+    let mut dirfd_group = None;
     if !is_at_fdcwd {
-        let fd_entry =
-            fdtables::translate_virtual_fd(cage_id, dirfd).map_err(|_| -(EBADF as i32))?;
-        dirfd_clamped = fd_entry.perfdinfo != 0;
-        if !dirfd_clamped {
+        let fd_entry = fdtables::translate_virtual_fd(cage_id, args[dirfd_arg_index])
+            .map_err(|_| -(EBADF as i32))?;
+        dirfd_group = helpers::perfdinfo_group(fd_entry.perfdinfo);
+        if dirfd_group.is_none() {
             args[dirfd_arg_index] = fd_entry.underfd;
         }
     }
 
-    let should_clamp = if path.is_empty() {
-        dirfd_clamped
-    } else if is_at_fdcwd {
-        helpers::path_matches_prefix(&helpers::resolve_path_for_cage(cage_id, &path))
-    } else {
-        dirfd_clamped
-    };
+    Ok(helpers::RoutedPath::unresolved(dirfd_group))
+}
 
-    Ok(should_clamp)
+/// Return the group both paths of a rename or link are routed to. Paths routed
+/// to different groups, or only one of them routed, fail with EXDEV, as a
+/// rename or link across mount points does on Linux.
+fn two_path_group(
+    old_group: Option<usize>,
+    new_group: Option<usize>,
+) -> Result<Option<usize>, i32> {
+    if old_group != new_group {
+        return Err(-(EXDEV as i32));
+    }
+    Ok(old_group)
 }
 
 pub extern "C" fn ns_symlink_handler(
@@ -135,19 +294,24 @@ pub extern "C" fn ns_symlink_handler(
     arg6: u64,
     arg6cage: u64,
 ) -> i32 {
-    let args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
-
-    let matches = helpers::read_path_from_cage(arg2, arg2cage)
-        .map(|path| helpers::path_matches_prefix(&helpers::resolve_path_for_cage(arg1cage, &path)))
-        .unwrap_or(false);
+    let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let nr = match helpers::get_route(arg1cage, SYS_SYMLINK) {
-        Some(alt) if matches => alt,
-        _ => SYS_SYMLINK,
+    // Only the link path is resolved; the target is stored as given.
+    let path = match helpers::route_path_from_cage(arg1cage, arg2, arg2cage, NOFOLLOW) {
+        Ok(path) => path,
+        Err(e) => return e,
     };
+    path.substitute(&mut args, &mut arg_cages, 1);
 
-    helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
+    let nr = path
+        .group
+        .and_then(|group| helpers::get_route(group, arg1cage, SYS_SYMLINK))
+        .unwrap_or(SYS_SYMLINK);
+
+    let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
+    helpers::forget_links();
+    ret
 }
 
 pub extern "C" fn ns_symlinkat_handler(
@@ -166,19 +330,21 @@ pub extern "C" fn ns_symlinkat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let should_clamp = match prepare_at_path_route(arg1cage, arg2, arg3, arg3cage, &mut args, 1) {
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 1, NOFOLLOW) {
         Ok(v) => v,
         Err(e) => return e,
     };
 
-    let nr = match helpers::get_route(arg1cage, SYS_SYMLINKAT) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_SYMLINKAT,
-    };
+    let nr = path
+        .group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_SYMLINKAT))
+        .unwrap_or(SYS_SYMLINKAT);
 
-    helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
+    let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
+    helpers::forget_links();
+    ret
 }
 
 pub extern "C" fn ns_readlinkat_handler(
@@ -197,17 +363,17 @@ pub extern "C" fn ns_readlinkat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let should_clamp = match prepare_at_path_route(arg1cage, arg1, arg2, arg2cage, &mut args, 0) {
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, NOFOLLOW) {
         Ok(v) => v,
         Err(e) => return e,
     };
 
-    let nr = match helpers::get_route(arg1cage, SYS_READLINKAT) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_READLINKAT,
-    };
+    let nr = path
+        .group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_READLINKAT))
+        .unwrap_or(SYS_READLINKAT);
 
     helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
 }
@@ -228,19 +394,21 @@ pub extern "C" fn ns_unlinkat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let should_clamp = match prepare_at_path_route(arg1cage, arg1, arg2, arg2cage, &mut args, 0) {
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, NOFOLLOW) {
         Ok(v) => v,
         Err(e) => return e,
     };
 
-    let nr = match helpers::get_route(arg1cage, SYS_UNLINKAT) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_UNLINKAT,
-    };
+    let nr = path
+        .group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_UNLINKAT))
+        .unwrap_or(SYS_UNLINKAT);
 
-    helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
+    let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
+    helpers::forget_links();
+    ret
 }
 
 pub extern "C" fn ns_faccessat_handler(
@@ -259,19 +427,25 @@ pub extern "C" fn ns_faccessat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let should_clamp = match prepare_at_path_route(arg1cage, arg1, arg2, arg2cage, &mut args, 0) {
+    let follow_final = arg4 & AT_SYMLINK_NOFOLLOW == 0;
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, follow_final) {
         Ok(v) => v,
         Err(e) => return e,
     };
 
-    let nr = match helpers::get_route(arg1cage, SYS_FACCESSAT) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_FACCESSAT,
-    };
+    let nr = path
+        .group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_FACCESSAT))
+        .unwrap_or(SYS_FACCESSAT);
 
-    helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
+    let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
+
+    if ret == -(ENOENT as i32) && path.path.as_deref().is_some_and(helpers::is_mount_path) {
+        return mount_point_access(arg3, arg3cage);
+    }
+    ret
 }
 
 pub extern "C" fn ns_statx_handler(
@@ -290,17 +464,18 @@ pub extern "C" fn ns_statx_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let should_clamp = match prepare_at_path_route(arg1cage, arg1, arg2, arg2cage, &mut args, 0) {
+    let follow_final = arg3 & AT_SYMLINK_NOFOLLOW == 0;
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, follow_final) {
         Ok(v) => v,
         Err(e) => return e,
     };
 
-    let nr = match helpers::get_route(arg1cage, SYS_STATX) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_STATX,
-    };
+    let nr = path
+        .group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_STATX))
+        .unwrap_or(SYS_STATX);
 
     helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
 }
@@ -321,17 +496,18 @@ pub extern "C" fn ns_fchmodat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let should_clamp = match prepare_at_path_route(arg1cage, arg1, arg2, arg2cage, &mut args, 0) {
+    let follow_final = arg4 & AT_SYMLINK_NOFOLLOW == 0;
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, follow_final) {
         Ok(v) => v,
         Err(e) => return e,
     };
 
-    let nr = match helpers::get_route(arg1cage, SYS_FCHMODAT) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_FCHMODAT,
-    };
+    let nr = path
+        .group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_FCHMODAT))
+        .unwrap_or(SYS_FCHMODAT);
 
     helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
 }
@@ -352,17 +528,18 @@ pub extern "C" fn ns_fchownat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let should_clamp = match prepare_at_path_route(arg1cage, arg1, arg2, arg2cage, &mut args, 0) {
+    let follow_final = arg5 & AT_SYMLINK_NOFOLLOW == 0;
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, follow_final) {
         Ok(v) => v,
         Err(e) => return e,
     };
 
-    let nr = match helpers::get_route(arg1cage, SYS_FCHOWNAT) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_FCHOWNAT,
-    };
+    let nr = path
+        .group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_FCHOWNAT))
+        .unwrap_or(SYS_FCHOWNAT);
 
     helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
 }
@@ -383,36 +560,94 @@ pub extern "C" fn ns_utimensat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let should_clamp = if arg2 == 0 {
+    let group = if arg2 == 0 {
         if (arg1 as i64) == AT_FDCWD_I64 {
-            false
+            None
         } else {
             match fdtables::translate_virtual_fd(arg1cage, arg1) {
                 Ok(entry) => {
                     args[0] = entry.underfd;
-                    entry.perfdinfo != 0
+                    helpers::perfdinfo_group(entry.perfdinfo)
                 }
                 Err(_) => return -(EBADF as i32),
             }
         }
     } else {
-        match prepare_at_path_route(arg1cage, arg1, arg2, arg2cage, &mut args, 0) {
-            Ok(v) => v,
+        let follow_final = arg4 & AT_SYMLINK_NOFOLLOW == 0;
+        match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, follow_final) {
+            Ok(path) => path.group,
             Err(e) => return e,
         }
     };
 
-    let nr = match helpers::get_route(arg1cage, SYS_UTIMENSAT) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_UTIMENSAT,
-    };
+    let nr = group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_UTIMENSAT))
+        .unwrap_or(SYS_UTIMENSAT);
 
     helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
 }
 
-pub extern "C" fn ns_linkat_handler(
+// Two-path calls whose paths are both `*at` style (dirfd, path) pairs, at
+// arguments 1-2 and 3-4.
+macro_rules! define_two_at_path_handler {
+    ($name:ident, $sysno:expr) => {
+        pub extern "C" fn $name(
+            _cageid: u64,
+            arg1: u64,
+            arg1cage: u64,
+            arg2: u64,
+            arg2cage: u64,
+            arg3: u64,
+            arg3cage: u64,
+            arg4: u64,
+            arg4cage: u64,
+            arg5: u64,
+            arg5cage: u64,
+            arg6: u64,
+            arg6cage: u64,
+        ) -> i32 {
+            let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
+            let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+
+            let old_follow = follows_old_path($sysno, arg5);
+            let old_path =
+                match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, old_follow) {
+                    Ok(v) => v,
+                    Err(e) => return e,
+                };
+            let new_path =
+                match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 2, NOFOLLOW) {
+                    Ok(v) => v,
+                    Err(e) => return e,
+                };
+
+            let group = match two_path_group(old_path.group, new_path.group) {
+                Ok(v) => v,
+                Err(e) => return e,
+            };
+            let nr = group
+                .and_then(|g| helpers::get_route(g, arg1cage, $sysno))
+                .unwrap_or($sysno);
+
+            let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
+            helpers::forget_links();
+            ret
+        }
+    };
+}
+
+/// Only linkat with AT_SYMLINK_FOLLOW follows a symlink in its old path.
+fn follows_old_path(sysno: u64, flags: u64) -> bool {
+    sysno == helpers::SYS_LINKAT_NR && flags & AT_SYMLINK_FOLLOW != 0
+}
+
+define_two_at_path_handler!(ns_linkat_handler, helpers::SYS_LINKAT_NR);
+define_two_at_path_handler!(ns_renameat_handler, SYS_RENAMEAT);
+define_two_at_path_handler!(ns_renameat2_handler, SYS_RENAMEAT2);
+
+pub extern "C" fn ns_mkdirat_handler(
     _cageid: u64,
     arg1: u64,
     arg1cage: u64,
@@ -428,22 +663,17 @@ pub extern "C" fn ns_linkat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let old_clamped = match prepare_at_path_route(arg1cage, arg1, arg2, arg2cage, &mut args, 0) {
-        Ok(v) => v,
-        Err(e) => return e,
-    };
-    let new_clamped = match prepare_at_path_route(arg1cage, arg3, arg4, arg4cage, &mut args, 2) {
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, NOFOLLOW) {
         Ok(v) => v,
         Err(e) => return e,
     };
 
-    let should_clamp = old_clamped || new_clamped;
-    let nr = match helpers::get_route(arg1cage, helpers::SYS_LINKAT_NR) {
-        Some(alt) if should_clamp => alt,
-        _ => helpers::SYS_LINKAT_NR,
-    };
+    let nr = path
+        .group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_MKDIRAT))
+        .unwrap_or(SYS_MKDIRAT);
 
     helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
 }
@@ -463,8 +693,8 @@ pub extern "C" fn ns_chdir_handler(
     arg6: u64,
     arg6cage: u64,
 ) -> i32 {
-    let args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
     let ns_cage = getcageid();
     let mut buf = vec![0u8; 4096];
@@ -493,14 +723,17 @@ pub extern "C" fn ns_chdir_handler(
         }
     };
 
-    let resolved_path: String = helpers::resolve_path_for_cage(arg1cage, &pathstr);
-
-    let matches: bool = helpers::path_matches_prefix(&resolved_path);
-
-    let nr = match helpers::get_route(arg1cage, SYS_CHDIR) {
-        Some(alt) if matches => alt,
-        _ => SYS_CHDIR,
+    let path = match helpers::route_path(arg1cage, &pathstr, FOLLOW) {
+        Ok(path) => path,
+        Err(e) => return e,
     };
+    path.substitute(&mut args, &mut arg_cages, 0);
+    let resolved_path = path.path.unwrap_or_default();
+
+    let nr = path
+        .group
+        .and_then(|group| helpers::get_route(group, arg1cage, SYS_CHDIR))
+        .unwrap_or(SYS_CHDIR);
 
     let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
 
@@ -509,11 +742,10 @@ pub extern "C" fn ns_chdir_handler(
     }
 
     // println!(
-    //     "[ns_handlers|chdir] cageid={} path={} resolved_path={} clamped={} routed_to={} ret={}",
+    //     "[ns_handlers|chdir] cageid={} path={} resolved_path={} routed_to={} ret={}",
     //     arg1cage,
     //     pathstr,
     //     resolved_path,
-    //     if matches { "clamped grate" } else { "kernel" },
     //     if nr == SYS_CHDIR { "kernel" } else { "clamped grate" },
     //     ret,
     // );
@@ -524,9 +756,9 @@ pub extern "C" fn ns_chdir_handler(
 //  FD-BASED SYSCALL HANDLERS
 //
 //  These handle syscalls where arg1 is a file descriptor. The handler
-//  checks fdtables to see if the fd was opened under the clamped prefix
-//  (perfdinfo == 1). If so, it routes to the clamped grate via the alt
-//  syscall. Otherwise it passes through to kernel.
+//  checks fdtables to see which group's prefix the fd was opened under
+//  (perfdinfo == group + 1). If any, it routes to that group's clamped grate
+//  via the alt syscall. Otherwise it passes through to kernel.
 //
 //  Some handlers (open, close, dup) also update fdtables as a side effect.
 // =====================================================================
@@ -564,13 +796,13 @@ macro_rules! fd_route_handler {
                 }
             };
 
-            let perfdinfo = old_fd_entry.perfdinfo != 0;
+            let group = helpers::perfdinfo_group(old_fd_entry.perfdinfo);
 
             args[0] = old_fd_entry.underfd; // replace virtual fd with underfd for the syscall
 
-            if perfdinfo {
+            if let Some(group) = group {
                 // Clamped path.
-                match helpers::get_route(arg1cage, $sysno) {
+                match helpers::get_route(group, arg1cage, $sysno) {
                     // Clamp entry grate has a handler for this call, invoke that.
                     Some(alt) => {
                         let ret = helpers::do_syscall(arg1cage, alt, &args, &arg_cages);
@@ -587,7 +819,8 @@ macro_rules! fd_route_handler {
                     // Clamp entry grate does not have a handler for this syscall, invoke through
                     // selfcage_id=entrycage
                     None => {
-                        let ret = helpers::do_clamp_syscall(arg1cage, $sysno, &args, &arg_cages);
+                        let ret =
+                            helpers::do_clamp_syscall(group, arg1cage, $sysno, &args, &arg_cages);
                         // println!(
                         //     "[ns_handlers|{}] cageid={} fd={} underfd={} clamped=clamped grate routed_to=kernel ret={}",
                         //     stringify!($name),
@@ -609,11 +842,8 @@ macro_rules! fd_route_handler {
             //     arg1cage,
             //     arg1,
             //     old_fd_entry.underfd,
-            //     if perfdinfo { "clamped grate" } else { "kernel" },
-            //     match helpers::get_route(arg1cage, $sysno) {
-            //         Some(alt) if perfdinfo => "clamped grate",
-            //         _ => "kernel",
-            //     },
+            //     if group.is_some() { "clamped grate" } else { "kernel" },
+            //     "kernel",
             //     ret,
             // );
 
@@ -622,7 +852,6 @@ macro_rules! fd_route_handler {
     };
 }
 
-fd_route_handler!(ns_getdents_handler, SYS_GETDENTS);
 fd_route_handler!(ns_read_handler, SYS_READ);
 fd_route_handler!(ns_write_handler, SYS_WRITE);
 fd_route_handler!(ns_pread_handler, SYS_PREAD);
@@ -648,11 +877,155 @@ fd_route_handler!(ns_sync_file_range_handler, SYS_SYNC_FILE_RANGE);
 //  additional namespace-grate state as a side effect.
 // =====================================================================
 
+/// Size of a `linux_dirent64` record before the name.
+const DIRENT64_FIXED_SIZE: usize = 19;
+
+/// getdents (syscall 217): list a directory.
+///
+/// Routes like the other fd-based calls. Once the listing of a directory that
+/// leads to a routed prefix is exhausted, the entries for the prefixes it
+/// lacks are added, so the prefix shows up like a mount point.
+pub extern "C" fn ns_getdents_handler(
+    _cageid: u64,
+    arg1: u64, // fd
+    arg1cage: u64,
+    arg2: u64, // dirp
+    arg2cage: u64,
+    arg3: u64, // count
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
+    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+
+    let old_fd_entry = match fdtables::translate_virtual_fd(arg1cage, arg1) {
+        Ok(entry) => entry,
+        Err(_) => return -(EBADF as i32),
+    };
+
+    args[0] = old_fd_entry.underfd; // replace virtual fd with underfd for the syscall
+
+    let ret = match helpers::perfdinfo_group(old_fd_entry.perfdinfo) {
+        Some(group) => match helpers::get_route(group, arg1cage, SYS_GETDENTS) {
+            Some(alt) => helpers::do_syscall(arg1cage, alt, &args, &arg_cages),
+            None => helpers::do_clamp_syscall(group, arg1cage, SYS_GETDENTS, &args, &arg_cages),
+        },
+        None => helpers::do_syscall(arg1cage, SYS_GETDENTS, &args, &arg_cages),
+    };
+
+    if ret < 0 {
+        return ret;
+    }
+
+    let mut missing = helpers::get_mount_entries(arg1cage, arg1);
+    if missing.is_empty() {
+        return ret;
+    }
+
+    let ns_cage = getcageid();
+    let mut buf = vec![0u8; ret as usize];
+
+    if ret > 0 {
+        // Drop the entries the directory already has.
+        if copy_data_between_cages(
+            ns_cage,
+            arg2cage,
+            arg2,
+            arg2cage,
+            buf.as_mut_ptr() as u64,
+            ns_cage,
+            ret as u64,
+            0,
+        )
+        .is_ok()
+        {
+            let listed = dirent_names(&buf);
+            missing.retain(|name| !listed.contains(name));
+            helpers::set_mount_entries(arg1cage, arg1, missing);
+        }
+        return ret;
+    }
+
+    // End of the directory: add the missing entries that fit.
+    let mut added = 0;
+    for name in &missing {
+        let record = dirent_record(name);
+        if buf.len() + record.len() > arg3 as usize {
+            break;
+        }
+        buf.extend_from_slice(&record);
+        added += 1;
+    }
+
+    if added == 0 {
+        return -(EINVAL as i32);
+    }
+
+    if copy_data_between_cages(
+        ns_cage,
+        arg2cage,
+        buf.as_ptr() as u64,
+        ns_cage,
+        arg2,
+        arg2cage,
+        buf.len() as u64,
+        0,
+    )
+    .is_err()
+    {
+        return -14;
+    }
+
+    helpers::set_mount_entries(arg1cage, arg1, missing.split_off(added));
+    buf.len() as i32
+}
+
+/// Names in a buffer of `linux_dirent64` records.
+fn dirent_names(buf: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut pos = 0;
+    while pos + DIRENT64_FIXED_SIZE <= buf.len() {
+        let reclen = u16::from_ne_bytes([buf[pos + 16], buf[pos + 17]]) as usize;
+        if reclen < DIRENT64_FIXED_SIZE || pos + reclen > buf.len() {
+            break;
+        }
+        let name = &buf[pos + DIRENT64_FIXED_SIZE..pos + reclen];
+        let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        names.push(String::from_utf8_lossy(&name[..len]).into_owned());
+        pos += reclen;
+    }
+    names
+}
+
+/// A `linux_dirent64` record for a directory named `name`.
+fn dirent_record(name: &str) -> Vec<u8> {
+    let reclen = (DIRENT64_FIXED_SIZE + name.len() + 1).next_multiple_of(8);
+    let mut record = vec![0u8; reclen];
+    record[0..8].copy_from_slice(&1u64.to_ne_bytes()); // d_ino
+    record[8..16].copy_from_slice(&i64::MAX.to_ne_bytes()); // d_off
+    record[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+    record[18] = DT_DIR;
+    record[DIRENT64_FIXED_SIZE..DIRENT64_FIXED_SIZE + name.len()].copy_from_slice(name.as_bytes());
+    record
+}
+
+/// Whether open or openat with `flags` follows a symlink in the final
+/// component: not with O_NOFOLLOW, nor when O_CREAT|O_EXCL must create it.
+fn open_follows(flags: u64) -> bool {
+    let excl = (O_CREAT | O_EXCL) as u64;
+    flags & O_NOFOLLOW == 0 && flags & excl != excl
+}
+
 /// open (syscall 2): open a file by path.
 ///
 /// This is both path-based (checks prefix) AND updates fdtables:
-/// after a successful open, records the new fd with perfdinfo=1 if the
-/// path matched the prefix, or perfdinfo=0 if it didn't.
+/// after a successful open, records the new fd with perfdinfo set to the
+/// matching group's index + 1, or perfdinfo=0 if no prefix matched.
 pub extern "C" fn ns_open_handler(
     cageid: u64,
     arg1: u64,
@@ -668,31 +1041,34 @@ pub extern "C" fn ns_open_handler(
     arg6: u64,
     arg6cage: u64,
 ) -> i32 {
-    let args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
-
-    // Check if the path matches the clamped prefix.
-    let matches = helpers::resolve_path_from_cage(arg2cage, arg1, arg1cage)
-        .map(|p| helpers::path_matches_prefix(&p))
-        .unwrap_or(false);
+    let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    // Route to alt if prefix matches, otherwise passthrough.
-    let nr = match helpers::get_route(arg1cage, SYS_OPEN) {
-        Some(alt) if matches => alt,
-        _ => SYS_OPEN,
+    // Find the group whose prefixes cover the path, once its symlinks are
+    // resolved.
+    let path = match helpers::route_path_from_cage(arg1cage, arg1, arg1cage, open_follows(arg2)) {
+        Ok(path) => path,
+        Err(e) => return e,
     };
+    path.substitute(&mut args, &mut arg_cages, 0);
+    let group = path.group;
+
+    // Route to the group's alt if a prefix matches, otherwise passthrough.
+    let nr = group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_OPEN))
+        .unwrap_or(SYS_OPEN);
 
     let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
 
     // On success, record the fd in fdtables with the clamped flag.
-    // perfdinfo=1 means "this fd was opened under the clamped prefix."
+    // perfdinfo=group+1 means "this fd was opened under that group's prefix."
     if ret >= 0 {
-        let clamped = if matches { 1u64 } else { 0 };
+        let clamped = helpers::group_perfdinfo(group);
         match fdtables::get_unused_virtual_fd(
             arg1cage, 0,          // fdkind (unused)
             ret as u64, // underfd = same (identity mapping)
             false,      // should_cloexec
-            clamped,    // perfdinfo: 1=clamped, 0=not
+            clamped,    // perfdinfo: group+1=clamped, 0=not
         ) {
             Ok(vfd) => {
                 // println!("[ns_handlers|open] cageid={} path={} underfd={} clamped={} virtualfd={}",
@@ -702,6 +1078,7 @@ pub extern "C" fn ns_open_handler(
                 //     if clamped != 0 { "clamped grate" } else { "kernel" },
                 //     vfd,
                 // );
+                helpers::track_mount_entries(arg1cage, vfd, path.path.as_deref());
                 return vfd as i32;
             }
             Err(_) => {
@@ -749,12 +1126,11 @@ pub extern "C" fn ns_close_handler(
         }
     };
 
-    let is_clamped = old_fd_entry.perfdinfo != 0;
+    let group = helpers::perfdinfo_group(old_fd_entry.perfdinfo);
 
-    let nr = match helpers::get_route(arg1cage, SYS_CLOSE) {
-        Some(alt) if is_clamped => alt,
-        _ => SYS_CLOSE,
-    };
+    let nr = group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_CLOSE))
+        .unwrap_or(SYS_CLOSE);
 
     args[0] = old_fd_entry.underfd; // replace virtual fd with underfd for the syscall
 
@@ -762,6 +1138,7 @@ pub extern "C" fn ns_close_handler(
 
     if ret >= 0 {
         let _ = fdtables::close_virtualfd(arg1cage, arg1);
+        helpers::set_mount_entries(arg1cage, arg1, Vec::new());
     }
 
     // println!(
@@ -769,7 +1146,7 @@ pub extern "C" fn ns_close_handler(
     //     arg1cage,
     //     arg1,
     //     old_fd_entry.underfd,
-    //     if is_clamped { "clamped grate" } else { "kernel" },
+    //     if group.is_some() { "clamped grate" } else { "kernel" },
     //     ret,
     // );
     ret
@@ -812,17 +1189,15 @@ pub extern "C" fn ns_mmap_handler(
             }
         };
 
-        let perfdinfo = old_fd_entry.perfdinfo;
-
         args[4] = old_fd_entry.underfd; // replace virtual fd with underfd for the syscall
 
-        if perfdinfo != 0 {
-            let ret = match helpers::get_route(arg1cage, SYS_MMAP) {
+        if let Some(group) = helpers::perfdinfo_group(old_fd_entry.perfdinfo) {
+            let ret = match helpers::get_route(group, arg1cage, SYS_MMAP) {
                 Some(alt) => helpers::do_syscall(arg1cage, alt, &args, &arg_cages),
-                None => helpers::do_clamp_syscall(arg1cage, SYS_MMAP, &args, &arg_cages),
+                None => helpers::do_clamp_syscall(group, arg1cage, SYS_MMAP, &args, &arg_cages),
             };
             if ret >= 0 {
-                helpers::record_clamped_mmap(arg1cage, ret as u64, arg2);
+                helpers::record_clamped_mmap(arg1cage, group, ret as u64, arg2);
             }
             return ret;
         }
@@ -841,7 +1216,8 @@ pub extern "C" fn ns_mmap_handler(
 ///
 /// munmap has no fd argument, so fd-based routing is impossible here.
 /// Instead, route if the addr/len overlaps a range previously returned by a
-/// clamped mmap. This lets clamped grates such as imfs decrement mmap_refs.
+/// clamped mmap, to the group that mapped it. This lets clamped grates such
+/// as imfs decrement mmap_refs.
 pub extern "C" fn ns_munmap_handler(
     _cageid: u64,
     arg1: u64, // addr
@@ -860,9 +1236,14 @@ pub extern "C" fn ns_munmap_handler(
     let args = [arg1, arg2, arg3, arg4, arg5, arg6];
     let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let is_clamped_mapping = helpers::is_clamped_mmap(arg1cage, arg1, arg2);
+    let mapping_group = helpers::clamped_mmap_group(arg1cage, arg1, arg2);
+
+    let route = match mapping_group {
+        Some(group) => helpers::get_route(group, arg1cage, SYS_MUNMAP),
+        None => helpers::get_any_route(arg1cage, SYS_MUNMAP),
+    };
 
-    if let Some(alt) = helpers::get_route(arg1cage, SYS_MUNMAP) {
+    if let Some(alt) = route {
         let ret = helpers::do_syscall(arg1cage, alt, &args, &arg_cages);
 
         if ret == 0 {
@@ -872,8 +1253,8 @@ pub extern "C" fn ns_munmap_handler(
         return ret;
     }
 
-    if is_clamped_mapping {
-        let ret = helpers::do_clamp_syscall(arg1cage, SYS_MUNMAP, &args, &arg_cages);
+    if let Some(group) = mapping_group {
+        let ret = helpers::do_clamp_syscall(group, arg1cage, SYS_MUNMAP, &args, &arg_cages);
 
         if ret == 0 {
             helpers::remove_clamped_mmap(arg1cage, arg1, arg2);
@@ -917,10 +1298,9 @@ pub extern "C" fn ns_fcntl_handler(
 
     let perfdinfo = old_fd_entry.perfdinfo;
 
-    let nr = match helpers::get_route(arg1cage, SYS_FCNTL) {
-        Some(alt) if perfdinfo != 0 => alt,
-        _ => SYS_FCNTL,
-    };
+    let nr = helpers::perfdinfo_group(perfdinfo)
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_FCNTL))
+        .unwrap_or(SYS_FCNTL);
 
     args[0] = old_fd_entry.underfd; // replace virtual fd with underfd for the syscall
     // arg_cages[0] = cageid;
@@ -984,67 +1364,36 @@ pub extern "C" fn ns_fstatat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
-
-    let is_at_fdcwd = (arg1 as i64) == AT_FDCWD_I64;
-
-    let path = helpers::read_path_from_cage(arg2, arg2cage).unwrap_or_default();
-
-    let mut dirfd_clamped = false;
-
-    /*
-     * If dirfd is not AT_FDCWD and path is relative/empty, translate virtual
-     * dirfd -> underlying kernel fd. Absolute paths ignore dirfd on Linux.
-     */
-    if !is_at_fdcwd && (path.is_empty() || !path.starts_with('/')) {
-        let fd_entry = match fdtables::translate_virtual_fd(arg1cage, arg1) {
-            Ok(entry) => entry,
-            Err(_) => {
-                // println!(
-                //     "[ns_handlers|fstatat] cageid={} dirfd={} invalid virtual fd, ret=EBADF",
-                //     arg1cage,
-                //     arg1,
-                // );
-                return -(EBADF as i32);
-            }
-        };
-
-        dirfd_clamped = fd_entry.perfdinfo != 0;
-        if !dirfd_clamped {
-            args[0] = fd_entry.underfd;
-        }
-    }
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
     /*
      * Routing decision:
      *
      * - empty path: usually AT_EMPTY_PATH; route by dirfd only.
-     * - absolute path: dirfd is ignored, route by absolute path prefix.
+     * - absolute path: dirfd is ignored, route by the resolved path.
      * - AT_FDCWD + relative path: resolve relative to caller cage cwd.
-     * - real dirfd + relative path: route by dirfd's clamped status.
+     * - real dirfd + relative path: route by dirfd's group.
      */
-    let should_clamp = if path.is_empty() {
-        dirfd_clamped
-    } else if path.starts_with('/') {
-        let resolved = helpers::normalize_path(&path);
-        helpers::path_matches_prefix(&resolved)
-    } else if is_at_fdcwd {
-        helpers::resolve_path_from_cage(arg2cage, arg2, arg2cage)
-            .map(|resolved| helpers::path_matches_prefix(&resolved))
-            .unwrap_or(false)
-    } else {
-        dirfd_clamped
+    let follow_final = arg4 & AT_SYMLINK_NOFOLLOW == 0;
+    let path = match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, follow_final) {
+        Ok(path) => path,
+        Err(e) => return e,
     };
+    let group = path.group;
 
-    let ret = if should_clamp {
-        match helpers::get_route(arg1cage, SYS_NEWFSTATAT) {
+    let ret = if let Some(group) = group {
+        match helpers::get_route(group, arg1cage, SYS_NEWFSTATAT) {
             Some(alt) => helpers::do_syscall(arg1cage, alt, &args, &arg_cages),
-            None => helpers::do_clamp_syscall(arg1cage, SYS_NEWFSTATAT, &args, &arg_cages),
+            None => helpers::do_clamp_syscall(group, arg1cage, SYS_NEWFSTATAT, &args, &arg_cages),
         }
     } else {
         helpers::do_syscall(arg1cage, SYS_NEWFSTATAT, &args, &arg_cages)
     };
 
+    if ret == -(ENOENT as i32) && path.path.as_deref().is_some_and(helpers::is_mount_path) {
+        return write_mount_point_stat(arg3, arg3cage);
+    }
+
     ret
 }
 
@@ -1064,76 +1413,39 @@ pub extern "C" fn ns_openat_handler(
     arg6cage: u64,
 ) -> i32 {
     let mut args = [arg1, arg2, arg3, arg4, arg5, arg6];
-    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
-
-    // Linux AT_FDCWD = -100.
-    let is_at_fdcwd = (arg1 as i64) == -100;
-
-    let path = helpers::read_path_from_cage(arg2, arg2cage).unwrap_or_default();
-
-    let mut dirfd_clamped = false;
-
-    /*
-     * If dirfd is not AT_FDCWD and path is relative, translate virtual fd ->
-     * kernel fd. Absolute paths ignore dirfd on Linux.
-     */
-    if !is_at_fdcwd && !path.starts_with('/') {
-        let fd_entry = match fdtables::translate_virtual_fd(arg1cage, arg1) {
-            Ok(entry) => entry,
-            Err(_) => {
-                // println!(
-                //     "[ns_handlers|fstatat] cageid={} dirfd={} invalid virtual fd, ret=EBADF",
-                //     arg1cage,
-                //     arg1,
-                // );
-                return -(EBADF as i32);
-            }
-        };
-
-        dirfd_clamped = fd_entry.perfdinfo != 0;
-        if !dirfd_clamped {
-            args[0] = fd_entry.underfd;
-        }
-    }
+    let mut arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
     /*
      * Routing decision:
      *
-     * 1. If dirfd is clamped, relative openat should route to the clamped grate.
-     * 2. If path is absolute or dirfd is AT_FDCWD, resolve by cage cwd and check prefix.
-     * 3. Otherwise, if dirfd is not clamped and path is relative, pass through kernel.
+     * 1. If path is absolute or dirfd is AT_FDCWD, resolve it and check prefixes.
+     * 2. Otherwise, relative openat routes to the dirfd's group, or passes
+     *    through to the kernel if the dirfd is not clamped.
      */
-    let path_matches = if !path.is_empty() {
-        if path.starts_with("/") || is_at_fdcwd {
-            helpers::resolve_path_from_cage(arg2cage, arg2, arg1cage)
-                .map(|p| helpers::path_matches_prefix(&p))
-                .unwrap_or(false)
-        } else {
-            false
-        }
-    } else {
-        false
-    };
-
-    let should_clamp = dirfd_clamped || path_matches;
+    let path =
+        match prepare_at_path_route(arg1cage, &mut args, &mut arg_cages, 0, open_follows(arg3)) {
+            Ok(path) => path,
+            Err(e) => return e,
+        };
+    let group = path.group;
 
-    let nr = match helpers::get_route(arg1cage, SYS_OPENAT) {
-        Some(alt) if should_clamp => alt,
-        _ => SYS_OPENAT,
-    };
+    let nr = group
+        .and_then(|g| helpers::get_route(g, arg1cage, SYS_OPENAT))
+        .unwrap_or(SYS_OPENAT);
 
-    let ret = if should_clamp && nr == SYS_OPENAT {
-        helpers::do_clamp_syscall(arg1cage, SYS_OPENAT, &args, &arg_cages)
-    } else {
-        helpers::do_syscall(arg1cage, nr, &args, &arg_cages)
+    let ret = match group {
+        Some(group) if nr == SYS_OPENAT => {
+            helpers::do_clamp_syscall(group, arg1cage, SYS_OPENAT, &args, &arg_cages)
+        }
+        _ => helpers::do_syscall(arg1cage, nr, &args, &arg_cages),
     };
 
     /*
      * On success, record returned kernel fd as a virtual fd.
-     * perfdinfo=1 means fd belongs to clamped namespace/grate.
+     * perfdinfo=group+1 means fd belongs to that group's clamped grates.
      */
     if ret >= 0 {
-        let clamped = if should_clamp { 1u64 } else { 0u64 };
+        let clamped = helpers::group_perfdinfo(group);
 
         match fdtables::get_unused_virtual_fd(
             arg1cage, 0,          // fdkind
@@ -1151,6 +1463,7 @@ pub extern "C" fn ns_openat_handler(
                 //     if clamped != 0 { "clamped grate" } else { "kernel" },
                 //     vfd,
                 // );
+                helpers::track_mount_entries(arg1cage, vfd, path.path.as_deref());
                 return vfd as i32;
             }
             Err(_) => {
@@ -1196,10 +1509,10 @@ pub extern "C" fn ns_dup_handler(
     let dup_args = [old_fd_entry.underfd, 0, 0, 0, 0, 0];
     let dup_cages = [arg1cage; 6];
 
-    let ret = if perfdinfo != 0 {
-        match helpers::get_route(arg1cage, SYS_DUP) {
+    let ret = if let Some(group) = helpers::perfdinfo_group(perfdinfo) {
+        match helpers::get_route(group, arg1cage, SYS_DUP) {
             Some(alt) => helpers::do_syscall(arg1cage, alt, &dup_args, &dup_cages),
-            None => helpers::do_clamp_syscall(arg1cage, SYS_DUP, &dup_args, &dup_cages),
+            None => helpers::do_clamp_syscall(group, arg1cage, SYS_DUP, &dup_args, &dup_cages),
         }
     } else {
         helpers::do_syscall(arg1cage, SYS_DUP, &dup_args, &dup_cages)
@@ -1288,6 +1601,7 @@ pub extern "C" fn ns_dup2_handler(
 
         let _ = helpers::do_syscall(arg1cage, SYS_CLOSE, &close_args, &close_cages);
         let _ = fdtables::close_virtualfd(arg1cage, arg2);
+        helpers::set_mount_entries(arg1cage, arg2, Vec::new());
     }
 
     // Duplicate the underlying old fd.
@@ -1297,10 +1611,10 @@ pub extern "C" fn ns_dup2_handler(
     let dup_args = [old_fd_entry.underfd, 0, 0, 0, 0, 0];
     let dup_cages = [arg1cage; 6];
 
-    let ret = if perfdinfo != 0 {
-        match helpers::get_route(arg1cage, SYS_DUP) {
+    let ret = if let Some(group) = helpers::perfdinfo_group(perfdinfo) {
+        match helpers::get_route(group, arg1cage, SYS_DUP) {
             Some(alt) => helpers::do_syscall(arg1cage, alt, &dup_args, &dup_cages),
-            None => helpers::do_clamp_syscall(arg1cage, SYS_DUP, &dup_args, &dup_cages),
+            None => helpers::do_clamp_syscall(group, arg1cage, SYS_DUP, &dup_args, &dup_cages),
         }
     } else {
         helpers::do_syscall(arg1cage, SYS_DUP, &dup_args, &dup_cages)
@@ -1406,6 +1720,7 @@ pub extern "C" fn ns_dup3_handler(
 
         let _ = helpers::do_syscall(arg1cage, SYS_CLOSE, &close_args, &close_cages);
         let _ = fdtables::close_virtualfd(arg1cage, arg2);
+        helpers::set_mount_entries(arg1cage, arg2, Vec::new());
     }
 
     // Duplicate old underfd to a fresh real fd.
@@ -1415,10 +1730,10 @@ pub extern "C" fn ns_dup3_handler(
     let dup_args = [old_fd_entry.underfd, 0, 0, 0, 0, 0];
     let dup_cages = [arg1cage; 6];
 
-    let ret = if perfdinfo != 0 {
-        match helpers::get_route(arg1cage, SYS_DUP) {
+    let ret = if let Some(group) = helpers::perfdinfo_group(perfdinfo) {
+        match helpers::get_route(group, arg1cage, SYS_DUP) {
             Some(alt) => helpers::do_syscall(arg1cage, alt, &dup_args, &dup_cages),
-            None => helpers::do_clamp_syscall(arg1cage, SYS_DUP, &dup_args, &dup_cages),
+            None => helpers::do_clamp_syscall(group, arg1cage, SYS_DUP, &dup_args, &dup_cages),
         }
     } else {
         helpers::do_syscall(arg1cage, SYS_DUP, &dup_args, &dup_cages)
@@ -1520,7 +1835,8 @@ pub extern "C" fn ns_clone_handler(
     let args = [arg1, arg2, arg3, arg4, arg5, arg6];
     let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
 
-    let nr = helpers::get_route(arg1cage, SYS_CLONE).unwrap_or(SYS_CLONE);
+    // Every stateful stack must see the fork to copy its state for the child.
+    let nr = helpers::get_last_route(arg1cage, SYS_CLONE).unwrap_or(SYS_CLONE);
     let is_thread = is_thread_clone(arg1, arg1cage);
     let ret = helpers::do_syscall(arg1cage, nr, &args, &arg_cages);
 
@@ -1601,8 +1917,11 @@ pub fn get_ns_handler(syscall_nr: u64) -> Option<SyscallHandler> {
         SYS_LINK => Some(ns_link_handler),
         helpers::SYS_LINKAT_NR => Some(ns_linkat_handler),
         SYS_MKDIR => Some(ns_mkdir_handler),
+        SYS_MKDIRAT => Some(ns_mkdirat_handler),
         SYS_RMDIR => Some(ns_rmdir_handler),
         SYS_RENAME => Some(ns_rename_handler),
+        SYS_RENAMEAT => Some(ns_renameat_handler),
+        SYS_RENAMEAT2 => Some(ns_renameat2_handler),
         SYS_TRUNCATE => Some(ns_truncate_handler),
         SYS_CHMOD => Some(ns_chmod_handler),
         SYS_CHOWN => Some(ns_chown_handler),
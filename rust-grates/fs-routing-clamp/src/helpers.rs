@@ -1,13 +1,16 @@
 //! Global state and routing helpers for the namespace grate.
 //!
 //! This module contains:
-//!   - Global routing table: (cage_id, syscall_nr) → alt syscall number
-//!   - Clamp phase flag and prefix condition
+//!   - Per-group routing tables: (cage_id, syscall_nr) → alt syscall number
+//!   - Clamp phase flag and per-group prefix conditions
 //!   - Per-cage clamped status tracking
 //!   - Helpers for reading paths from cage memory and making syscalls
 
+use grate_rs::constants::error::{ELOOP, ENOENT};
+use grate_rs::constants::lind::GRATE_MEMORY_FLAG;
 use grate_rs::constants::*;
 use std::collections::HashMap;
+use std::ffi::CString;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Mutex, OnceLock};
 
@@ -18,7 +21,7 @@ pub const SYS_LINKAT_NR: u64 = 265;
 // These are all the calls that the fs-namespace grate cares about. All of the
 // following calls from the target must be routed through the grate regardless
 // of whether the clamp interposed on them.
-pub const FS_CALLS: [u64; 57] = [
+pub const FS_CALLS: [u64; 60] = [
     SYS_OPEN,
     SYS_OPENAT,
     SYS_XSTAT,
@@ -28,8 +31,11 @@ pub const FS_CALLS: [u64; 57] = [
     SYS_LINK,
     SYS_LINKAT_NR,
     SYS_MKDIR,
+    SYS_MKDIRAT,
     SYS_RMDIR,
     SYS_RENAME,
+    SYS_RENAMEAT,
+    SYS_RENAMEAT2,
     SYS_TRUNCATE,
     SYS_CHMOD,
     SYS_CHOWN,
@@ -85,26 +91,50 @@ pub const FS_CALLS: [u64; 57] = [
 //  Global state
 // =====================================================================
 
-pub struct NSClampState {
+/// One `--prefix ... %{ stack %}` group from the command line.
+pub struct ClampGroup {
+    /// Path prefixes routed to this group's stack (`--prefix`), normalized.
+    routing_prefixes: Vec<String>,
+
+    /// Path prefixes carved out of the routed ones (`--exclude`), normalized.
+    excluded_prefixes: Vec<String>,
+
     /// Routing table: (cage_id, syscall_nr) → alt syscall number.
-    /// When a clamped grate registers a handler, we store the alt number here.
-    routes: Option<HashMap<(u64, u64), u64>>,
+    /// When a grate in this group's stack registers a handler, we store the alt number here.
+    routes: HashMap<(u64, u64), u64>,
+
+    /// Cage ID captured at this group's clamp entry point.
+    clamp_entry_cage: u64,
+}
+
+impl ClampGroup {
+    pub fn new(prefixes: Vec<String>, excludes: Vec<String>) -> Self {
+        Self {
+            routing_prefixes: prefixes,
+            excluded_prefixes: excludes,
+            routes: HashMap::new(),
+            clamp_entry_cage: 0,
+        }
+    }
+}
+
+pub struct NSClampState {
+    /// Prefix groups, in command-line order. A group's index is stored in an
+    /// fd's perfdinfo as `index + 1`; 0 means the fd is not clamped.
+    groups: Vec<ClampGroup>,
+
+    /// Index of the group whose `%{ ... %}` stack is currently being set up.
+    current_group: usize,
 
     /// The namespace grate's own cage ID.
     ns_cage_id: u64,
 
-    /// Cage ID captured at the clamp entry point.
-    clamp_entry_cage: u64,
-
     /// Lexical nesting depth of `%{ ... %}` blocks currently being traversed.
     ///
     /// The outer clamp managed by fs-routing-clamp starts at depth 1. Nested grates may introduce
     /// additional `%{ ... %}` pairs that fs-routing-clamp must pass through without consuming.
     clamp_depth: usize,
 
-    /// The path prefix condition for routing.
-    routing_prefix: Option<String>,
-
     /// Set of cage IDs that are inside the clamp.
     clamped_cages: Option<HashMap<u64, ()>>,
 
@@ -118,20 +148,24 @@ pub struct NSClampState {
     /// Recorded inner-grate handler registrations as
     /// `(target_cage, syscall_nr, grate_id, handler_fn_ptr)`.
     interposition_map: Vec<(u64, u64, u64, u64)>,
+
+    /// Mount point entries still to be added to the listing of an open
+    /// directory, keyed by `(cage_id, fd)`.
+    mount_entries: Option<HashMap<(u64, u64), Vec<String>>>,
 }
 
 impl NSClampState {
-    pub fn new(ns_cage_id: u64, prefix: String) -> Self {
+    pub fn new(ns_cage_id: u64, groups: Vec<ClampGroup>) -> Self {
         Self {
-            routes: None,
+            groups,
+            current_group: 0,
             ns_cage_id: ns_cage_id,
-            clamp_entry_cage: 0,
             clamp_depth: 1,
-            routing_prefix: Some(prefix),
             clamped_cages: None,
             cwd_by_cage: None,
             alt_allocator: 3000,
             interposition_map: Vec::new(),
+            mount_entries: None,
         }
     }
 }
@@ -158,8 +192,8 @@ macro_rules! log_error {
 }
 
 /// Initialize all global state. Called once at startup.
-pub fn init_globals(ns_cage_id: u64, prefix: String, logging_enabled: bool) {
-    *CLAMP_STATE.lock().unwrap() = Some(NSClampState::new(ns_cage_id, prefix));
+pub fn init_globals(ns_cage_id: u64, groups: Vec<ClampGroup>, logging_enabled: bool) {
+    *CLAMP_STATE.lock().unwrap() = Some(NSClampState::new(ns_cage_id, groups));
     LOGGING_ENABLED.store(logging_enabled, Ordering::Relaxed);
 }
 
@@ -172,35 +206,41 @@ pub fn get_ns_cage_id() -> u64 {
     CLAMP_STATE.lock().unwrap().as_ref().unwrap().ns_cage_id
 }
 
-/// Return the cage ID saved at the clamp entry point.
-pub fn get_clamp_entry() -> u64 {
-    CLAMP_STATE
-        .lock()
-        .unwrap()
-        .as_ref()
-        .unwrap()
-        .clamp_entry_cage
+/// Return the cage ID saved at the clamp entry point of `group`.
+pub fn get_clamp_entry(group: usize) -> u64 {
+    CLAMP_STATE.lock().unwrap().as_ref().unwrap().groups[group].clamp_entry_cage
 }
 
-pub fn get_clamp_depth() -> usize {
+/// Return the index of the group whose stack is currently being set up.
+pub fn get_current_group() -> usize {
     CLAMP_STATE
         .lock()
         .unwrap()
         .as_ref()
         .expect("CLAMP_STATE not initialized")
-        .clamp_depth
+        .current_group
 }
 
-pub fn get_routing_prefix() -> String {
+/// Move on to the next group's stack. Returns false if the current group was
+/// the last one.
+pub fn advance_group() -> bool {
+    let mut state = CLAMP_STATE.lock().unwrap();
+    let s = state.as_mut().expect("CLAMP_STATE not initialized");
+
+    if s.current_group + 1 >= s.groups.len() {
+        return false;
+    }
+    s.current_group += 1;
+    true
+}
+
+pub fn get_clamp_depth() -> usize {
     CLAMP_STATE
         .lock()
         .unwrap()
         .as_ref()
-        .unwrap()
-        .routing_prefix
-        .as_ref()
-        .unwrap()
-        .clone()
+        .expect("CLAMP_STATE not initialized")
+        .clamp_depth
 }
 
 /// Allocate the next available alt syscall number.
@@ -225,7 +265,8 @@ pub fn set_clamp_entry(cage_id: u64) {
     let mut state = CLAMP_STATE.lock().unwrap();
     let s = state.as_mut().expect("CLAMP_STATE not initialized");
 
-    s.clamp_entry_cage = cage_id;
+    let group = s.current_group;
+    s.groups[group].clamp_entry_cage = cage_id;
 }
 
 pub fn enter_nested_clamp() -> usize {
@@ -304,38 +345,60 @@ pub fn get_interposition_request(target_cage: u64, fs_syscall: u64) -> Option<(u
 //  Route table
 // =====================================================================
 
-pub fn set_route(cage_id: u64, syscall_nr: u64, alt_nr: u64) -> bool {
+pub fn set_route(group: usize, cage_id: u64, syscall_nr: u64, alt_nr: u64) -> bool {
     let mut state = CLAMP_STATE.lock().unwrap();
     let s = state.as_mut().expect("CLAMP_STATE not initialized");
 
-    s.routes
-        .get_or_insert_with(HashMap::new)
+    s.groups[group]
+        .routes
         .insert((cage_id, syscall_nr), alt_nr)
         .is_some()
 }
 
-pub fn get_route(cage_id: u64, syscall_nr: u64) -> Option<u64> {
+pub fn get_route(group: usize, cage_id: u64, syscall_nr: u64) -> Option<u64> {
     CLAMP_STATE
         .lock()
         .unwrap()
         .as_ref()
-        .and_then(|s| s.routes.as_ref())
-        .and_then(|r| r.get(&(cage_id, syscall_nr)).copied())
+        .and_then(|s| s.groups.get(group))
+        .and_then(|g| g.routes.get(&(cage_id, syscall_nr)).copied())
+}
+
+/// Return the route of the first group that has one for this cage and syscall.
+pub fn get_any_route(cage_id: u64, syscall_nr: u64) -> Option<u64> {
+    CLAMP_STATE.lock().unwrap().as_ref().and_then(|s| {
+        s.groups
+            .iter()
+            .find_map(|g| g.routes.get(&(cage_id, syscall_nr)).copied())
+    })
+}
+
+/// Return the route of the last group that has one for this cage and
+/// syscall. Later groups' stacks run under the routing of earlier ones, so
+/// that route passes through every stack that handles the syscall.
+pub fn get_last_route(cage_id: u64, syscall_nr: u64) -> Option<u64> {
+    CLAMP_STATE.lock().unwrap().as_ref().and_then(|s| {
+        s.groups
+            .iter()
+            .rev()
+            .find_map(|g| g.routes.get(&(cage_id, syscall_nr)).copied())
+    })
 }
 
 pub fn clone_cage_routes(parent: u64, child: u64) {
     let mut state = CLAMP_STATE.lock().unwrap();
     let s = state.as_mut().expect("CLAMP_STATE not initialized");
 
-    if let Some(map) = s.routes.as_mut() {
-        let parent_routes: Vec<(u64, u64)> = map
+    for group in s.groups.iter_mut() {
+        let parent_routes: Vec<(u64, u64)> = group
+            .routes
             .iter()
             .filter(|&(&(cid, _), _)| cid == parent)
             .map(|(&(_, nr), &alt)| (nr, alt))
             .collect();
 
         for (nr, alt) in parent_routes {
-            map.insert((child, nr), alt);
+            group.routes.insert((child, nr), alt);
         }
     }
 }
@@ -369,8 +432,8 @@ pub fn remove_cage_state(cage_id: u64) {
     let mut state = CLAMP_STATE.lock().unwrap();
     let s = state.as_mut().expect("CLAMP_STATE not initialized");
 
-    if let Some(routes) = s.routes.as_mut() {
-        routes.retain(|&(cid, _), _| cid != cage_id);
+    for group in s.groups.iter_mut() {
+        group.routes.retain(|&(cid, _), _| cid != cage_id);
     }
 
     if let Some(cages) = s.clamped_cages.as_mut() {
@@ -380,6 +443,16 @@ pub fn remove_cage_state(cage_id: u64) {
     if let Some(cwds) = s.cwd_by_cage.as_mut() {
         cwds.remove(&cage_id);
     }
+
+    if let Some(entries) = s.mount_entries.as_mut() {
+        entries.retain(|&(cid, _), _| cid != cage_id);
+    }
+    drop(state);
+
+    link_cache()
+        .lock()
+        .unwrap()
+        .retain(|(cid, _), _| *cid != cage_id);
 }
 
 // =====================================================================
@@ -431,22 +504,318 @@ pub fn normalize_path(path: &str) -> String {
     }
 }
 
-pub fn resolve_path_for_cage(current_cage: u64, path: &str) -> String {
-    if path.starts_with('/') {
-        normalize_path(path)
+/// Check whether `path` is `prefix` itself or lies below it. Matching is on
+/// whole components, so `/tmp` covers `/tmp/a` but not `/tmpfiles`.
+fn path_under_prefix(path: &str, prefix: &str) -> bool {
+    prefix == "/"
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Return the group a normalized path is routed to, if any.
+///
+/// Within a group, the most specific `--prefix` or `--exclude` covering the
+/// path decides, so an exclusion can carve a subtree out of a routed prefix and
+/// a longer `--prefix` can route part of an excluded subtree again. When
+/// several groups route the path, the one with the longest matching prefix
+/// wins.
+pub fn path_group(path: &str) -> Option<usize> {
+    let state = CLAMP_STATE.lock().unwrap();
+    let s = state.as_ref().unwrap();
+
+    let longest = |prefixes: &[String]| {
+        prefixes
+            .iter()
+            .filter(|prefix| path_under_prefix(path, prefix))
+            .map(|prefix| prefix.len())
+            .max()
+    };
+
+    s.groups
+        .iter()
+        .enumerate()
+        .filter_map(|(index, group)| {
+            match (
+                longest(&group.routing_prefixes),
+                longest(&group.excluded_prefixes),
+            ) {
+                (Some(routed), Some(excluded)) if routed > excluded => Some((routed, index)),
+                (Some(routed), None) => Some((routed, index)),
+                _ => None,
+            }
+        })
+        .max_by_key(|&(routed, _)| routed)
+        .map(|(_, index)| index)
+}
+
+/// Most symlinks followed while resolving a single path, as on Linux.
+const MAXSYMLINKS: usize = 40;
+
+/// A path argument resolved for routing.
+pub struct RoutedPath {
+    /// Absolute path with its symlinks resolved, or `None` for a path that is
+    /// routed without being resolved here, such as one relative to a dirfd.
+    pub path: Option<String>,
+    /// Group the resolved path is routed to, if any.
+    pub group: Option<usize>,
+    /// NUL-terminated copy of `path`, kept only when a symlink was followed.
+    followed: Option<CString>,
+}
+
+impl RoutedPath {
+    /// A path that is not resolved here, routed to `group`.
+    pub fn unresolved(group: Option<usize>) -> Self {
+        Self {
+            path: None,
+            group,
+            followed: None,
+        }
+    }
+
+    /// Point argument `index` at the resolved path if a symlink was followed,
+    /// so that the layer handling the call does not follow it again. The slot
+    /// keeps the calling cage, with GRATE_MEMORY_FLAG marking the buffer as
+    /// ours, so layers that take the caller from the path argument still see
+    /// it.
+    pub fn substitute(&self, args: &mut [u64; 6], arg_cages: &mut [u64; 6], index: usize) {
+        if let Some(path) = &self.followed {
+            args[index] = path.as_ptr() as u64;
+            arg_cages[index] |= GRATE_MEMORY_FLAG;
+        }
+    }
+}
+
+/// Resolve `path` for `cage_id` and find the group it is routed to.
+///
+/// Symlinks are followed one component at a time. Each component is looked up
+/// with readlink(2) on the layer its path is routed to: the clamped stack for
+/// a routed path, the host otherwise. A link target is then walked like any
+/// other path, so a link may lead into or out of a routed prefix, and the
+/// path it ends up at decides the routing. The final component is only
+/// followed when `follow_final` is set; a trailing slash always follows it.
+/// Lookups are cached in `LINK_CACHE`, so a path walked before costs no
+/// readlink(2) calls until a symlink may have changed.
+///
+/// Returns `-ELOOP` once more than `MAXSYMLINKS` links were followed.
+pub fn route_path(cage_id: u64, path: &str, follow_final: bool) -> Result<RoutedPath, i32> {
+    let mut resolved: Vec<String> = Vec::new();
+    if !path.starts_with('/') {
+        let cwd = get_cage_cwd(cage_id);
+        resolved.extend(cwd.split('/').filter(|c| !c.is_empty()).map(String::from));
+    }
+
+    // Components still to walk, last one first.
+    let mut pending: Vec<String> = path.rsplit('/').map(String::from).collect();
+    let mut links = 0;
+
+    while let Some(component) = pending.pop() {
+        match component.as_str() {
+            "" | "." => continue,
+            ".." => {
+                resolved.pop();
+                continue;
+            }
+            _ => resolved.push(component),
+        }
+
+        if pending.is_empty() && !follow_final {
+            break;
+        }
+
+        let current = format!("/{}", resolved.join("/"));
+        let Some(target) = layer_readlink(cage_id, &current) else {
+            continue;
+        };
+
+        links += 1;
+        if links > MAXSYMLINKS {
+            return Err(-ELOOP);
+        }
+        if target.is_empty() {
+            return Err(-ENOENT);
+        }
+
+        resolved.pop();
+        if target.starts_with('/') {
+            resolved.clear();
+        }
+        pending.extend(target.rsplit('/').map(String::from));
+    }
+
+    let resolved = format!("/{}", resolved.join("/"));
+    let followed = if links == 0 {
+        None
+    } else if path.ends_with('/') && resolved != "/" {
+        CString::new(format!("{resolved}/")).ok()
+    } else {
+        CString::new(resolved.as_str()).ok()
+    };
+
+    Ok(RoutedPath {
+        group: path_group(&resolved),
+        path: Some(resolved),
+        followed,
+    })
+}
+
+/// Read a path argument from a cage and resolve it with `route_path`. A path
+/// that cannot be read is not routed, and is left for the kernel to reject.
+pub fn route_path_from_cage(
+    cage_id: u64,
+    path_ptr: u64,
+    path_cage: u64,
+    follow_final: bool,
+) -> Result<RoutedPath, i32> {
+    match read_path_from_cage(path_ptr, path_cage) {
+        Some(path) if !path.is_empty() => route_path(cage_id, &path, follow_final),
+        _ => Ok(RoutedPath::unresolved(None)),
+    }
+}
+
+/// Most lookups `LINK_CACHE` holds before it is emptied.
+const LINK_CACHE_MAX: usize = 4096;
+
+/// Result of each `layer_readlink` lookup, by cage and path, so that a path
+/// is not probed component by component on every call. `None` records a
+/// path that is not a symlink. Cages are kept apart because each has routes,
+/// and possibly a namespace, of its own.
+type LinkCache = HashMap<(u64, String), Option<String>>;
+
+static LINK_CACHE: OnceLock<Mutex<LinkCache>> = OnceLock::new();
+
+fn link_cache() -> &'static Mutex<LinkCache> {
+    LINK_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Drop every cached lookup. Called after each call that can create, remove
+/// or move a symlink (symlink, link, rename, unlink and their *at forms).
+pub fn forget_links() {
+    link_cache().lock().unwrap().clear();
+}
+
+/// Read the target of the symlink at `path` from the layer `path` is routed
+/// to, or `None` if it is not a symlink (or does not exist).
+fn layer_readlink(cage_id: u64, path: &str) -> Option<String> {
+    let key = (cage_id, path.to_string());
+    if let Some(target) = link_cache().lock().unwrap().get(&key) {
+        return target.clone();
+    }
+
+    let target = probe_readlink(cage_id, path);
+    let mut cache = link_cache().lock().unwrap();
+    if cache.len() >= LINK_CACHE_MAX {
+        cache.clear();
+    }
+    cache.insert(key, target.clone());
+    target
+}
+
+/// Issue readlink(2) for `layer_readlink` without the cache.
+fn probe_readlink(cage_id: u64, path: &str) -> Option<String> {
+    let c_path = CString::new(path).ok()?;
+    let mut buf = vec![0u8; MAX_PATH_LEN];
+
+    let args = [
+        c_path.as_ptr() as u64,
+        buf.as_mut_ptr() as u64,
+        buf.len() as u64,
+        0,
+        0,
+        0,
+    ];
+    // Both buffers are ours, but like substitute() their slots keep the
+    // calling cage.
+    let arg_cages = [
+        cage_id | GRATE_MEMORY_FLAG,
+        cage_id | GRATE_MEMORY_FLAG,
+        cage_id,
+        cage_id,
+        cage_id,
+        cage_id,
+    ];
+
+    let nr = path_group(path)
+        .and_then(|group| get_route(group, cage_id, SYS_READLINK))
+        .unwrap_or(SYS_READLINK);
+
+    match do_syscall(cage_id, nr, &args, &arg_cages) {
+        len if len >= 0 => Some(String::from_utf8_lossy(&buf[..len as usize]).into_owned()),
+        _ => None,
+    }
+}
+
+/// Names `dir` must list so that every routed prefix below it is reachable:
+/// the component following `dir` in each such prefix.
+pub fn mount_children(dir: &str) -> Vec<String> {
+    let state = CLAMP_STATE.lock().unwrap();
+    let s = state.as_ref().unwrap();
+
+    let mut names: Vec<String> = Vec::new();
+    for prefix in s.groups.iter().flat_map(|g| g.routing_prefixes.iter()) {
+        if prefix == dir || !path_under_prefix(prefix, dir) {
+            continue;
+        }
+        let rest = prefix[dir.len()..].trim_start_matches('/');
+        let name = rest.split('/').next().unwrap_or_default().to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Check whether `path` is a routed prefix or one of its ancestors. These
+/// always exist, like a mount point and the directories leading to it.
+pub fn is_mount_path(path: &str) -> bool {
+    let state = CLAMP_STATE.lock().unwrap();
+    let s = state.as_ref().unwrap();
+
+    s.groups
+        .iter()
+        .flat_map(|g| g.routing_prefixes.iter())
+        .any(|prefix| path_under_prefix(prefix, path))
+}
+
+/// Start tracking the mount point entries of a newly opened fd, replacing any
+/// left over from a closed fd with the same number.
+pub fn track_mount_entries(cage_id: u64, fd: u64, path: Option<&str>) {
+    let names = path.map(mount_children).unwrap_or_default();
+    set_mount_entries(cage_id, fd, names);
+}
+
+/// Return the mount point entries still missing from a directory listing.
+pub fn get_mount_entries(cage_id: u64, fd: u64) -> Vec<String> {
+    CLAMP_STATE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|s| s.mount_entries.as_ref())
+        .and_then(|m| m.get(&(cage_id, fd)))
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub fn set_mount_entries(cage_id: u64, fd: u64, names: Vec<String>) {
+    let mut state = CLAMP_STATE.lock().unwrap();
+    let s = state.as_mut().expect("CLAMP_STATE not initialized");
+
+    let entries = s.mount_entries.get_or_insert_with(HashMap::new);
+    if names.is_empty() {
+        entries.remove(&(cage_id, fd));
     } else {
-        let cwd = get_cage_cwd(current_cage);
-        normalize_path(&format!("{}/{}", cwd.trim_end_matches('/'), path))
+        entries.insert((cage_id, fd), names);
     }
 }
 
-pub fn resolve_path_from_cage(current_cage: u64, path_ptr: u64, path_cage: u64) -> Option<String> {
-    read_path_from_cage(path_ptr, path_cage).map(|path| resolve_path_for_cage(current_cage, &path))
+/// The perfdinfo recorded for an fd opened through `group`.
+pub fn group_perfdinfo(group: Option<usize>) -> u64 {
+    group.map_or(0, |g| g as u64 + 1)
 }
 
-/// Check whether a path matches the routing prefix condition.
-pub fn path_matches_prefix(path: &str) -> bool {
-    path.starts_with(&get_routing_prefix())
+/// The group an fd was opened through, from its perfdinfo.
+pub fn perfdinfo_group(perfdinfo: u64) -> Option<usize> {
+    perfdinfo.checked_sub(1).map(|g| g as usize)
 }
 
 /// Make a syscall via threei with the standard 6-arg pattern.
@@ -479,12 +848,19 @@ pub fn do_syscall(callingcage: u64, nr: u64, args: &[u64; 6], arg_cages: &[u64;
     }
 }
 
-/// Make a syscall via threei, using the saved clamp-entry cage as source.
-pub fn do_clamp_syscall(callingcage: u64, nr: u64, args: &[u64; 6], arg_cages: &[u64; 6]) -> i32 {
+/// Make a syscall via threei, using the clamp-entry cage saved for `group` as
+/// source.
+pub fn do_clamp_syscall(
+    group: usize,
+    callingcage: u64,
+    nr: u64,
+    args: &[u64; 6],
+    arg_cages: &[u64; 6],
+) -> i32 {
     match make_threei_call(
         nr as u32,
         0,
-        get_clamp_entry(),
+        get_clamp_entry(group),
         callingcage,
         args[0],
         arg_cages[0],
@@ -510,6 +886,7 @@ pub fn do_clamp_syscall(callingcage: u64, nr: u64, args: &[u64; 6], arg_cages: &
 struct ClampedMmapRange {
     start: u64,
     end: u64,
+    group: usize,
 }
 
 static CLAMPED_MMAPS: OnceLock<Mutex<HashMap<u64, Vec<ClampedMmapRange>>>> = OnceLock::new();
@@ -518,7 +895,7 @@ fn clamped_mmaps() -> &'static Mutex<HashMap<u64, Vec<ClampedMmapRange>>> {
     CLAMPED_MMAPS.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
-pub fn record_clamped_mmap(cageid: u64, addr: u64, len: u64) {
+pub fn record_clamped_mmap(cageid: u64, group: usize, addr: u64, len: u64) {
     if len == 0 {
         return;
     }
@@ -528,25 +905,27 @@ pub fn record_clamped_mmap(cageid: u64, addr: u64, len: u64) {
     };
 
     let mut maps = clamped_mmaps().lock().unwrap();
-    maps.entry(cageid)
-        .or_default()
-        .push(ClampedMmapRange { start: addr, end });
+    maps.entry(cageid).or_default().push(ClampedMmapRange {
+        start: addr,
+        end,
+        group,
+    });
 }
 
-pub fn is_clamped_mmap(cageid: u64, addr: u64, len: u64) -> bool {
+/// Return the group of a clamped mapping overlapping `addr..addr + len`.
+pub fn clamped_mmap_group(cageid: u64, addr: u64, len: u64) -> Option<usize> {
     if len == 0 {
-        return false;
+        return None;
     }
 
-    let Some(end) = addr.checked_add(len) else {
-        return false;
-    };
+    let end = addr.checked_add(len)?;
 
     let maps = clamped_mmaps().lock().unwrap();
 
-    maps.get(&cageid)
-        .map(|ranges| ranges.iter().any(|r| addr < r.end && end > r.start))
-        .unwrap_or(false)
+    maps.get(&cageid)?
+        .iter()
+        .find(|r| addr < r.end && end > r.start)
+        .map(|r| r.group)
 }
 
 pub fn remove_clamped_mmap(cageid: u64, addr: u64, len: u64) {
@@ -578,6 +957,7 @@ pub fn remove_clamped_mmap(cageid: u64, addr: u64, len: u64) {
             new_ranges.push(ClampedMmapRange {
                 start: r.start,
                 end: addr,
+                group: r.group,
             });
         }
 
@@ -586,6 +966,7 @@ pub fn remove_clamped_mmap(cageid: u64, addr: u64, len: u64) {
             new_ranges.push(ClampedMmapRange {
                 start: end,
                 end: r.end,
+                group: r.group,
             });
         }
     }
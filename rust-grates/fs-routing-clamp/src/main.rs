@@ -1,10 +1,11 @@
 //! FS Routing Clamp
 //!
-//! A meta-grate that selectively routes syscalls to clamped grates based on a
-//! path-prefix condition. This interposes on register_handler, exec, fork, and
+//! A meta-grate that selectively routes syscalls to clamped grates based on
+//! path-prefix conditions. This interposes on register_handler, exec, fork, and
 //! exit to dynamically build routing tables and conditionally dispatch syscalls.
 //!
-//! Usage: fs-routing-clamp --prefix /tmp %{ imfs-grate strace-grate %} python
+//! Usage: fs-routing-clamp --prefix /tmp [--prefix /var/cache] [--exclude /tmp/share]
+//!        %{ imfs-grate strace-grate %} [--prefix /secrets %{ encrypt-grate %}] python
 
 mod handlers;
 mod helpers;
@@ -18,10 +19,19 @@ use grate_rs::getcageid;
 use grate_rs::constants::mman::*;
 use grate_rs::ffi::*;
 
+#[derive(Debug)]
+struct GroupConfig {
+    /// Path prefixes routed to this group's stack (e.g., "/tmp"), normalized.
+    prefixes: Vec<String>,
+    /// Path prefixes carved out of `prefixes`, normalized.
+    excludes: Vec<String>,
+}
+
 struct NamespaceConfig {
-    /// The path prefix condition (e.g., "/tmp").
-    prefix: String,
-    /// The full exec chain: [clamped_grates..., "%}", unclamped_argv...].
+    /// One entry per `--prefix ... %{ ... %}` group, in command-line order.
+    groups: Vec<GroupConfig>,
+    /// The full exec chain: [group 0 grates..., "%}", group 1 grates..., "%}", ...,
+    /// unclamped_argv...].
     exec_chain: Vec<String>,
     /// Whether internal logging is enabled.
     log_enabled: bool,
@@ -29,68 +39,148 @@ struct NamespaceConfig {
 
 /// Parse argv into a NamespaceConfig.
 ///
-/// Expected syntax: fs-routing-clamp --prefix /tmp %{ imfs-grate strace-grate %} python
+/// Expected syntax: fs-routing-clamp --prefix /tmp --exclude /tmp/share
+///                  %{ imfs-grate strace-grate %}
+///                  --prefix /secrets %{ encrypt-grate %} python
 ///
 /// After parsing:
-///   prefix = "/tmp"
-///   exec_chain = ["imfs-grate", "strace-grate", "%}", "python"]
+///   groups = [{ prefixes: ["/tmp"], excludes: ["/tmp/share"] },
+///             { prefixes: ["/secrets"], excludes: [] }]
+///   exec_chain = ["imfs-grate", "strace-grate", "%}", "encrypt-grate", "%}", "python"]
+///
+/// `--prefix` and `--exclude` may each be given several times per group. A
+/// group's `%{ ... %}` block may contain nested blocks, which are kept as-is.
 fn parse_argv(args: Vec<String>) -> Result<NamespaceConfig, String> {
-    let mut prefix: Option<String> = None;
+    let mut groups: Vec<GroupConfig> = Vec::new();
+    let mut exec_chain: Vec<String> = Vec::new();
     let mut log_enabled = false;
     let mut i = 0;
 
-    // Parse options before %{
-    while i < args.len() {
-        match args[i].as_str() {
-            "--prefix" => {
-                i += 1;
-                if i >= args.len() {
-                    return Err("--prefix requires an argument".into());
+    loop {
+        let mut prefixes: Vec<String> = Vec::new();
+        let mut excludes: Vec<String> = Vec::new();
+        let mut opened = false;
+
+        // Parse the group's options before its %{
+        while i < args.len() {
+            match args[i].as_str() {
+                "--prefix" => {
+                    i += 1;
+                    if i >= args.len() {
+                        return Err("--prefix requires an argument".into());
+                    }
+                    prefixes.push(parse_prefix("--prefix", &args[i])?);
+                    i += 1;
                 }
-                prefix = Some(args[i].clone());
-                i += 1;
-            }
-            "--log" => {
-                log_enabled = true;
-                i += 1;
-            }
-            "%{" => {
-                i += 1;
-                break;
-            }
-            other => {
-                // Check for --prefix=value syntax
-                if let Some(val) = other.strip_prefix("--prefix=") {
-                    prefix = Some(val.to_string());
+                "--exclude" => {
                     i += 1;
-                } else {
-                    return Err(format!("unexpected argument: {}", other));
+                    if i >= args.len() {
+                        return Err("--exclude requires an argument".into());
+                    }
+                    excludes.push(parse_prefix("--exclude", &args[i])?);
+                    i += 1;
+                }
+                "--log" => {
+                    log_enabled = true;
+                    i += 1;
+                }
+                "%{" => {
+                    i += 1;
+                    opened = true;
+                    break;
+                }
+                other => {
+                    // Check for --prefix=value and --exclude=value syntax
+                    if let Some(val) = other.strip_prefix("--prefix=") {
+                        prefixes.push(parse_prefix("--prefix", val)?);
+                        i += 1;
+                    } else if let Some(val) = other.strip_prefix("--exclude=") {
+                        excludes.push(parse_prefix("--exclude", val)?);
+                        i += 1;
+                    } else {
+                        return Err(format!("unexpected argument: {}", other));
+                    }
                 }
             }
         }
-    }
 
-    let prefix = prefix.ok_or("--prefix is required")?;
+        if prefixes.is_empty() {
+            return Err("--prefix is required".into());
+        }
+
+        if !opened {
+            return Err("missing %{ ... %} block".into());
+        }
 
-    if i >= args.len() {
-        return Err("missing %{ ... %} block".into());
-    }
+        if let Some(prefix) = prefixes
+            .iter()
+            .find(|p| groups.iter().any(|g| g.prefixes.contains(p)))
+        {
+            return Err(format!("--prefix {} is given for more than one group", prefix));
+        }
 
-    // Everything from here is the exec chain (clamped args, %}, unclamped args).
-    // The exec chain is passed as-is to the first clamped grate.
-    let exec_chain: Vec<String> = args[i..].to_vec();
+        // Find the %} closing this group, passing over nested blocks.
+        let start = i;
+        let mut depth = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                "%{" => depth += 1,
+                "%}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        if i >= args.len() {
+            return Err("missing %} in command line".into());
+        }
 
-    if !exec_chain.contains(&"%}".to_string()) {
-        return Err("missing %} in command line".into());
+        exec_chain.extend_from_slice(&args[start..=i]);
+        groups.push(GroupConfig { prefixes, excludes });
+        i += 1;
+
+        // Another group follows if the next argument is one of its options.
+        let next_is_group = args.get(i).is_some_and(|arg| {
+            arg == "--prefix"
+                || arg == "--exclude"
+                || arg.starts_with("--prefix=")
+                || arg.starts_with("--exclude=")
+        });
+        if !next_is_group {
+            break;
+        }
+
+        // The %} rewrite execs the next argument directly, so only the last
+        // group may be empty.
+        if start == i - 1 {
+            return Err("empty %{ %} block before another group".into());
+        }
     }
 
+    // Everything after the last group is the unclamped program. The exec chain
+    // is passed as-is to the first clamped grate.
+    exec_chain.extend_from_slice(&args[i..]);
+
     Ok(NamespaceConfig {
-        prefix,
+        groups,
         exec_chain,
         log_enabled,
     })
 }
 
+/// Normalize the path given to `option`, which must be absolute.
+fn parse_prefix(option: &str, path: &str) -> Result<String, String> {
+    if !path.starts_with('/') {
+        return Err(format!("{} must be an absolute path", option));
+    }
+    Ok(helpers::normalize_path(path))
+}
+
 unsafe fn mmap_shared<T>() -> *mut T {
     let ptr = unsafe {
         mmap(
@@ -114,7 +204,7 @@ fn main() {
 
     if args.is_empty() {
         eprintln!(
-            "Usage: fs-routing-clamp [--log] --prefix <path> %{{ <grates...> %}} <program> [args...]"
+            "Usage: fs-routing-clamp [--log] --prefix <path> [--prefix <path>]... [--exclude <path>]... %{{ <grates...> %}} [--prefix <path>... [--exclude <path>]... %{{ <grates...> %}}]... <program> [args...]"
         );
         std::process::exit(1);
     }
@@ -127,15 +217,19 @@ fn main() {
         }
     };
 
-    let prefix = config.prefix;
+    let groups = config.groups;
     let exec_chain = config.exec_chain;
     let log_enabled = config.log_enabled;
 
-    log!("prefix={}, exec_chain={:?}", prefix, exec_chain);
+    log!("groups={:?}, exec_chain={:?}", groups, exec_chain);
 
     // Initialize global state.
     let ns_cage_id = getcageid();
-    helpers::init_globals(ns_cage_id, prefix, log_enabled);
+    let groups = groups
+        .into_iter()
+        .map(|g| helpers::ClampGroup::new(g.prefixes, g.excludes))
+        .collect();
+    helpers::init_globals(ns_cage_id, groups, log_enabled);
 
     // Prepare the exec chain as C strings.
     let cstrings: Vec<CString> = exec_chain
@@ -0,0 +1,216 @@
+/* cross_prefix_rename_test.c - cross-prefix rename/link for fs-routing-clamp.
+ *
+ * Routes /tmp to imfs-grate, so /tmp and the host filesystem behave like two
+ * mounts:
+ *   - rename, renameat and link/linkat with one path inside /tmp and the
+ *     other outside fail with EXDEV, in both directions and through dirfds;
+ *   - the same calls with both paths on one side still work;
+ *   - a mv-style move (rename, then copy+unlink on EXDEV) crosses the
+ *     boundary either way;
+ *   - mkdirat is routed like mkdir.
+ *
+ * Example invocation:
+ *      lind-boot fs-routing-clamp.cwasm --prefix /tmp
+ * %{ imfs-grate.cwasm %} cross_prefix_rename_test.cwasm
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <sys/stat.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+#define CHECK_ERRNO(desc, expr, err)                                           \
+	do {                                                                   \
+		errno = 0;                                                     \
+		CHECK(desc, (expr) == -1 && errno == (err));                   \
+	} while (0)
+
+static char host_a[64];
+static char host_b[64];
+
+static int write_file(const char *path, const char *data) {
+	int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+	if (fd < 0)
+		return -1;
+	ssize_t n = write(fd, data, strlen(data));
+	close(fd);
+	return n == (ssize_t)strlen(data) ? 0 : -1;
+}
+
+static int file_has(const char *path, const char *data) {
+	char buf[64] = {0};
+	int fd = open(path, O_RDONLY);
+	if (fd < 0)
+		return 0;
+	ssize_t n = read(fd, buf, sizeof(buf) - 1);
+	close(fd);
+	return n == (ssize_t)strlen(data) && strcmp(buf, data) == 0;
+}
+
+static int exists(const char *path) {
+	struct stat st;
+	return stat(path, &st) == 0;
+}
+
+/* Move a file the way mv(1) does: rename, and on EXDEV copy then unlink. */
+static int move_file(const char *from, const char *to) {
+	char buf[256];
+	ssize_t n;
+
+	if (rename(from, to) == 0)
+		return 0;
+	if (errno != EXDEV)
+		return -1;
+
+	int in = open(from, O_RDONLY);
+	if (in < 0)
+		return -1;
+	int out = open(to, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+	if (out < 0) {
+		close(in);
+		return -1;
+	}
+	while ((n = read(in, buf, sizeof(buf))) > 0) {
+		if (write(out, buf, n) != n) {
+			n = -1;
+			break;
+		}
+	}
+	close(in);
+	close(out);
+	if (n < 0)
+		return -1;
+	return unlink(from);
+}
+
+static void test_exdev(void) {
+	printf("\n[test_exdev]\n");
+
+	CHECK("create /tmp/xdev-a", write_file("/tmp/xdev-a", "inside") == 0);
+	CHECK("create host file", write_file(host_a, "outside") == 0);
+
+	CHECK_ERRNO("rename /tmp -> host fails with EXDEV",
+		    rename("/tmp/xdev-a", host_b), EXDEV);
+	CHECK_ERRNO("rename host -> /tmp fails with EXDEV",
+		    rename(host_a, "/tmp/xdev-b"), EXDEV);
+	CHECK_ERRNO("link /tmp -> host fails with EXDEV",
+		    link("/tmp/xdev-a", host_b), EXDEV);
+	CHECK_ERRNO("link host -> /tmp fails with EXDEV",
+		    link(host_a, "/tmp/xdev-b"), EXDEV);
+	CHECK_ERRNO("linkat /tmp -> host fails with EXDEV",
+		    linkat(AT_FDCWD, "/tmp/xdev-a", AT_FDCWD, host_b, 0),
+		    EXDEV);
+	CHECK_ERRNO("renameat host -> /tmp fails with EXDEV",
+		    renameat(AT_FDCWD, host_a, AT_FDCWD, "/tmp/xdev-b"), EXDEV);
+
+	int dirfd = open("/tmp", O_RDONLY | O_DIRECTORY);
+	CHECK("open /tmp as a directory", dirfd >= 0);
+	CHECK_ERRNO("renameat through a /tmp dirfd -> host fails with EXDEV",
+		    renameat(dirfd, "xdev-a", AT_FDCWD, host_b), EXDEV);
+	CHECK_ERRNO("renameat host -> through a /tmp dirfd fails with EXDEV",
+		    renameat(AT_FDCWD, host_a, dirfd, "xdev-b"), EXDEV);
+	close(dirfd);
+
+	CHECK("source in /tmp left in place", file_has("/tmp/xdev-a", "inside"));
+	CHECK("source on host left in place", file_has(host_a, "outside"));
+	CHECK("nothing created on host", !exists(host_b));
+	CHECK("nothing created in /tmp", !exists("/tmp/xdev-b"));
+}
+
+static void test_same_side(void) {
+	printf("\n[test_same_side]\n");
+
+	errno = 0;
+	CHECK("rename within /tmp",
+	      rename("/tmp/xdev-a", "/tmp/xdev-b") == 0 &&
+		      file_has("/tmp/xdev-b", "inside"));
+	errno = 0;
+	CHECK("renameat within /tmp",
+	      renameat(AT_FDCWD, "/tmp/xdev-b", AT_FDCWD, "/tmp/xdev-a") == 0 &&
+		      file_has("/tmp/xdev-a", "inside"));
+	errno = 0;
+	CHECK("rename within host",
+	      rename(host_a, host_b) == 0 && file_has(host_b, "outside"));
+	errno = 0;
+	CHECK("rename back within host", rename(host_b, host_a) == 0);
+
+	CHECK("unlink /tmp/xdev-a", unlink("/tmp/xdev-a") == 0);
+	CHECK("unlink host file", unlink(host_a) == 0);
+}
+
+static void test_mv_fallback(void) {
+	printf("\n[test_mv_fallback]\n");
+
+	CHECK("create /tmp/xdev-mv", write_file("/tmp/xdev-mv", "moved") == 0);
+
+	errno = 0;
+	CHECK("mv /tmp -> host", move_file("/tmp/xdev-mv", host_a) == 0);
+	CHECK("moved file is on host", file_has(host_a, "moved"));
+	CHECK("moved file is gone from /tmp", !exists("/tmp/xdev-mv"));
+
+	errno = 0;
+	CHECK("mv host -> /tmp", move_file(host_a, "/tmp/xdev-mv") == 0);
+	CHECK("moved file is in /tmp", file_has("/tmp/xdev-mv", "moved"));
+	CHECK("moved file is gone from host", !exists(host_a));
+
+	CHECK("unlink /tmp/xdev-mv", unlink("/tmp/xdev-mv") == 0);
+}
+
+static void test_mkdirat(void) {
+	printf("\n[test_mkdirat]\n");
+
+	errno = 0;
+	CHECK("mkdirat /tmp/xdev-dir",
+	      mkdirat(AT_FDCWD, "/tmp/xdev-dir", 0755) == 0);
+
+	int dirfd = open("/tmp", O_RDONLY | O_DIRECTORY);
+	CHECK("open /tmp as a directory", dirfd >= 0);
+	errno = 0;
+	CHECK("mkdirat through a /tmp dirfd",
+	      mkdirat(dirfd, "xdev-dir2", 0755) == 0);
+	close(dirfd);
+
+	CHECK("both directories are visible under /tmp",
+	      exists("/tmp/xdev-dir") && exists("/tmp/xdev-dir2"));
+	CHECK("rmdir /tmp/xdev-dir", rmdir("/tmp/xdev-dir") == 0);
+	CHECK("rmdir /tmp/xdev-dir2", rmdir("/tmp/xdev-dir2") == 0);
+}
+
+int main(int argc, char *argv[]) {
+	(void)argc;
+	(void)argv;
+
+	printf("=== fs-routing-clamp cross-prefix rename test ===\n");
+
+	snprintf(host_a, sizeof(host_a), "/xdev-host-a-%ld", (long)getpid());
+	snprintf(host_b, sizeof(host_b), "/xdev-host-b-%ld", (long)getpid());
+
+	/* imfs starts empty; /tmp itself is routed to it. */
+	errno = 0;
+	CHECK("mkdir /tmp", mkdir("/tmp", 0755) == 0 || errno == EEXIST);
+
+	test_exdev();
+	test_same_side();
+	test_mv_fallback();
+	test_mkdirat();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
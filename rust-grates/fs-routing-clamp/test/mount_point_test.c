@@ -0,0 +1,137 @@
+/* mount_point_test.c - mount point visibility for fs-routing-clamp.
+ *
+ * Routes /mount-point-test/workspace to imfs-grate. Neither
+ * /mount-point-test nor the workspace exists on the host or in imfs, yet
+ * they should behave like a mount point and the directory leading to it:
+ *   - listing / shows mount-point-test, exactly once;
+ *   - stat, lstat, fstatat, access and faccessat report both as directories;
+ *   - once /mount-point-test exists on the host, listing it shows workspace,
+ *     and listing / still shows mount-point-test only once;
+ *   - paths next to them are still missing.
+ *
+ * Example invocation:
+ *      lind-boot fs-routing-clamp.cwasm --prefix /mount-point-test/workspace
+ * %{ imfs-grate.cwasm %} mount_point_test.cwasm
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <dirent.h>
+#include <sys/stat.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+#define EXPECT_DIR(desc, path)                                                 \
+	do {                                                                   \
+		struct stat st;                                                \
+		errno = 0;                                                     \
+		CHECK(desc, stat(path, &st) == 0 && S_ISDIR(st.st_mode));      \
+	} while (0)
+
+#define EXPECT_MISSING(desc, path)                                             \
+	do {                                                                   \
+		struct stat st;                                                \
+		errno = 0;                                                     \
+		CHECK(desc, stat(path, &st) == -1 && errno == ENOENT);         \
+	} while (0)
+
+/* Number of times `name` appears in the listing of `dir`, or -1. */
+static int count_entries(const char *dir, const char *name) {
+	DIR *d = opendir(dir);
+	struct dirent *ent;
+	int count = 0;
+
+	if (d == NULL)
+		return -1;
+	while ((ent = readdir(d)) != NULL) {
+		if (strcmp(ent->d_name, name) == 0)
+			count++;
+	}
+	closedir(d);
+	return count;
+}
+
+static void test_missing_on_host(void) {
+	struct stat st;
+
+	printf("\n[test_missing_on_host]\n");
+
+	errno = 0;
+	CHECK("/ lists mount-point-test once",
+	      count_entries("/", "mount-point-test") == 1);
+
+	EXPECT_DIR("stat /mount-point-test", "/mount-point-test");
+	EXPECT_DIR("stat /mount-point-test/workspace",
+		   "/mount-point-test/workspace");
+
+	errno = 0;
+	CHECK("lstat /mount-point-test",
+	      lstat("/mount-point-test", &st) == 0 && S_ISDIR(st.st_mode));
+	errno = 0;
+	CHECK("fstatat /mount-point-test",
+	      fstatat(AT_FDCWD, "/mount-point-test", &st, 0) == 0 &&
+		      S_ISDIR(st.st_mode));
+	errno = 0;
+	CHECK("access /mount-point-test",
+	      access("/mount-point-test", R_OK | X_OK) == 0);
+	errno = 0;
+	CHECK("faccessat /mount-point-test/workspace",
+	      faccessat(AT_FDCWD, "/mount-point-test/workspace", F_OK, 0) == 0);
+
+	EXPECT_MISSING("stat /mount-point-test/other",
+		       "/mount-point-test/other");
+	EXPECT_MISSING("stat /mount-point-testx", "/mount-point-testx");
+	errno = 0;
+	CHECK("access /mount-point-testx",
+	      access("/mount-point-testx", F_OK) == -1 && errno == ENOENT);
+}
+
+static void test_present_on_host(void) {
+	printf("\n[test_present_on_host]\n");
+
+	errno = 0;
+	CHECK("mkdir /mount-point-test on host",
+	      mkdir("/mount-point-test", 0755) == 0);
+
+	errno = 0;
+	CHECK("/ still lists mount-point-test once",
+	      count_entries("/", "mount-point-test") == 1);
+	errno = 0;
+	CHECK("/mount-point-test lists workspace once",
+	      count_entries("/mount-point-test", "workspace") == 1);
+	EXPECT_MISSING("stat /mount-point-test/other",
+		       "/mount-point-test/other");
+
+	errno = 0;
+	CHECK("rmdir /mount-point-test on host",
+	      rmdir("/mount-point-test") == 0);
+}
+
+int main(int argc, char *argv[]) {
+	(void)argc;
+	(void)argv;
+
+	printf("=== fs-routing-clamp mount point test ===\n");
+
+	test_missing_on_host();
+	test_present_on_host();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
@@ -0,0 +1,147 @@
+/* multi_group_fork_test.c - fork across two stateful prefix groups.
+ *
+ * Routes /fork-a and /fork-b to two separate imfs-grate instances. Each
+ * instance keeps its own fd table, so both must see the fork to give the
+ * child its copy. The child:
+ *   - reads through fds it inherited from both stacks;
+ *   - opens, creates and writes files by path in both stacks;
+ * and the parent then sees the child's files in the right stack only.
+ *
+ * Example invocation:
+ *      lind-boot fs-routing-clamp.cwasm --prefix /fork-a
+ * %{ imfs-grate.cwasm %} --prefix /fork-b %{ imfs-grate.cwasm %}
+ * multi_group_fork_test.cwasm
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+static int write_file(const char *path, const char *data) {
+	int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+	if (fd < 0)
+		return -1;
+	ssize_t n = write(fd, data, strlen(data));
+	close(fd);
+	return n == (ssize_t)strlen(data) ? 0 : -1;
+}
+
+static int fd_has(int fd, const char *data) {
+	char buf[64] = {0};
+	if (lseek(fd, 0, SEEK_SET) != 0)
+		return 0;
+	ssize_t n = read(fd, buf, sizeof(buf) - 1);
+	return n == (ssize_t)strlen(data) && strcmp(buf, data) == 0;
+}
+
+static int file_has(const char *path, const char *data) {
+	int fd = open(path, O_RDONLY);
+	if (fd < 0)
+		return 0;
+	int ok = fd_has(fd, data);
+	close(fd);
+	return ok;
+}
+
+/* Runs in the child; the exit status reports whether every check passed. */
+static void child_checks(int fd_a, int fd_b) {
+	printf("\n[child]\n");
+
+	errno = 0;
+	CHECK("read inherited fd in the first stack", fd_has(fd_a, "alpha"));
+	errno = 0;
+	CHECK("read inherited fd in the second stack", fd_has(fd_b, "beta"));
+
+	errno = 0;
+	CHECK("open by path in the first stack",
+	      file_has("/fork-a/file", "alpha"));
+	errno = 0;
+	CHECK("open by path in the second stack",
+	      file_has("/fork-b/file", "beta"));
+
+	errno = 0;
+	CHECK("create in the first stack",
+	      write_file("/fork-a/child", "child-a") == 0);
+	errno = 0;
+	CHECK("create in the second stack",
+	      write_file("/fork-b/child", "child-b") == 0);
+
+	CHECK("close inherited fd in the first stack", close(fd_a) == 0);
+	CHECK("close inherited fd in the second stack", close(fd_b) == 0);
+
+	fflush(stdout);
+	_exit(tests_passed == tests_run ? 0 : 1);
+}
+
+int main(int argc, char *argv[]) {
+	int status = -1;
+
+	(void)argc;
+	(void)argv;
+
+	printf("=== fs-routing-clamp multi-group fork test ===\n");
+
+	/* Both imfs instances start empty; the prefixes are routed to them. */
+	errno = 0;
+	CHECK("mkdir /fork-a", mkdir("/fork-a", 0755) == 0 || errno == EEXIST);
+	errno = 0;
+	CHECK("mkdir /fork-b", mkdir("/fork-b", 0755) == 0 || errno == EEXIST);
+	CHECK("create /fork-a/file", write_file("/fork-a/file", "alpha") == 0);
+	CHECK("create /fork-b/file", write_file("/fork-b/file", "beta") == 0);
+
+	int fd_a = open("/fork-a/file", O_RDONLY);
+	int fd_b = open("/fork-b/file", O_RDONLY);
+	CHECK("open /fork-a/file", fd_a >= 0);
+	CHECK("open /fork-b/file", fd_b >= 0);
+
+	fflush(stdout);
+	pid_t pid = fork();
+	if (pid == 0)
+		child_checks(fd_a, fd_b);
+
+	printf("\n[parent]\n");
+	CHECK("fork", pid > 0);
+	CHECK("waitpid", pid > 0 && waitpid(pid, &status, 0) == pid);
+	CHECK("child passed its checks",
+	      WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	errno = 0;
+	CHECK("parent fd in the first stack still reads",
+	      fd_has(fd_a, "alpha"));
+	errno = 0;
+	CHECK("parent fd in the second stack still reads",
+	      fd_has(fd_b, "beta"));
+	CHECK("child's file is in the first stack",
+	      file_has("/fork-a/child", "child-a"));
+	CHECK("child's file is in the second stack",
+	      file_has("/fork-b/child", "child-b"));
+
+	close(fd_a);
+	close(fd_b);
+	CHECK("unlink /fork-a/child", unlink("/fork-a/child") == 0);
+	CHECK("unlink /fork-b/child", unlink("/fork-b/child") == 0);
+	CHECK("unlink /fork-a/file", unlink("/fork-a/file") == 0);
+	CHECK("unlink /fork-b/file", unlink("/fork-b/file") == 0);
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
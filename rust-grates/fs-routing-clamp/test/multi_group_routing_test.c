@@ -0,0 +1,120 @@
+/* multi_group_routing_test.c - prefix groups for fs-routing-clamp.
+ *
+ * Routes two prefix groups to two different clamped stacks. Each stack stubs
+ * stat(2) and open(2) with its own negative errno, so we can tell which stack
+ * a path reached:
+ *   - /tmp goes to the first stack (errno 167);
+ *   - /secrets and /tmp/secrets go to the second stack (errno 166), except
+ *     /secrets/public, which is excluded from it;
+ *   - everything else is not routed.
+ *
+ * Example invocation:
+ *      lind-boot fs-routing-clamp.cwasm --prefix /tmp
+ * %{ testing-grate.cwasm -s 2:-167,4:-167 %} --prefix /secrets
+ * --prefix /tmp/secrets --exclude /secrets/public
+ * %{ testing-grate.cwasm -s 2:-166,4:-166 %} multi_group_routing_test.cwasm
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <sys/stat.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define FIRST_ERRNO 167
+#define SECOND_ERRNO 166
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+#define EXPECT_ROUTED(desc, path, err)                                         \
+	do {                                                                   \
+		struct stat st;                                                \
+		errno = 0;                                                     \
+		CHECK(desc, stat(path, &st) == -1 && errno == (err));          \
+	} while (0)
+
+#define EXPECT_NOT_ROUTED(desc, path)                                          \
+	do {                                                                   \
+		struct stat st;                                                \
+		errno = 0;                                                     \
+		CHECK(desc, stat(path, &st) == 0 ||                            \
+				    (errno != FIRST_ERRNO &&                   \
+				     errno != SECOND_ERRNO));                  \
+	} while (0)
+
+static void test_first_group(void) {
+	printf("\n[test_first_group]\n");
+
+	EXPECT_ROUTED("stat /tmp routed to first stack", "/tmp", FIRST_ERRNO);
+	EXPECT_ROUTED("stat /tmp/a routed to first stack", "/tmp/a",
+		      FIRST_ERRNO);
+
+	errno = 0;
+	CHECK("open /tmp/file routed to first stack",
+	      open("/tmp/file", O_CREAT | O_RDWR, 0644) == -1 &&
+		      errno == FIRST_ERRNO);
+}
+
+static void test_second_group(void) {
+	printf("\n[test_second_group]\n");
+
+	EXPECT_ROUTED("stat /secrets routed to second stack", "/secrets",
+		      SECOND_ERRNO);
+	EXPECT_ROUTED("stat /secrets/key routed to second stack",
+		      "/secrets/key", SECOND_ERRNO);
+
+	errno = 0;
+	CHECK("open /secrets/key routed to second stack",
+	      open("/secrets/key", O_CREAT | O_RDWR, 0644) == -1 &&
+		      errno == SECOND_ERRNO);
+}
+
+static void test_longest_prefix(void) {
+	printf("\n[test_longest_prefix]\n");
+
+	EXPECT_ROUTED("stat /tmp/secrets routed to second stack",
+		      "/tmp/secrets", SECOND_ERRNO);
+	EXPECT_ROUTED("stat /tmp/secrets/key routed to second stack",
+		      "/tmp/secrets/key", SECOND_ERRNO);
+	EXPECT_ROUTED("stat /tmp/secretsx routed to first stack",
+		      "/tmp/secretsx", FIRST_ERRNO);
+}
+
+static void test_unrouted(void) {
+	printf("\n[test_unrouted]\n");
+
+	EXPECT_NOT_ROUTED("stat /secrets/public not routed", "/secrets/public");
+	EXPECT_NOT_ROUTED("stat /secrets/public/file not routed",
+			  "/secrets/public/file");
+	EXPECT_NOT_ROUTED("stat /secretsx not routed", "/secretsx");
+	EXPECT_NOT_ROUTED("stat / not routed", "/");
+}
+
+int main(int argc, char *argv[]) {
+	(void)argc;
+	(void)argv;
+
+	printf("=== fs-routing-clamp prefix group test ===\n");
+
+	test_first_group();
+	test_second_group();
+	test_longest_prefix();
+	test_unrouted();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
@@ -0,0 +1,158 @@
+/* namespace_symlink_test.c - symlinks into a prefix routed to a per-cage
+ * namespace.
+ *
+ * Routes /ns-ws to imfs-grate with --namespaces isolated, so a forked child
+ * gets an empty tree of its own. A host symlink into /ns-ws is resolved by
+ * fs-routing-clamp, which hands imfs the path it leads to. imfs must still
+ * see the call as coming from the child, so stat, readlink, mkdir and unlink
+ * through the link act on the child's tree and never the parent's.
+ *
+ * Example invocation:
+ *      lind-boot fs-routing-clamp.cwasm --prefix /ns-ws
+ * %{ imfs-grate.cwasm --namespaces isolated %} namespace_symlink_test.cwasm
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+#define CHECK_ERRNO(desc, expr, err)                                           \
+	do {                                                                   \
+		errno = 0;                                                     \
+		CHECK(desc, (expr) == -1 && errno == (err));                   \
+	} while (0)
+
+#define PARENT_DATA "parent-data"
+#define CHILD_DATA "child"
+
+static char host_link[64];
+static char link_file[96];
+static char link_inner[96];
+static char link_sub[96];
+
+static int write_file(const char *path, const char *data) {
+	int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+	if (fd < 0)
+		return -1;
+	ssize_t n = write(fd, data, strlen(data));
+	close(fd);
+	return n == (ssize_t)strlen(data) ? 0 : -1;
+}
+
+static int size_is(const char *path, const char *data) {
+	struct stat st;
+	return stat(path, &st) == 0 && st.st_size == (off_t)strlen(data);
+}
+
+static int link_is(const char *path, const char *target) {
+	char buf[PATH_MAX] = {0};
+	ssize_t n = readlink(path, buf, sizeof(buf) - 1);
+	return n == (ssize_t)strlen(target) && strcmp(buf, target) == 0;
+}
+
+/* Runs in the child; the exit status reports whether every check passed. */
+static void child_checks(void) {
+	printf("\n[child]\n");
+
+	CHECK_ERRNO("parent's file is not in the child's tree",
+		    access("/ns-ws/dir/file", F_OK), ENOENT);
+	CHECK_ERRNO("access through the link before the child creates the file",
+		    access(link_file, F_OK), ENOENT);
+
+	errno = 0;
+	CHECK("mkdir /ns-ws in the child's tree",
+	      mkdir("/ns-ws", 0755) == 0 || errno == EEXIST);
+	CHECK("mkdir /ns-ws/dir", mkdir("/ns-ws/dir", 0755) == 0);
+	CHECK("create /ns-ws/dir/file",
+	      write_file("/ns-ws/dir/file", CHILD_DATA) == 0);
+	CHECK("symlink /ns-ws/dir/inner", symlink("file", "/ns-ws/dir/inner") == 0);
+
+	errno = 0;
+	CHECK("stat through the link sees the child's file",
+	      size_is(link_file, CHILD_DATA));
+	CHECK("readlink through the link reads the child's link",
+	      link_is(link_inner, "file"));
+	CHECK("mkdir through the link", mkdir(link_sub, 0755) == 0);
+	CHECK("the new directory is in the child's tree",
+	      access("/ns-ws/dir/sub", F_OK) == 0);
+	CHECK("rmdir through the link", rmdir(link_sub) == 0);
+	CHECK("unlink the child's link through the link",
+	      unlink(link_inner) == 0);
+	CHECK("unlink the child's file through the link",
+	      unlink(link_file) == 0);
+	CHECK_ERRNO("the child's file is gone",
+		    access("/ns-ws/dir/file", F_OK), ENOENT);
+
+	fflush(stdout);
+	_exit(tests_passed == tests_run ? 0 : 1);
+}
+
+int main(int argc, char *argv[]) {
+	int status = -1;
+
+	(void)argc;
+	(void)argv;
+
+	printf("=== fs-routing-clamp namespace symlink test ===\n");
+
+	snprintf(host_link, sizeof(host_link), "/ns-host-link-%ld",
+		 (long)getpid());
+	snprintf(link_file, sizeof(link_file), "%s/file", host_link);
+	snprintf(link_inner, sizeof(link_inner), "%s/inner", host_link);
+	snprintf(link_sub, sizeof(link_sub), "%s/sub", host_link);
+
+	errno = 0;
+	CHECK("mkdir /ns-ws", mkdir("/ns-ws", 0755) == 0 || errno == EEXIST);
+	CHECK("mkdir /ns-ws/dir", mkdir("/ns-ws/dir", 0755) == 0);
+	CHECK("create /ns-ws/dir/file",
+	      write_file("/ns-ws/dir/file", PARENT_DATA) == 0);
+	CHECK("symlink on host -> /ns-ws/dir",
+	      symlink("/ns-ws/dir", host_link) == 0);
+	CHECK("stat through the link sees the parent's file",
+	      size_is(link_file, PARENT_DATA));
+
+	fflush(stdout);
+	pid_t pid = fork();
+	if (pid == 0)
+		child_checks();
+
+	printf("\n[parent]\n");
+	CHECK("fork", pid > 0);
+	CHECK("waitpid", pid > 0 && waitpid(pid, &status, 0) == pid);
+	CHECK("child passed its checks",
+	      WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	errno = 0;
+	CHECK("the parent's file is untouched",
+	      size_is(link_file, PARENT_DATA));
+	CHECK_ERRNO("the child's directory is not in the parent's tree",
+		    access("/ns-ws/dir/sub", F_OK), ENOENT);
+
+	CHECK("unlink the parent's file through the link",
+	      unlink(link_file) == 0);
+	CHECK("unlink host link", unlink(host_link) == 0);
+	CHECK("rmdir /ns-ws/dir", rmdir("/ns-ws/dir") == 0);
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
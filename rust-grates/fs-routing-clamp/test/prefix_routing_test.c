@@ -0,0 +1,106 @@
+/* prefix_routing_test.c - prefix matching tests for fs-routing-clamp.
+ *
+ * Routes two prefixes to one clamp and carves a subtree out of one of them.
+ * Clamped stat(2) and open(2) are stubbed to a fixed negative errno, so we
+ * only verify which paths reach the clamp:
+ *   - /tmp and /var/cache, and everything below them, are routed;
+ *   - /tmp/host-share and everything below it is not;
+ *   - names that merely start with a prefix (/tmpfiles, /var/cachex,
+ *     /tmp/host-shared) are matched on whole components.
+ *
+ * Example invocation:
+ *      lind-boot fs-routing-clamp.cwasm --prefix /tmp --prefix /var/cache
+ * --exclude /tmp/host-share %{ testing-grate.cwasm -s 2:-167,4:-167 %}
+ * prefix_routing_test.cwasm
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <sys/stat.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CLAMP_ERRNO 167
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+#define EXPECT_CLAMPED(desc, path)                                             \
+	do {                                                                   \
+		struct stat st;                                                \
+		errno = 0;                                                     \
+		CHECK(desc, stat(path, &st) == -1 && errno == CLAMP_ERRNO);    \
+	} while (0)
+
+#define EXPECT_NOT_CLAMPED(desc, path)                                         \
+	do {                                                                   \
+		struct stat st;                                                \
+		errno = 0;                                                     \
+		CHECK(desc, stat(path, &st) == 0 || errno != CLAMP_ERRNO);     \
+	} while (0)
+
+static void test_multiple_prefixes(void) {
+	printf("\n[test_multiple_prefixes]\n");
+
+	EXPECT_CLAMPED("stat /tmp routed", "/tmp");
+	EXPECT_CLAMPED("stat /tmp/a routed", "/tmp/a");
+	EXPECT_CLAMPED("stat /var/cache routed", "/var/cache");
+	EXPECT_CLAMPED("stat /var/cache/pkg/index routed",
+		       "/var/cache/pkg/index");
+
+	errno = 0;
+	CHECK("open /var/cache/file routed",
+	      open("/var/cache/file", O_CREAT | O_RDWR, 0644) == -1 &&
+		      errno == CLAMP_ERRNO);
+}
+
+static void test_component_boundaries(void) {
+	printf("\n[test_component_boundaries]\n");
+
+	EXPECT_NOT_CLAMPED("stat /tmpfiles not routed", "/tmpfiles");
+	EXPECT_NOT_CLAMPED("stat /tmp2/a not routed", "/tmp2/a");
+	EXPECT_NOT_CLAMPED("stat /var/cachex not routed", "/var/cachex");
+	EXPECT_NOT_CLAMPED("stat /var not routed", "/var");
+	EXPECT_CLAMPED("stat /tmp/../tmp/a routed after normalization",
+		       "/tmp/../tmp/a");
+	EXPECT_NOT_CLAMPED("stat /tmp/../etc not routed after normalization",
+			   "/tmp/../etc");
+}
+
+static void test_exclusions(void) {
+	printf("\n[test_exclusions]\n");
+
+	EXPECT_NOT_CLAMPED("stat /tmp/host-share not routed",
+			   "/tmp/host-share");
+	EXPECT_NOT_CLAMPED("stat /tmp/host-share/file not routed",
+			   "/tmp/host-share/file");
+	EXPECT_CLAMPED("stat /tmp/host-shared routed", "/tmp/host-shared");
+	EXPECT_CLAMPED("stat /tmp/host routed", "/tmp/host");
+}
+
+int main(int argc, char *argv[]) {
+	(void)argc;
+	(void)argv;
+
+	printf("=== fs-routing-clamp prefix matching test ===\n");
+
+	test_multiple_prefixes();
+	test_component_boundaries();
+	test_exclusions();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
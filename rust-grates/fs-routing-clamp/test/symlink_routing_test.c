@@ -0,0 +1,198 @@
+/* symlink_routing_test.c - symlink-following routing for fs-routing-clamp.
+ *
+ * Routes /workspace to imfs-grate. Symlinks are resolved before routing, and
+ * the path a link leads to decides where the call goes:
+ *   - a host link into /workspace reaches imfs, for reads, creates and chdir;
+ *   - a link stored in imfs that points at the host reaches the host, and a
+ *     relative one stays in imfs;
+ *   - calls that do not follow a final link (lstat, readlink, unlink) act on
+ *     the link itself, in whichever layer holds it;
+ *   - a rename through a link into /workspace fails with EXDEV;
+ *   - a link loop fails with ELOOP.
+ *
+ * Example invocation:
+ *      lind-boot fs-routing-clamp.cwasm --prefix /workspace
+ * %{ imfs-grate.cwasm %} symlink_routing_test.cwasm
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <sys/stat.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+#define CHECK_ERRNO(desc, expr, err)                                           \
+	do {                                                                   \
+		errno = 0;                                                     \
+		CHECK(desc, (expr) == -1 && errno == (err));                   \
+	} while (0)
+
+static char host_link[64];
+static char host_file[64];
+static char host_link_file[96];
+static char host_link_new[96];
+
+static int write_file(const char *path, const char *data) {
+	int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+	if (fd < 0)
+		return -1;
+	ssize_t n = write(fd, data, strlen(data));
+	close(fd);
+	return n == (ssize_t)strlen(data) ? 0 : -1;
+}
+
+static int file_has(const char *path, const char *data) {
+	char buf[64] = {0};
+	int fd = open(path, O_RDONLY);
+	if (fd < 0)
+		return 0;
+	ssize_t n = read(fd, buf, sizeof(buf) - 1);
+	close(fd);
+	return n == (ssize_t)strlen(data) && strcmp(buf, data) == 0;
+}
+
+static int is_symlink(const char *path) {
+	struct stat st;
+	return lstat(path, &st) == 0 && S_ISLNK(st.st_mode);
+}
+
+static int link_is(const char *path, const char *target) {
+	char buf[PATH_MAX] = {0};
+	ssize_t n = readlink(path, buf, sizeof(buf) - 1);
+	return n == (ssize_t)strlen(target) && strcmp(buf, target) == 0;
+}
+
+static void test_host_link_into_prefix(void) {
+	struct stat st;
+	char cwd[PATH_MAX];
+
+	printf("\n[test_host_link_into_prefix]\n");
+
+	errno = 0;
+	CHECK("symlink on host -> /workspace/proj",
+	      symlink("/workspace/proj", host_link) == 0);
+	CHECK("lstat sees the host link", is_symlink(host_link));
+	CHECK("readlink reads the host link",
+	      link_is(host_link, "/workspace/proj"));
+
+	errno = 0;
+	CHECK("stat through the link finds the imfs directory",
+	      stat(host_link, &st) == 0 && S_ISDIR(st.st_mode));
+	CHECK("read through the link reaches imfs",
+	      file_has(host_link_file, "inside"));
+
+	errno = 0;
+	CHECK("create through the link",
+	      write_file(host_link_new, "created") == 0);
+	CHECK("created file is in imfs",
+	      file_has("/workspace/proj/new", "created"));
+
+	errno = 0;
+	CHECK("chdir through the link", chdir(host_link) == 0);
+	CHECK("getcwd reports the path in /workspace",
+	      getcwd(cwd, sizeof(cwd)) != NULL &&
+		      strcmp(cwd, "/workspace/proj") == 0);
+	CHECK("relative read after chdir reaches imfs",
+	      file_has("file", "inside"));
+	CHECK("chdir back to /", chdir("/") == 0);
+
+	CHECK_ERRNO("rename through the link to the host fails with EXDEV",
+		    rename(host_link_file, host_file), EXDEV);
+	CHECK("unlink through the link removes the imfs file",
+	      unlink(host_link_new) == 0 && access("/workspace/proj/new", F_OK) != 0);
+}
+
+static void test_imfs_link_out_of_prefix(void) {
+	printf("\n[test_imfs_link_out_of_prefix]\n");
+
+	CHECK("create host file", write_file(host_file, "outside") == 0);
+
+	errno = 0;
+	CHECK("symlink in imfs -> host file",
+	      symlink(host_file, "/workspace/out") == 0);
+	CHECK("lstat sees the imfs link", is_symlink("/workspace/out"));
+	CHECK("readlink reads the imfs link",
+	      link_is("/workspace/out", host_file));
+	CHECK("read through the imfs link reaches the host",
+	      file_has("/workspace/out", "outside"));
+
+	errno = 0;
+	CHECK("relative symlink in imfs",
+	      symlink("proj/file", "/workspace/rel") == 0);
+	CHECK("read through the relative link stays in imfs",
+	      file_has("/workspace/rel", "inside"));
+
+	errno = 0;
+	CHECK("unlink removes the imfs link, not its target",
+	      unlink("/workspace/out") == 0 && !is_symlink("/workspace/out") &&
+		      file_has(host_file, "outside"));
+	CHECK("unlink the relative link", unlink("/workspace/rel") == 0);
+}
+
+static void test_loop(void) {
+	struct stat st;
+
+	printf("\n[test_loop]\n");
+
+	errno = 0;
+	CHECK("symlink loop in imfs",
+	      symlink("/workspace/loop", "/workspace/loop") == 0);
+	CHECK_ERRNO("stat on the loop fails with ELOOP",
+		    stat("/workspace/loop", &st), ELOOP);
+	CHECK_ERRNO("open on the loop fails with ELOOP",
+		    open("/workspace/loop", O_RDONLY), ELOOP);
+	CHECK("lstat on the loop sees the link", is_symlink("/workspace/loop"));
+	CHECK("unlink the loop", unlink("/workspace/loop") == 0);
+}
+
+int main(int argc, char *argv[]) {
+	(void)argc;
+	(void)argv;
+
+	printf("=== fs-routing-clamp symlink routing test ===\n");
+
+	snprintf(host_link, sizeof(host_link), "/sl-host-link-%ld",
+		 (long)getpid());
+	snprintf(host_file, sizeof(host_file), "/sl-host-file-%ld",
+		 (long)getpid());
+	snprintf(host_link_file, sizeof(host_link_file), "%s/file", host_link);
+	snprintf(host_link_new, sizeof(host_link_new), "%s/new", host_link);
+
+	/* imfs starts empty; /workspace itself is routed to it. */
+	errno = 0;
+	CHECK("mkdir /workspace",
+	      mkdir("/workspace", 0755) == 0 || errno == EEXIST);
+	CHECK("mkdir /workspace/proj", mkdir("/workspace/proj", 0755) == 0);
+	CHECK("create /workspace/proj/file",
+	      write_file("/workspace/proj/file", "inside") == 0);
+
+	test_host_link_into_prefix();
+	test_imfs_link_out_of_prefix();
+	test_loop();
+
+	CHECK("unlink host link", unlink(host_link) == 0);
+	CHECK("unlink host file", unlink(host_file) == 0);
+	CHECK("unlink /workspace/proj/file",
+	      unlink("/workspace/proj/file") == 0);
+	CHECK("rmdir /workspace/proj", rmdir("/workspace/proj") == 0);
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
@@ -66,6 +66,16 @@ fn copy_iovecs_from_cage(iov_ptr: u64, iov_cage: u64, iovcnt: usize) -> Result<V
     }
 }
 
+/// fds 0-2 are passed through to the host unless the cage has replaced them
+/// with an IMFS pipe (e.g. via dup2 when setting up a shell pipeline) or
+/// device (e.g. redirecting to /dev/null).
+fn is_host_stdio(cage_id: u64, fd: u64) -> bool {
+    fd < 3
+        && !imfs::with_imfs(|state| {
+            state.is_pipe_fd(cage_id, fd) || state.is_device_fd(cage_id, fd)
+        })
+}
+
 fn total_iovec_len(iovecs: &[iovec]) -> Result<usize, i32> {
     iovecs.iter().try_fold(0usize, |acc, iov| {
         let len = usize::try_from(iov.iov_len).map_err(|_| -22)?;
@@ -73,24 +83,6 @@ fn total_iovec_len(iovecs: &[iovec]) -> Result<usize, i32> {
     })
 }
 
-pub extern "C" fn enosys_handler(
-    _cageid: u64,
-    _arg1: u64,
-    _arg1cage: u64,
-    _arg2: u64,
-    _arg2cage: u64,
-    _arg3: u64,
-    _arg3cage: u64,
-    _arg4: u64,
-    _arg4cage: u64,
-    _arg5: u64,
-    _arg5cage: u64,
-    _arg6: u64,
-    _arg6cage: u64,
-) -> i32 {
-    -38 // ENOSYS
-}
-
 // =====================================================================
 //  open (syscall 2)
 //
@@ -130,7 +122,7 @@ pub extern "C" fn open_handler(
     let flags = arg2 as i32;
     let mode = arg3 as u32;
 
-    imfs::with_imfs(|state| state.open(cage_id, &pathname, flags, mode))
+    imfs::pipe::blocking_open(cage_id, |state| state.open(cage_id, &pathname, flags, mode))
 }
 
 pub extern "C" fn openat_handler(
@@ -159,7 +151,9 @@ pub extern "C" fn openat_handler(
     let flags = arg3 as i32;
     let mode = arg4 as u32;
 
-    imfs::with_imfs(|state| state.openat(cage_id, dirfd, &pathname, flags, mode))
+    imfs::pipe::blocking_open(cage_id, |state| {
+        state.openat(cage_id, dirfd, &pathname, flags, mode)
+    })
 }
 
 pub extern "C" fn getcwd_handler(
@@ -369,7 +363,7 @@ pub extern "C" fn read_handler(
     // Allocate a local buffer, read into it, then copy to cage.
     let mut buf = vec![0u8; count];
 
-    let ret = imfs::with_imfs(|state| state.read(cage_id, fd, &mut buf));
+    let ret = imfs::pipe::blocking_read(cage_id, fd, &mut buf);
 
     // Copy result to the cage's buffer (if buf ptr is non-null and read succeeded).
     if ret > 0 && arg2 != 0 {
@@ -431,7 +425,7 @@ pub extern "C" fn write_handler(
     );
 
     // Special case: fd 0/1/2 (stdin/stdout/stderr) pass through to real write.
-    if fd < 3 {
+    if is_host_stdio(cage_id, fd) {
         // Write directly to the real fd.
         unsafe {
             let ret = libc::write(fd as i32, buf.as_ptr() as *const _, count);
@@ -439,7 +433,7 @@ pub extern "C" fn write_handler(
         }
     }
 
-    imfs::with_imfs(|state| state.write(cage_id, fd, &buf))
+    imfs::pipe::blocking_write(cage_id, fd, &buf)
 }
 
 // =====================================================================
@@ -484,7 +478,7 @@ pub extern "C" fn fcntl_handler(
     arg2: u64,
     _arg2cage: u64,
     arg3: u64,
-    _arg3cage: u64,
+    arg3cage: u64,
     _arg4: u64,
     _arg4cage: u64,
     _arg5: u64,
@@ -495,7 +489,103 @@ pub extern "C" fn fcntl_handler(
     let op = arg2 as i32;
     let arg = arg3 as i32;
 
-    imfs::with_imfs(|state| state.fcntl(arg1cage, arg1, op, arg))
+    match op {
+        imfs::lock::F_GETLK | imfs::lock::F_SETLK | imfs::lock::F_SETLKW => {
+            fcntl_lock::<imfs::lock::Flock32>(arg1cage, arg1, op, arg3, arg3cage)
+        }
+        imfs::lock::F_GETLK64 => {
+            fcntl_lock::<imfs::lock::Flock>(arg1cage, arg1, imfs::lock::F_GETLK, arg3, arg3cage)
+        }
+        imfs::lock::F_SETLK64 => {
+            fcntl_lock::<imfs::lock::Flock>(arg1cage, arg1, imfs::lock::F_SETLK, arg3, arg3cage)
+        }
+        imfs::lock::F_SETLKW64 => {
+            fcntl_lock::<imfs::lock::Flock>(arg1cage, arg1, imfs::lock::F_SETLKW, arg3, arg3cage)
+        }
+        _ => imfs::with_imfs(|state| state.fcntl(arg1cage, arg1, op, arg)),
+    }
+}
+
+/// fcntl record-lock ops: arg3 points to a struct flock (layout T) in
+/// arg3cage, which F_GETLK fills in.
+fn fcntl_lock<T>(cage_id: u64, fd: u64, op: i32, flock_ptr: u64, flock_cage: u64) -> i32
+where
+    T: Copy + Default + Into<imfs::lock::Flock> + TryFrom<imfs::lock::Flock>,
+{
+    if flock_ptr == 0 {
+        return -14; // EFAULT
+    }
+
+    let this_cage = getcageid();
+    let mut guest_fl = T::default();
+    if copy_data_between_cages(
+        this_cage,
+        flock_cage,
+        flock_ptr,
+        flock_cage,
+        &mut guest_fl as *mut T as u64,
+        this_cage,
+        std::mem::size_of::<T>() as u64,
+        0,
+    )
+    .is_err()
+    {
+        return -14; // EFAULT
+    }
+
+    let mut fl: imfs::lock::Flock = guest_fl.into();
+    let ret = match op {
+        imfs::lock::F_SETLKW => imfs::lock::blocking_setlkw(cage_id, fd, &mut fl),
+        _ => imfs::with_imfs(|state| state.fcntl_lock(cage_id, fd, op, &mut fl)),
+    };
+
+    if ret == 0 && op == imfs::lock::F_GETLK {
+        guest_fl = match T::try_from(fl) {
+            Ok(guest_fl) => guest_fl,
+            Err(_) => return -75, // EOVERFLOW
+        };
+        if copy_data_between_cages(
+            this_cage,
+            flock_cage,
+            &guest_fl as *const T as u64,
+            this_cage,
+            flock_ptr,
+            flock_cage,
+            std::mem::size_of::<T>() as u64,
+            0,
+        )
+        .is_err()
+        {
+            return -14; // EFAULT
+        }
+    }
+
+    ret
+}
+
+// =====================================================================
+//  flock (syscall 73)
+//
+//  arg1 = fd, arg1cage = cage_id
+//  arg2 = operation (LOCK_SH/LOCK_EX/LOCK_UN, optionally | LOCK_NB)
+// =====================================================================
+
+pub extern "C" fn flock_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    _arg2cage: u64,
+    _arg3: u64,
+    _arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    imfs::lock::blocking_flock(arg1cage, arg1, arg2 as i32)
 }
 
 // =====================================================================
@@ -729,6 +819,24 @@ pub extern "C" fn ftruncate_handler(
     imfs::with_imfs(|state| state.ftruncate(arg1cage, arg1, arg2 as i64))
 }
 
+pub extern "C" fn fallocate_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    _arg2cage: u64,
+    arg3: u64,
+    _arg3cage: u64,
+    arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    imfs::with_imfs(|state| state.fallocate(arg1cage, arg1, arg2 as i32, arg3 as i64, arg4 as i64))
+}
+
 pub extern "C" fn stat_handler(
     _cageid: u64,
     arg1: u64,
@@ -871,6 +979,78 @@ pub extern "C" fn fstatat_handler(
     ret
 }
 
+// =====================================================================
+//  statx (syscall 332)
+//
+//  arg1 = dirfd, arg1cage = cage_id
+//  arg2 = pathname ptr (may be NULL with AT_EMPTY_PATH)
+//  arg3 = flags, arg4 = mask, arg5 = struct statx ptr
+// =====================================================================
+
+pub extern "C" fn statx_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    _arg3cage: u64,
+    arg4: u64,
+    _arg4cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    if arg5 == 0 {
+        return -14;
+    }
+
+    let flags = arg3 as i32;
+    let pathname = if arg2 == 0 && flags & LIND_AT_EMPTY_PATH != 0 {
+        String::new()
+    } else {
+        match copy_path_from_cage(arg2, arg2cage) {
+            Some(p) => p,
+            None => return -14,
+        }
+    };
+
+    let mut statxbuf = imfs::Statx::default();
+    let ret = imfs::with_imfs(|state| {
+        state.statx(
+            arg1cage,
+            arg1 as i32,
+            &pathname,
+            flags,
+            arg4 as u32,
+            &mut statxbuf,
+        )
+    });
+
+    if ret < 0 {
+        return ret;
+    }
+
+    let this_cage = getcageid();
+    if copy_data_between_cages(
+        this_cage,
+        arg5cage,
+        &statxbuf as *const imfs::Statx as u64,
+        this_cage,
+        arg5,
+        arg5cage,
+        std::mem::size_of::<imfs::Statx>() as u64,
+        0,
+    )
+    .is_err()
+    {
+        return -14;
+    }
+
+    ret
+}
+
 pub extern "C" fn fstat_handler(
     _cageid: u64,
     arg1: u64,
@@ -1094,9 +1274,14 @@ pub extern "C" fn linkat_handler(
     _arg6: u64,
     _arg6cage: u64,
 ) -> i32 {
-    let oldpath = match copy_path_from_cage(arg2, arg2cage) {
-        Some(p) => p,
-        None => return -14,
+    // With AT_EMPTY_PATH the old file is olddirfd itself.
+    let oldpath = if arg2 == 0 && arg5 as i32 & LIND_AT_EMPTY_PATH != 0 {
+        String::new()
+    } else {
+        match copy_path_from_cage(arg2, arg2cage) {
+            Some(p) => p,
+            None => return -14,
+        }
     };
 
     let newpath = match copy_path_from_cage(arg4, arg4cage) {
@@ -1444,7 +1629,7 @@ pub extern "C" fn pwrite_handler(
     );
 
     // fd < 3 passthrough.
-    if fd < 3 {
+    if is_host_stdio(cage_id, fd) {
         unsafe {
             let ret = libc::write(fd as i32, buf.as_ptr() as *const _, count);
             return ret as i32;
@@ -1483,10 +1668,10 @@ pub extern "C" fn readv_handler(
 
     let this_cage = getcageid();
     let mut buf = vec![0u8; total_len];
-    let ret = if fd < 3 {
+    let ret = if is_host_stdio(cage_id, fd) {
         unsafe { libc::read(fd as i32, buf.as_mut_ptr() as *mut _, total_len) as i32 }
     } else {
-        imfs::with_imfs(|state| state.read(cage_id, fd, &mut buf))
+        imfs::pipe::blocking_read(cage_id, fd, &mut buf)
     };
 
     if ret <= 0 {
@@ -1583,10 +1768,10 @@ pub extern "C" fn writev_handler(
         }
     }
 
-    if fd < 3 {
+    if is_host_stdio(cage_id, fd) {
         unsafe { libc::write(fd as i32, buf.as_ptr() as *const _, buf.len()) as i32 }
     } else {
-        imfs::with_imfs(|state| state.write(cage_id, fd, &buf))
+        imfs::pipe::blocking_write(cage_id, fd, &buf)
     }
 }
 
@@ -1620,7 +1805,7 @@ pub extern "C" fn preadv_handler(
 
     let this_cage = getcageid();
     let mut buf = vec![0u8; total_len];
-    let ret = if fd < 3 {
+    let ret = if is_host_stdio(cage_id, fd) {
         unsafe { libc::pread(fd as i32, buf.as_mut_ptr() as *mut _, total_len, offset) as i32 }
     } else {
         imfs::with_imfs(|state| state.pread(cage_id, fd, &mut buf, offset))
@@ -1721,7 +1906,7 @@ pub extern "C" fn pwritev_handler(
         }
     }
 
-    if fd < 3 {
+    if is_host_stdio(cage_id, fd) {
         unsafe { libc::pwrite(fd as i32, buf.as_ptr() as *const _, buf.len(), offset) as i32 }
     } else {
         imfs::with_imfs(|state| state.pwrite(cage_id, fd, &buf, offset))
@@ -1848,6 +2033,105 @@ pub extern "C" fn mknod_handler(
     imfs::with_imfs(|state| state.mknod(arg1cage, &pathname, arg2 as u32))
 }
 
+pub extern "C" fn mknodat_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    _arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let pathname = match copy_path_from_cage(arg2, arg2cage) {
+        Some(p) => p,
+        None => return -14,
+    };
+
+    imfs::with_imfs(|state| state.mknodat(arg1cage, arg1 as i32, &pathname, arg3 as u32))
+}
+
+// =====================================================================
+//  pipe (syscall 22) / pipe2 (syscall 293)
+//
+//  arg1 = int[2] ptr, arg1cage = cage that owns the array
+//  arg2 = flags (pipe2 only)
+// =====================================================================
+
+pub extern "C" fn pipe_handler(
+    cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    _arg2: u64,
+    _arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    pipe2_handler(
+        cageid, arg1, arg1cage, 0, arg1cage, arg3, arg3cage, arg4, arg4cage, arg5, arg5cage, arg6,
+        arg6cage,
+    )
+}
+
+pub extern "C" fn pipe2_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    _arg3: u64,
+    _arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    // arg1 is the fd array pointer, which a transient grate may have
+    // rewritten; the integer flags in arg2 carry the calling cage.
+    let cage_id = arg2cage;
+    let this_cage = getcageid();
+
+    let (readfd, writefd) = match imfs::with_imfs(|state| state.pipe(cage_id, arg2 as i32)) {
+        Ok(fds) => fds,
+        Err(e) => return e,
+    };
+
+    let fds: [i32; 2] = [readfd, writefd];
+    if copy_data_between_cages(
+        this_cage,
+        arg1cage,
+        fds.as_ptr() as u64,
+        this_cage,
+        arg1,
+        arg1cage,
+        std::mem::size_of_val(&fds) as u64,
+        0,
+    )
+    .is_err()
+    {
+        imfs::with_imfs(|state| {
+            state.close(cage_id, readfd as u64);
+            state.close(cage_id, writefd as u64);
+        });
+        return -14; // EFAULT
+    }
+
+    0
+}
+
 pub extern "C" fn fsync_handler(
     _cageid: u64,
     _arg1: u64,
@@ -1902,6 +2186,26 @@ pub extern "C" fn fchmod_handler(
     imfs::with_imfs(|state| state.fchmod(arg1cage, arg1, arg2 as u32))
 }
 
+/// umask is kept by IMFS, which applies it to the files it creates, so it is
+/// not forwarded.
+pub extern "C" fn umask_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    _arg2: u64,
+    _arg2cage: u64,
+    _arg3: u64,
+    _arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    imfs::with_imfs(|state| state.umask(arg1cage, arg1 as u32))
+}
+
 pub extern "C" fn utimensat_handler(
     _cageid: u64,
     arg1: u64,
@@ -1934,25 +2238,486 @@ pub extern "C" fn utimensat_handler(
 }
 
 // =====================================================================
-//  fork (syscall 57)
+//  Extended attributes (syscalls 188-199)
 //
-//  Forward the fork, then clone the fdtables and offset state for the
-//  new child cage so it inherits the parent's open fds.
+//  Path variants: arg1 = pathname ptr; f* variants: arg1 = fd.
+//  set:    arg2 = name ptr, arg3 = value ptr, arg4 = size, arg5 = flags
+//  get:    arg2 = name ptr, arg3 = value buf, arg4 = size
+//  list:   arg2 = list buf, arg3 = size
+//  remove: arg2 = name ptr
+//
+//  Path variants take the cage id from the integer size/name argument
+//  rather than arg1cage, for the reason given in open_handler.
 // =====================================================================
 
-pub extern "C" fn fork_handler(
-    _cageid: u64,
-    arg1: u64,
-    arg1cage: u64,
-    arg2: u64,
-    arg2cage: u64,
-    arg3: u64,
-    arg3cage: u64,
-    arg4: u64,
-    arg4cage: u64,
-    arg5: u64,
-    arg5cage: u64,
-    arg6: u64,
+fn copy_xattr_path(path_ptr: u64, path_cage: u64) -> Result<String, i32> {
+    copy_path_from_cage(path_ptr, path_cage).ok_or(-14) // EFAULT
+}
+
+#[allow(clippy::too_many_arguments)]
+fn xattr_set(
+    cage_id: u64,
+    target: imfs::xattr::XattrTarget,
+    name_ptr: u64,
+    name_cage: u64,
+    value_ptr: u64,
+    value_cage: u64,
+    size: u64,
+    flags: i32,
+) -> i32 {
+    let Some(name) = copy_path_from_cage(name_ptr, name_cage) else {
+        return -14; // EFAULT
+    };
+
+    let size = size as usize;
+    if size > imfs::xattr::XATTR_SIZE_MAX {
+        return -7; // E2BIG
+    }
+
+    let this_cage = getcageid();
+    let mut value = vec![0u8; size];
+    if size > 0
+        && copy_data_between_cages(
+            this_cage,
+            value_cage,
+            value_ptr,
+            value_cage,
+            value.as_mut_ptr() as u64,
+            this_cage,
+            size as u64,
+            0,
+        )
+        .is_err()
+    {
+        return -14; // EFAULT
+    }
+
+    imfs::with_imfs(|state| state.setxattr(cage_id, target, &name, &value, flags))
+}
+
+fn xattr_get(
+    cage_id: u64,
+    target: imfs::xattr::XattrTarget,
+    name_ptr: u64,
+    name_cage: u64,
+    buf_ptr: u64,
+    buf_cage: u64,
+    size: u64,
+) -> i32 {
+    let Some(name) = copy_path_from_cage(name_ptr, name_cage) else {
+        return -14; // EFAULT
+    };
+
+    let mut buf = vec![0u8; (size as usize).min(imfs::xattr::XATTR_SIZE_MAX)];
+    let ret = imfs::with_imfs(|state| state.getxattr(cage_id, target, &name, &mut buf));
+
+    copy_xattr_out(&buf, ret, buf_ptr, buf_cage)
+}
+
+fn xattr_list(
+    cage_id: u64,
+    target: imfs::xattr::XattrTarget,
+    buf_ptr: u64,
+    buf_cage: u64,
+    size: u64,
+) -> i32 {
+    let mut buf = vec![0u8; (size as usize).min(imfs::xattr::XATTR_LIST_MAX)];
+    let ret = imfs::with_imfs(|state| state.listxattr(cage_id, target, &mut buf));
+
+    copy_xattr_out(&buf, ret, buf_ptr, buf_cage)
+}
+
+fn xattr_remove(
+    cage_id: u64,
+    target: imfs::xattr::XattrTarget,
+    name_ptr: u64,
+    name_cage: u64,
+) -> i32 {
+    let Some(name) = copy_path_from_cage(name_ptr, name_cage) else {
+        return -14; // EFAULT
+    };
+
+    imfs::with_imfs(|state| state.removexattr(cage_id, target, &name))
+}
+
+/// Copy the first `ret` bytes of buf to the cage, unless the call only asked
+/// for the size (empty buf) or failed.
+fn copy_xattr_out(buf: &[u8], ret: i32, buf_ptr: u64, buf_cage: u64) -> i32 {
+    if ret <= 0 || buf.is_empty() {
+        return ret;
+    }
+
+    let this_cage = getcageid();
+    match copy_data_between_cages(
+        this_cage,
+        buf_cage,
+        buf.as_ptr() as u64,
+        this_cage,
+        buf_ptr,
+        buf_cage,
+        ret as u64,
+        0,
+    ) {
+        Ok(_) => ret,
+        Err(_) => -14, // EFAULT
+    }
+}
+
+pub extern "C" fn setxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let path = match copy_xattr_path(arg1, arg1cage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    xattr_set(
+        arg4cage,
+        imfs::xattr::XattrTarget::Path(&path),
+        arg2,
+        arg2cage,
+        arg3,
+        arg3cage,
+        arg4,
+        arg5 as i32,
+    )
+}
+
+pub extern "C" fn getxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let path = match copy_xattr_path(arg1, arg1cage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    xattr_get(
+        arg4cage,
+        imfs::xattr::XattrTarget::Path(&path),
+        arg2,
+        arg2cage,
+        arg3,
+        arg3cage,
+        arg4,
+    )
+}
+
+pub extern "C" fn listxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let path = match copy_xattr_path(arg1, arg1cage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    xattr_list(
+        arg3cage,
+        imfs::xattr::XattrTarget::Path(&path),
+        arg2,
+        arg2cage,
+        arg3,
+    )
+}
+
+pub extern "C" fn removexattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    _arg3: u64,
+    arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let path = match copy_xattr_path(arg1, arg1cage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    xattr_remove(
+        arg3cage,
+        imfs::xattr::XattrTarget::Path(&path),
+        arg2,
+        arg2cage,
+    )
+}
+
+pub extern "C" fn lsetxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let path = match copy_xattr_path(arg1, arg1cage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    xattr_set(
+        arg4cage,
+        imfs::xattr::XattrTarget::LinkPath(&path),
+        arg2,
+        arg2cage,
+        arg3,
+        arg3cage,
+        arg4,
+        arg5 as i32,
+    )
+}
+
+pub extern "C" fn lgetxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let path = match copy_xattr_path(arg1, arg1cage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    xattr_get(
+        arg4cage,
+        imfs::xattr::XattrTarget::LinkPath(&path),
+        arg2,
+        arg2cage,
+        arg3,
+        arg3cage,
+        arg4,
+    )
+}
+
+pub extern "C" fn llistxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let path = match copy_xattr_path(arg1, arg1cage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    xattr_list(
+        arg3cage,
+        imfs::xattr::XattrTarget::LinkPath(&path),
+        arg2,
+        arg2cage,
+        arg3,
+    )
+}
+
+pub extern "C" fn lremovexattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    _arg3: u64,
+    arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    let path = match copy_xattr_path(arg1, arg1cage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    xattr_remove(
+        arg3cage,
+        imfs::xattr::XattrTarget::LinkPath(&path),
+        arg2,
+        arg2cage,
+    )
+}
+
+pub extern "C" fn fsetxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    _arg4cage: u64,
+    arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    xattr_set(
+        arg1cage,
+        imfs::xattr::XattrTarget::Fd(arg1),
+        arg2,
+        arg2cage,
+        arg3,
+        arg3cage,
+        arg4,
+        arg5 as i32,
+    )
+}
+
+pub extern "C" fn fgetxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    xattr_get(
+        arg1cage,
+        imfs::xattr::XattrTarget::Fd(arg1),
+        arg2,
+        arg2cage,
+        arg3,
+        arg3cage,
+        arg4,
+    )
+}
+
+pub extern "C" fn flistxattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    _arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    xattr_list(
+        arg1cage,
+        imfs::xattr::XattrTarget::Fd(arg1),
+        arg2,
+        arg2cage,
+        arg3,
+    )
+}
+
+pub extern "C" fn fremovexattr_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    _arg3: u64,
+    _arg3cage: u64,
+    _arg4: u64,
+    _arg4cage: u64,
+    _arg5: u64,
+    _arg5cage: u64,
+    _arg6: u64,
+    _arg6cage: u64,
+) -> i32 {
+    xattr_remove(arg1cage, imfs::xattr::XattrTarget::Fd(arg1), arg2, arg2cage)
+}
+
+// =====================================================================
+//  fork (syscall 57)
+//
+//  Forward the fork, then clone the fdtables and offset state for the
+//  new child cage so it inherits the parent's open fds.
+// =====================================================================
+
+pub extern "C" fn fork_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
     arg6cage: u64,
 ) -> i32 {
     let this_cage = getcageid();
@@ -2028,21 +2793,35 @@ pub extern "C" fn exec_handler(
         true => {}
     };
 
-    // Close all fds with O_CLOEXEC set. fdtables handles this —
-    // it calls the registered close handlers for each closed fd.
+    if let Some(path) = copy_path_from_cage(arg1, arg1cage) {
+        imfs::with_imfs(|state| state.set_exe_path(cage_id, &path));
+    }
+
+    // Close all fds with O_CLOEXEC set. IMFS releases the nodes (and pipe
+    // ends) behind them first, then fdtables drops whatever is left.
+    imfs::with_imfs(|state| state.exec(cage_id));
     fdtables::empty_fds_for_exec(cage_id);
 
     // fdtables allocates virtual FDs, which start from 0 instead of 3.
     // Unlike regular lind, `underfd` does not point to an actual FD allocation mechanism,
     // so we need to manually open stdin/stdout/stderr file descriptors to reserve them.
-    for _fd in 0..3 {
-        let _ = fdtables::get_unused_virtual_fd(
+    // fds inherited across exec (e.g. a pipe dup2'd onto stdout) are left alone.
+    for fd in 0..3 {
+        if fdtables::translate_virtual_fd(cage_id, fd).is_ok() {
+            continue;
+        }
+        if fdtables::get_specific_virtual_fd(
             cage_id,
+            fd,
             crate::imfs::IMFS_FDKIND,
             0, // underfd: which node
             false,
             0,
-        );
+        )
+        .is_ok()
+        {
+            imfs::with_imfs(|state| state.insert_perfdinfo(cage_id, fd, fs::O_RDWR as u64));
+        }
     }
 
     // Forward the exec to the runtime.
@@ -2069,3 +2848,78 @@ pub extern "C" fn exec_handler(
         Err(_) => -1,
     }
 }
+
+// =====================================================================
+//  exit (syscall 60) / exit_group (syscall 231)
+//
+//  Release every fd the cage still holds so pipe ends and unlinked files
+//  are closed, then forward the exit to the runtime.
+// =====================================================================
+
+pub extern "C" fn exit_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    let args = [arg1, arg2, arg3, arg4, arg5, arg6];
+    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    exit_common(SYS_EXIT, &args, &arg_cages)
+}
+
+pub extern "C" fn exit_group_handler(
+    _cageid: u64,
+    arg1: u64,
+    arg1cage: u64,
+    arg2: u64,
+    arg2cage: u64,
+    arg3: u64,
+    arg3cage: u64,
+    arg4: u64,
+    arg4cage: u64,
+    arg5: u64,
+    arg5cage: u64,
+    arg6: u64,
+    arg6cage: u64,
+) -> i32 {
+    let args = [arg1, arg2, arg3, arg4, arg5, arg6];
+    let arg_cages = [arg1cage, arg2cage, arg3cage, arg4cage, arg5cage, arg6cage];
+    exit_common(SYS_EXIT_GROUP, &args, &arg_cages)
+}
+
+fn exit_common(syscall: u64, args: &[u64; 6], arg_cages: &[u64; 6]) -> i32 {
+    let cage_id = arg_cages[0];
+    let this_cage = getcageid();
+
+    imfs::with_imfs(|state| state.exit(cage_id));
+
+    make_threei_call(
+        syscall as u32,
+        0,
+        this_cage,
+        cage_id,
+        args[0],
+        arg_cages[0],
+        args[1],
+        arg_cages[1],
+        args[2],
+        arg_cages[2],
+        args[3],
+        arg_cages[3],
+        args[4],
+        arg_cages[4],
+        args[5],
+        arg_cages[5],
+        0,
+    )
+    .unwrap_or(-1)
+}
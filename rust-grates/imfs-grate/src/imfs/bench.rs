@@ -0,0 +1,145 @@
+//! Regular file I/O and large directory benchmarks.
+//!
+//! These are ignored tests rather than cargo benches because the grate is a
+//! binary crate. Run them natively with:
+//!
+//!   cargo test --release -- --ignored --nocapture benchmarks
+//!
+//! Each case drives ImfsState directly through cage 0, so the numbers measure
+//! the storage layer and not the 3i round trip of a real syscall.
+
+use super::*;
+use std::time::{Duration, Instant};
+
+const FILE_SIZE: usize = 32 << 20;
+const SEQ_IO_SIZE: usize = 64 << 10;
+const RANDOM_IO_SIZE: usize = 4 << 10;
+const RANDOM_OPS: usize = 4096;
+const DIR_ENTRIES: usize = 20_000;
+const GETDENTS_BUF_SIZE: usize = 4096;
+
+/// Offsets for the random cases, from a fixed-seed LCG so runs compare.
+fn random_offsets() -> Vec<i64> {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    (0..RANDOM_OPS)
+        .map(|_| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize % (FILE_SIZE - RANDOM_IO_SIZE)) as i64
+        })
+        .collect()
+}
+
+fn report(name: &str, bytes: usize, elapsed: Duration) {
+    let mib_per_sec = bytes as f64 / (1 << 20) as f64 / elapsed.as_secs_f64();
+    println!(
+        "{:<20} {:>10.2?} {:>10.1} MiB/s",
+        name, elapsed, mib_per_sec
+    );
+}
+
+fn report_ops(name: &str, ops: usize, elapsed: Duration) {
+    let ops_per_sec = ops as f64 / elapsed.as_secs_f64();
+    println!(
+        "{:<20} {:>10.2?} {:>10.0} ops/s",
+        name, elapsed, ops_per_sec
+    );
+}
+
+#[test]
+#[ignore]
+fn file_io_benchmarks() {
+    init();
+    if !fdtables::check_cage_exists(0) {
+        fdtables::init_empty_cage(0);
+    }
+
+    with_imfs(|state| {
+        let fd = state.open(0, "/bench", O_CREAT | O_RDWR | O_TRUNC, 0o644) as u64;
+        let seq_buf = vec![0xa5u8; SEQ_IO_SIZE];
+        let mut read_buf = vec![0u8; SEQ_IO_SIZE];
+        let offsets = random_offsets();
+
+        let start = Instant::now();
+        for _ in 0..FILE_SIZE / SEQ_IO_SIZE {
+            state.write(0, fd, &seq_buf);
+        }
+        report("sequential write", FILE_SIZE, start.elapsed());
+
+        state.lseek(0, fd, 0, SEEK_SET);
+        let start = Instant::now();
+        for _ in 0..FILE_SIZE / SEQ_IO_SIZE {
+            state.read(0, fd, &mut read_buf);
+        }
+        report("sequential read", FILE_SIZE, start.elapsed());
+
+        let start = Instant::now();
+        for &offset in &offsets {
+            state.pread(0, fd, &mut read_buf[..RANDOM_IO_SIZE], offset);
+        }
+        report(
+            "random 4k read",
+            RANDOM_OPS * RANDOM_IO_SIZE,
+            start.elapsed(),
+        );
+
+        let start = Instant::now();
+        for &offset in &offsets {
+            state.pwrite(0, fd, &seq_buf[..RANDOM_IO_SIZE], offset);
+        }
+        report(
+            "random 4k write",
+            RANDOM_OPS * RANDOM_IO_SIZE,
+            start.elapsed(),
+        );
+
+        state.close(0, fd);
+        state.unlink(0, "/bench");
+    });
+}
+
+#[test]
+#[ignore]
+fn directory_benchmarks() {
+    init();
+    if !fdtables::check_cage_exists(0) {
+        fdtables::init_empty_cage(0);
+    }
+
+    with_imfs(|state| {
+        state.mkdir(0, "/bench_dir", 0o755);
+        let paths: Vec<String> = (0..DIR_ENTRIES)
+            .map(|i| format!("/bench_dir/entry-{}", i))
+            .collect();
+
+        let start = Instant::now();
+        for path in &paths {
+            let fd = state.open(0, path, O_CREAT | O_WRONLY, 0o644);
+            state.close(0, fd as u64);
+        }
+        report_ops("create", DIR_ENTRIES, start.elapsed());
+
+        let mut statbuf: stat = unsafe { std::mem::zeroed() };
+        let start = Instant::now();
+        for path in &paths {
+            state.stat(0, path, &mut statbuf);
+        }
+        report_ops("lookup", DIR_ENTRIES, start.elapsed());
+
+        let fd = state.open(0, "/bench_dir", O_RDONLY | O_DIRECTORY, 0) as u64;
+        let mut buf = vec![0u8; GETDENTS_BUF_SIZE];
+        let start = Instant::now();
+        while state.getdents(0, fd, &mut buf) > 0 {}
+        report_ops("getdents", DIR_ENTRIES, start.elapsed());
+        state.close(0, fd);
+
+        let start = Instant::now();
+        for path in &paths {
+            state.unlink(0, path);
+        }
+        report_ops("unlink", DIR_ENTRIES, start.elapsed());
+
+        state.rmdir(0, "/bench_dir");
+    });
+}
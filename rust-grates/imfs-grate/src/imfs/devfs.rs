@@ -0,0 +1,191 @@
+//! Synthetic /dev.
+//!
+//! With `--dev`, IMFS creates /dev holding the character devices programs
+//! expect to find: null, zero, full, random and urandom. They are Chr nodes
+//! whose reads and writes are served by CharDev instead of stored data, so
+//! they cost no memory and can be opened, stat'ed and dup'ed onto stdio like
+//! on Linux. /dev/shm is an ordinary sticky directory. With `--proc` as well,
+//! /dev/fd and /dev/std{in,out,err} link into /proc/self/fd.
+//!
+//! Device nodes can be renamed or removed like any other node, but mknod
+//! still cannot create new ones.
+
+use super::*;
+
+/// What a character device does on read and write.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CharDev {
+    /// Reads return EOF, writes are discarded.
+    Null,
+    /// Reads return zeros, writes are discarded.
+    Zero,
+    /// Reads return zeros, writes fail with ENOSPC.
+    Full,
+    /// Reads return random bytes, writes are discarded.
+    Random,
+    Urandom,
+}
+
+/// Devices created in /dev, by name.
+const DEVICES: [(&str, CharDev); 5] = [
+    ("null", CharDev::Null),
+    ("zero", CharDev::Zero),
+    ("full", CharDev::Full),
+    ("random", CharDev::Random),
+    ("urandom", CharDev::Urandom),
+];
+
+/// Links into /proc/self/fd, created when /proc is mounted too.
+const FD_LINKS: [(&str, &str); 4] = [
+    ("fd", "/proc/self/fd"),
+    ("stdin", "/proc/self/fd/0"),
+    ("stdout", "/proc/self/fd/1"),
+    ("stderr", "/proc/self/fd/2"),
+];
+
+/// getentropy fails for requests over 256 bytes.
+const ENTROPY_CHUNK: usize = 256;
+
+impl CharDev {
+    /// Linux device number as (major, minor).
+    pub fn number(self) -> (u32, u32) {
+        let minor = match self {
+            CharDev::Null => 3,
+            CharDev::Zero => 5,
+            CharDev::Full => 7,
+            CharDev::Random => 8,
+            CharDev::Urandom => 9,
+        };
+        (1, minor)
+    }
+
+    pub fn read(self, buf: &mut [u8]) -> i32 {
+        match self {
+            CharDev::Null => 0,
+            CharDev::Zero | CharDev::Full => {
+                buf.fill(0);
+                buf.len() as i32
+            }
+            CharDev::Random | CharDev::Urandom => {
+                for chunk in buf.chunks_mut(ENTROPY_CHUNK) {
+                    let ret = unsafe { libc::getentropy(chunk.as_mut_ptr().cast(), chunk.len()) };
+                    if ret < 0 {
+                        return -5; // EIO
+                    }
+                }
+                buf.len() as i32
+            }
+        }
+    }
+
+    pub fn write(self, buf: &[u8]) -> i32 {
+        match self {
+            CharDev::Full => -28, // ENOSPC
+            _ => buf.len() as i32,
+        }
+    }
+}
+
+/// Encode a device number the way glibc's makedev does.
+pub(super) fn makedev(major: u32, minor: u32) -> u64 {
+    let (major, minor) = (major as u64, minor as u64);
+    ((major & 0xfffff000) << 32)
+        | ((major & 0xfff) << 8)
+        | ((minor & 0xffffff00) << 12)
+        | (minor & 0xff)
+}
+
+impl ImfsState {
+    /// Turn on the synthetic /proc and /dev trees and create them in the
+    /// initial root. Namespace roots created later get them as well.
+    pub fn mount_synthetic(&mut self, dev: bool, proc: bool) {
+        self.dev_mounted = dev;
+        self.proc_mounted = proc;
+        self.mount_synthetic_in(self.root_idx);
+    }
+
+    /// Create the synthetic trees that are turned on under root_idx.
+    pub(super) fn mount_synthetic_in(&mut self, root_idx: usize) {
+        if self.proc_mounted {
+            self.mount_proc(root_idx);
+        }
+        if self.dev_mounted {
+            self.mount_dev(root_idx);
+        }
+    }
+
+    fn mount_dev(&mut self, root_idx: usize) {
+        let dev = self.synthetic_dir(root_idx, "dev", 0o755);
+
+        for (name, device) in DEVICES {
+            if self.lookup_child(dev, name).is_some() {
+                continue;
+            }
+            let idx = self.create_node(0, name, NodeType::Chr, 0o666);
+            self.nodes[idx].info = NodeInfo::Chr { dev: device };
+            self.nodes[idx].synthetic = true;
+            self.set_new_node_owner(0, dev, idx);
+            self.add_child(dev, idx);
+        }
+
+        self.synthetic_dir(dev, "shm", 0o1777);
+
+        if self.proc_mounted {
+            for (name, target) in FD_LINKS {
+                if self.lookup_child(dev, name).is_none() {
+                    self.synthetic_link(dev, name, target);
+                }
+            }
+        }
+    }
+
+    /// The directory `name` under parent_idx, created owned by the grate if
+    /// it does not exist.
+    pub(super) fn synthetic_dir(&mut self, parent_idx: usize, name: &str, mode: u32) -> usize {
+        if let Some(idx) = self.lookup_child(parent_idx, name)
+            && self.nodes[idx].node_type == NodeType::Dir
+        {
+            return idx;
+        }
+
+        let idx = self.create_node(0, name, NodeType::Dir, mode);
+        self.nodes[idx].synthetic = true;
+        self.set_new_node_owner(0, parent_idx, idx);
+        self.add_child(parent_idx, idx);
+        self.add_dot_entries(idx, parent_idx);
+        idx
+    }
+
+    /// Add a symlink named `name` under parent_idx, owned by the grate.
+    pub(super) fn synthetic_link(&mut self, parent_idx: usize, name: &str, target: &str) -> usize {
+        let idx = self.create_node(0, name, NodeType::Lnk, 0o777);
+        self.nodes[idx].total_size = target.len();
+        self.nodes[idx].info = NodeInfo::Symlink {
+            target: target.to_string(),
+        };
+        self.nodes[idx].synthetic = true;
+        self.set_new_node_owner(0, parent_idx, idx);
+        self.add_child(parent_idx, idx);
+        idx
+    }
+
+    /// Device number of a node as (major, minor), (0, 0) if it is not a
+    /// device.
+    pub(super) fn device_number(&self, node_idx: usize) -> (u32, u32) {
+        match self.nodes[node_idx].info {
+            NodeInfo::Chr { dev } => dev.number(),
+            _ => (0, 0),
+        }
+    }
+
+    /// Whether fd refers to a device node.
+    pub fn is_device_fd(&self, cage_id: u64, fd: u64) -> bool {
+        match fdtables::translate_virtual_fd(cage_id, fd) {
+            Ok(entry) => matches!(
+                self.nodes.get(entry.underfd as usize).map(|n| &n.info),
+                Some(NodeInfo::Chr { .. })
+            ),
+            Err(_) => false,
+        }
+    }
+}
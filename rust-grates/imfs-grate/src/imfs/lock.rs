@@ -0,0 +1,395 @@
+//! Advisory file locks: flock(2) and POSIX fcntl record locks.
+//!
+//! The two kinds are independent, as on Linux:
+//!   - flock locks belong to an open file description (an FDInfo, shared by
+//!     dup'd and fork-inherited fds) and are released when its last fd closes.
+//!   - Record locks belong to a cage and cover a byte range of a node. They
+//!     are not inherited across fork, and closing *any* fd of the cage that
+//!     refers to the node releases all of the cage's locks on it.
+//!
+//! Both are keyed by the node a fd resolves to, so every cage sharing the
+//! IMFS sees the same locks. As with pipes, the ImfsState methods never
+//! block; blocking_flock and blocking_setlkw retry with the IMFS lock released.
+
+use super::*;
+
+pub const F_GETLK: i32 = 5;
+pub const F_SETLK: i32 = 6;
+pub const F_SETLKW: i32 = 7;
+pub const F_GETLK64: i32 = 12;
+pub const F_SETLK64: i32 = 13;
+pub const F_SETLKW64: i32 = 14;
+
+const F_RDLCK: i16 = 0;
+const F_WRLCK: i16 = 1;
+const F_UNLCK: i16 = 2;
+
+const LOCK_SH: i32 = 1;
+const LOCK_EX: i32 = 2;
+const LOCK_NB: i32 = 4;
+const LOCK_UN: i32 = 8;
+
+/// `struct flock64` layout, used by F_GETLK64/F_SETLK64/F_SETLKW64 and as
+/// the lock description everywhere inside IMFS.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Flock {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i64,
+    pub l_len: i64,
+    pub l_pid: i32,
+}
+
+/// `struct flock` layout for the guest's 32-bit off_t (see
+/// grate_rs::ffi::off_t), used by F_GETLK/F_SETLK/F_SETLKW.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Flock32 {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i32,
+    pub l_len: i32,
+    pub l_pid: i32,
+}
+
+impl From<Flock32> for Flock {
+    fn from(fl: Flock32) -> Self {
+        Flock {
+            l_type: fl.l_type,
+            l_whence: fl.l_whence,
+            l_start: fl.l_start as i64,
+            l_len: fl.l_len as i64,
+            l_pid: fl.l_pid,
+        }
+    }
+}
+
+impl TryFrom<Flock> for Flock32 {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(fl: Flock) -> Result<Self, Self::Error> {
+        Ok(Flock32 {
+            l_type: fl.l_type,
+            l_whence: fl.l_whence,
+            l_start: i32::try_from(fl.l_start)?,
+            l_len: i32::try_from(fl.l_len)?,
+            l_pid: fl.l_pid,
+        })
+    }
+}
+
+struct FlockHolder {
+    /// Address of the FDInfo the lock belongs to.
+    ofd: usize,
+    exclusive: bool,
+}
+
+#[derive(Clone, Copy)]
+struct RecordLock {
+    cage_id: u64,
+    start: u64,
+    /// Exclusive end; u64::MAX means "to end of file and beyond".
+    end: u64,
+    write: bool,
+}
+
+impl RecordLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// All advisory locks held on IMFS nodes.
+#[derive(Default)]
+pub struct LockTable {
+    flocks: HashMap<usize, Vec<FlockHolder>>,
+    records: HashMap<usize, Vec<RecordLock>>,
+    /// Cages blocked in F_SETLKW, and the cage holding the lock they wait
+    /// for. Used to detect deadlocks.
+    waiting: HashMap<u64, u64>,
+}
+
+fn ofd_key(fd_info: &Arc<Mutex<FDInfo>>) -> usize {
+    Arc::as_ptr(fd_info) as usize
+}
+
+impl ImfsState {
+    /// flock: apply, convert or remove the fd's open-file-description lock.
+    /// Returns EWOULDBLOCK (EAGAIN) when another description holds a
+    /// conflicting lock; the caller decides whether to wait.
+    pub fn flock(&mut self, cage_id: u64, fd: u64, operation: i32) -> i32 {
+        let (node_idx, _) = match self.get_node_and_flags(cage_id, fd) {
+            Ok(n) => n,
+            Err(e) => return e,
+        };
+        let ofd = match self.fd_info.get(&(cage_id, fd)) {
+            Some(info) => ofd_key(info),
+            None => return -9, // EBADF
+        };
+
+        let exclusive = match operation & !LOCK_NB {
+            LOCK_SH => false,
+            LOCK_EX => true,
+            LOCK_UN => {
+                self.release_flock(node_idx, ofd);
+                return 0;
+            }
+            _ => return -22, // EINVAL
+        };
+
+        let holders = self.locks.flocks.entry(node_idx).or_default();
+        let conflict = holders
+            .iter()
+            .any(|h| h.ofd != ofd && (h.exclusive || exclusive));
+        if conflict {
+            return -11; // EWOULDBLOCK
+        }
+
+        holders.retain(|h| h.ofd != ofd);
+        holders.push(FlockHolder { ofd, exclusive });
+        0
+    }
+
+    fn release_flock(&mut self, node_idx: usize, ofd: usize) {
+        if let Some(holders) = self.locks.flocks.get_mut(&node_idx) {
+            holders.retain(|h| h.ofd != ofd);
+            if holders.is_empty() {
+                self.locks.flocks.remove(&node_idx);
+            }
+        }
+    }
+
+    /// Convert a struct flock's whence/start/len into an absolute
+    /// [start, end) range.
+    fn lock_range(
+        &self,
+        cage_id: u64,
+        fd: u64,
+        node_idx: usize,
+        fl: &Flock,
+    ) -> Result<(u64, u64), i32> {
+        let base = match fl.l_whence as i32 {
+            SEEK_SET => 0,
+            SEEK_CUR => self.get_offset(cage_id, fd),
+            SEEK_END => self.nodes[node_idx].total_size as i64,
+            _ => return Err(-22), // EINVAL
+        };
+
+        let start = base.checked_add(fl.l_start).ok_or(-75)?; // EOVERFLOW
+        let (start, end) = match fl.l_len {
+            0 => (start, None),
+            len if len > 0 => (start, Some(start.checked_add(len).ok_or(-75)?)),
+            len => (start.checked_add(len).ok_or(-75)?, Some(start)),
+        };
+
+        if start < 0 {
+            return Err(-22); // EINVAL
+        }
+
+        Ok((start as u64, end.map(|e| e as u64).unwrap_or(u64::MAX)))
+    }
+
+    fn conflicting_record(
+        &self,
+        cage_id: u64,
+        node_idx: usize,
+        start: u64,
+        end: u64,
+        write: bool,
+    ) -> Option<RecordLock> {
+        self.locks.records.get(&node_idx).and_then(|locks| {
+            locks
+                .iter()
+                .find(|l| l.cage_id != cage_id && l.overlaps(start, end) && (l.write || write))
+                .copied()
+        })
+    }
+
+    /// fcntl F_GETLK/F_SETLK. F_SETLKW is handled like F_SETLK here and
+    /// returns EAGAIN on conflict; blocking_setlkw does the waiting.
+    pub fn fcntl_lock(&mut self, cage_id: u64, fd: u64, op: i32, fl: &mut Flock) -> i32 {
+        let (node_idx, flags) = match self.get_node_and_flags(cage_id, fd) {
+            Ok(n) => n,
+            Err(e) => return e,
+        };
+
+        let (start, end) = match self.lock_range(cage_id, fd, node_idx, fl) {
+            Ok(range) => range,
+            Err(e) => return e,
+        };
+
+        if op == F_GETLK {
+            let write = match fl.l_type {
+                F_RDLCK => false,
+                F_WRLCK => true,
+                _ => return -22, // EINVAL
+            };
+
+            match self.conflicting_record(cage_id, node_idx, start, end, write) {
+                Some(lock) => {
+                    fl.l_type = if lock.write { F_WRLCK } else { F_RDLCK };
+                    fl.l_whence = SEEK_SET as i16;
+                    fl.l_start = lock.start as i64;
+                    fl.l_len = if lock.end == u64::MAX {
+                        0
+                    } else {
+                        (lock.end - lock.start) as i64
+                    };
+                    fl.l_pid = lock.cage_id as i32;
+                }
+                None => fl.l_type = F_UNLCK,
+            }
+            return 0;
+        }
+
+        let write = match fl.l_type {
+            F_RDLCK if flags & O_ACCMODE == O_WRONLY => return -9, // EBADF
+            F_WRLCK if flags & O_ACCMODE == O_RDONLY => return -9, // EBADF
+            F_RDLCK => false,
+            F_WRLCK => true,
+            F_UNLCK => {
+                self.replace_record_range(cage_id, node_idx, start, end, None);
+                return 0;
+            }
+            _ => return -22, // EINVAL
+        };
+
+        if let Some(lock) = self.conflicting_record(cage_id, node_idx, start, end, write) {
+            if op == F_SETLKW {
+                self.locks.waiting.insert(cage_id, lock.cage_id);
+            }
+            return -11; // EAGAIN
+        }
+
+        self.locks.waiting.remove(&cage_id);
+        self.replace_record_range(cage_id, node_idx, start, end, Some(write));
+        0
+    }
+
+    /// Drop the cage's locks in [start, end), splitting any that extend
+    /// past it, then add a lock of the given kind over the range and merge
+    /// it with adjacent locks of the same kind.
+    fn replace_record_range(
+        &mut self,
+        cage_id: u64,
+        node_idx: usize,
+        start: u64,
+        end: u64,
+        write: Option<bool>,
+    ) {
+        let locks = self.locks.records.entry(node_idx).or_default();
+
+        let mut kept = Vec::with_capacity(locks.len() + 1);
+        for lock in locks.drain(..) {
+            if lock.cage_id != cage_id || !lock.overlaps(start, end) {
+                kept.push(lock);
+                continue;
+            }
+            if lock.start < start {
+                kept.push(RecordLock { end: start, ..lock });
+            }
+            if lock.end > end {
+                kept.push(RecordLock { start: end, ..lock });
+            }
+        }
+
+        if let Some(write) = write {
+            let mut new = RecordLock {
+                cage_id,
+                start,
+                end,
+                write,
+            };
+            kept.retain(|l| {
+                let mergeable = l.cage_id == cage_id
+                    && l.write == write
+                    && (l.end == new.start || l.start == new.end);
+                if mergeable {
+                    new.start = new.start.min(l.start);
+                    new.end = new.end.max(l.end);
+                }
+                !mergeable
+            });
+            kept.push(new);
+        }
+
+        if kept.is_empty() {
+            self.locks.records.remove(&node_idx);
+        } else {
+            *locks = kept;
+        }
+    }
+
+    /// Release everything fd holds before it is closed: the cage's record
+    /// locks on the node and, if this is the description's last fd, its
+    /// flock lock.
+    pub(super) fn release_locks_on_close(&mut self, cage_id: u64, fd: u64, node_idx: usize) {
+        if let Some(fd_info) = self.fd_info.get(&(cage_id, fd))
+            && Arc::strong_count(fd_info) == 1
+        {
+            let ofd = ofd_key(fd_info);
+            self.release_flock(node_idx, ofd);
+        }
+
+        if let Some(locks) = self.locks.records.get_mut(&node_idx) {
+            locks.retain(|l| l.cage_id != cage_id);
+            if locks.is_empty() {
+                self.locks.records.remove(&node_idx);
+            }
+        }
+    }
+
+    /// Whether the cages waiting on each other, starting from the holder of
+    /// the lock cage_id is blocked on, lead back to cage_id.
+    fn lock_wait_is_deadlock(&self, cage_id: u64) -> bool {
+        let mut current = cage_id;
+        for _ in 0..self.locks.waiting.len() {
+            match self.locks.waiting.get(&current) {
+                Some(&holder) if holder == cage_id => return true,
+                Some(&holder) => current = holder,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    pub(super) fn clear_lock_wait(&mut self, cage_id: u64) {
+        self.locks.waiting.remove(&cage_id);
+    }
+}
+
+/// flock, waiting for conflicting locks to go away unless LOCK_NB is set.
+pub fn blocking_flock(cage_id: u64, fd: u64, operation: i32) -> i32 {
+    loop {
+        let ret = with_imfs(|state| state.flock(cage_id, fd, operation));
+        if ret != -11 || operation & LOCK_NB != 0 {
+            return ret;
+        }
+        if nap_signal_aware() {
+            return -4; // EINTR
+        }
+    }
+}
+
+/// fcntl(F_SETLKW): wait until the lock can be applied. Fails with EDEADLK
+/// if waiting would close a cycle of cages waiting on each other's locks.
+pub fn blocking_setlkw(cage_id: u64, fd: u64, fl: &mut Flock) -> i32 {
+    loop {
+        let ret = with_imfs(|state| {
+            let ret = state.fcntl_lock(cage_id, fd, F_SETLKW, fl);
+            if ret == -11 && state.lock_wait_is_deadlock(cage_id) {
+                state.clear_lock_wait(cage_id);
+                return -35; // EDEADLK
+            }
+            ret
+        });
+        if ret != -11 {
+            return ret;
+        }
+        if nap_signal_aware() {
+            with_imfs(|state| state.clear_lock_wait(cage_id));
+            return -4; // EINTR
+        }
+    }
+}
@@ -1,7 +1,7 @@
 //! In-Memory Filesystem (IMFS).
 //!
 //! The filesystem is a tree of Nodes stored in an arena-style Vec.
-//! File data is stored in chains of 1024-byte Chunks.
+//! File data is stored in fixed-size extents, looked up by extent number.
 //!
 //! fd management is handled entirely by the fdtables library:
 //!   - underfd  = node index (which file this fd points to)
@@ -9,7 +9,20 @@
 //! The only per-fd state we track ourselves is the read/write offset,
 //! stored in a HashMap<(cage_id, fd), offset>.
 
+pub mod devfs;
+pub mod lock;
+pub mod namespace;
 pub mod node;
+pub mod overlay;
+pub mod perm;
+pub mod pipe;
+pub mod procfs;
+pub mod quota;
+pub mod tmpfile;
+pub mod xattr;
+
+#[cfg(test)]
+mod bench;
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -25,6 +38,15 @@ const IMFS_F_GETFD: i32 = 1;
 const IMFS_F_SETFD: i32 = 2;
 const IMFS_FD_CLOEXEC: i32 = 1;
 const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+const O_NONBLOCK: i32 = 0o4000;
+const F_SETFL: i32 = 4;
+const SEEK_DATA: i32 = 3;
+const SEEK_HOLE: i32 = 4;
+const FALLOC_FL_KEEP_SIZE: i32 = 0x01;
+const FALLOC_FL_PUNCH_HOLE: i32 = 0x02;
+const FALLOC_FL_ZERO_RANGE: i32 = 0x10;
+// off_t is 32 bits in the lind guest, so no file can grow past this.
+const IMFS_MAX_FILE_SIZE: i64 = i32::MAX as i64;
 
 /// Global IMFS state.
 pub static IMFS: Mutex<Option<ImfsState>> = Mutex::new(None);
@@ -38,9 +60,34 @@ where
     f(guard.as_mut().expect("IMFS not initialized"))
 }
 
+/// Sleep 1ms in a way that is interruptible by signals queued for the
+/// calling cage. Returns true if the sleep was interrupted, in which case the
+/// caller should return EINTR so the cage's signal handler can run. See
+/// ipc-grate's pipe.rs for why 1ms rather than something shorter.
+fn nap_signal_aware() -> bool {
+    unsafe {
+        let ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 1_000_000,
+        };
+        libc::nanosleep(&ts, std::ptr::null_mut()) < 0
+    }
+}
+
 pub struct FDInfo {
     flags: u64,
     offset: i64,
+    /// Host fd of an overlay file that has not been copied up, opened on the
+    /// first read through this open file description. See overlay.rs.
+    lower_fd: Option<i32>,
+}
+
+/// A normalized absolute path together with the root it resolves from and
+/// the cage it is resolved as.
+struct NormPath {
+    path: String,
+    root: usize,
+    cage_id: u64,
 }
 
 /// Lind-compatible statfs data layout.
@@ -60,6 +107,45 @@ pub struct FsData {
     pub f_spare: [u8; 32],
 }
 
+/// statx timestamp layout.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StatxTimestamp {
+    pub tv_sec: i64,
+    pub tv_nsec: u32,
+    pub __reserved: i32,
+}
+
+/// Linux `struct statx` layout (256 bytes).
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Statx {
+    pub stx_mask: u32,
+    pub stx_blksize: u32,
+    pub stx_attributes: u64,
+    pub stx_nlink: u32,
+    pub stx_uid: u32,
+    pub stx_gid: u32,
+    pub stx_mode: u16,
+    pub __spare0: u16,
+    pub stx_ino: u64,
+    pub stx_size: u64,
+    pub stx_blocks: u64,
+    pub stx_attributes_mask: u64,
+    pub stx_atime: StatxTimestamp,
+    pub stx_btime: StatxTimestamp,
+    pub stx_ctime: StatxTimestamp,
+    pub stx_mtime: StatxTimestamp,
+    pub stx_rdev_major: u32,
+    pub stx_rdev_minor: u32,
+    pub stx_dev_major: u32,
+    pub stx_dev_minor: u32,
+    pub stx_mnt_id: u64,
+    pub stx_dio_mem_align: u32,
+    pub stx_dio_offset_align: u32,
+    pub __spare3: [u64; 12],
+}
+
 const DIRENT64_FIXED_SIZE: usize = 8 + 8 + 2 + 1;
 const IMFS_BLOCK_SIZE: i32 = 512;
 // stat.st_blksize: preferred I/O size that glibc stdio sizes its buffer to.
@@ -73,7 +159,6 @@ const LIND_AT_NO_AUTOMOUNT: i32 = 0x800;
 const LIND_AT_EMPTY_PATH: i32 = 0x1000;
 const IMFS_STATFS_MAGIC: u64 = 0x494d_4653; // "IMFS"
 const IMFS_STATFS_BLOCK_SIZE: u64 = 4096;
-const IMFS_STATFS_TOTAL_BLOCKS: u64 = 1024 * 1024;
 const IMFS_STATFS_NAME_MAX: u64 = 254;
 const S_IFMT: u32 = 0o170000;
 const S_IFIFO: u32 = 0o010000;
@@ -82,13 +167,16 @@ const S_MODE_BITS: u32 = 0o7777;
 const S_ISUID: u32 = 0o4000;
 const S_ISGID: u32 = 0o2000;
 const CHOWN_NO_CHANGE: u32 = u32::MAX;
+const AT_STATX_FORCE_SYNC: i32 = 0x2000;
+const AT_STATX_DONT_SYNC: i32 = 0x4000;
+const STATX_BASIC_STATS: u32 = 0x07ff;
+const STATX_BTIME: u32 = 0x0800;
+const STATX_RESERVED: u32 = 0x8000_0000;
 
 /// The complete IMFS state.
 pub struct ImfsState {
     pub nodes: Vec<Node>,
-    pub chunks: Vec<Chunk>,
     node_free_list: Vec<usize>,
-    chunk_free_list: Vec<usize>,
     pub root_idx: usize,
 
     /// Per-fd read/write offsets: (cage_id, fd) -> offset.
@@ -99,36 +187,62 @@ pub struct ImfsState {
 
     /// List of current working directories for each cage.
     pub cwd_info: HashMap<u64, String>,
+
+    /// flock and fcntl record locks, shared by all cages.
+    locks: lock::LockTable,
+
+    /// Whether forked cages get their own root, see namespace.rs.
+    namespace_mode: namespace::NamespaceMode,
+    /// Forks below the initial cage at which a cage gets a new namespace.
+    namespace_depth: u32,
+    /// Number of forks between each forked cage and the initial cage.
+    fork_depths: HashMap<u64, u32>,
+    /// Namespace root of each cage outside the initial tree. Other cages use
+    /// root_idx.
+    namespaces: HashMap<u64, usize>,
+    /// Credentials and umask of each cage, see perm.rs.
+    creds: HashMap<u64, perm::Credentials>,
+    umasks: HashMap<u64, u32>,
+    /// Host directory IMFS is layered over, if any, see overlay.rs.
+    overlay: Option<overlay::Overlay>,
+    /// Bytes and nodes used by each cage, and their limits, see quota.rs.
+    quota: quota::QuotaState,
+    /// Whether new roots get a synthetic /dev and /proc, see devfs.rs and
+    /// procfs.rs.
+    dev_mounted: bool,
+    proc_mounted: bool,
+    /// Program each cage last exec'ed, for /proc/<cage>/exe.
+    exe_paths: HashMap<u64, String>,
 }
 
 /// Initialize the global IMFS. Called once at startup.
 pub fn init() {
     let mut state = ImfsState {
         nodes: Vec::with_capacity(MAX_NODES),
-        chunks: Vec::new(),
         node_free_list: Vec::new(),
-        chunk_free_list: Vec::new(),
         root_idx: 0,
         fd_info: HashMap::new(),
         cwd_info: HashMap::new(),
+        locks: lock::LockTable::default(),
+        namespace_mode: namespace::NamespaceMode::default(),
+        namespace_depth: 1,
+        fork_depths: HashMap::new(),
+        namespaces: HashMap::new(),
+        creds: HashMap::new(),
+        umasks: HashMap::new(),
+        overlay: None,
+        quota: quota::QuotaState::default(),
+        dev_mounted: false,
+        proc_mounted: false,
+        exe_paths: HashMap::new(),
     };
 
     state.cwd_info.insert(0, "/".to_string());
 
-    // Create root directory.
-    let root_idx = state.create_node("/", NodeType::Dir, 0o755);
-    state.nodes[root_idx].parent_idx = root_idx;
+    // Create root directory with its . and .. entries.
+    let root_idx = state.create_root(0);
     state.root_idx = root_idx;
 
-    // Create . and .. in root.
-    let dot_idx = state.create_node(".", NodeType::Lnk, 0);
-    state.nodes[dot_idx].info = NodeInfo::HardLink { target: root_idx };
-    state.add_child(root_idx, dot_idx);
-
-    let dotdot_idx = state.create_node("..", NodeType::Lnk, 0);
-    state.nodes[dotdot_idx].info = NodeInfo::HardLink { target: root_idx };
-    state.add_child(root_idx, dotdot_idx);
-
     *IMFS.lock().unwrap() = Some(state);
 }
 
@@ -154,17 +268,12 @@ impl ImfsState {
     // =====================================================================
 
     fn dirent_type(&self, node_idx: usize) -> u8 {
-        let mut idx = node_idx;
-
-        while let NodeInfo::HardLink { target } = &self.nodes[idx].info {
-            idx = *target;
-        }
-
-        match self.nodes[idx].node_type {
+        match self.nodes[node_idx].node_type {
             NodeType::Dir => DT_DIR,
             NodeType::Reg => DT_REG,
             NodeType::Lnk => DT_LNK,
             NodeType::Pip => DT_FIFO,
+            NodeType::Chr => DT_CHR,
             _ => DT_UNKNOWN,
         }
     }
@@ -201,51 +310,61 @@ impl ImfsState {
     //  Node management
     // =====================================================================
 
-    /// Allocate a new node. Reuses slots from the free list if available,
-    /// otherwise appends to the nodes Vec. Returns the node's index.
-    fn create_node(&mut self, name: &str, node_type: NodeType, mode: u32) -> usize {
-        if let Some(free_idx) = self.node_free_list.pop() {
+    /// Allocate a new node, charged to cage_id. Reuses slots from the free
+    /// list if available, otherwise appends to the nodes Vec. Returns the
+    /// node's index.
+    fn create_node(&mut self, cage_id: u64, name: &str, node_type: NodeType, mode: u32) -> usize {
+        let idx = if let Some(free_idx) = self.node_free_list.pop() {
             self.nodes[free_idx] = Node::new(free_idx, name, node_type, mode);
             free_idx
         } else {
             let idx = self.nodes.len();
             self.nodes.push(Node::new(idx, name, node_type, mode));
             idx
-        }
+        };
+        self.nodes[idx].quota_cage = cage_id;
+        self.charge(cage_id, 0, 1);
+        idx
     }
 
-    /// Add a child node to a directory. Updates the child's parent_idx.
+    /// Add a directory entry for a node under its own name. Updates the
+    /// child's parent_idx.
     fn add_child(&mut self, parent_idx: usize, child_idx: usize) {
         let child_name = self.nodes[child_idx].name.clone();
-        self.nodes[parent_idx].children_mut().push(DirEntry {
-            name: child_name,
-            node_idx: child_idx,
-        });
+        self.add_entry(parent_idx, &child_name, child_idx);
         self.nodes[child_idx].parent_idx = parent_idx;
     }
 
-    /// Remove a child from its parent directory's children list.
-    fn remove_child(&mut self, node_idx: usize) {
-        let parent_idx = self.nodes[node_idx].parent_idx;
-        let name = self.nodes[node_idx].name.clone();
-        self.nodes[parent_idx]
-            .children_mut()
-            .retain(|e| e.name != name);
+    /// Give a new directory its . and .. entries.
+    fn add_dot_entries(&mut self, dir_idx: usize, parent_idx: usize) {
+        self.add_entry(dir_idx, ".", dir_idx);
+        self.add_entry(dir_idx, "..", parent_idx);
     }
 
-    /// Mark a node slot as free and return it to the free list for reuse.
-    fn reclaim_node(&mut self, idx: usize) {
-        let chunk_head = match &mut self.nodes[idx].info {
-            NodeInfo::Reg { head, tail } => {
-                let old_head = *head;
-                *head = None;
-                *tail = None;
-                old_head
-            }
-            _ => None,
+    /// Add a directory entry, counting it as a link of a non-directory node.
+    fn add_entry(&mut self, dir_idx: usize, name: &str, node_idx: usize) {
+        self.nodes[dir_idx].children_mut().insert(name, node_idx);
+        if self.nodes[node_idx].node_type != NodeType::Dir {
+            self.nodes[node_idx].nlink += 1;
+        }
+    }
+
+    /// Remove a directory entry. The node it named stays allocated; see
+    /// unlink_node.
+    fn remove_entry(&mut self, dir_idx: usize, name: &str) {
+        let Some(DirEntry { node_idx, .. }) = self.nodes[dir_idx].children_mut().remove(name)
+        else {
+            return;
         };
-        self.reclaim_chunk_chain(chunk_head);
+        if self.nodes[node_idx].node_type != NodeType::Dir {
+            self.nodes[node_idx].nlink = self.nodes[node_idx].nlink.saturating_sub(1);
+        }
+    }
 
+    /// Mark a node slot as free and return it to the free list for reuse.
+    fn reclaim_node(&mut self, idx: usize) {
+        let bytes = self.stored_bytes(idx) as u64;
+        self.uncharge(self.nodes[idx].quota_cage, bytes, 1);
         self.nodes[idx].node_type = NodeType::Free;
         self.nodes[idx].info = NodeInfo::Free;
         self.node_free_list.push(idx);
@@ -253,6 +372,8 @@ impl ImfsState {
 
     fn link_count(&self, node_idx: usize) -> u32 {
         match &self.nodes[node_idx].info {
+            // A removed directory still holds its . and .. entries.
+            _ if self.nodes[node_idx].doomed => 0,
             NodeInfo::Dir { children } => {
                 let child_dirs = children
                     .iter()
@@ -261,74 +382,21 @@ impl ImfsState {
                     .count() as u32;
                 2 + child_dirs
             }
-            _ => self
-                .nodes
-                .iter()
-                .filter_map(|node| match &node.info {
-                    NodeInfo::Dir { children } => Some(children),
-                    _ => None,
-                })
-                .flat_map(|children| children.iter())
-                .filter(|entry| {
-                    entry.node_idx == node_idx
-                        || matches!(
-                            &self.nodes[entry.node_idx].info,
-                            NodeInfo::HardLink { target } if *target == node_idx
-                        )
-                })
-                .count() as u32,
+            _ => self.nodes[node_idx].nlink,
         }
     }
 
+    /// Called after an entry naming node_idx was removed. Once nothing names
+    /// the node it is freed, or, while an fd still references it, doomed so
+    /// the last close frees it. Its data stays readable through those fds.
     fn unlink_node(&mut self, node_idx: usize) {
-        if let NodeInfo::HardLink { target } = &self.nodes[node_idx].info {
-            let target = *target;
-            self.update_ctime(target);
-            if self.link_count(target) == 0 {
-                self.nodes[target].doomed = true;
-                if self.nodes[target].in_use == 0 {
-                    self.reclaim_node(target);
-                }
-            }
-            self.reclaim_node(node_idx);
+        if self.nodes[node_idx].node_type != NodeType::Dir && self.nodes[node_idx].nlink > 0 {
             return;
         }
 
-        if self.link_count(node_idx) == 0 {
-            self.nodes[node_idx].doomed = true;
-            if self.nodes[node_idx].in_use == 0 {
-                self.reclaim_node(node_idx);
-            }
-        }
-    }
-
-    // =====================================================================
-    //  Chunk management
-    // =====================================================================
-
-    /// Allocate a new empty chunk. Reuses from free list or appends.
-    /// Returns the chunk index in self.chunks.
-    fn alloc_chunk(&mut self) -> usize {
-        if let Some(free_idx) = self.chunk_free_list.pop() {
-            self.chunks[free_idx] = Chunk::new();
-            free_idx
-        } else {
-            let idx = self.chunks.len();
-            self.chunks.push(Chunk::new());
-            idx
-        }
-    }
-
-    fn reclaim_chunk(&mut self, idx: usize) {
-        self.chunks[idx] = Chunk::new();
-        self.chunk_free_list.push(idx);
-    }
-
-    fn reclaim_chunk_chain(&mut self, mut chunk_idx: Option<usize>) {
-        while let Some(ci) = chunk_idx {
-            let next = self.chunks[ci].next;
-            self.reclaim_chunk(ci);
-            chunk_idx = next;
+        self.nodes[node_idx].doomed = true;
+        if self.nodes[node_idx].in_use == 0 {
+            self.reclaim_node(node_idx);
         }
     }
 
@@ -336,7 +404,7 @@ impl ImfsState {
     //  Path resolution
     // =====================================================================
 
-    fn normalize_path_for_cage(&self, cage_id: u64, path: &str) -> String {
+    fn normalize_path_for_cage(&self, cage_id: u64, path: &str) -> NormPath {
         let base = if path.starts_with('/') {
             "/".to_string()
         } else {
@@ -358,10 +426,16 @@ impl ImfsState {
             }
         }
 
-        if parts.is_empty() {
+        let path = if parts.is_empty() {
             "/".to_string()
         } else {
             format!("/{}", parts.join("/"))
+        };
+
+        NormPath {
+            path,
+            root: self.namespace_root(cage_id),
+            cage_id,
         }
     }
 
@@ -386,11 +460,17 @@ impl ImfsState {
         }
     }
 
+    /// Bring in entries that are added on demand: host entries in overlay
+    /// mode and generated /proc entries.
+    fn populate_dir(&mut self, cage_id: u64, dir_idx: usize) {
+        self.merge_lower_dir(dir_idx);
+        self.refresh_proc_dir(cage_id, dir_idx);
+    }
+
     fn lookup_child(&self, parent_idx: usize, name: &str) -> Option<usize> {
         self.nodes[parent_idx]
             .children()
-            .iter()
-            .find(|entry| entry.name == name)
+            .get(name)
             .map(|entry| entry.node_idx)
     }
 
@@ -398,12 +478,20 @@ impl ImfsState {
     /// Returns ENOENT if a component is missing, ENOTDIR if an intermediate
     /// component is not a directory. If follow_final is false, the last path
     /// component is returned without following a link node.
-    fn resolve_path(&self, path: &str, follow_final: bool) -> Result<usize, i32> {
-        self.resolve_path_inner(path, follow_final, 0)
+    fn resolve_path(&mut self, norm_path: &NormPath, follow_final: bool) -> Result<usize, i32> {
+        self.resolve_path_inner(
+            norm_path.root,
+            norm_path.cage_id,
+            &norm_path.path,
+            follow_final,
+            0,
+        )
     }
 
     fn resolve_path_inner(
-        &self,
+        &mut self,
+        root_idx: usize,
+        cage_id: u64,
         path: &str,
         follow_final: bool,
         depth: usize,
@@ -412,29 +500,26 @@ impl ImfsState {
             return Err(-40); // ELOOP
         }
 
-        if path == "/" {
-            return Ok(self.root_idx);
-        }
-
         let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
         if components.is_empty() {
-            return Ok(self.root_idx);
+            self.populate_dir(cage_id, root_idx);
+            return Ok(root_idx);
         }
 
-        let mut current = self.root_idx;
+        let mut current = root_idx;
 
         for (idx, component) in components.iter().enumerate() {
             if self.nodes[current].node_type != NodeType::Dir {
                 return Err(-20); // ENOTDIR
             }
 
+            self.check_access(cage_id, current, perm::MAY_EXEC)?;
+            self.populate_dir(cage_id, current);
             let entry_idx = self.lookup_child(current, component).ok_or(-2)?; // ENOENT
             let is_final = idx + 1 == components.len();
 
             current = if is_final && !follow_final {
                 entry_idx
-            } else if let Some(target) = self.nodes[entry_idx].hardlink_target() {
-                target
             } else if let Some(target) = self.nodes[entry_idx].symlink_target() {
                 let parent_path = self.absolute_path_for_node(current);
                 let mut resolved = Self::normalize_path_from_base(&parent_path, target);
@@ -442,20 +527,33 @@ impl ImfsState {
                 if !remaining.is_empty() {
                     resolved = Self::normalize_path_from_base(&resolved, &remaining.join("/"));
                 }
-                return self.resolve_path_inner(&resolved, follow_final, depth + 1);
+                return self.resolve_path_inner(
+                    root_idx,
+                    cage_id,
+                    &resolved,
+                    follow_final,
+                    depth + 1,
+                );
             } else {
                 entry_idx
             };
         }
 
+        if self.nodes[current].node_type == NodeType::Dir {
+            self.populate_dir(cage_id, current);
+        }
         Ok(current)
     }
 
     /// Split a path into its parent directory and final filename.
     /// Returns ENOENT if a parent component is missing, ENOTDIR if an
     /// intermediate component is not a directory.
-    fn resolve_parent_and_name(&self, path: &str) -> Result<(usize, String), i32> {
-        let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    fn resolve_parent_and_name(&mut self, norm_path: &NormPath) -> Result<(usize, String), i32> {
+        let components: Vec<&str> = norm_path
+            .path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
         if components.is_empty() {
             return Err(-2); // ENOENT
         }
@@ -463,10 +561,14 @@ impl ImfsState {
         let filename = components.last().unwrap().to_string();
 
         if components.len() == 1 {
-            return Ok((self.root_idx, filename));
+            self.populate_dir(norm_path.cage_id, norm_path.root);
+            return Ok((norm_path.root, filename));
         }
 
-        let parent_path = format!("/{}", components[..components.len() - 1].join("/"));
+        let parent_path = NormPath {
+            path: format!("/{}", components[..components.len() - 1].join("/")),
+            ..*norm_path
+        };
         let parent_idx = self.resolve_path(&parent_path, true)?;
         if self.nodes[parent_idx].node_type != NodeType::Dir {
             return Err(-20); // ENOTDIR
@@ -476,14 +578,11 @@ impl ImfsState {
     }
 
     fn absolute_path_for_node(&self, node_idx: usize) -> String {
-        if node_idx == self.root_idx {
-            return "/".to_string();
-        }
-
         let mut parts = Vec::new();
         let mut current = node_idx;
 
-        while current != self.root_idx {
+        // Stop at the root of whichever namespace the node is in.
+        while self.nodes[current].parent_idx != current {
             parts.push(self.nodes[current].name.clone());
             current = self.nodes[current].parent_idx;
         }
@@ -492,7 +591,7 @@ impl ImfsState {
         format!("/{}", parts.join("/"))
     }
 
-    fn normalize_path_at(&self, cage_id: u64, dirfd: i32, path: &str) -> Result<String, i32> {
+    fn normalize_path_at(&self, cage_id: u64, dirfd: i32, path: &str) -> Result<NormPath, i32> {
         if path.starts_with('/') {
             return Ok(self.normalize_path_for_cage(cage_id, path));
         }
@@ -506,17 +605,17 @@ impl ImfsState {
             Err(_) => return Err(-9), // EBADF
         };
 
-        let mut node_idx = entry.underfd as usize;
-        while let NodeInfo::HardLink { target } = &self.nodes[node_idx].info {
-            node_idx = *target;
-        }
-
+        let node_idx = entry.underfd as usize;
         if self.nodes[node_idx].node_type != NodeType::Dir {
             return Err(-20); // ENOTDIR
         }
 
         let base = self.absolute_path_for_node(node_idx);
-        Ok(self.normalize_path_for_cage(cage_id, &format!("{}/{}", base, path)))
+        let mut norm_path = self.normalize_path_for_cage(cage_id, &format!("{}/{}", base, path));
+
+        // A dirfd inherited from another namespace resolves in that one.
+        norm_path.root = self.root_of_node(node_idx);
+        Ok(norm_path)
     }
 
     // =====================================================================
@@ -525,6 +624,7 @@ impl ImfsState {
 
     fn fill_stat(&self, node_idx: usize, statbuf: &mut stat) {
         let node = &self.nodes[node_idx];
+        let (rdev_major, rdev_minor) = self.device_number(node_idx);
 
         *statbuf = stat {
             st_dev: 1,
@@ -533,39 +633,65 @@ impl ImfsState {
             st_nlink: self.link_count(node_idx),
             st_uid: node.owner,
             st_gid: node.group,
-            st_rdev: 0,
+            st_rdev: devfs::makedev(rdev_major, rdev_minor),
             st_size: node.total_size as u64,
             st_blksize: IMFS_PREFERRED_IO_SIZE,
-            st_blocks: (node.total_size / IMFS_BLOCK_SIZE as usize) as u32,
+            st_blocks: self
+                .allocated_bytes(node_idx)
+                .div_ceil(IMFS_BLOCK_SIZE as usize) as u32,
             st_atim: node.atime.as_stat_pair(),
             st_mtim: node.mtime.as_stat_pair(),
             st_ctim: node.ctime.as_stat_pair(),
         };
     }
 
-    fn fill_statfs(&self, statbuf: &mut FsData) {
-        let used_bytes: u64 = self.nodes.iter().map(|node| node.total_size as u64).sum();
-        let used_blocks = used_bytes.div_ceil(IMFS_STATFS_BLOCK_SIZE);
-        let free_blocks = IMFS_STATFS_TOTAL_BLOCKS.saturating_sub(used_blocks);
-        let free_nodes =
-            (MAX_NODES.saturating_sub(self.nodes.len()) + self.node_free_list.len()) as u64;
-
-        *statbuf = FsData {
-            f_type: IMFS_STATFS_MAGIC,
-            f_bsize: IMFS_STATFS_BLOCK_SIZE,
-            f_blocks: IMFS_STATFS_TOTAL_BLOCKS,
-            f_bfree: free_blocks,
-            f_bavail: free_blocks,
-            f_files: MAX_NODES as u64,
-            f_ffiles: free_nodes,
-            f_fsid: 0,
-            f_namelen: IMFS_STATFS_NAME_MAX,
-            f_frsize: IMFS_STATFS_BLOCK_SIZE,
-            f_spare: [0; 32],
-        };
-    }
+    /// Fill a statx buffer. Every field IMFS tracks is filled regardless of
+    /// the requested mask, and stx_mask reports all of them, which statx(2)
+    /// permits.
+    fn fill_statx(&self, node_idx: usize, statxbuf: &mut Statx) {
+        let node = &self.nodes[node_idx];
+        let (rdev_major, rdev_minor) = self.device_number(node_idx);
+        let timestamp = |t: NodeTime| StatxTimestamp {
+            tv_sec: t.secs as i64,
+            tv_nsec: t.nanos as u32,
+            __reserved: 0,
+        };
+
+        *statxbuf = Statx {
+            stx_mask: STATX_BASIC_STATS | STATX_BTIME,
+            stx_blksize: IMFS_PREFERRED_IO_SIZE as u32,
+            stx_nlink: self.link_count(node_idx),
+            stx_uid: node.owner,
+            stx_gid: node.group,
+            stx_mode: node.mode as u16,
+            stx_ino: node_idx as u64,
+            stx_size: node.total_size as u64,
+            stx_blocks: self
+                .allocated_bytes(node_idx)
+                .div_ceil(IMFS_BLOCK_SIZE as usize) as u64,
+            stx_atime: timestamp(node.atime),
+            stx_btime: timestamp(node.btime),
+            stx_ctime: timestamp(node.ctime),
+            stx_mtime: timestamp(node.mtime),
+            stx_dev_major: 0,
+            stx_dev_minor: 1,
+            stx_rdev_major: rdev_major,
+            stx_rdev_minor: rdev_minor,
+            ..Statx::default()
+        };
+    }
+
+    fn open_resolved_path(
+        &mut self,
+        cage_id: u64,
+        norm_path: &NormPath,
+        flags: i32,
+        mode: u32,
+    ) -> i32 {
+        if flags & tmpfile::O_TMPFILE == tmpfile::O_TMPFILE {
+            return self.open_tmpfile(cage_id, norm_path, flags, mode);
+        }
 
-    fn open_resolved_path(&mut self, cage_id: u64, norm_path: &str, flags: i32, mode: u32) -> i32 {
         let node_idx = if let Ok(idx) = self.resolve_path(norm_path, true) {
             if (flags & O_EXCL) != 0 && (flags & O_CREAT) != 0 {
                 return -17; // EEXIST
@@ -581,12 +707,29 @@ impl ImfsState {
             }
 
             // Check permissions.
-            let m = self.nodes[idx].mode;
-            match flags & O_ACCMODE {
-                O_RDONLY if m & S_IRUSR == 0 => return -13,
-                O_WRONLY if m & S_IWUSR == 0 => return -13,
-                O_RDWR if m & S_IRUSR == 0 || m & S_IWUSR == 0 => return -13,
-                _ => {}
+            let want = match flags & O_ACCMODE {
+                O_WRONLY => perm::MAY_WRITE,
+                O_RDWR => perm::MAY_READ | perm::MAY_WRITE,
+                _ => perm::MAY_READ,
+            };
+            if let Err(e) = self.check_access(cage_id, idx, want) {
+                return e;
+            }
+
+            if let NodeInfo::Pip { readers, .. } = &self.nodes[idx].info
+                && (flags & O_ACCMODE) == O_WRONLY
+                && (flags & O_NONBLOCK) != 0
+                && *readers == 0
+            {
+                return -6; // ENXIO
+            }
+
+            // A host file in overlay mode is copied up before it can be
+            // written through this fd.
+            if (flags & O_ACCMODE) != O_RDONLY
+                && let Err(e) = self.copy_up(idx, (flags & O_TRUNC) != 0)
+            {
+                return e;
             }
 
             if self.nodes[idx].node_type == NodeType::Reg
@@ -610,7 +753,19 @@ impl ImfsState {
             if filename.len() >= MAX_NODE_NAME {
                 return -36; // ENAMETOOLONG
             }
-            let new_idx = self.create_node(&filename, NodeType::Reg, mode);
+            if let Err(e) = self.check_dir_write(cage_id, parent_idx) {
+                return e;
+            }
+            if let Err(e) = self.check_quota(cage_id, 0, 1) {
+                return e;
+            }
+            let new_idx = self.create_node(
+                cage_id,
+                &filename,
+                NodeType::Reg,
+                self.apply_umask(cage_id, mode),
+            );
+            self.set_new_node_owner(cage_id, parent_idx, new_idx);
             self.add_child(parent_idx, new_idx);
             self.update_mtime(parent_idx);
             self.update_ctime(parent_idx);
@@ -619,24 +774,43 @@ impl ImfsState {
             new_idx
         };
 
-        self.nodes[node_idx].in_use += 1;
+        match self.install_fd(cage_id, node_idx, flags, (flags & O_CLOEXEC) != 0) {
+            Ok(vfd) => vfd as i32,
+            Err(e) => e,
+        }
+    }
+
+    /// Allocate an fd for node_idx with a fresh FDInfo (a new open file
+    /// description) and account for it on the node.
+    fn install_fd(
+        &mut self,
+        cage_id: u64,
+        node_idx: usize,
+        flags: i32,
+        cloexec: bool,
+    ) -> Result<u64, i32> {
+        let vfd = match fdtables::get_unused_virtual_fd(
+            cage_id,
+            IMFS_FDKIND,
+            node_idx as u64,
+            cloexec,
+            0,
+        ) {
+            Ok(vfd) => vfd,
+            Err(_) => return Err(-24), // EMFILE
+        };
 
-        match fdtables::get_unused_virtual_fd(cage_id, IMFS_FDKIND, node_idx as u64, false, 0) {
-            Ok(vfd) => {
-                let new_fdinfo = Arc::new(Mutex::new(FDInfo {
-                    flags: flags as u64,
-                    offset: 0,
-                }));
+        self.nodes[node_idx].in_use += 1;
+        self.pipe_attach(node_idx, flags);
 
-                self.fd_info.insert((cage_id, vfd), new_fdinfo.clone());
+        let new_fdinfo = Arc::new(Mutex::new(FDInfo {
+            flags: flags as u64,
+            offset: 0,
+            lower_fd: None,
+        }));
+        self.fd_info.insert((cage_id, vfd), new_fdinfo);
 
-                vfd as i32
-            }
-            Err(_) => {
-                self.nodes[node_idx].in_use -= 1;
-                -24 // EMFILE
-            }
-        }
+        Ok(vfd)
     }
 
     fn get_offset(&self, cageid: u64, fd: u64) -> i64 {
@@ -652,12 +826,12 @@ impl ImfsState {
     }
 
     // =====================================================================
-    //  Internal chunk read/write
+    //  Internal extent read/write
     // =====================================================================
 
-    /// Read bytes from a regular file's chunk chain starting at the given byte offset.
-    /// Walks the linked list of chunks, skipping past the offset, then copies data
-    /// into buf. Returns the number of bytes actually read (may be less than buf.len()
+    /// Read bytes from a regular file starting at the given byte offset.
+    /// Extents are looked up directly by number, and holes read as zeros.
+    /// Returns the number of bytes actually read (may be less than buf.len()
     /// if EOF is reached).
     fn read_from_node(&self, node_idx: usize, offset: usize, buf: &mut [u8]) -> usize {
         let node = &self.nodes[node_idx];
@@ -666,200 +840,183 @@ impl ImfsState {
         }
 
         let count = buf.len().min(node.total_size - offset);
-        let head = match &node.info {
-            NodeInfo::Reg { head, .. } => *head,
+        let extents = match &node.info {
+            NodeInfo::Reg { extents } => extents,
             _ => return 0,
         };
 
-        let mut chunk_idx = head;
-        let mut local_offset = offset;
-
-        // Skip chunks before the read offset.
-        while let Some(ci) = chunk_idx {
-            if local_offset < CHUNK_SIZE {
-                break;
-            }
-            local_offset -= CHUNK_SIZE;
-            chunk_idx = self.chunks[ci].next;
-        }
-
         let mut read = 0;
         while read < count {
-            let ci = match chunk_idx {
-                Some(ci) => ci,
-                None => break,
-            };
-            let available = self.chunks[ci].used.saturating_sub(local_offset);
-            let to_copy = (count - read).min(available);
-            buf[read..read + to_copy]
-                .copy_from_slice(&self.chunks[ci].data[local_offset..local_offset + to_copy]);
+            let pos = offset + read;
+            let local_offset = pos % EXTENT_SIZE;
+            let to_copy = (count - read).min(EXTENT_SIZE - local_offset);
+            let dst = &mut buf[read..read + to_copy];
+
+            // An extent only stores bytes up to the last one written; the
+            // rest of it, like a missing extent, is zeros.
+            let data: &[u8] = extents
+                .get(&(pos / EXTENT_SIZE))
+                .map(|extent| extent.as_slice())
+                .unwrap_or(&[]);
+            let stored = data.len().saturating_sub(local_offset).min(to_copy);
+            dst[..stored].copy_from_slice(&data[local_offset..local_offset + stored]);
+            dst[stored..].fill(0);
+
             read += to_copy;
-            local_offset = 0;
-            chunk_idx = self.chunks[ci].next;
         }
 
         read
     }
 
-    /// Write bytes to a regular file's chunk chain starting at the given byte offset.
-    /// Allocates new chunks as needed when writing past the end. Zero-fills any holes
-    /// between the chunk's current used size and the write offset. Updates the node's
-    /// total_size if the write extends the file. Returns the number of bytes written.
-    fn write_to_node(&mut self, node_idx: usize, offset: usize, buf: &[u8]) -> usize {
-        if buf.is_empty() {
-            return 0;
+    /// Read from a regular file, going to its host file in overlay mode if
+    /// it has not been copied up. Returns the number of bytes read or a
+    /// negative errno.
+    fn read_reg(
+        &self,
+        cage_id: u64,
+        fd: u64,
+        node_idx: usize,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> i32 {
+        if self.nodes[node_idx].lower_pending {
+            return self.read_lower(cage_id, fd, node_idx, offset, buf);
         }
+        self.read_from_node(node_idx, offset, buf) as i32
+    }
 
-        let mut chunk_idx = match &self.nodes[node_idx].info {
-            NodeInfo::Reg { head, .. } => *head,
+    /// Write bytes to a regular file starting at the given byte offset.
+    /// Allocates extents as needed; skipped-over ranges stay holes. Updates
+    /// the node's total_size if the write extends the file. Returns the
+    /// number of bytes written. Callers check the quota first, see
+    /// check_file_growth.
+    fn write_to_node(&mut self, node_idx: usize, offset: usize, buf: &[u8]) -> usize {
+        let extents = match &mut self.nodes[node_idx].info {
+            NodeInfo::Reg { extents } => extents,
             _ => return 0,
         };
 
-        let mut local_offset = offset;
-
-        // Skip to the chunk containing the write offset.
-        while let Some(ci) = chunk_idx {
-            if local_offset < CHUNK_SIZE {
-                break;
-            }
-            self.chunks[ci].used = CHUNK_SIZE;
-            local_offset -= CHUNK_SIZE;
-            chunk_idx = self.chunks[ci].next;
-        }
-
         let mut written = 0;
-
+        let mut grown = 0;
         while written < buf.len() {
-            let ci = match chunk_idx {
-                Some(ci) => ci,
-                None => {
-                    // Allocate a new chunk and link it.
-                    let new_ci = self.alloc_chunk();
-                    match &mut self.nodes[node_idx].info {
-                        NodeInfo::Reg { head, tail } => {
-                            if let Some(t) = *tail {
-                                self.chunks[t].next = Some(new_ci);
-                            }
-                            if head.is_none() {
-                                *head = Some(new_ci);
-                            }
-                            *tail = Some(new_ci);
-                        }
-                        _ => return written,
-                    }
-                    new_ci
-                }
-            };
-
-            if local_offset >= CHUNK_SIZE {
-                self.chunks[ci].used = CHUNK_SIZE;
-                local_offset -= CHUNK_SIZE;
-                chunk_idx = self.chunks[ci].next;
-                continue;
+            let pos = offset + written;
+            let local_offset = pos % EXTENT_SIZE;
+            let to_copy = (buf.len() - written).min(EXTENT_SIZE - local_offset);
+
+            // make_mut copies an extent still shared with a namespace clone.
+            let extent = Arc::make_mut(extents.entry(pos / EXTENT_SIZE).or_default());
+            if extent.len() < local_offset + to_copy {
+                grown += local_offset + to_copy - extent.len();
+                extent.resize(local_offset + to_copy, 0);
             }
-
-            let space = CHUNK_SIZE - local_offset;
-            let to_copy = (buf.len() - written).min(space);
-
-            // Zero-fill holes.
-            let used = self.chunks[ci].used;
-            if local_offset > used {
-                self.chunks[ci].data[used..local_offset].fill(0);
-            }
-
-            self.chunks[ci].data[local_offset..local_offset + to_copy]
+            extent[local_offset..local_offset + to_copy]
                 .copy_from_slice(&buf[written..written + to_copy]);
 
-            if local_offset + to_copy > self.chunks[ci].used {
-                self.chunks[ci].used = local_offset + to_copy;
-            }
-
             written += to_copy;
-            local_offset = 0;
-            chunk_idx = self.chunks[ci].next;
         }
 
         if offset + written > self.nodes[node_idx].total_size {
             self.nodes[node_idx].total_size = offset + written;
         }
+        self.charge_file_bytes(node_idx, 0, grown);
 
         written
     }
 
-    fn truncate_node(&mut self, node_idx: usize, new_size: usize) {
-        let old_size = self.nodes[node_idx].total_size;
-
-        if new_size == old_size {
+    /// Make sure every extent overlapping [start, end) is allocated, filling
+    /// new space with zeros.
+    fn allocate_range(&mut self, node_idx: usize, start: usize, end: usize) {
+        let NodeInfo::Reg { extents } = &mut self.nodes[node_idx].info else {
             return;
-        }
+        };
 
-        if new_size > old_size {
-            let zero = [0u8; 1];
-            let _ = self.write_to_node(node_idx, new_size - 1, &zero);
-            return;
+        let mut grown = 0;
+        for idx in start / EXTENT_SIZE..end.div_ceil(EXTENT_SIZE) {
+            let len = (end - idx * EXTENT_SIZE).min(EXTENT_SIZE);
+            let extent = extents.entry(idx).or_default();
+            if extent.len() < len {
+                grown += len - extent.len();
+                Arc::make_mut(extent).resize(len, 0);
+            }
         }
+        self.charge_file_bytes(node_idx, 0, grown);
+    }
 
-        if new_size == 0 {
-            let head = match &mut self.nodes[node_idx].info {
-                NodeInfo::Reg { head, tail } => {
-                    let old_head = *head;
-                    *head = None;
-                    *tail = None;
-                    old_head
-                }
-                _ => return,
-            };
-
-            self.reclaim_chunk_chain(head);
-            self.nodes[node_idx].total_size = 0;
+    /// Zero [start, end), dropping extents the range covers completely so
+    /// they become holes.
+    fn punch_range(&mut self, node_idx: usize, start: usize, end: usize) {
+        let NodeInfo::Reg { extents } = &mut self.nodes[node_idx].info else {
             return;
-        }
-
-        let mut remaining = new_size;
-        let mut current = match &self.nodes[node_idx].info {
-            NodeInfo::Reg { head, .. } => *head,
-            _ => return,
         };
 
-        let mut last_keep = None;
+        let first = start / EXTENT_SIZE;
+        let last = end.div_ceil(EXTENT_SIZE);
+        let covered: Vec<usize> = extents.range(first..last).map(|(idx, _)| *idx).collect();
 
-        while let Some(ci) = current {
-            if remaining > CHUNK_SIZE {
-                remaining -= CHUNK_SIZE;
-                last_keep = Some(ci);
-                current = self.chunks[ci].next;
+        let mut freed = 0;
+        for idx in covered {
+            let extent_start = idx * EXTENT_SIZE;
+            let local_start = start.saturating_sub(extent_start);
+            let local_end = (end - extent_start).min(EXTENT_SIZE);
+
+            if local_start == 0 && local_end == EXTENT_SIZE {
+                freed += extents.remove(&idx).map_or(0, |extent| extent.len());
                 continue;
             }
 
-            self.chunks[ci].used = remaining;
-            let to_reclaim = self.chunks[ci].next;
-            self.chunks[ci].next = None;
-            self.reclaim_chunk_chain(to_reclaim);
-
-            if let NodeInfo::Reg { tail, .. } = &mut self.nodes[node_idx].info {
-                *tail = Some(ci);
+            let extent = extents.get_mut(&idx).unwrap();
+            if local_start < extent.len() {
+                let local_end = local_end.min(extent.len());
+                Arc::make_mut(extent)[local_start..local_end].fill(0);
             }
-
-            self.nodes[node_idx].total_size = new_size;
-            return;
         }
+        self.charge_file_bytes(node_idx, freed, 0);
+    }
+
+    fn truncate_node(&mut self, node_idx: usize, new_size: usize) {
+        let old_size = self.nodes[node_idx].total_size;
 
-        if let Some(ci) = last_keep {
-            self.chunks[ci].next = None;
-            if let NodeInfo::Reg { tail, .. } = &mut self.nodes[node_idx].info {
-                *tail = Some(ci);
+        // Growing a file only moves EOF: the new range is a hole.
+        if new_size < old_size
+            && let NodeInfo::Reg { extents } = &mut self.nodes[node_idx].info
+        {
+            let mut freed: usize = extents
+                .split_off(&new_size.div_ceil(EXTENT_SIZE))
+                .values()
+                .map(|extent| extent.len())
+                .sum();
+
+            let local_end = new_size % EXTENT_SIZE;
+            if local_end != 0
+                && let Some(extent) = extents.get_mut(&(new_size / EXTENT_SIZE))
+                && extent.len() > local_end
+            {
+                freed += extent.len() - local_end;
+                Arc::make_mut(extent).truncate(local_end);
             }
+            self.charge_file_bytes(node_idx, freed, 0);
         }
 
         self.nodes[node_idx].total_size = new_size;
     }
 
+    /// Bytes of file data actually stored for a node, for st_blocks and
+    /// statfs. Holes don't count. A host file in overlay mode counts as
+    /// fully allocated.
+    fn allocated_bytes(&self, node_idx: usize) -> usize {
+        if self.nodes[node_idx].lower_pending {
+            return self.nodes[node_idx].total_size;
+        }
+        match &self.nodes[node_idx].info {
+            NodeInfo::Reg { extents } => extents.values().map(|extent| extent.len()).sum(),
+            _ => self.nodes[node_idx].total_size,
+        }
+    }
     // =====================================================================
     //  FD resolution helpers
     // =====================================================================
 
     // Helper function to get the node_idx and flag for a give cageid and fd.
-    // In case the node_idx points to a Lnk, it follows the link until we hit a real Node.
     fn get_node_and_flags(&mut self, cage_id: u64, fd: u64) -> Result<(usize, i32), i32> {
         let entry = match fdtables::translate_virtual_fd(cage_id, fd) {
             Ok(e) => e,
@@ -871,15 +1028,7 @@ impl ImfsState {
         let fd_info = self.fd_info.get(&(cage_id, fd)).unwrap().lock().unwrap();
         let flags = fd_info.flags as i32;
 
-        let mut idx = node_idx;
-
-        // If the node is a Link, follow until we hit an actual Node.
-        // Streamlines process of reading/writing symlink'd files.
-        while let NodeInfo::HardLink { target } = &self.nodes[idx].info {
-            idx = *target;
-        }
-
-        return Ok((idx as usize, flags));
+        return Ok((node_idx, flags));
     }
 
     pub fn insert_perfdinfo(&mut self, cageid: u64, fd: u64, flags: u64) {
@@ -888,6 +1037,7 @@ impl ImfsState {
             Arc::new(Mutex::new(FDInfo {
                 flags: flags,
                 offset: 0,
+                lower_fd: None,
             })),
         );
     }
@@ -908,20 +1058,11 @@ impl ImfsState {
             return -9;
         }
 
-        if let NodeInfo::Pip {
-            readers, writers, ..
-        } = &mut self.nodes[node_idx].info
-        {
-            let flags = fd_info.lock().unwrap().flags as i32;
-            match flags & O_ACCMODE {
-                O_WRONLY => *writers += 1,
-                _ => *readers += 1,
-            }
-        }
-
         match fdtables::get_unused_virtual_fd(cage_id, IMFS_FDKIND, entry.underfd, false, 0) {
             Ok(newfd) => {
                 self.nodes[node_idx].in_use += 1;
+                let flags = fd_info.lock().unwrap().flags as i32;
+                self.pipe_attach(node_idx, flags);
                 self.fd_info.insert((cage_id, newfd), fd_info);
                 newfd as i32
             }
@@ -966,16 +1107,8 @@ impl ImfsState {
         ) {
             Ok(_) => {
                 self.nodes[node_idx].in_use += 1;
-                if let NodeInfo::Pip {
-                    readers, writers, ..
-                } = &mut self.nodes[node_idx].info
-                {
-                    let flags = fd_info.lock().unwrap().flags as i32;
-                    match flags & O_ACCMODE {
-                        O_WRONLY => *writers += 1,
-                        _ => *readers += 1,
-                    }
-                }
+                let flags = fd_info.lock().unwrap().flags as i32;
+                self.pipe_attach(node_idx, flags);
                 self.fd_info.insert((cage_id, newfd), fd_info);
                 newfd as i32
             }
@@ -1013,16 +1146,8 @@ impl ImfsState {
         ) {
             Ok(newfd) => {
                 self.nodes[node_idx].in_use += 1;
-                if let NodeInfo::Pip {
-                    readers, writers, ..
-                } = &mut self.nodes[node_idx].info
-                {
-                    let flags = fd_info.lock().unwrap().flags as i32;
-                    match flags & O_ACCMODE {
-                        O_WRONLY => *writers += 1,
-                        _ => *readers += 1,
-                    }
-                }
+                let flags = fd_info.lock().unwrap().flags as i32;
+                self.pipe_attach(node_idx, flags);
                 self.fd_info.insert((cage_id, newfd), fd_info);
                 newfd as i32
             }
@@ -1038,10 +1163,20 @@ impl ImfsState {
     //    - offsets HashMap for per-fd read/write position
     // =====================================================================
 
-    /// fork: shares the FDInfo information to the child cage.
+    /// fork: shares the FDInfo information to the child cage. The child's
+    /// copies of the parent's fds count as extra references to their nodes,
+    /// so a pipe stays open until both cages have closed their ends.
     pub fn fork(&mut self, parent_cage: u64, child_cage: u64) {
         for ((cage_id, fd), underfd_arc) in self.fd_info.clone().iter() {
             if *cage_id == parent_cage {
+                if let Ok(entry) = fdtables::translate_virtual_fd(parent_cage, *fd) {
+                    let node_idx = entry.underfd as usize;
+                    if node_idx < self.nodes.len() {
+                        let flags = underfd_arc.lock().unwrap().flags as i32;
+                        self.nodes[node_idx].in_use += 1;
+                        self.pipe_attach(node_idx, flags);
+                    }
+                }
                 self.fd_info.insert((child_cage, *fd), underfd_arc.clone());
             }
         }
@@ -1049,6 +1184,57 @@ impl ImfsState {
         if let Some(cwd) = self.cwd_info.get(&parent_cage).cloned() {
             self.cwd_info.insert(child_cage, cwd);
         }
+        if let Some(exe) = self.exe_paths.get(&parent_cage).cloned() {
+            self.exe_paths.insert(child_cage, exe);
+        }
+
+        self.fork_credentials(parent_cage, child_cage);
+        self.fork_namespace(parent_cage, child_cage);
+    }
+
+    /// exec: close every fd of the cage that is marked close-on-exec. Must run
+    /// before fdtables::empty_fds_for_exec so the nodes (and pipe ends) those
+    /// fds reference are released.
+    pub fn exec(&mut self, cage_id: u64) {
+        let cloexec_fds: Vec<u64> = self
+            .fd_info
+            .keys()
+            .filter(|(cage, _)| *cage == cage_id)
+            .map(|(_, fd)| *fd)
+            .filter(|fd| {
+                fdtables::translate_virtual_fd(cage_id, *fd)
+                    .map(|entry| entry.should_cloexec)
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        for fd in cloexec_fds {
+            self.close(cage_id, fd);
+        }
+    }
+
+    /// exit: close every fd of the exiting cage and drop its fd table and
+    /// working directory.
+    pub fn exit(&mut self, cage_id: u64) {
+        let fds: Vec<u64> = self
+            .fd_info
+            .keys()
+            .filter(|(cage, _)| *cage == cage_id)
+            .map(|(_, fd)| *fd)
+            .collect();
+
+        for fd in fds {
+            self.close(cage_id, fd);
+        }
+
+        if fdtables::check_cage_exists(cage_id) {
+            fdtables::remove_cage_from_fdtable(cage_id);
+        }
+        self.cwd_info.remove(&cage_id);
+        self.exe_paths.remove(&cage_id);
+        self.clear_lock_wait(cage_id);
+        self.exit_credentials(cage_id);
+        self.exit_namespace(cage_id);
     }
 
     /// chdir
@@ -1062,8 +1248,11 @@ impl ImfsState {
         if self.nodes[node_idx].node_type != NodeType::Dir {
             return -20; // ENOTDIR
         }
+        if let Err(e) = self.check_access(cage_id, node_idx, perm::MAY_EXEC) {
+            return e;
+        }
 
-        self.cwd_info.insert(cage_id, norm_path);
+        self.cwd_info.insert(cage_id, norm_path.path);
         0
     }
 
@@ -1074,24 +1263,18 @@ impl ImfsState {
             Err(_) => return -9, // EBADF
         };
 
-        let mut node_idx = entry.underfd as usize;
+        let node_idx = entry.underfd as usize;
 
         if node_idx >= self.nodes.len() {
             return -9; // EBADF
         }
 
-        // Follow link nodes until reaching the actual target.
-        while let NodeInfo::HardLink { target } = &self.nodes[node_idx].info {
-            node_idx = *target;
-
-            if node_idx >= self.nodes.len() {
-                return -9; // EBADF
-            }
-        }
-
         if self.nodes[node_idx].node_type != NodeType::Dir {
             return -20; // ENOTDIR
         }
+        if let Err(e) = self.check_access(cage_id, node_idx, perm::MAY_EXEC) {
+            return e;
+        }
 
         let cwd = self.absolute_path_for_node(node_idx);
         self.cwd_info.insert(cage_id, cwd);
@@ -1116,7 +1299,7 @@ impl ImfsState {
         self.access_resolved_path(&norm_path, mode)
     }
 
-    fn access_resolved_path(&mut self, norm_path: &str, mode: i32) -> i32 {
+    fn access_resolved_path(&mut self, norm_path: &NormPath, mode: i32) -> i32 {
         let node_idx = match self.resolve_path(&norm_path, true) {
             Ok(idx) => idx,
             Err(e) => return e,
@@ -1126,18 +1309,11 @@ impl ImfsState {
             return if node_idx < self.nodes.len() { 0 } else { -2 };
         }
 
-        let m = self.nodes[node_idx].mode;
-        if (mode & R_OK) != 0 && (m & S_IRUSR) == 0 {
-            return -13; // EACCES
-        }
-        if (mode & W_OK) != 0 && (m & S_IWUSR) == 0 {
-            return -13; // EACCES
-        }
-        if (mode & X_OK) != 0 && (m & S_IXUSR) == 0 {
-            return -13; // EACCES
+        // R_OK, W_OK and X_OK have the same values as the MAY_* bits.
+        match self.check_access(norm_path.cage_id, node_idx, mode as u32 & 0o7) {
+            Ok(()) => 0,
+            Err(e) => e,
         }
-
-        0
     }
 
     /// xstat
@@ -1155,7 +1331,7 @@ impl ImfsState {
         let norm_path = self.normalize_path_for_cage(cage_id, path);
         match self.resolve_path(&norm_path, true) {
             Ok(_) => {
-                self.fill_statfs(statbuf);
+                self.fill_statfs(cage_id, statbuf);
                 0
             }
             Err(e) => e,
@@ -1177,23 +1353,10 @@ impl ImfsState {
         }
 
         if path.is_empty() && flags & LIND_AT_EMPTY_PATH != 0 {
-            let node_idx = if dirfd == LIND_AT_FDCWD {
-                let norm_path = self.normalize_path_for_cage(cage_id, ".");
-                match self.resolve_path(&norm_path, true) {
-                    Ok(idx) => idx,
-                    Err(e) => return e,
-                }
-            } else {
-                let entry = match fdtables::translate_virtual_fd(cage_id, dirfd as u64) {
-                    Ok(entry) => entry,
-                    Err(_) => return -9, // EBADF
-                };
-                entry.underfd as usize
+            let node_idx = match self.empty_path_node_idx(cage_id, dirfd) {
+                Ok(idx) => idx,
+                Err(e) => return e,
             };
-
-            if node_idx >= self.nodes.len() {
-                return -9; // EBADF
-            }
             self.fill_stat(node_idx, statbuf);
             self.update_atime(node_idx);
             return 0;
@@ -1206,9 +1369,75 @@ impl ImfsState {
         self.stat_resolved_path(&norm_path, statbuf, flags & AT_SYMLINK_NOFOLLOW == 0)
     }
 
+    /// statx: like statat, plus the requested-field mask and birth time.
+    pub fn statx(
+        &mut self,
+        cage_id: u64,
+        dirfd: i32,
+        path: &str,
+        flags: i32,
+        mask: u32,
+        statxbuf: &mut Statx,
+    ) -> i32 {
+        let supported_flags = AT_SYMLINK_NOFOLLOW
+            | LIND_AT_NO_AUTOMOUNT
+            | LIND_AT_EMPTY_PATH
+            | AT_STATX_FORCE_SYNC
+            | AT_STATX_DONT_SYNC;
+        if flags & !supported_flags != 0 {
+            return -22; // EINVAL
+        }
+        if flags & AT_STATX_FORCE_SYNC != 0 && flags & AT_STATX_DONT_SYNC != 0 {
+            return -22; // EINVAL
+        }
+        if mask & STATX_RESERVED != 0 {
+            return -22; // EINVAL
+        }
+
+        let node_idx = if path.is_empty() {
+            if flags & LIND_AT_EMPTY_PATH == 0 {
+                return -2; // ENOENT
+            }
+            match self.empty_path_node_idx(cage_id, dirfd) {
+                Ok(idx) => idx,
+                Err(e) => return e,
+            }
+        } else {
+            let norm_path = match self.normalize_path_at(cage_id, dirfd, path) {
+                Ok(path) => path,
+                Err(e) => return e,
+            };
+            match self.resolve_path(&norm_path, flags & AT_SYMLINK_NOFOLLOW == 0) {
+                Ok(idx) => idx,
+                Err(e) => return e,
+            }
+        };
+
+        self.fill_statx(node_idx, statxbuf);
+        0
+    }
+
+    /// Node referred to by dirfd alone, for AT_EMPTY_PATH calls.
+    fn empty_path_node_idx(&mut self, cage_id: u64, dirfd: i32) -> Result<usize, i32> {
+        let node_idx = if dirfd == LIND_AT_FDCWD {
+            let norm_path = self.normalize_path_for_cage(cage_id, ".");
+            self.resolve_path(&norm_path, true)?
+        } else {
+            match fdtables::translate_virtual_fd(cage_id, dirfd as u64) {
+                Ok(entry) => entry.underfd as usize,
+                Err(_) => return Err(-9), // EBADF
+            }
+        };
+
+        if node_idx >= self.nodes.len() {
+            return Err(-9); // EBADF
+        }
+        Ok(node_idx)
+    }
+
     fn stat_resolved_path(
         &mut self,
-        norm_path: &str,
+        norm_path: &NormPath,
         statbuf: &mut stat,
         follow_final: bool,
     ) -> i32 {
@@ -1239,7 +1468,7 @@ impl ImfsState {
             return e;
         }
 
-        self.fill_statfs(statbuf);
+        self.fill_statfs(cage_id, statbuf);
         0
     }
 
@@ -1249,13 +1478,13 @@ impl ImfsState {
         self.rmdir_resolved_path(&norm_path)
     }
 
-    fn rmdir_resolved_path(&mut self, norm_path: &str) -> i32 {
+    fn rmdir_resolved_path(&mut self, norm_path: &NormPath) -> i32 {
         let node_idx = match self.resolve_path(&norm_path, true) {
             Ok(idx) => idx,
             Err(e) => return e,
         };
 
-        if node_idx == self.root_idx {
+        if self.nodes[node_idx].parent_idx == node_idx {
             return -16; // EBUSY
         }
 
@@ -1272,7 +1501,17 @@ impl ImfsState {
         }
 
         let parent_idx = self.nodes[node_idx].parent_idx;
-        self.remove_child(node_idx);
+        let name = self.nodes[node_idx].name.clone();
+        let cage_id = norm_path.cage_id;
+        if let Err(e) = self
+            .check_dir_write(cage_id, parent_idx)
+            .and_then(|_| self.check_sticky(cage_id, parent_idx, node_idx))
+        {
+            return e;
+        }
+
+        self.record_whiteout(parent_idx, &name, node_idx);
+        self.remove_entry(parent_idx, &name);
         self.update_mtime(parent_idx);
         self.update_ctime(parent_idx);
         self.update_ctime(node_idx);
@@ -1301,6 +1540,11 @@ impl ImfsState {
         if let Ok(entry) = fdtables::translate_virtual_fd(cage_id, fd) {
             let node_idx = entry.underfd as usize;
             if node_idx < self.nodes.len() {
+                if let Some(fd_info) = self.fd_info.get(&(cage_id, fd)) {
+                    let flags = fd_info.lock().unwrap().flags as i32;
+                    self.pipe_detach(node_idx, flags);
+                }
+                self.release_locks_on_close(cage_id, fd, node_idx);
                 self.nodes[node_idx].in_use = self.nodes[node_idx].in_use.saturating_sub(1);
                 if self.nodes[node_idx].doomed && self.nodes[node_idx].in_use == 0 {
                     self.reclaim_node(node_idx);
@@ -1326,19 +1570,24 @@ impl ImfsState {
             Err(e) => return e,
         };
 
-        match &self.nodes[node_idx].info {
-            NodeInfo::Reg { head: _, tail: _ } => {}
-            _ => return -9,
-        };
-
         if (flags & O_ACCMODE) == O_WRONLY {
             return -9;
         }
 
+        match &self.nodes[node_idx].info {
+            NodeInfo::Reg { .. } => {}
+            NodeInfo::Pip { .. } => return self.pipe_read(node_idx, buf),
+            NodeInfo::Chr { dev } => return dev.read(buf),
+            _ => return -9,
+        };
+
         // Get the current offset from our tracking.
         let offset = self.get_offset(cage_id, fd);
 
-        let n = self.read_from_node(node_idx, offset as usize, buf);
+        let n = self.read_reg(cage_id, fd, node_idx, offset as usize, buf);
+        if n < 0 {
+            return n;
+        }
         if n > 0 {
             self.update_atime(node_idx);
         }
@@ -1346,7 +1595,7 @@ impl ImfsState {
         // Advance the offset.
         self.set_offset(cage_id, fd, offset + n as i64);
 
-        n as i32
+        n
     }
 
     /// pread: read at a specific offset without changing the fd offset.
@@ -1362,22 +1611,25 @@ impl ImfsState {
         };
 
         // Return EBADF for reads on non regular files.
-        // TODO: Implement pipe reads.
         match &self.nodes[node_idx].info {
-            NodeInfo::Reg { head: _, tail: _ } => {}
+            NodeInfo::Reg { .. } | NodeInfo::Chr { .. } => {}
+            NodeInfo::Pip { .. } => return -29, // ESPIPE
             _ => return -9,
         };
 
         if (flags & O_ACCMODE) == O_WRONLY {
             return -9;
         }
+        if let NodeInfo::Chr { dev } = self.nodes[node_idx].info {
+            return dev.read(buf);
+        }
 
-        let n = self.read_from_node(node_idx, offset as usize, buf);
+        let n = self.read_reg(cage_id, fd, node_idx, offset as usize, buf);
         if n > 0 {
             self.update_atime(node_idx);
         }
 
-        n as i32
+        n
     }
 
     /// write: write to a file at the current offset.
@@ -1387,22 +1639,28 @@ impl ImfsState {
             Err(e) => return e,
         };
 
-        match &self.nodes[node_idx].info {
-            NodeInfo::Reg { head: _, tail: _ } => {}
-            _ => return -9,
-        };
-
         if (flags & O_ACCMODE) == O_RDONLY {
             return -9;
         }
 
+        match &self.nodes[node_idx].info {
+            NodeInfo::Reg { .. } => {}
+            NodeInfo::Pip { .. } => return self.pipe_write(node_idx, buf),
+            NodeInfo::Chr { dev } => return dev.write(buf),
+            _ => return -9,
+        };
+
         let offset = if (flags & O_APPEND) != 0 {
             self.nodes[node_idx].total_size as i64
         } else {
             self.get_offset(cage_id, fd)
         };
 
-        let n = self.write_to_node(node_idx, offset as usize, buf);
+        let start = offset as usize;
+        if let Err(e) = self.check_file_growth(node_idx, start, start + buf.len()) {
+            return e;
+        }
+        let n = self.write_to_node(node_idx, start, buf);
         if n > 0 {
             self.update_mtime(node_idx);
             self.update_ctime(node_idx);
@@ -1426,17 +1684,24 @@ impl ImfsState {
         };
 
         // Return EBADF for writes on non regular files.
-        // TODO: Implement pipe reads.
         match &self.nodes[node_idx].info {
-            NodeInfo::Reg { head: _, tail: _ } => {}
+            NodeInfo::Reg { .. } | NodeInfo::Chr { .. } => {}
+            NodeInfo::Pip { .. } => return -29, // ESPIPE
             _ => return -9,
         };
 
         if (flags & O_ACCMODE) == O_RDONLY {
             return -9;
         }
+        if let NodeInfo::Chr { dev } = self.nodes[node_idx].info {
+            return dev.write(buf);
+        }
 
-        let n = self.write_to_node(node_idx, offset as usize, buf);
+        let start = offset as usize;
+        if let Err(e) = self.check_file_growth(node_idx, start, start + buf.len()) {
+            return e;
+        }
+        let n = self.write_to_node(node_idx, start, buf);
         if n > 0 {
             self.update_mtime(node_idx);
             self.update_ctime(node_idx);
@@ -1452,21 +1717,38 @@ impl ImfsState {
             Err(e) => return e,
         };
 
-        // Only valid for regular files,
+        let current = self.get_offset(cage_id, fd);
+
+        // Only valid for regular files and directories. A directory offset
+        // is a getdents cookie, so only absolute and relative seeks work.
         match &self.nodes[node_idx].info {
             NodeInfo::Reg { .. } => {}
             NodeInfo::Pip { .. } => return -29, // EISPIPE
-            NodeInfo::Dir { .. } => return offset as i32, // On directory lseeks, we return offset
-            // immediately.
+            // Devices have no position, like /dev/null on Linux.
+            NodeInfo::Chr { .. } => return 0,
+            NodeInfo::Dir { .. } => {
+                let new_offset = match whence {
+                    SEEK_SET => offset,
+                    SEEK_CUR => current + offset,
+                    _ => return -22, // EINVAL
+                };
+                if new_offset < 0 {
+                    return -22;
+                }
+                self.set_offset(cage_id, fd, new_offset);
+                return new_offset as i32;
+            }
             _ => return -9, // EBADF on Free/Lnk (will never be hit)
         };
 
-        let current = self.get_offset(cage_id, fd);
-
         let new_offset = match whence {
             SEEK_SET => offset,
             SEEK_CUR => current + offset,
             SEEK_END => self.nodes[node_idx].total_size as i64 + offset,
+            SEEK_DATA | SEEK_HOLE => match self.seek_data_or_hole(node_idx, offset, whence) {
+                Ok(pos) => pos,
+                Err(e) => return e,
+            },
             _ => return -22,
         };
 
@@ -1479,7 +1761,7 @@ impl ImfsState {
         new_offset as i32
     }
 
-    /// fcntl: only F_GETFL implemented — returns flags from fdtables perfdinfo.
+    /// fcntl: dup, close-on-exec and file status flag operations.
     pub fn fcntl(&mut self, cage_id: u64, fd: u64, op: i32, arg: i32) -> i32 {
         match op {
             F_DUPFD => self.dup_from_startfd(cage_id, fd, arg, false),
@@ -1506,6 +1788,17 @@ impl ImfsState {
 
                 fd_info.flags as i32
             }
+            F_SETFL => {
+                let Some(fd_info) = self.fd_info.get(&(cage_id, fd)) else {
+                    return -9;
+                };
+                let mut fd_info = fd_info.lock().unwrap();
+
+                // Only the status flags can change; the access mode is fixed at open.
+                let settable = (O_APPEND | O_NONBLOCK) as u64;
+                fd_info.flags = (fd_info.flags & !settable) | (arg as u64 & settable);
+                0
+            }
             _ => -1,
         }
     }
@@ -1530,17 +1823,14 @@ impl ImfsState {
         if start < 0 {
             return -22; // EINVAL
         }
+        self.refresh_proc_dir(cage_id, node_idx);
 
-        let children = match &self.nodes[node_idx].info {
-            NodeInfo::Dir { children } => children.clone(),
-            _ => unreachable!(),
-        };
-
-        let mut entry_idx = start as usize;
+        // The offset is the cookie of the next entry to return, see
+        // DirEntries.
+        let mut next_cookie = start as u64;
         let mut written = 0usize;
 
-        while entry_idx < children.len() {
-            let entry = &children[entry_idx];
+        for (cookie, entry) in self.nodes[node_idx].children().iter_from(next_cookie) {
             let reclen = Self::dirent_reclen(entry.name.len());
 
             if reclen > buf.len() {
@@ -1550,21 +1840,20 @@ impl ImfsState {
                 break;
             }
 
-            let next_offset = (entry_idx + 1) as u64;
             let d_type = self.dirent_type(entry.node_idx);
             let ino = (entry.node_idx as u64) + 1;
 
             written += Self::write_dirent_record(
                 &mut buf[written..written + reclen],
                 ino,
-                next_offset,
+                cookie + 1,
                 d_type,
                 &entry.name,
             );
-            entry_idx += 1;
+            next_cookie = cookie + 1;
         }
 
-        self.set_offset(cage_id, fd, entry_idx as i64);
+        self.set_offset(cage_id, fd, next_cookie as i64);
 
         written as i32
     }
@@ -1593,8 +1882,8 @@ impl ImfsState {
         }
     }
 
-    fn unlink_resolved_path(&mut self, norm_path: &str) -> i32 {
-        if norm_path == "/" {
+    fn unlink_resolved_path(&mut self, norm_path: &NormPath) -> i32 {
+        if norm_path.path == "/" {
             return -1; // EPERM
         }
 
@@ -1616,7 +1905,16 @@ impl ImfsState {
             return -21; // EISDIR
         };
 
-        self.remove_child(node_idx);
+        let cage_id = norm_path.cage_id;
+        if let Err(e) = self
+            .check_dir_write(cage_id, parent_idx)
+            .and_then(|_| self.check_sticky(cage_id, parent_idx, node_idx))
+        {
+            return e;
+        }
+
+        self.record_whiteout(parent_idx, &filename, node_idx);
+        self.remove_entry(parent_idx, &filename);
         self.update_mtime(parent_idx);
         self.update_ctime(parent_idx);
         self.update_ctime(node_idx);
@@ -1640,11 +1938,28 @@ impl ImfsState {
         newpath: &str,
         flags: i32,
     ) -> i32 {
-        let supported_flags = LIND_AT_SYMLINK_FOLLOW;
+        let supported_flags = LIND_AT_SYMLINK_FOLLOW | LIND_AT_EMPTY_PATH;
         if flags & !supported_flags != 0 {
             return -22; // EINVAL
         }
 
+        // AT_EMPTY_PATH links the file olddirfd refers to, which may be an
+        // O_TMPFILE file with no name yet.
+        if oldpath.is_empty() {
+            if flags & LIND_AT_EMPTY_PATH == 0 {
+                return -2; // ENOENT
+            }
+            let old_idx = match self.empty_path_node_idx(cage_id, olddirfd) {
+                Ok(idx) => idx,
+                Err(e) => return e,
+            };
+            let norm_newpath = match self.normalize_path_at(cage_id, newdirfd, newpath) {
+                Ok(path) => path,
+                Err(e) => return e,
+            };
+            return self.link_node(old_idx, &norm_newpath);
+        }
+
         let norm_oldpath = match self.normalize_path_at(cage_id, olddirfd, oldpath) {
             Ok(path) => path,
             Err(e) => return e,
@@ -1662,15 +1977,19 @@ impl ImfsState {
 
     fn link_resolved_paths(
         &mut self,
-        norm_oldpath: &str,
-        norm_newpath: &str,
+        norm_oldpath: &NormPath,
+        norm_newpath: &NormPath,
         follow_old: bool,
     ) -> i32 {
         let old_idx = match self.resolve_path(norm_oldpath, follow_old) {
             Ok(idx) => idx,
             Err(e) => return e,
         };
+        self.link_node(old_idx, norm_newpath)
+    }
 
+    /// Add a directory entry for old_idx at norm_newpath.
+    fn link_node(&mut self, old_idx: usize, norm_newpath: &NormPath) -> i32 {
         if self.nodes[old_idx].node_type == NodeType::Dir {
             return -1; // EPERM
         }
@@ -1696,21 +2015,27 @@ impl ImfsState {
             return -36; // ENAMETOOLONG
         }
 
-        let mode = self.nodes[old_idx].mode;
+        if let Err(e) = self.check_dir_write(norm_newpath.cage_id, parent_idx) {
+            return e;
+        }
 
-        // Create new Lnk, update target.
-        let new_idx = self.create_node(&filename, NodeType::Lnk, mode);
-        self.add_child(parent_idx, new_idx);
-        if let NodeInfo::HardLink { target } = &mut self.nodes[new_idx].info {
-            *target = old_idx;
-        } else {
-            self.nodes[new_idx].info = NodeInfo::HardLink { target: old_idx };
+        // A file without links can only come back if it is an O_TMPFILE
+        // file that was never named.
+        if self.nodes[old_idx].doomed {
+            if !self.nodes[old_idx].linkable_tmpfile {
+                return -2; // ENOENT
+            }
+            self.name_tmpfile(old_idx, parent_idx, &filename);
+            self.update_mtime(parent_idx);
+            self.update_ctime(parent_idx);
+            self.update_ctime(old_idx);
+            return 0;
         }
+
+        self.add_entry(parent_idx, &filename, old_idx);
         self.update_mtime(parent_idx);
         self.update_ctime(parent_idx);
         self.update_ctime(old_idx);
-        self.update_mtime(new_idx);
-        self.update_ctime(new_idx);
 
         0
     }
@@ -1728,7 +2053,7 @@ impl ImfsState {
         self.symlink_resolved_path(target, &norm_linkpath)
     }
 
-    fn symlink_resolved_path(&mut self, target: &str, norm_linkpath: &str) -> i32 {
+    fn symlink_resolved_path(&mut self, target: &str, norm_linkpath: &NormPath) -> i32 {
         match self.resolve_path(norm_linkpath, false) {
             Ok(_) => return -17, // EEXIST
             Err(-2) => {}
@@ -1748,7 +2073,16 @@ impl ImfsState {
             return -36; // ENAMETOOLONG
         }
 
-        let new_idx = self.create_node(&filename, NodeType::Lnk, 0o777);
+        let cage_id = norm_linkpath.cage_id;
+        if let Err(e) = self.check_dir_write(cage_id, parent_idx) {
+            return e;
+        }
+        if let Err(e) = self.check_quota(cage_id, 0, 1) {
+            return e;
+        }
+
+        let new_idx = self.create_node(cage_id, &filename, NodeType::Lnk, 0o777);
+        self.set_new_node_owner(cage_id, parent_idx, new_idx);
         self.nodes[new_idx].info = NodeInfo::Symlink {
             target: target.to_string(),
         };
@@ -1775,7 +2109,7 @@ impl ImfsState {
         self.readlink_resolved_path(&norm_path)
     }
 
-    fn readlink_resolved_path(&mut self, norm_path: &str) -> Result<String, i32> {
+    fn readlink_resolved_path(&mut self, norm_path: &NormPath) -> Result<String, i32> {
         let node_idx = self.resolve_path(norm_path, false)?;
         let target = match &self.nodes[node_idx].info {
             NodeInfo::Symlink { target } => target.clone(),
@@ -1810,8 +2144,8 @@ impl ImfsState {
         self.rename_resolved_paths(&norm_oldpath, &norm_newpath)
     }
 
-    fn rename_resolved_paths(&mut self, norm_oldpath: &str, norm_newpath: &str) -> i32 {
-        if norm_oldpath == "/" {
+    fn rename_resolved_paths(&mut self, norm_oldpath: &NormPath, norm_newpath: &NormPath) -> i32 {
+        if norm_oldpath.path == "/" {
             return -1; // EPERM
         }
 
@@ -1828,7 +2162,7 @@ impl ImfsState {
             return -1; // EPERM
         }
 
-        if norm_newpath == "/" {
+        if norm_newpath.path == "/" {
             return -1; // EPERM
         }
 
@@ -1846,11 +2180,33 @@ impl ImfsState {
         }
 
         if self.nodes[old_idx].node_type == NodeType::Dir
-            && norm_newpath.starts_with(&format!("{}/", norm_oldpath.trim_end_matches('/')))
+            && norm_newpath
+                .path
+                .starts_with(&format!("{}/", norm_oldpath.path.trim_end_matches('/')))
         {
             return -22; // EINVAL
         }
 
+        // Both directories change, and a directory moving to a new parent
+        // also has its .. entry rewritten.
+        let cage_id = norm_oldpath.cage_id;
+        let moves_dir =
+            self.nodes[old_idx].node_type == NodeType::Dir && new_parent_idx != old_parent_idx;
+        if let Err(e) = self
+            .check_dir_write(cage_id, old_parent_idx)
+            .and_then(|_| self.check_dir_write(cage_id, new_parent_idx))
+            .and_then(|_| self.check_sticky(cage_id, old_parent_idx, old_idx))
+            .and_then(|_| {
+                if moves_dir {
+                    self.check_access(cage_id, old_idx, perm::MAY_WRITE)
+                } else {
+                    Ok(())
+                }
+            })
+        {
+            return e;
+        }
+
         match self.resolve_path(&norm_newpath, false) {
             Ok(existing_idx) => {
                 if existing_idx == old_idx {
@@ -1860,8 +2216,12 @@ impl ImfsState {
                 if self.nodes[existing_idx].node_type == NodeType::Dir {
                     return -1; // EPERM
                 }
+                if let Err(e) = self.check_sticky(cage_id, new_parent_idx, existing_idx) {
+                    return e;
+                }
 
-                self.remove_child(existing_idx);
+                self.record_whiteout(new_parent_idx, &new_name, existing_idx);
+                self.remove_entry(new_parent_idx, &new_name);
                 self.update_mtime(new_parent_idx);
                 self.update_ctime(new_parent_idx);
                 self.update_ctime(existing_idx);
@@ -1871,9 +2231,15 @@ impl ImfsState {
             Err(e) => return e,
         }
 
-        self.remove_child(old_idx);
+        self.record_whiteout(old_parent_idx, &old_name, old_idx);
+        self.remove_entry(old_parent_idx, &old_name);
         self.nodes[old_idx].name = new_name;
         self.add_child(new_parent_idx, old_idx);
+        if self.nodes[old_idx].node_type == NodeType::Dir
+            && let Some(dotdot) = self.nodes[old_idx].children_mut().get_mut("..")
+        {
+            dotdot.node_idx = new_parent_idx;
+        }
         self.update_mtime(old_parent_idx);
         self.update_ctime(old_parent_idx);
         if new_parent_idx != old_parent_idx {
@@ -1899,16 +2265,13 @@ impl ImfsState {
         self.chmod_resolved_path(&norm_path, mode)
     }
 
-    fn chmod_resolved_path(&mut self, norm_path: &str, mode: u32) -> i32 {
+    fn chmod_resolved_path(&mut self, norm_path: &NormPath, mode: u32) -> i32 {
         let node_idx = match self.resolve_path(&norm_path, true) {
             Ok(idx) => idx,
             Err(e) => return e,
         };
 
-        self.nodes[node_idx].mode = (self.nodes[node_idx].mode & S_IFMT) | (mode & S_MODE_BITS);
-        self.update_ctime(node_idx);
-
-        0
+        self.chmod_node(norm_path.cage_id, node_idx, mode)
     }
 
     pub fn fchmod(&mut self, cage_id: u64, fd: u64, mode: u32) -> i32 {
@@ -1917,7 +2280,16 @@ impl ImfsState {
             Err(e) => return e,
         };
 
-        self.nodes[node_idx].mode = (self.nodes[node_idx].mode & S_IFMT) | (mode & S_MODE_BITS);
+        self.chmod_node(cage_id, node_idx, mode)
+    }
+
+    fn chmod_node(&mut self, cage_id: u64, node_idx: usize, mode: u32) -> i32 {
+        if let Err(e) = self.check_owner(cage_id, node_idx) {
+            return e;
+        }
+
+        let mode = self.chmod_bits(cage_id, node_idx, mode & S_MODE_BITS);
+        self.nodes[node_idx].mode = (self.nodes[node_idx].mode & S_IFMT) | mode;
         self.update_ctime(node_idx);
 
         0
@@ -1926,7 +2298,7 @@ impl ImfsState {
     pub fn chown(&mut self, cage_id: u64, path: &str, owner: u32, group: u32) -> i32 {
         let norm_path = self.normalize_path_for_cage(cage_id, path);
         match self.resolve_path(&norm_path, true) {
-            Ok(node_idx) => self.chown_node(node_idx, owner, group),
+            Ok(node_idx) => self.chown_node(cage_id, node_idx, owner, group),
             Err(e) => e,
         }
     }
@@ -1934,7 +2306,7 @@ impl ImfsState {
     pub fn lchown(&mut self, cage_id: u64, path: &str, owner: u32, group: u32) -> i32 {
         let norm_path = self.normalize_path_for_cage(cage_id, path);
         match self.resolve_path(&norm_path, false) {
-            Ok(node_idx) => self.chown_node(node_idx, owner, group),
+            Ok(node_idx) => self.chown_node(cage_id, node_idx, owner, group),
             Err(e) => e,
         }
     }
@@ -1954,11 +2326,11 @@ impl ImfsState {
             Err(e) => return e,
         };
 
-        self.chown_node(node_idx, owner, group)
+        self.chown_node(cage_id, node_idx, owner, group)
     }
 
     fn node_idx_at(
-        &self,
+        &mut self,
         cage_id: u64,
         dirfd: i32,
         path: &str,
@@ -1966,11 +2338,7 @@ impl ImfsState {
     ) -> Result<usize, i32> {
         let norm_path = if path.is_empty() && dirfd != LIND_AT_FDCWD {
             let entry = fdtables::translate_virtual_fd(cage_id, dirfd as u64).map_err(|_| -9)?;
-            let mut node_idx = entry.underfd as usize;
-            while let NodeInfo::HardLink { target } = &self.nodes[node_idx].info {
-                node_idx = *target;
-            }
-            return Ok(node_idx);
+            return Ok(entry.underfd as usize);
         } else {
             self.normalize_path_at(cage_id, dirfd, path)?
         };
@@ -1978,7 +2346,11 @@ impl ImfsState {
         self.resolve_path(&norm_path, follow_final)
     }
 
-    fn chown_node(&mut self, node_idx: usize, owner: u32, group: u32) -> i32 {
+    fn chown_node(&mut self, cage_id: u64, node_idx: usize, owner: u32, group: u32) -> i32 {
+        if let Err(e) = self.check_chown(cage_id, node_idx, owner, group) {
+            return e;
+        }
+
         let mut changed = false;
 
         if owner != CHOWN_NO_CHANGE && self.nodes[node_idx].owner != owner {
@@ -2022,6 +2394,13 @@ impl ImfsState {
             return -9;
         }
 
+        // Setting the times to now needs ownership or write permission.
+        if self.check_owner(cage_id, node_idx).is_err()
+            && let Err(e) = self.check_access(cage_id, node_idx, perm::MAY_WRITE)
+        {
+            return e;
+        }
+
         self.update_atime(node_idx);
         self.update_mtime(node_idx);
         self.update_ctime(node_idx);
@@ -2029,18 +2408,30 @@ impl ImfsState {
     }
 
     pub fn mknod(&mut self, cage_id: u64, path: &str, mode: u32) -> i32 {
+        let norm_path = self.normalize_path_for_cage(cage_id, path);
+        self.mknod_resolved_path(&norm_path, mode)
+    }
+
+    pub fn mknodat(&mut self, cage_id: u64, dirfd: i32, path: &str, mode: u32) -> i32 {
+        let norm_path = match self.normalize_path_at(cage_id, dirfd, path) {
+            Ok(path) => path,
+            Err(e) => return e,
+        };
+        self.mknod_resolved_path(&norm_path, mode)
+    }
+
+    fn mknod_resolved_path(&mut self, norm_path: &NormPath, mode: u32) -> i32 {
         let node_type = match mode & S_IFMT {
             S_IFIFO => NodeType::Pip,
             S_IFREG | 0 => NodeType::Reg,
             _ => return -1, // EPERM
         };
 
-        let norm_path = self.normalize_path_for_cage(cage_id, path);
-        if self.resolve_path(&norm_path, true).is_ok() {
+        if self.resolve_path(norm_path, true).is_ok() {
             return -17; // EEXIST
         }
 
-        let (parent_idx, name) = match self.resolve_parent_and_name(&norm_path) {
+        let (parent_idx, name) = match self.resolve_parent_and_name(norm_path) {
             Ok(parent) => parent,
             Err(e) => return e,
         };
@@ -2052,7 +2443,16 @@ impl ImfsState {
             return -36; // ENAMETOOLONG
         }
 
-        let node_idx = self.create_node(&name, node_type, mode);
+        let cage_id = norm_path.cage_id;
+        if let Err(e) = self.check_dir_write(cage_id, parent_idx) {
+            return e;
+        }
+        if let Err(e) = self.check_quota(cage_id, 0, 1) {
+            return e;
+        }
+
+        let node_idx = self.create_node(cage_id, &name, node_type, self.apply_umask(cage_id, mode));
+        self.set_new_node_owner(cage_id, parent_idx, node_idx);
         self.add_child(parent_idx, node_idx);
         self.update_mtime(parent_idx);
         self.update_ctime(parent_idx);
@@ -2079,6 +2479,12 @@ impl ImfsState {
             _ => return -22,             // EINVAL
         }
 
+        if let Err(e) = self.check_access(cage_id, node_idx, perm::MAY_WRITE) {
+            return e;
+        }
+        if let Err(e) = self.copy_up(node_idx, length == 0) {
+            return e;
+        }
         self.truncate_node(node_idx, length as usize);
         self.update_mtime(node_idx);
         self.update_ctime(node_idx);
@@ -2113,18 +2519,128 @@ impl ImfsState {
         0
     }
 
+    /// SEEK_DATA/SEEK_HOLE: find the next allocated extent, or the next hole,
+    /// at or after offset. The end of the file counts as a hole.
+    fn seek_data_or_hole(&self, node_idx: usize, offset: i64, whence: i32) -> Result<i64, i32> {
+        let size = self.nodes[node_idx].total_size;
+        if offset < 0 || offset as usize >= size {
+            return Err(-6); // ENXIO
+        }
+        let offset = offset as usize;
+
+        // Holes in a host file that was not copied up are not visible here,
+        // so treat it as all data.
+        if self.nodes[node_idx].lower_pending {
+            return Ok(if whence == SEEK_DATA { offset } else { size } as i64);
+        }
+
+        let NodeInfo::Reg { extents } = &self.nodes[node_idx].info else {
+            return Err(-22); // EINVAL
+        };
+
+        let pos = if whence == SEEK_DATA {
+            match extents.range(offset / EXTENT_SIZE..).next() {
+                Some((idx, _)) => (idx * EXTENT_SIZE).max(offset),
+                None => return Err(-6), // ENXIO
+            }
+        } else {
+            let mut idx = offset / EXTENT_SIZE;
+            while extents.contains_key(&idx) {
+                idx += 1;
+            }
+            (idx * EXTENT_SIZE).max(offset)
+        };
+
+        if pos >= size {
+            return if whence == SEEK_DATA {
+                Err(-6) // ENXIO
+            } else {
+                Ok(size as i64)
+            };
+        }
+
+        Ok(pos as i64)
+    }
+
+    /// fallocate: allocate a range of a file, or turn it into a hole
+    /// (FALLOC_FL_PUNCH_HOLE) or zeros (FALLOC_FL_ZERO_RANGE). Allocating
+    /// past EOF extends the file unless FALLOC_FL_KEEP_SIZE is set.
+    pub fn fallocate(&mut self, cage_id: u64, fd: u64, mode: i32, offset: i64, len: i64) -> i32 {
+        if offset < 0 || len <= 0 {
+            return -22; // EINVAL
+        }
+        if mode & !(FALLOC_FL_KEEP_SIZE | FALLOC_FL_PUNCH_HOLE | FALLOC_FL_ZERO_RANGE) != 0
+            || mode & FALLOC_FL_PUNCH_HOLE != 0 && mode & FALLOC_FL_ZERO_RANGE != 0
+            || mode & FALLOC_FL_PUNCH_HOLE != 0 && mode & FALLOC_FL_KEEP_SIZE == 0
+        {
+            return -95; // EOPNOTSUPP
+        }
+
+        let (node_idx, flags) = match self.get_node_and_flags(cage_id, fd) {
+            Ok((n, f)) => (n, f),
+            Err(e) => return e,
+        };
+
+        if (flags & O_ACCMODE) == O_RDONLY {
+            return -9; // EBADF
+        }
+
+        match self.nodes[node_idx].node_type {
+            NodeType::Reg => {}
+            NodeType::Pip => return -29, // ESPIPE
+            NodeType::Dir => return -21, // EISDIR
+            _ => return -19,             // ENODEV
+        }
+
+        let Some(end) = offset
+            .checked_add(len)
+            .filter(|end| *end <= IMFS_MAX_FILE_SIZE)
+        else {
+            return -27; // EFBIG
+        };
+        let (start, end) = (offset as usize, end as usize);
+        let size = self.nodes[node_idx].total_size;
+
+        if mode & FALLOC_FL_PUNCH_HOLE != 0 {
+            self.punch_range(node_idx, start, end.min(size));
+        } else {
+            if let Err(e) = self.check_file_growth(node_idx, start, end) {
+                return e;
+            }
+
+            if mode & FALLOC_FL_ZERO_RANGE != 0 {
+                self.punch_range(node_idx, start, end);
+            }
+            self.allocate_range(node_idx, start, end);
+            if mode & FALLOC_FL_KEEP_SIZE == 0 && end > size {
+                self.nodes[node_idx].total_size = end;
+            }
+        }
+
+        if mode != 0 || end > size {
+            self.update_mtime(node_idx);
+            self.update_ctime(node_idx);
+        }
+
+        0
+    }
+
     /// mkdir: create a directory.
     pub fn mkdir(&mut self, cage_id: u64, path: &str, mode: u32) -> i32 {
         let norm_path = self.normalize_path_for_cage(cage_id, path);
-        if self.resolve_path(&norm_path, true).is_ok() {
+        self.mkdir_resolved_path(&norm_path, mode)
+    }
+
+    fn mkdir_resolved_path(&mut self, norm_path: &NormPath, mode: u32) -> i32 {
+        if self.resolve_path(norm_path, true).is_ok() {
             return -17; // EEXIST
         }
 
-        if norm_path == "/" {
+        if norm_path.path == "/" {
             return -17; // EEXIST
         }
 
-        let (parent_idx, dirname) = match self.resolve_parent_and_name(&norm_path) {
+        let (parent_idx, dirname) = match self.resolve_parent_and_name(norm_path) {
             Ok(p) => p,
             Err(e) => return e,
         };
@@ -2133,17 +2649,24 @@ impl ImfsState {
             return -17; // EEXIST
         }
 
-        let dir_idx = self.create_node(&dirname, NodeType::Dir, mode);
-        self.add_child(parent_idx, dir_idx);
+        let cage_id = norm_path.cage_id;
+        if let Err(e) = self.check_dir_write(cage_id, parent_idx) {
+            return e;
+        }
+        if let Err(e) = self.check_quota(cage_id, 0, 1) {
+            return e;
+        }
 
-        // Add . and ..
-        let dot_idx = self.create_node(".", NodeType::Lnk, 0);
-        self.nodes[dot_idx].info = NodeInfo::HardLink { target: dir_idx };
-        self.add_child(dir_idx, dot_idx);
+        let dir_idx = self.create_node(
+            cage_id,
+            &dirname,
+            NodeType::Dir,
+            self.apply_umask(cage_id, mode),
+        );
+        self.set_new_node_owner(cage_id, parent_idx, dir_idx);
+        self.add_child(parent_idx, dir_idx);
 
-        let dotdot_idx = self.create_node("..", NodeType::Lnk, 0);
-        self.nodes[dotdot_idx].info = NodeInfo::HardLink { target: parent_idx };
-        self.add_child(dir_idx, dotdot_idx);
+        self.add_dot_entries(dir_idx, parent_idx);
         self.update_mtime(parent_idx);
         self.update_ctime(parent_idx);
         self.update_mtime(dir_idx);
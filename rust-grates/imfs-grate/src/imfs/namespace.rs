@@ -0,0 +1,238 @@
+//! Per-cage namespaces.
+//!
+//! By default every cage resolves paths from the same root. With
+//! `--namespaces isolated` or `--namespaces clone`, each cage forked at the
+//! namespace depth (the initial cage's children, unless --namespace-depth
+//! says otherwise) instead gets a root of its own:
+//!   - isolated: a fresh, empty tree holding only /tmp, and /dev and /proc
+//!     if they are turned on,
+//!   - clone: a copy of the parent's tree taken at fork time. Directories and
+//!     metadata are copied right away, while regular file data is shared
+//!     copy-on-write, so preloaded inputs cost nothing until a cage writes
+//!     to them.
+//!
+//! Every cage forked below that depth shares its parent's root, so a whole
+//! process subtree works in one namespace. A namespace is freed when the
+//! last cage in it exits.
+//!
+//! Namespace roots are ordinary Dir nodes in the shared arena that are their
+//! own parent. The initial cage, and cage 0 for PRELOADS and DUMPS, use the
+//! root created by init(). Open fds are not remapped at fork, so a child
+//! keeps access to files it inherited from its parent's namespace.
+
+use std::collections::HashSet;
+
+use super::*;
+
+/// How forked cages see the filesystem.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum NamespaceMode {
+    /// All cages share one tree.
+    #[default]
+    Shared,
+    /// A cage forked at the namespace depth starts with an empty tree.
+    Isolated,
+    /// A cage forked at the namespace depth starts with a copy-on-write
+    /// clone of its parent's tree.
+    Clone,
+}
+
+impl std::str::FromStr for NamespaceMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shared" => Ok(NamespaceMode::Shared),
+            "isolated" => Ok(NamespaceMode::Isolated),
+            "clone" => Ok(NamespaceMode::Clone),
+            _ => Err(format!("unknown namespace mode: {}", s)),
+        }
+    }
+}
+
+impl ImfsState {
+    pub fn set_namespace_mode(&mut self, mode: NamespaceMode) {
+        self.namespace_mode = mode;
+    }
+
+    /// Number of forks below the initial cage at which a cage gets a new
+    /// namespace. Must be at least 1.
+    pub fn set_namespace_depth(&mut self, depth: u32) {
+        self.namespace_depth = depth;
+    }
+
+    /// Root of the namespace the cage resolves paths in.
+    pub(super) fn namespace_root(&self, cage_id: u64) -> usize {
+        self.namespaces
+            .get(&cage_id)
+            .copied()
+            .unwrap_or(self.root_idx)
+    }
+
+    /// Root of the tree a node belongs to.
+    pub(super) fn root_of_node(&self, mut node_idx: usize) -> usize {
+        while self.nodes[node_idx].parent_idx != node_idx {
+            node_idx = self.nodes[node_idx].parent_idx;
+        }
+        node_idx
+    }
+
+    /// Create an empty root directory with its . and .. entries, charged to
+    /// cage_id.
+    pub(super) fn create_root(&mut self, cage_id: u64) -> usize {
+        let root_idx = self.create_node(cage_id, "/", NodeType::Dir, 0o755);
+        self.nodes[root_idx].parent_idx = root_idx;
+        self.add_dot_entries(root_idx, root_idx);
+
+        root_idx
+    }
+
+    /// Give a newly forked cage its own namespace if it sits at the
+    /// namespace depth, or else put it in its parent's namespace.
+    pub(super) fn fork_namespace(&mut self, parent_cage: u64, child_cage: u64) {
+        let depth = self.fork_depths.get(&parent_cage).copied().unwrap_or(0) + 1;
+        self.fork_depths.insert(child_cage, depth);
+        if depth != self.namespace_depth {
+            if let Some(&root) = self.namespaces.get(&parent_cage) {
+                self.namespaces.insert(child_cage, root);
+            }
+            return;
+        }
+
+        let root = match self.namespace_mode {
+            NamespaceMode::Shared => return,
+            NamespaceMode::Isolated => {
+                let root = self.create_root(child_cage);
+                self.set_new_node_owner(child_cage, root, root);
+                // Created as cage 0, so the umask does not strip the mode.
+                let tmp = NormPath {
+                    path: "/tmp".to_string(),
+                    root,
+                    cage_id: 0,
+                };
+                let _ = self.mkdir_resolved_path(&tmp, 0o1777);
+                self.mount_synthetic_in(root);
+                self.cwd_info.insert(child_cage, "/".to_string());
+                root
+            }
+            NamespaceMode::Clone => {
+                let parent_root = self.namespace_root(parent_cage);
+                self.clone_tree(parent_root, child_cage)
+            }
+        };
+
+        self.namespaces.insert(child_cage, root);
+    }
+
+    /// Copy every node reachable from root into a new tree, charged to
+    /// cage_id, and return the new root. Regular files share their extents
+    /// with the original until one side writes to them, and a file with
+    /// several links in the tree is copied once.
+    fn clone_tree(&mut self, root: usize, cage_id: u64) -> usize {
+        let mut order = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![root];
+        while let Some(idx) = stack.pop() {
+            if !seen.insert(idx) {
+                continue;
+            }
+            order.push(idx);
+            if let NodeInfo::Dir { children } = &self.nodes[idx].info {
+                stack.extend(
+                    children
+                        .iter()
+                        .filter(|entry| entry.name != "." && entry.name != "..")
+                        .map(|entry| entry.node_idx),
+                );
+            }
+        }
+
+        let mut map = HashMap::new();
+        for &old in &order {
+            let mut node = self.nodes[old].clone();
+            node.in_use = 0;
+            node.nlink = 0;
+            node.doomed = false;
+            if let NodeInfo::Pip {
+                data,
+                readers,
+                writers,
+                ..
+            } = &mut node.info
+            {
+                data.clear();
+                *readers = 0;
+                *writers = 0;
+            }
+
+            let new = self.create_node(cage_id, "", NodeType::Free, 0);
+            node.index = new;
+            node.quota_cage = cage_id;
+            self.nodes[new] = node;
+            let bytes = self.stored_bytes(new) as u64;
+            self.charge(cage_id, bytes, 0);
+            map.insert(old, new);
+        }
+
+        // Point every copied entry, . and .. included, at the copies. Links
+        // are counted again, since only the ones inside the tree were copied.
+        for &new in map.values() {
+            let node = &mut self.nodes[new];
+            if let Some(&parent) = map.get(&node.parent_idx) {
+                node.parent_idx = parent;
+            }
+            let NodeInfo::Dir { children } = &mut node.info else {
+                continue;
+            };
+
+            let mut linked = Vec::new();
+            for entry in children.iter_mut() {
+                if let Some(&idx) = map.get(&entry.node_idx) {
+                    entry.node_idx = idx;
+                }
+                if entry.name != "." && entry.name != ".." {
+                    linked.push(entry.node_idx);
+                }
+            }
+            for idx in linked {
+                if self.nodes[idx].node_type != NodeType::Dir {
+                    self.nodes[idx].nlink += 1;
+                }
+            }
+        }
+
+        map[&root]
+    }
+
+    /// Drop an exiting cage from its namespace, and free the namespace if no
+    /// other cage is left in it. Files that are still open elsewhere stay
+    /// around until their last fd is closed, like unlinked files.
+    pub(super) fn exit_namespace(&mut self, cage_id: u64) {
+        self.fork_depths.remove(&cage_id);
+        let Some(root) = self.namespaces.remove(&cage_id) else {
+            return;
+        };
+        if self.namespaces.values().any(|&other| other == root) {
+            return;
+        }
+
+        let mut stack = vec![root];
+        while let Some(idx) = stack.pop() {
+            if let NodeInfo::Dir { children } = &mut self.nodes[idx].info {
+                for entry in std::mem::take(children) {
+                    if entry.name == "." || entry.name == ".." {
+                        continue;
+                    }
+                    if self.nodes[entry.node_idx].node_type == NodeType::Dir {
+                        stack.push(entry.node_idx);
+                    } else {
+                        let node = &mut self.nodes[entry.node_idx];
+                        node.nlink = node.nlink.saturating_sub(1);
+                        self.unlink_node(entry.node_idx);
+                    }
+                }
+            }
+            self.unlink_node(idx);
+        }
+    }
+}
@@ -1,10 +1,24 @@
-//! IMFS node and chunk types.
+//! IMFS node types.
 //!
-//! The filesystem is a tree of Nodes (directories, regular files, symlinks, pipes).
-//! Regular file data is stored in linked-list chains of 1024-byte Chunks.
-//! All nodes and chunks live in arena-style Vec storage and are referenced by index.
+//! The filesystem is a tree of Nodes (directories, regular files, symlinks, pipes,
+//! character devices).
+//! Anonymous pipes are Pip nodes that are never linked into a directory.
+//! A node is an inode: every directory entry naming it, including hard links
+//! and a directory's "." and ".." entries, holds its index directly.
+//! Regular file data is stored in fixed-size extents kept in a BTreeMap keyed by
+//! extent number, so any offset is found in O(log n). A missing extent is a
+//! hole. Extents are reference counted, which lets namespace clones share them
+//! copy-on-write.
+//! All nodes live in arena-style Vec storage and are referenced by index.
 
-pub const CHUNK_SIZE: usize = 1024;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+
+use super::devfs::CharDev;
+use super::procfs::ProcDir;
+
+/// Size of a file data extent (64 KiB).
+pub const EXTENT_SIZE: usize = 65536;
 pub const MAX_NODE_NAME: usize = 65;
 pub const MAX_NODES: usize = 1024;
 
@@ -20,11 +34,12 @@ pub enum NodeType {
     Reg,
     /// Directory.
     Dir,
-    /// Link node.
+    /// Symbolic link.
     Lnk,
-    /// Pipe.
-    #[allow(unused)]
+    /// Pipe or FIFO.
     Pip,
+    /// Character device, see devfs.rs.
+    Chr,
     /// Free / unallocated slot.
     Free,
 }
@@ -36,6 +51,73 @@ pub struct DirEntry {
     pub node_idx: usize,
 }
 
+/// A directory's entries, indexed by name and kept in the order they were
+/// added.
+///
+/// Each entry is given the next cookie when it is added, and cookies are
+/// never reused. getdents offsets are cookies, so an offset stays valid
+/// while other entries are added or removed.
+#[derive(Clone, Debug, Default)]
+pub struct DirEntries {
+    by_cookie: BTreeMap<u64, DirEntry>,
+    by_name: HashMap<String, u64>,
+    next_cookie: u64,
+}
+
+impl DirEntries {
+    /// Add an entry. The caller checks that the name is not taken.
+    pub fn insert(&mut self, name: &str, node_idx: usize) {
+        let cookie = self.next_cookie;
+        self.next_cookie += 1;
+        self.by_name.insert(name.to_string(), cookie);
+        self.by_cookie.insert(
+            cookie,
+            DirEntry {
+                name: name.to_string(),
+                node_idx,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<DirEntry> {
+        let cookie = self.by_name.remove(name)?;
+        self.by_cookie.remove(&cookie)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DirEntry> {
+        self.by_cookie.get(self.by_name.get(name)?)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut DirEntry> {
+        self.by_cookie.get_mut(self.by_name.get(name)?)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DirEntry> {
+        self.by_cookie.values()
+    }
+
+    /// Entries in order, for repointing them. Names must not be changed.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut DirEntry> {
+        self.by_cookie.values_mut()
+    }
+
+    /// Entries from the given cookie on, each with its cookie.
+    pub fn iter_from(&self, cookie: u64) -> impl Iterator<Item = (u64, &DirEntry)> {
+        self.by_cookie
+            .range(cookie..)
+            .map(|(&cookie, entry)| (cookie, entry))
+    }
+}
+
+impl IntoIterator for DirEntries {
+    type Item = DirEntry;
+    type IntoIter = std::collections::btree_map::IntoValues<u64, DirEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.by_cookie.into_values()
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct NodeTime {
     pub secs: u64,
@@ -55,23 +137,30 @@ impl NodeTime {
 /// Type-specific data for a node.
 #[derive(Clone, Debug)]
 pub enum NodeInfo {
-    /// Regular file: linked list of chunks.
+    /// Regular file: extents keyed by extent number (offset / EXTENT_SIZE).
+    /// An extent stores bytes up to the last one written in it; the rest of
+    /// the extent reads as zeros.
     Reg {
-        head: Option<usize>,
-        tail: Option<usize>,
+        extents: BTreeMap<usize, Arc<Vec<u8>>>,
     },
-    /// Directory: list of child entries.
-    Dir { children: Vec<DirEntry> },
-    /// Hard link: index of the target node.
-    HardLink { target: usize },
+    /// Directory: its entries, including . and ..
+    Dir { children: DirEntries },
     /// Symbolic link: stored target path.
     Symlink { target: String },
-    /// Pipe (limited implementation).
+    /// Pipe or FIFO: buffered bytes and the number of open ends.
+    ///
+    /// `read_opens`/`write_opens` count every open of each end and never
+    /// decrease, so a blocking FIFO open can tell that a peer came and went
+    /// while it was waiting.
     Pip {
-        data: Vec<u8>,
+        data: VecDeque<u8>,
         readers: u32,
         writers: u32,
+        read_opens: u64,
+        write_opens: u64,
     },
+    /// Character device: reads and writes go to the device.
+    Chr { dev: CharDev },
     /// Free slot.
     Free,
 }
@@ -85,12 +174,23 @@ pub struct Node {
     pub index: usize,
 
     pub total_size: usize,
+    /// Name and parent directory of the entry the node was created or last
+    /// moved under. A directory only ever has that one entry; for a file with
+    /// several links they are just one of them.
     pub name: String,
     pub parent_idx: usize,
+    /// Directory entries naming a non-directory node. Directories derive
+    /// their link count from their subdirectories instead.
+    pub nlink: u32,
     /// Number of open file descriptions referencing this node.
     pub in_use: u32,
     /// Marked for deletion once all references are closed.
     pub doomed: bool,
+    /// Cage the node and its data are charged to, see quota.rs.
+    pub quota_cage: u64,
+    /// Unnamed O_TMPFILE file that linkat may still give a name, see
+    /// tmpfile.rs.
+    pub linkable_tmpfile: bool,
     pub mode: u32,
 
     #[allow(unused)]
@@ -104,6 +204,21 @@ pub struct Node {
     pub ctime: NodeTime,
     pub atime: NodeTime,
     pub mtime: NodeTime,
+    /// Creation time, reported as statx's stx_btime.
+    pub btime: NodeTime,
+    /// Extended attributes, keyed by full name (e.g. "user.tag").
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+    /// Host path this node was brought in from in overlay mode, see
+    /// overlay.rs. Kept after copy-up so the diff can tell moved entries.
+    pub lower_path: Option<String>,
+    /// Overlay node whose host contents are not in memory yet: a regular
+    /// file that was not copied up, or a directory that was not merged.
+    pub lower_pending: bool,
+    /// Created by --dev or --proc rather than by a cage. Left out of the
+    /// overlay diff.
+    pub synthetic: bool,
+    /// Directory whose entries are generated, see procfs.rs.
+    pub proc_dir: Option<ProcDir>,
 }
 
 impl Node {
@@ -114,25 +229,28 @@ impl Node {
             NodeType::Dir => 0o040000 | (mode & 0o7777), // S_IFDIR
             NodeType::Lnk => 0o120000 | (mode & 0o7777), // S_IFLNK
             NodeType::Pip => 0o010000 | (mode & 0o7777), // S_IFIFO
+            NodeType::Chr => 0o020000 | (mode & 0o7777), // S_IFCHR
             NodeType::Free => mode & 0o7777,
         };
 
         let info = match node_type {
             NodeType::Reg => NodeInfo::Reg {
-                head: None,
-                tail: None,
+                extents: BTreeMap::new(),
             },
             NodeType::Dir => NodeInfo::Dir {
-                children: Vec::new(),
+                children: DirEntries::default(),
             },
             NodeType::Lnk => NodeInfo::Symlink {
                 target: String::new(),
             },
             NodeType::Pip => NodeInfo::Pip {
-                data: Vec::new(),
+                data: VecDeque::new(),
                 readers: 0,
                 writers: 0,
+                read_opens: 0,
+                write_opens: 0,
             },
+            NodeType::Chr => NodeInfo::Chr { dev: CharDev::Null },
             NodeType::Free => NodeInfo::Free,
         };
 
@@ -144,8 +262,11 @@ impl Node {
             total_size: 0,
             name: name.to_string(),
             parent_idx: 0,
+            nlink: 0,
             in_use: 0,
             doomed: false,
+            quota_cage: 0,
+            linkable_tmpfile: false,
             mode: mode_bits,
             owner: GET_UID,
             group: GET_GID,
@@ -153,11 +274,17 @@ impl Node {
             atime: now,
             ctime: now,
             mtime: now,
+            btime: now,
+            xattrs: BTreeMap::new(),
+            lower_path: None,
+            lower_pending: false,
+            synthetic: false,
+            proc_dir: None,
         }
     }
 
     /// Get mutable children of a directory node.
-    pub fn children_mut(&mut self) -> &mut Vec<DirEntry> {
+    pub fn children_mut(&mut self) -> &mut DirEntries {
         match &mut self.info {
             NodeInfo::Dir { children } => children,
             _ => panic!("not a directory"),
@@ -165,21 +292,13 @@ impl Node {
     }
 
     /// Get children of a directory node.
-    pub fn children(&self) -> &Vec<DirEntry> {
+    pub fn children(&self) -> &DirEntries {
         match &self.info {
             NodeInfo::Dir { children } => children,
             _ => panic!("not a directory"),
         }
     }
 
-    /// Get the hard-link target index of a link node.
-    pub fn hardlink_target(&self) -> Option<usize> {
-        match &self.info {
-            NodeInfo::HardLink { target } => Some(*target),
-            _ => None,
-        }
-    }
-
     pub fn symlink_target(&self) -> Option<&str> {
         match &self.info {
             NodeInfo::Symlink { target } => Some(target),
@@ -187,22 +306,3 @@ impl Node {
         }
     }
 }
-
-/// A 1024-byte chunk of file data, part of a linked list.
-#[derive(Clone)]
-pub struct Chunk {
-    pub data: [u8; CHUNK_SIZE],
-    pub used: usize,
-    /// Index of the next chunk in the chain, or None.
-    pub next: Option<usize>,
-}
-
-impl Chunk {
-    pub fn new() -> Self {
-        Chunk {
-            data: [0u8; CHUNK_SIZE],
-            used: 0,
-            next: None,
-        }
-    }
-}
@@ -0,0 +1,541 @@
+//! Overlay mode.
+//!
+//! With `--overlay <dir>`, IMFS is the upper layer over a read-only host
+//! directory. The host directory is never written to:
+//!   - A directory's host entries are merged into its node the first time a
+//!     path is resolved through it. Each entry becomes a lazy node that
+//!     remembers its host path (`lower_path`). Merging happens once, so a
+//!     later unlink only has to drop the in-memory entry to hide the host file.
+//!   - A regular file is read from the host until it is opened for writing
+//!     or truncated. At that point it is copied up into extents.
+//!   - Removing or renaming away an entry that came from the host records a
+//!     whiteout, so the change survives in the diff.
+//!
+//! overlay_diff() lists everything the upper layer changed. main.rs writes it
+//! out at teardown as an OCI-style layer, with `.wh.<name>` files for
+//! whiteouts and `.wh..wh..opq` for directories that replaced a deleted one.
+//!
+//! Host access goes through 3i as the grate's own cage, like PRELOADS and
+//! DUMPS do.
+
+use super::*;
+use crate::{raw_threei_syscall, read_host_file};
+use grate_rs::constants::lind::GRATE_MEMORY_FLAG;
+use grate_rs::constants::{SYS_CLOSE, SYS_GETDENTS, SYS_LSTAT, SYS_OPEN, SYS_PREAD, SYS_READLINK};
+use grate_rs::getcageid;
+use std::collections::BTreeSet;
+use std::ffi::CString;
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+const HOST_DIRENT_BUF_SIZE: usize = 4096;
+const HOST_PATH_MAX: usize = 4096;
+
+/// State of an overlay over a host directory.
+pub struct Overlay {
+    lower_dir: String,
+    /// Absolute IMFS paths of host entries that were removed from the upper
+    /// layer.
+    whiteouts: BTreeSet<String>,
+}
+
+/// Changes made in the upper layer, as absolute IMFS paths.
+#[derive(Default, Debug)]
+pub struct OverlayDiff {
+    /// Directories created in the upper layer.
+    pub dirs: Vec<String>,
+    /// Directories that replaced a deleted host directory, hiding its entries.
+    pub opaque_dirs: Vec<String>,
+    /// Regular files that were created, written, or moved.
+    pub files: Vec<String>,
+    /// Symlinks that were created or moved, with their targets.
+    pub symlinks: Vec<(String, String)>,
+    /// Host entries that were removed.
+    pub whiteouts: Vec<String>,
+    /// Mode and owner of every entry in dirs, files and symlinks.
+    pub metadata: Vec<EntryMeta>,
+}
+
+/// Mode and owner an entry of the diff is given once it is written.
+#[derive(Debug)]
+pub struct EntryMeta {
+    pub path: String,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub is_symlink: bool,
+}
+
+impl EntryMeta {
+    fn of(node: &Node, path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            mode: node.mode & 0o7777,
+            uid: node.owner,
+            gid: node.group,
+            is_symlink: node.node_type == NodeType::Lnk,
+        }
+    }
+}
+
+impl ImfsState {
+    /// Layer IMFS over a host directory. Must be called before anything is
+    /// created in IMFS.
+    pub fn set_overlay(&mut self, lower_dir: &str) {
+        let lower_dir = lower_dir.trim_end_matches('/').to_string();
+        let root = &mut self.nodes[self.root_idx];
+        root.lower_path = Some(format!("{}/", lower_dir));
+        root.lower_pending = true;
+
+        self.overlay = Some(Overlay {
+            lower_dir,
+            whiteouts: BTreeSet::new(),
+        });
+    }
+
+    /// Merge the host entries of a directory into its node, unless that
+    /// already happened. Entries that exist in memory win.
+    pub(super) fn merge_lower_dir(&mut self, dir_idx: usize) {
+        if !self.nodes[dir_idx].lower_pending {
+            return;
+        }
+        self.nodes[dir_idx].lower_pending = false;
+
+        let Some(host_dir) = self.nodes[dir_idx].lower_path.clone() else {
+            return;
+        };
+        let names = match host_list_dir(&host_dir) {
+            Ok(names) => names,
+            Err(_) => return,
+        };
+
+        for name in names {
+            if name == "." || name == ".." {
+                continue;
+            }
+            if name.len() >= MAX_NODE_NAME {
+                crate::log!("overlay: skipping {}/{}: name too long", host_dir, name);
+                continue;
+            }
+            if self.lookup_child(dir_idx, &name).is_some() {
+                continue;
+            }
+
+            let host_path = format!("{}/{}", host_dir.trim_end_matches('/'), name);
+            let Ok(st) = host_lstat(&host_path) else {
+                continue;
+            };
+            let mode = st.st_mode & 0o7777;
+
+            let node_idx = match st.st_mode & S_IFMT {
+                S_IFDIR => {
+                    let idx = self.create_node(0, &name, NodeType::Dir, mode);
+                    self.add_child(dir_idx, idx);
+                    self.add_dot_entries(idx, dir_idx);
+
+                    self.nodes[idx].lower_pending = true;
+                    idx
+                }
+                S_IFREG => {
+                    let idx = self.create_node(0, &name, NodeType::Reg, mode);
+                    self.nodes[idx].total_size = st.st_size as usize;
+                    self.nodes[idx].lower_pending = true;
+                    self.add_child(dir_idx, idx);
+                    idx
+                }
+                S_IFLNK => {
+                    let Ok(target) = host_readlink(&host_path) else {
+                        continue;
+                    };
+                    let idx = self.create_node(0, &name, NodeType::Lnk, 0o777);
+                    self.nodes[idx].total_size = target.len();
+                    self.nodes[idx].info = NodeInfo::Symlink { target };
+                    self.add_child(dir_idx, idx);
+                    idx
+                }
+                // Devices, sockets and FIFOs are not brought into IMFS.
+                _ => continue,
+            };
+
+            let node = &mut self.nodes[node_idx];
+            node.owner = st.st_uid;
+            node.group = st.st_gid;
+            node.atime = host_time(st.st_atim);
+            node.mtime = host_time(st.st_mtim);
+            node.ctime = host_time(st.st_ctim);
+            node.lower_path = Some(host_path);
+        }
+    }
+
+    /// Read a regular file that has not been copied up from its host file.
+    /// The host file is opened once per open file description and kept
+    /// until the description is closed, see FDInfo's Drop below.
+    pub(super) fn read_lower(
+        &self,
+        cage_id: u64,
+        fd: u64,
+        node_idx: usize,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> i32 {
+        let node = &self.nodes[node_idx];
+        let Some(host_path) = node.lower_path.as_deref() else {
+            return -5; // EIO
+        };
+        if offset >= node.total_size {
+            return 0;
+        }
+        let Some(fd_info) = self.fd_info.get(&(cage_id, fd)) else {
+            return -9; // EBADF
+        };
+
+        let mut fd_info = fd_info.lock().unwrap();
+        let host_fd = match fd_info.lower_fd {
+            Some(host_fd) => host_fd,
+            None => match host_open(host_path, O_RDONLY) {
+                Ok(host_fd) => *fd_info.lower_fd.insert(host_fd),
+                Err(e) => return e,
+            },
+        };
+
+        let count = buf.len().min(node.total_size - offset);
+        host_pread(host_fd, &mut buf[..count], offset)
+    }
+
+    /// Copy a regular file's host contents into memory so it can be
+    /// modified. With `discard` the contents are about to be truncated away,
+    /// so they are not read at all.
+    pub(super) fn copy_up(&mut self, node_idx: usize, discard: bool) -> Result<(), i32> {
+        if !self.nodes[node_idx].lower_pending || self.nodes[node_idx].node_type != NodeType::Reg {
+            return Ok(());
+        }
+
+        if discard {
+            self.nodes[node_idx].total_size = 0;
+        } else {
+            let host_path = self.nodes[node_idx].lower_path.clone().unwrap_or_default();
+            let data = read_host_file(&host_path).map_err(|_| -5)?; // EIO
+            self.check_quota(self.nodes[node_idx].quota_cage, data.len() as u64, 0)?;
+            self.nodes[node_idx].total_size = 0;
+            self.write_to_node(node_idx, 0, &data);
+        }
+
+        self.nodes[node_idx].lower_pending = false;
+        Ok(())
+    }
+
+    /// Note that the entry name in parent_idx, naming node_idx, is about to
+    /// be removed, so the diff can hide the host entry it came from.
+    pub(super) fn record_whiteout(&mut self, parent_idx: usize, name: &str, node_idx: usize) {
+        // Only the initial tree is diffed, see namespace.rs.
+        if self.root_of_node(parent_idx) != self.root_idx {
+            return;
+        }
+        let path = format!(
+            "{}/{}",
+            self.absolute_path_for_node(parent_idx)
+                .trim_end_matches('/'),
+            name
+        );
+        let Some(overlay) = &mut self.overlay else {
+            return;
+        };
+        // An entry that was already moved left a whiteout at its old path.
+        if self.nodes[node_idx].lower_path.as_deref()
+            == Some(&format!("{}{}", overlay.lower_dir, path))
+        {
+            overlay.whiteouts.insert(path);
+        }
+    }
+
+    /// Everything the upper layer changed in the initial tree.
+    pub fn overlay_diff(&mut self) -> OverlayDiff {
+        let mut diff = OverlayDiff::default();
+        let Some(overlay) = &self.overlay else {
+            return diff;
+        };
+        let lower_dir = overlay.lower_dir.clone();
+        let whiteouts = overlay.whiteouts.clone();
+
+        let moved = |node: &Node, path: &str| {
+            node.lower_path.as_deref() != Some(&format!("{}{}", lower_dir, path))
+        };
+
+        let mut stack = vec![(self.root_idx, String::new())];
+        while let Some((dir_idx, dir_path)) = stack.pop() {
+            let children = self.nodes[dir_idx].children().clone();
+            for entry in children {
+                if entry.name == "." || entry.name == ".." {
+                    continue;
+                }
+                if self.nodes[entry.node_idx].synthetic {
+                    continue;
+                }
+                let path = format!("{}/{}", dir_path, entry.name);
+                let node = &self.nodes[entry.node_idx];
+
+                match &node.info {
+                    NodeInfo::Dir { .. } => {
+                        let is_moved = moved(node, &path);
+                        if node.lower_path.is_none() || is_moved || lower_meta_changed(node) {
+                            diff.dirs.push(path.clone());
+                            diff.metadata.push(EntryMeta::of(node, &path));
+                            if whiteouts.contains(&path) {
+                                diff.opaque_dirs.push(path.clone());
+                            }
+                        }
+                        // A directory still at its host path that was never
+                        // merged has no changes below it.
+                        if is_moved {
+                            self.merge_lower_dir(entry.node_idx);
+                        }
+                        if !self.nodes[entry.node_idx].lower_pending {
+                            stack.push((entry.node_idx, path));
+                        }
+                    }
+                    NodeInfo::Reg { .. }
+                        if !node.lower_pending
+                            || moved(node, &path)
+                            || lower_meta_changed(node) =>
+                    {
+                        diff.metadata.push(EntryMeta::of(node, &path));
+                        diff.files.push(path);
+                    }
+                    NodeInfo::Symlink { target }
+                        if moved(node, &path) || lower_meta_changed(node) =>
+                    {
+                        diff.metadata.push(EntryMeta::of(node, &path));
+                        diff.symlinks.push((path, target.clone()));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for path in &whiteouts {
+            // A whiteout below another one is covered by it, and one whose
+            // path was re-created is replaced by the new entry.
+            let covered = whiteouts
+                .iter()
+                .any(|other| path.starts_with(&format!("{}/", other)));
+            let norm_path = NormPath {
+                path: path.clone(),
+                root: self.root_idx,
+                cage_id: 0,
+            };
+            if !covered && self.resolve_path(&norm_path, false).is_err() {
+                diff.whiteouts.push(path.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+/// Whether a node still at its host path was chmod'ed, chown'ed or had its
+/// times set since it was merged, which puts it in the diff as well.
+fn lower_meta_changed(node: &Node) -> bool {
+    let Some(host_path) = node.lower_path.as_deref() else {
+        return false;
+    };
+    let Ok(st) = host_lstat(host_path) else {
+        return false;
+    };
+    node.mode & 0o7777 != st.st_mode & 0o7777
+        || node.owner != st.st_uid
+        || node.group != st.st_gid
+        || (node.mtime.secs, node.mtime.nanos) != (st.st_mtim[0], st.st_mtim[1])
+}
+
+fn host_time(time: [u64; 2]) -> NodeTime {
+    NodeTime {
+        secs: time[0],
+        nanos: time[1],
+    }
+}
+
+/// The last fd sharing an open file description is gone (close, exec or
+/// exit), so its host file is no longer needed.
+impl Drop for FDInfo {
+    fn drop(&mut self) {
+        if let Some(host_fd) = self.lower_fd.take() {
+            host_close(host_fd);
+        }
+    }
+}
+
+fn host_lstat(path: &str) -> Result<stat, i32> {
+    let c_path = CString::new(path).map_err(|_| -22)?; // EINVAL
+    let this_cage = getcageid();
+    let mut st = stat::default();
+    let ret = raw_threei_syscall(
+        SYS_LSTAT,
+        [
+            c_path.as_ptr() as u64,
+            &mut st as *mut stat as u64,
+            0,
+            0,
+            0,
+            0,
+        ],
+        [
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+        ],
+    );
+    if ret < 0 { Err(ret) } else { Ok(st) }
+}
+
+fn host_readlink(path: &str) -> Result<String, i32> {
+    let c_path = CString::new(path).map_err(|_| -22)?; // EINVAL
+    let this_cage = getcageid();
+    let mut buf = vec![0u8; HOST_PATH_MAX];
+    let ret = raw_threei_syscall(
+        SYS_READLINK,
+        [
+            c_path.as_ptr() as u64,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+            0,
+            0,
+            0,
+        ],
+        [
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+        ],
+    );
+    if ret < 0 {
+        return Err(ret);
+    }
+    buf.truncate(ret as usize);
+    String::from_utf8(buf).map_err(|_| -22) // EINVAL
+}
+
+fn host_open(path: &str, flags: i32) -> Result<i32, i32> {
+    let c_path = CString::new(path).map_err(|_| -22)?; // EINVAL
+    let this_cage = getcageid();
+    let fd = raw_threei_syscall(
+        SYS_OPEN,
+        [c_path.as_ptr() as u64, flags as u64, 0, 0, 0, 0],
+        [
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+        ],
+    );
+    if fd < 0 { Err(fd) } else { Ok(fd) }
+}
+
+fn host_close(fd: i32) {
+    let this_cage = getcageid();
+    let _ = raw_threei_syscall(
+        SYS_CLOSE,
+        [fd as u64, 0, 0, 0, 0, 0],
+        [
+            this_cage, this_cage, this_cage, this_cage, this_cage, this_cage,
+        ],
+    );
+}
+
+/// pread from a host fd, retrying short reads until buf is full or EOF.
+fn host_pread(fd: i32, buf: &mut [u8], offset: usize) -> i32 {
+    let this_cage = getcageid();
+    let mut total = 0usize;
+    while total < buf.len() {
+        let ret = raw_threei_syscall(
+            SYS_PREAD,
+            [
+                fd as u64,
+                buf[total..].as_mut_ptr() as u64,
+                (buf.len() - total) as u64,
+                (offset + total) as u64,
+                0,
+                0,
+            ],
+            [
+                this_cage,
+                this_cage | GRATE_MEMORY_FLAG,
+                this_cage,
+                this_cage,
+                this_cage,
+                this_cage,
+            ],
+        );
+        if ret < 0 {
+            return ret;
+        }
+        if ret == 0 {
+            break;
+        }
+        total += ret as usize;
+    }
+
+    total as i32
+}
+
+/// Names in a host directory, from its getdents64 records.
+fn host_list_dir(path: &str) -> Result<Vec<String>, i32> {
+    let fd = host_open(path, O_RDONLY | O_DIRECTORY)?;
+    let this_cage = getcageid();
+    let mut buf = vec![0u8; HOST_DIRENT_BUF_SIZE];
+    let mut names = Vec::new();
+
+    loop {
+        let ret = raw_threei_syscall(
+            SYS_GETDENTS,
+            [
+                fd as u64,
+                buf.as_mut_ptr() as u64,
+                buf.len() as u64,
+                0,
+                0,
+                0,
+            ],
+            [
+                this_cage,
+                this_cage | GRATE_MEMORY_FLAG,
+                this_cage,
+                this_cage,
+                this_cage,
+                this_cage,
+            ],
+        );
+        if ret < 0 {
+            host_close(fd);
+            return Err(ret);
+        }
+        if ret == 0 {
+            break;
+        }
+
+        // Same layout as write_dirent_record: d_ino, d_off, d_reclen,
+        // d_type, then the NUL-terminated name.
+        let mut pos = 0;
+        while pos + DIRENT64_FIXED_SIZE <= ret as usize {
+            let reclen = u16::from_ne_bytes([buf[pos + 16], buf[pos + 17]]) as usize;
+            if reclen == 0 {
+                break;
+            }
+            let name = &buf[pos + DIRENT64_FIXED_SIZE..pos + reclen];
+            let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            names.push(String::from_utf8_lossy(&name[..len]).into_owned());
+            pos += reclen;
+        }
+    }
+
+    host_close(fd);
+    Ok(names)
+}
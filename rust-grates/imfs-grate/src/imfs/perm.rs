@@ -0,0 +1,225 @@
+//! Ownership and permission checks.
+//!
+//! Every cage has credentials (uid and gid) and a umask. main.rs looks the
+//! credentials up through 3i when a cage starts, and forked cages inherit
+//! both from their parent. New nodes are owned by the creating cage, take
+//! their group from a set-group-ID parent directory, and have the umask
+//! applied to their mode.
+//!
+//! Checks follow POSIX with a single group per cage (no supplementary
+//! groups). uid 0 and utility cage 0, which runs PRELOADS and DUMPS on the
+//! grate's behalf, are privileged: they pass every check except execute
+//! permission on a file with no execute bit.
+//!
+//! Path-based operations know the calling cage through the NormPath that
+//! normalize_path_for_cage returns alongside the root to resolve from.
+
+use super::*;
+
+/// Read, write and execute/search bits, as used by access(2).
+pub const MAY_READ: u32 = 0o4;
+pub const MAY_WRITE: u32 = 0o2;
+pub const MAY_EXEC: u32 = 0o1;
+
+const S_ISVTX: u32 = 0o1000;
+/// umask of a cage that has not set one.
+pub const DEFAULT_UMASK: u32 = 0o022;
+
+/// Who a cage acts as.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Default for Credentials {
+    /// Used for cages main.rs could not look up, and in unit tests.
+    fn default() -> Self {
+        Credentials {
+            uid: GET_UID,
+            gid: GET_GID,
+        }
+    }
+}
+
+impl ImfsState {
+    pub fn set_credentials(&mut self, cage_id: u64, creds: Credentials) {
+        self.creds.insert(cage_id, creds);
+    }
+
+    /// Set cage 0's credentials. It created the initial root, so the root
+    /// is handed to it as well.
+    pub fn set_utility_credentials(&mut self, creds: Credentials) {
+        self.set_credentials(0, creds);
+        let root = &mut self.nodes[self.root_idx];
+        root.owner = creds.uid;
+        root.group = creds.gid;
+    }
+
+    pub(super) fn credentials(&self, cage_id: u64) -> Credentials {
+        self.creds.get(&cage_id).copied().unwrap_or_default()
+    }
+
+    pub(super) fn is_privileged(&self, cage_id: u64) -> bool {
+        cage_id == 0 || self.credentials(cage_id).uid == 0
+    }
+
+    /// umask: set the cage's file mode creation mask and return the old one.
+    pub fn umask(&mut self, cage_id: u64, mask: u32) -> i32 {
+        let old = self.cage_umask(cage_id);
+        self.umasks.insert(cage_id, mask & 0o777);
+        old as i32
+    }
+
+    fn cage_umask(&self, cage_id: u64) -> u32 {
+        // Cage 0 stages PRELOADS with the modes it asks for.
+        let default = if cage_id == 0 { 0 } else { DEFAULT_UMASK };
+        self.umasks.get(&cage_id).copied().unwrap_or(default)
+    }
+
+    /// Apply a cage's umask to the permission bits of a mode.
+    pub(super) fn apply_umask(&self, cage_id: u64, mode: u32) -> u32 {
+        mode & !self.cage_umask(cage_id)
+    }
+
+    /// Give a forked cage its parent's credentials and umask.
+    pub(super) fn fork_credentials(&mut self, parent_cage: u64, child_cage: u64) {
+        if let Some(creds) = self.creds.get(&parent_cage).copied() {
+            self.creds.insert(child_cage, creds);
+        }
+        if let Some(mask) = self.umasks.get(&parent_cage).copied() {
+            self.umasks.insert(child_cage, mask);
+        }
+    }
+
+    pub(super) fn exit_credentials(&mut self, cage_id: u64) {
+        self.creds.remove(&cage_id);
+        self.umasks.remove(&cage_id);
+    }
+
+    /// Make a new node owned by the cage creating it. In a set-group-ID
+    /// directory the node takes the directory's group, and a new
+    /// subdirectory keeps the set-group-ID bit.
+    pub(super) fn set_new_node_owner(&mut self, cage_id: u64, parent_idx: usize, node_idx: usize) {
+        let creds = self.credentials(cage_id);
+        let parent = &self.nodes[parent_idx];
+        let sgid_parent = parent.mode & S_ISGID != 0;
+        let group = if sgid_parent { parent.group } else { creds.gid };
+
+        let node = &mut self.nodes[node_idx];
+        node.owner = creds.uid;
+        node.group = group;
+        if sgid_parent && node.node_type == NodeType::Dir {
+            node.mode |= S_ISGID;
+        }
+    }
+
+    /// Whether the cage may access the node with all of the MAY_* bits in
+    /// want.
+    pub(super) fn may_access(&self, cage_id: u64, node_idx: usize, want: u32) -> bool {
+        let node = &self.nodes[node_idx];
+
+        if self.is_privileged(cage_id) {
+            return want & MAY_EXEC == 0
+                || node.node_type == NodeType::Dir
+                || node.mode & 0o111 != 0;
+        }
+
+        let creds = self.credentials(cage_id);
+        let bits = if creds.uid == node.owner {
+            node.mode >> 6
+        } else if creds.gid == node.group {
+            node.mode >> 3
+        } else {
+            node.mode
+        };
+        bits & want == want
+    }
+
+    /// EACCES unless the cage may access the node with want.
+    pub(super) fn check_access(&self, cage_id: u64, node_idx: usize, want: u32) -> Result<(), i32> {
+        if self.may_access(cage_id, node_idx, want) {
+            Ok(())
+        } else {
+            Err(-13) // EACCES
+        }
+    }
+
+    /// Adding or removing an entry needs write and search permission on the
+    /// directory.
+    pub(super) fn check_dir_write(&self, cage_id: u64, dir_idx: usize) -> Result<(), i32> {
+        self.check_access(cage_id, dir_idx, MAY_WRITE | MAY_EXEC)
+    }
+
+    /// In a sticky directory, only the owner of an entry, the owner of the
+    /// directory, or a privileged cage may remove or rename the entry.
+    pub(super) fn check_sticky(
+        &self,
+        cage_id: u64,
+        dir_idx: usize,
+        node_idx: usize,
+    ) -> Result<(), i32> {
+        if self.nodes[dir_idx].mode & S_ISVTX == 0 || self.is_privileged(cage_id) {
+            return Ok(());
+        }
+
+        let uid = self.credentials(cage_id).uid;
+        let owner = self.nodes[node_idx].owner;
+        if uid == owner || uid == self.nodes[dir_idx].owner {
+            Ok(())
+        } else {
+            Err(-1) // EPERM
+        }
+    }
+
+    /// EPERM unless the cage owns the node or is privileged. Needed to
+    /// change its mode, timestamps or, for a non-privileged cage, group.
+    pub(super) fn check_owner(&self, cage_id: u64, node_idx: usize) -> Result<(), i32> {
+        if self.is_privileged(cage_id)
+            || self.credentials(cage_id).uid == self.nodes[node_idx].owner
+        {
+            Ok(())
+        } else {
+            Err(-1) // EPERM
+        }
+    }
+
+    /// chown rules: a privileged cage may set any owner and group. Otherwise
+    /// the cage must own the node, may not give it away, and may only set
+    /// the group to its own.
+    pub(super) fn check_chown(
+        &self,
+        cage_id: u64,
+        node_idx: usize,
+        owner: u32,
+        group: u32,
+    ) -> Result<(), i32> {
+        if self.is_privileged(cage_id) {
+            return Ok(());
+        }
+
+        self.check_owner(cage_id, node_idx)?;
+        let creds = self.credentials(cage_id);
+        let node = &self.nodes[node_idx];
+        if owner != CHOWN_NO_CHANGE && owner != node.owner {
+            return Err(-1); // EPERM
+        }
+        if group != CHOWN_NO_CHANGE && group != node.group && group != creds.gid {
+            return Err(-1); // EPERM
+        }
+        Ok(())
+    }
+
+    /// Mode bits chmod actually sets: a non-privileged cage that is not in
+    /// the node's group cannot set the set-group-ID bit, which is dropped
+    /// without an error as on Linux.
+    pub(super) fn chmod_bits(&self, cage_id: u64, node_idx: usize, mode: u32) -> u32 {
+        if !self.is_privileged(cage_id)
+            && self.credentials(cage_id).gid != self.nodes[node_idx].group
+        {
+            mode & !S_ISGID
+        } else {
+            mode
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Pipes and FIFOs.
+//!
+//! Anonymous pipes from pipe/pipe2 and named FIFOs from mknod(S_IFIFO) share
+//! one representation: a Pip node holding a byte queue and the number of open
+//! read and write ends. An anonymous pipe is a Pip node that is never linked
+//! into a directory, so it is reclaimed when its last fd closes.
+//!
+//! The ImfsState methods here never block. They return EAGAIN when a pipe is
+//! empty or full, and the `blocking_*` functions at the bottom of this file
+//! retry with the global IMFS lock released, so the cage on the other end of
+//! the pipe can make progress while this one waits.
+
+use super::*;
+
+/// Pipe capacity in bytes (64 KiB, matching Linux).
+pub const PIPE_CAPACITY: usize = 65536;
+/// Writes of at most PIPE_BUF bytes are atomic.
+const PIPE_BUF: usize = 4096;
+
+impl ImfsState {
+    /// pipe2: create an anonymous pipe and return its (read fd, write fd).
+    pub fn pipe(&mut self, cage_id: u64, flags: i32) -> Result<(i32, i32), i32> {
+        if flags & !(O_NONBLOCK | O_CLOEXEC) != 0 {
+            return Err(-22); // EINVAL
+        }
+
+        let node_idx = self.create_node(cage_id, "pipe", NodeType::Pip, 0o600);
+        let creds = self.credentials(cage_id);
+        self.nodes[node_idx].owner = creds.uid;
+        self.nodes[node_idx].group = creds.gid;
+        self.nodes[node_idx].doomed = true;
+        self.update_mtime(node_idx);
+        self.update_ctime(node_idx);
+
+        let cloexec = flags & O_CLOEXEC != 0;
+        let status_flags = flags & O_NONBLOCK;
+
+        let readfd = match self.install_fd(cage_id, node_idx, O_RDONLY | status_flags, cloexec) {
+            Ok(fd) => fd,
+            Err(e) => {
+                self.reclaim_node(node_idx);
+                return Err(e);
+            }
+        };
+        let writefd = match self.install_fd(cage_id, node_idx, O_WRONLY | status_flags, cloexec) {
+            Ok(fd) => fd,
+            Err(e) => {
+                self.close(cage_id, readfd);
+                return Err(e);
+            }
+        };
+
+        Ok((readfd as i32, writefd as i32))
+    }
+
+    /// Account for a new open file description on a pipe node.
+    pub(super) fn pipe_attach(&mut self, node_idx: usize, flags: i32) {
+        if let NodeInfo::Pip {
+            readers,
+            writers,
+            read_opens,
+            write_opens,
+            ..
+        } = &mut self.nodes[node_idx].info
+        {
+            if flags & O_ACCMODE != O_WRONLY {
+                *readers += 1;
+                *read_opens += 1;
+            }
+            if flags & O_ACCMODE != O_RDONLY {
+                *writers += 1;
+                *write_opens += 1;
+            }
+        }
+    }
+
+    /// Drop an open file description from a pipe node. Once neither end is
+    /// open any buffered data is discarded, as on Linux.
+    pub(super) fn pipe_detach(&mut self, node_idx: usize, flags: i32) {
+        if let NodeInfo::Pip {
+            data,
+            readers,
+            writers,
+            ..
+        } = &mut self.nodes[node_idx].info
+        {
+            if flags & O_ACCMODE != O_WRONLY {
+                *readers = readers.saturating_sub(1);
+            }
+            if flags & O_ACCMODE != O_RDONLY {
+                *writers = writers.saturating_sub(1);
+            }
+            if *readers == 0 && *writers == 0 {
+                data.clear();
+            }
+        }
+    }
+
+    /// Move up to buf.len() bytes out of a pipe. Returns 0 at EOF (no
+    /// writers left) and EAGAIN while writers exist but nothing is buffered.
+    pub(super) fn pipe_read(&mut self, node_idx: usize, buf: &mut [u8]) -> i32 {
+        let NodeInfo::Pip { data, writers, .. } = &mut self.nodes[node_idx].info else {
+            return -9; // EBADF
+        };
+
+        if buf.is_empty() {
+            return 0;
+        }
+
+        if data.is_empty() {
+            return if *writers == 0 { 0 } else { -11 }; // EAGAIN
+        }
+
+        let n = buf.len().min(data.len());
+        for (dst, src) in buf.iter_mut().zip(data.drain(..n)) {
+            *dst = src;
+        }
+
+        self.update_atime(node_idx);
+        n as i32
+    }
+
+    /// Append as much of buf as fits to a pipe. Returns EPIPE when no reader
+    /// is left and EAGAIN when the pipe is full, or when a write of at most
+    /// PIPE_BUF bytes does not fit in one piece.
+    pub(super) fn pipe_write(&mut self, node_idx: usize, buf: &[u8]) -> i32 {
+        let NodeInfo::Pip { data, readers, .. } = &mut self.nodes[node_idx].info else {
+            return -9; // EBADF
+        };
+
+        if *readers == 0 {
+            return -32; // EPIPE
+        }
+
+        if buf.is_empty() {
+            return 0;
+        }
+
+        let space = PIPE_CAPACITY - data.len();
+        if space == 0 || (buf.len() <= PIPE_BUF && space < buf.len()) {
+            return -11; // EAGAIN
+        }
+
+        let n = buf.len().min(space);
+        data.extend(&buf[..n]);
+
+        self.update_mtime(node_idx);
+        self.update_ctime(node_idx);
+        n as i32
+    }
+
+    /// Whether fd refers to a pipe or FIFO.
+    pub fn is_pipe_fd(&self, cage_id: u64, fd: u64) -> bool {
+        match fdtables::translate_virtual_fd(cage_id, fd) {
+            Ok(entry) => matches!(
+                self.nodes.get(entry.underfd as usize).map(|n| &n.info),
+                Some(NodeInfo::Pip { .. })
+            ),
+            Err(_) => false,
+        }
+    }
+
+    fn is_nonblocking_fd(&self, cage_id: u64, fd: u64) -> bool {
+        self.fd_info
+            .get(&(cage_id, fd))
+            .map(|info| info.lock().unwrap().flags as i32 & O_NONBLOCK != 0)
+            .unwrap_or(false)
+    }
+
+    /// After a blocking open of a FIFO for reading only or writing only,
+    /// returns the peer's open count if the caller has to wait for the other
+    /// end to be opened.
+    fn fifo_open_wait(&self, cage_id: u64, fd: u64) -> Option<u64> {
+        let entry = fdtables::translate_virtual_fd(cage_id, fd).ok()?;
+        let flags = self.fd_info.get(&(cage_id, fd))?.lock().unwrap().flags as i32;
+        if flags & O_NONBLOCK != 0 {
+            return None;
+        }
+
+        let NodeInfo::Pip {
+            readers,
+            writers,
+            read_opens,
+            write_opens,
+            ..
+        } = &self.nodes[entry.underfd as usize].info
+        else {
+            return None;
+        };
+
+        match flags & O_ACCMODE {
+            O_RDONLY if *writers == 0 => Some(*write_opens),
+            O_WRONLY if *readers == 0 => Some(*read_opens),
+            _ => None,
+        }
+    }
+
+    /// Whether the other end of a FIFO is open, or has been opened since
+    /// `since` was sampled by fifo_open_wait.
+    fn fifo_has_peer(&self, cage_id: u64, fd: u64, since: u64) -> bool {
+        let Ok(entry) = fdtables::translate_virtual_fd(cage_id, fd) else {
+            return true;
+        };
+        let Some(flags) = self
+            .fd_info
+            .get(&(cage_id, fd))
+            .map(|info| info.lock().unwrap().flags as i32)
+        else {
+            return true;
+        };
+
+        match &self.nodes[entry.underfd as usize].info {
+            NodeInfo::Pip {
+                readers,
+                writers,
+                read_opens,
+                write_opens,
+                ..
+            } => match flags & O_ACCMODE {
+                O_RDONLY => *writers > 0 || *write_opens != since,
+                _ => *readers > 0 || *read_opens != since,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// Run an open and, if it opened a FIFO for reading only or writing only
+/// without O_NONBLOCK, wait until the other end has been opened as well.
+pub fn blocking_open<F>(cage_id: u64, open: F) -> i32
+where
+    F: FnOnce(&mut ImfsState) -> i32,
+{
+    let (fd, wait) = with_imfs(|state| {
+        let fd = open(state);
+        let wait = if fd >= 0 {
+            state.fifo_open_wait(cage_id, fd as u64)
+        } else {
+            None
+        };
+        (fd, wait)
+    });
+
+    let Some(since) = wait else {
+        return fd;
+    };
+
+    loop {
+        if with_imfs(|state| state.fifo_has_peer(cage_id, fd as u64, since)) {
+            return fd;
+        }
+        if nap_signal_aware() {
+            with_imfs(|state| state.close(cage_id, fd as u64));
+            return -4; // EINTR
+        }
+    }
+}
+
+/// read, waiting for data while fd is an empty pipe that still has writers.
+pub fn blocking_read(cage_id: u64, fd: u64, buf: &mut [u8]) -> i32 {
+    loop {
+        let ret = with_imfs(|state| state.read(cage_id, fd, buf));
+        if ret != -11 || with_imfs(|state| state.is_nonblocking_fd(cage_id, fd)) {
+            return ret;
+        }
+        if nap_signal_aware() {
+            return -4; // EINTR
+        }
+    }
+}
+
+/// write, waiting for room while fd is a full pipe until all of buf has been
+/// written. A signal or O_NONBLOCK cuts the write short, in which case the
+/// number of bytes written so far is returned if there are any.
+pub fn blocking_write(cage_id: u64, fd: u64, buf: &[u8]) -> i32 {
+    let mut written = 0usize;
+
+    loop {
+        let ret = with_imfs(|state| state.write(cage_id, fd, &buf[written..]));
+        if ret > 0 {
+            written += ret as usize;
+            if written == buf.len() {
+                return written as i32;
+            }
+            continue;
+        }
+
+        let short_or = |err: i32| if written > 0 { written as i32 } else { err };
+
+        if ret == 0 {
+            return written as i32;
+        }
+        if ret != -11 || with_imfs(|state| state.is_nonblocking_fd(cage_id, fd)) {
+            return short_or(ret);
+        }
+        if nap_signal_aware() {
+            return short_or(-4); // EINTR
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Synthetic /proc.
+//!
+//! With `--proc`, IMFS creates /proc holding:
+//!   - cpuinfo, a fixed description of the CPUs the grate runs on,
+//!   - a directory per running cage, named by cage id, with `cwd` and `exe`
+//!     symlinks and an `fd` directory holding a symlink per open fd,
+//!   - self, a symlink to the calling cage's directory.
+//!
+//! The generated directories are brought up to date from cwd_info, exe_paths
+//! and the fd tables each time a path is resolved through them or they are
+//! listed. Entries that did not change keep their nodes, so a listing that
+//! takes several getdents calls sees each entry once.
+//!
+//! An fd link points to the path its file has in the cage's namespace, with
+//! " (deleted)" appended once it was removed. Pipes show as `pipe:[ino]`, and
+//! stdio that is passed through to the host as `host:[fd]`. Opening a link
+//! opens that path again, so unlike on Linux it cannot reach a removed file
+//! or a pipe.
+
+use super::*;
+
+/// A generated /proc directory.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ProcDir {
+    /// /proc itself.
+    Root,
+    /// /proc/<cage>.
+    Cage(u64),
+    /// /proc/<cage>/fd.
+    Fds(u64),
+}
+
+/// An entry a generated directory should hold.
+enum ProcEntry {
+    Link(String),
+    Dir(ProcDir),
+}
+
+impl ImfsState {
+    /// Record the program a cage runs, for /proc/<cage>/exe.
+    pub fn set_exe_path(&mut self, cage_id: u64, path: &str) {
+        let norm_path = self.normalize_path_for_cage(cage_id, path);
+        self.exe_paths.insert(cage_id, norm_path.path);
+    }
+
+    pub(super) fn mount_proc(&mut self, root_idx: usize) {
+        let proc = self.synthetic_dir(root_idx, "proc", 0o555);
+        self.nodes[proc].proc_dir = Some(ProcDir::Root);
+
+        if self.lookup_child(proc, "cpuinfo").is_none() {
+            let idx = self.create_node(0, "cpuinfo", NodeType::Reg, 0o444);
+            self.nodes[idx].synthetic = true;
+            self.set_new_node_owner(0, proc, idx);
+            self.add_child(proc, idx);
+            self.write_to_node(idx, 0, cpuinfo().as_bytes());
+        }
+    }
+
+    /// Bring a generated /proc directory up to date for cage_id. Does
+    /// nothing for other directories.
+    pub(super) fn refresh_proc_dir(&mut self, cage_id: u64, dir_idx: usize) {
+        let Some(kind) = self.nodes[dir_idx].proc_dir else {
+            return;
+        };
+
+        let mut wanted = Vec::new();
+        match kind {
+            ProcDir::Root => {
+                wanted.push(("self".to_string(), ProcEntry::Link(cage_id.to_string())));
+                let mut cages: Vec<u64> =
+                    self.cwd_info.keys().copied().filter(|&c| c != 0).collect();
+                cages.sort_unstable();
+                for cage in cages {
+                    wanted.push((cage.to_string(), ProcEntry::Dir(ProcDir::Cage(cage))));
+                }
+            }
+            ProcDir::Cage(cage) => {
+                if let Some(cwd) = self.cwd_info.get(&cage) {
+                    wanted.push(("cwd".to_string(), ProcEntry::Link(cwd.clone())));
+                    if let Some(exe) = self.exe_paths.get(&cage) {
+                        wanted.push(("exe".to_string(), ProcEntry::Link(exe.clone())));
+                    }
+                    wanted.push(("fd".to_string(), ProcEntry::Dir(ProcDir::Fds(cage))));
+                }
+            }
+            ProcDir::Fds(cage) => {
+                let mut fds: Vec<u64> = self
+                    .fd_info
+                    .keys()
+                    .filter(|(c, _)| *c == cage)
+                    .map(|(_, fd)| *fd)
+                    .collect();
+                fds.sort_unstable();
+                for fd in fds {
+                    if let Some(target) = self.fd_link_target(cage, fd) {
+                        wanted.push((fd.to_string(), ProcEntry::Link(target)));
+                    }
+                }
+            }
+        }
+
+        self.sync_proc_entries(dir_idx, wanted);
+    }
+
+    /// Make a generated directory hold exactly the wanted entries, reusing
+    /// the nodes of entries that are already there. Entries that were not
+    /// generated, like cpuinfo, are left alone.
+    fn sync_proc_entries(&mut self, dir_idx: usize, wanted: Vec<(String, ProcEntry)>) {
+        let stale: Vec<String> = self.nodes[dir_idx]
+            .children()
+            .iter()
+            .filter(|entry| entry.name != "." && entry.name != "..")
+            .filter(|entry| {
+                let node = &self.nodes[entry.node_idx];
+                node.node_type == NodeType::Lnk || node.proc_dir.is_some()
+            })
+            .filter(|entry| !wanted.iter().any(|(name, _)| *name == entry.name))
+            .map(|entry| entry.name.clone())
+            .collect();
+        for name in stale {
+            self.remove_proc_entry(dir_idx, &name);
+        }
+
+        for (name, entry) in wanted {
+            let existing = self.lookup_child(dir_idx, &name);
+            match (existing, entry) {
+                (Some(idx), ProcEntry::Link(target)) => {
+                    let node = &mut self.nodes[idx];
+                    if let NodeInfo::Symlink { target: old } = &mut node.info
+                        && *old != target
+                    {
+                        node.total_size = target.len();
+                        *old = target;
+                    }
+                }
+                (Some(_), ProcEntry::Dir(_)) => {}
+                (None, ProcEntry::Link(target)) => {
+                    self.synthetic_link(dir_idx, &name, &target);
+                }
+                (None, ProcEntry::Dir(kind)) => {
+                    let idx = self.synthetic_dir(dir_idx, &name, 0o555);
+                    self.nodes[idx].proc_dir = Some(kind);
+                }
+            }
+        }
+    }
+
+    /// Remove a generated entry and everything below it.
+    fn remove_proc_entry(&mut self, dir_idx: usize, name: &str) {
+        let Some(idx) = self.lookup_child(dir_idx, name) else {
+            return;
+        };
+        if self.nodes[idx].node_type == NodeType::Dir {
+            let names: Vec<String> = self.nodes[idx]
+                .children()
+                .iter()
+                .filter(|entry| entry.name != "." && entry.name != "..")
+                .map(|entry| entry.name.clone())
+                .collect();
+            for child in names {
+                self.remove_proc_entry(idx, &child);
+            }
+        }
+        self.remove_entry(dir_idx, name);
+        self.unlink_node(idx);
+    }
+
+    /// What /proc/<cage>/fd/<fd> points to.
+    fn fd_link_target(&self, cage_id: u64, fd: u64) -> Option<String> {
+        let entry = fdtables::translate_virtual_fd(cage_id, fd).ok()?;
+        let node_idx = entry.underfd as usize;
+        let node = self.nodes.get(node_idx)?;
+
+        let passthrough = !matches!(node.info, NodeInfo::Pip { .. } | NodeInfo::Chr { .. });
+        Some(
+            if matches!(node.info, NodeInfo::Pip { .. }) && node.nlink == 0 {
+                format!("pipe:[{}]", node_idx)
+            } else if fd < 3 && passthrough {
+                // Same rule as is_host_stdio in handlers.rs.
+                format!("host:[{}]", fd)
+            } else if node.doomed {
+                format!("{} (deleted)", self.absolute_path_for_node(node_idx))
+            } else {
+                self.absolute_path_for_node(node_idx)
+            },
+        )
+    }
+}
+
+/// Contents of /proc/cpuinfo.
+fn cpuinfo() -> String {
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut text = String::new();
+    for cpu in 0..cpus {
+        text.push_str(&format!(
+            "processor\t: {}\nvendor_id\t: lind\nmodel name\t: Lind WebAssembly\n\
+             cpu cores\t: {}\nflags\t\t:\n\n",
+            cpu, cpus
+        ));
+    }
+    text
+}
@@ -0,0 +1,296 @@
+//! Memory accounting and quotas.
+//!
+//! Every node and every byte of file data lives in the grate's own memory,
+//! so IMFS counts what each cage uses. A node is charged to the cage that
+//! created it (`Node.quota_cage`) as one inode, plus the file data it stores.
+//! Holes are free. Usage is also summed over all cages, and allocations are
+//! checked like the kernel checks disk quotas:
+//!   - Going over a global limit fails with ENOSPC. statfs reports the global
+//!     limits as the size of the filesystem.
+//!   - Going over a cage's hard limit fails with EDQUOT.
+//!   - A cage may stay over its soft limit for the grace period. After that,
+//!     the soft limit acts as a hard one until the cage drops back under it.
+//!
+//! Writing to a file checks the quota of the cage the file is charged to, not
+//! the writer's. Cage 0 (PRELOADS, and host entries in overlay mode) only has
+//! the global limits. A forked cage starts with no usage of its own, and its
+//! files stay charged to it after it exits. A cloned namespace is charged to
+//! the forked cage without checking its limits, since fork cannot fail with
+//! EDQUOT. Anonymous pipes are charged an inode but never refused one.
+
+use super::*;
+
+/// Default size of IMFS as a whole: the wasm32 address space.
+pub const DEFAULT_MAX_BYTES: u64 = 4 << 30;
+/// Default grace period for soft limits (7 days, as for Linux quotas).
+pub const DEFAULT_GRACE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Bytes of file data and number of nodes in use.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Usage {
+    pub bytes: u64,
+    pub inodes: u64,
+}
+
+/// Limits on bytes and nodes. None is unlimited.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Limits {
+    pub bytes: Option<u64>,
+    pub inodes: Option<u64>,
+}
+
+impl std::str::FromStr for Limits {
+    type Err = String;
+
+    /// Parse `BYTES[:INODES]`. BYTES takes a K, M or G suffix, and either
+    /// part may be left empty for no limit.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (bytes, inodes) = s.split_once(':').unwrap_or((s, ""));
+        let parse = |part: &str, suffixes: bool| -> Result<Option<u64>, String> {
+            if part.is_empty() {
+                return Ok(None);
+            }
+            let (digits, scale) = match part.as_bytes()[part.len() - 1] {
+                b'K' | b'k' if suffixes => (&part[..part.len() - 1], 1 << 10),
+                b'M' | b'm' if suffixes => (&part[..part.len() - 1], 1 << 20),
+                b'G' | b'g' if suffixes => (&part[..part.len() - 1], 1 << 30),
+                _ => (part, 1),
+            };
+            digits
+                .parse::<u64>()
+                .ok()
+                .and_then(|n| n.checked_mul(scale))
+                .map(Some)
+                .ok_or_else(|| format!("bad limit: {}", s))
+        };
+
+        Ok(Limits {
+            bytes: parse(bytes, true)?,
+            inodes: parse(inodes, false)?,
+        })
+    }
+}
+
+/// Limits set on the command line, see main.rs.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct QuotaConfig {
+    /// Limits on IMFS as a whole.
+    pub global: Limits,
+    /// Limits on each cage.
+    pub hard: Limits,
+    pub soft: Limits,
+    /// How long a cage may stay over a soft limit, in seconds.
+    pub grace_secs: u64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        QuotaConfig {
+            global: Limits {
+                bytes: Some(DEFAULT_MAX_BYTES),
+                inodes: None,
+            },
+            hard: Limits::default(),
+            soft: Limits::default(),
+            grace_secs: DEFAULT_GRACE_SECS,
+        }
+    }
+}
+
+/// A cage's usage, and when it went over each of its soft limits.
+#[derive(Default)]
+struct CageUsage {
+    used: Usage,
+    bytes_over_since: Option<u64>,
+    inodes_over_since: Option<u64>,
+}
+
+/// Usage of every cage and of IMFS as a whole.
+#[derive(Default)]
+pub struct QuotaState {
+    config: QuotaConfig,
+    total: Usage,
+    cages: HashMap<u64, CageUsage>,
+}
+
+/// Seconds on the monotonic clock, for grace periods.
+fn now_secs() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    ts.tv_sec as u64
+}
+
+/// Whether used + more goes over limit.
+fn exceeds(used: u64, more: u64, limit: Option<u64>) -> bool {
+    limit.is_some_and(|limit| used.saturating_add(more) > limit)
+}
+
+impl ImfsState {
+    pub fn set_quota(&mut self, config: QuotaConfig) {
+        self.quota.config = config;
+    }
+
+    /// Check that cage_id may allocate `bytes` more bytes and `inodes` more
+    /// nodes.
+    pub(super) fn check_quota(&self, cage_id: u64, bytes: u64, inodes: u64) -> Result<(), i32> {
+        let quota = &self.quota;
+        if exceeds(quota.total.bytes, bytes, quota.config.global.bytes)
+            || exceeds(quota.total.inodes, inodes, quota.config.global.inodes)
+        {
+            return Err(-28); // ENOSPC
+        }
+        if cage_id == 0 {
+            return Ok(());
+        }
+
+        let none = CageUsage::default();
+        let cage = quota.cages.get(&cage_id).unwrap_or(&none);
+        let grace_over = |since: Option<u64>| {
+            since.is_some_and(|since| now_secs().saturating_sub(since) >= quota.config.grace_secs)
+        };
+        if bytes > 0
+            && (exceeds(cage.used.bytes, bytes, quota.config.hard.bytes)
+                || grace_over(cage.bytes_over_since))
+        {
+            return Err(-122); // EDQUOT
+        }
+        if inodes > 0
+            && (exceeds(cage.used.inodes, inodes, quota.config.hard.inodes)
+                || grace_over(cage.inodes_over_since))
+        {
+            return Err(-122); // EDQUOT
+        }
+        Ok(())
+    }
+
+    /// Check that a regular file may store data for [start, end), charged to
+    /// the cage the file belongs to.
+    pub(super) fn check_file_growth(
+        &self,
+        node_idx: usize,
+        start: usize,
+        end: usize,
+    ) -> Result<(), i32> {
+        let NodeInfo::Reg { extents } = &self.nodes[node_idx].info else {
+            return Ok(());
+        };
+
+        let mut growth = 0;
+        if start < end {
+            for idx in start / EXTENT_SIZE..end.div_ceil(EXTENT_SIZE) {
+                let needed = (end - idx * EXTENT_SIZE).min(EXTENT_SIZE);
+                let stored = extents.get(&idx).map_or(0, |extent| extent.len());
+                growth += needed.saturating_sub(stored);
+            }
+        }
+
+        self.check_quota(self.nodes[node_idx].quota_cage, growth as u64, 0)
+    }
+
+    /// Add to a cage's usage. Does not check the limits.
+    pub(super) fn charge(&mut self, cage_id: u64, bytes: u64, inodes: u64) {
+        self.quota.total.bytes += bytes;
+        self.quota.total.inodes += inodes;
+        let cage = self.quota.cages.entry(cage_id).or_default();
+        cage.used.bytes += bytes;
+        cage.used.inodes += inodes;
+        self.update_grace(cage_id);
+    }
+
+    pub(super) fn uncharge(&mut self, cage_id: u64, bytes: u64, inodes: u64) {
+        let total = &mut self.quota.total;
+        total.bytes = total.bytes.saturating_sub(bytes);
+        total.inodes = total.inodes.saturating_sub(inodes);
+        let Some(cage) = self.quota.cages.get_mut(&cage_id) else {
+            return;
+        };
+        cage.used.bytes = cage.used.bytes.saturating_sub(bytes);
+        cage.used.inodes = cage.used.inodes.saturating_sub(inodes);
+        if cage.used == Usage::default() {
+            self.quota.cages.remove(&cage_id);
+        } else {
+            self.update_grace(cage_id);
+        }
+    }
+
+    /// Start or stop a cage's grace periods as it crosses its soft limits.
+    fn update_grace(&mut self, cage_id: u64) {
+        let soft = self.quota.config.soft;
+        let Some(cage) = self.quota.cages.get_mut(&cage_id) else {
+            return;
+        };
+
+        if exceeds(cage.used.bytes, 0, soft.bytes) {
+            cage.bytes_over_since.get_or_insert_with(now_secs);
+        } else {
+            cage.bytes_over_since = None;
+        }
+        if exceeds(cage.used.inodes, 0, soft.inodes) {
+            cage.inodes_over_since.get_or_insert_with(now_secs);
+        } else {
+            cage.inodes_over_since = None;
+        }
+    }
+
+    /// Charge or refund the change in a file's stored bytes to the cage it
+    /// belongs to.
+    pub(super) fn charge_file_bytes(&mut self, node_idx: usize, before: usize, after: usize) {
+        let cage_id = self.nodes[node_idx].quota_cage;
+        if after > before {
+            self.charge(cage_id, (after - before) as u64, 0);
+        } else if before > after {
+            self.uncharge(cage_id, (before - after) as u64, 0);
+        }
+    }
+
+    /// Bytes of file data a node holds in memory.
+    pub(super) fn stored_bytes(&self, node_idx: usize) -> usize {
+        match &self.nodes[node_idx].info {
+            NodeInfo::Reg { extents } => extents.values().map(|extent| extent.len()).sum(),
+            _ => 0,
+        }
+    }
+
+    /// Fill in statfs from the usage counts. Free space is what the global
+    /// limits leave; f_bavail is also capped by the calling cage's own
+    /// hard limit. A missing limit is reported as 0, as tmpfs does.
+    pub(super) fn fill_statfs(&self, cage_id: u64, statbuf: &mut FsData) {
+        let quota = &self.quota;
+        let blocks = quota
+            .config
+            .global
+            .bytes
+            .map_or(0, |bytes| bytes / IMFS_STATFS_BLOCK_SIZE);
+        let used_blocks = quota.total.bytes.div_ceil(IMFS_STATFS_BLOCK_SIZE);
+        let free_blocks = blocks.saturating_sub(used_blocks);
+        let files = quota.config.global.inodes.unwrap_or(0);
+        let free_files = files.saturating_sub(quota.total.inodes);
+
+        let mut avail_blocks = free_blocks;
+        if cage_id != 0
+            && let Some(limit) = quota.config.hard.bytes
+        {
+            let used = quota.cages.get(&cage_id).map_or(0, |cage| cage.used.bytes);
+            avail_blocks = avail_blocks.min(limit.saturating_sub(used) / IMFS_STATFS_BLOCK_SIZE);
+        }
+
+        *statbuf = FsData {
+            f_type: IMFS_STATFS_MAGIC,
+            f_bsize: IMFS_STATFS_BLOCK_SIZE,
+            f_blocks: blocks,
+            f_bfree: free_blocks,
+            f_bavail: avail_blocks,
+            f_files: files,
+            f_ffiles: free_files,
+            f_fsid: 0,
+            f_namelen: IMFS_STATFS_NAME_MAX,
+            f_frsize: IMFS_STATFS_BLOCK_SIZE,
+            f_spare: [0; 32],
+        };
+    }
+}
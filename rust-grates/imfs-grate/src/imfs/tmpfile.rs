@@ -0,0 +1,77 @@
+//! Unnamed files.
+//!
+//! open(O_TMPFILE) on a directory creates a regular file with no directory
+//! entry. It is doomed from the start, so it is freed when its last fd
+//! closes, like a file that was unlinked while open. Its parent_idx still
+//! points at the directory, which decides its namespace and, for a
+//! set-group-ID directory, its group.
+//!
+//! Unless it was opened with O_EXCL, linkat(fd, "", dirfd, name,
+//! AT_EMPTY_PATH) gives the file a name, after which it is an ordinary
+//! file. Any other file without links (unlinked while open, or an
+//! anonymous pipe) cannot be linked back in and fails with ENOENT.
+
+use super::*;
+
+/// O_TMPFILE as the guest passes it. It includes O_DIRECTORY, so that
+/// kernels without O_TMPFILE fail the open instead of creating a file.
+pub const O_TMPFILE: i32 = 0o20000000 | O_DIRECTORY;
+
+impl ImfsState {
+    /// open(O_TMPFILE): create an unnamed regular file in the directory at
+    /// norm_path and return an fd for it.
+    pub(super) fn open_tmpfile(
+        &mut self,
+        cage_id: u64,
+        norm_path: &NormPath,
+        flags: i32,
+        mode: u32,
+    ) -> i32 {
+        // The file can only be used through this fd, so it must be writable.
+        if flags & O_CREAT != 0 || flags & O_ACCMODE == O_RDONLY {
+            return -22; // EINVAL
+        }
+
+        let dir_idx = match self.resolve_path(norm_path, true) {
+            Ok(idx) => idx,
+            Err(e) => return e,
+        };
+        if self.nodes[dir_idx].node_type != NodeType::Dir {
+            return -20; // ENOTDIR
+        }
+        if let Err(e) = self.check_dir_write(cage_id, dir_idx) {
+            return e;
+        }
+        if let Err(e) = self.check_quota(cage_id, 0, 1) {
+            return e;
+        }
+
+        let node_idx =
+            self.create_node(cage_id, "", NodeType::Reg, self.apply_umask(cage_id, mode));
+        self.set_new_node_owner(cage_id, dir_idx, node_idx);
+        let node = &mut self.nodes[node_idx];
+        node.parent_idx = dir_idx;
+        node.doomed = true;
+        node.linkable_tmpfile = flags & O_EXCL == 0;
+        self.update_mtime(node_idx);
+        self.update_ctime(node_idx);
+
+        match self.install_fd(cage_id, node_idx, flags, (flags & O_CLOEXEC) != 0) {
+            Ok(vfd) => vfd as i32,
+            Err(e) => {
+                self.reclaim_node(node_idx);
+                e
+            }
+        }
+    }
+
+    /// Give an O_TMPFILE file its first name: the node itself becomes the
+    /// directory entry, and it is no longer freed on its last close.
+    pub(super) fn name_tmpfile(&mut self, node_idx: usize, parent_idx: usize, filename: &str) {
+        let node = &mut self.nodes[node_idx];
+        node.name = filename.to_string();
+        node.doomed = false;
+        node.linkable_tmpfile = false;
+        self.add_child(parent_idx, node_idx);
+    }
+}
@@ -0,0 +1,235 @@
+//! Extended attributes.
+//!
+//! Each node keeps its xattrs in a name -> value map. The path, l* and f*
+//! syscall variants only differ in how the node is found, so they all go
+//! through one XattrTarget. Limits and errors follow Linux:
+//!   - names need a "user.", "trusted." or "security." prefix (EOPNOTSUPP
+//!     otherwise; "system." would mean POSIX ACLs, which IMFS does not have),
+//!   - names are at most XATTR_NAME_MAX bytes and values XATTR_SIZE_MAX bytes,
+//!   - a missing attribute is ENODATA and a too-small buffer is ERANGE,
+//!   - user.* attributes need read permission to get and write permission to
+//!     set or remove, and trusted.* attributes are invisible to cages that
+//!     are not privileged.
+
+use super::*;
+
+/// Maximum length of an xattr name.
+pub const XATTR_NAME_MAX: usize = 255;
+/// Maximum size of an xattr value.
+pub const XATTR_SIZE_MAX: usize = 65536;
+/// Maximum size of a node's NUL-separated name list.
+pub const XATTR_LIST_MAX: usize = 65536;
+
+const XATTR_CREATE: i32 = 0x1;
+const XATTR_REPLACE: i32 = 0x2;
+const XATTR_SUPPORTED_PREFIXES: [&str; 3] = ["user.", "trusted.", "security."];
+
+/// How an xattr syscall names its node.
+pub enum XattrTarget<'a> {
+    /// setxattr/getxattr/listxattr/removexattr.
+    Path(&'a str),
+    /// lsetxattr and friends: a final symlink is not followed.
+    LinkPath(&'a str),
+    /// fsetxattr and friends.
+    Fd(u64),
+}
+
+impl ImfsState {
+    fn xattr_node_idx(&mut self, cage_id: u64, target: &XattrTarget) -> Result<usize, i32> {
+        match target {
+            XattrTarget::Path(path) | XattrTarget::LinkPath(path) => {
+                let follow = matches!(target, XattrTarget::Path(_));
+                let norm_path = self.normalize_path_for_cage(cage_id, path);
+                self.resolve_path(&norm_path, follow)
+            }
+            XattrTarget::Fd(fd) => self.get_node_and_flags(cage_id, *fd).map(|(idx, _)| idx),
+        }
+    }
+
+    fn check_xattr_name(name: &str) -> Result<(), i32> {
+        if name.is_empty() || name.len() > XATTR_NAME_MAX {
+            return Err(-34); // ERANGE
+        }
+        if !XATTR_SUPPORTED_PREFIXES
+            .iter()
+            .any(|prefix| name.len() > prefix.len() && name.starts_with(prefix))
+        {
+            return Err(-95); // EOPNOTSUPP
+        }
+        Ok(())
+    }
+
+    /// Permission to get (MAY_READ) or change (MAY_WRITE) the named
+    /// attribute. An unprivileged cage cannot see trusted.* attributes, so
+    /// reading one is ENODATA and changing one is EPERM.
+    fn check_xattr_access(
+        &self,
+        cage_id: u64,
+        node_idx: usize,
+        name: &str,
+        want: u32,
+    ) -> Result<(), i32> {
+        if name.starts_with("trusted.") && !self.is_privileged(cage_id) {
+            return Err(if want == perm::MAY_WRITE {
+                -1 // EPERM
+            } else {
+                -61 // ENODATA
+            });
+        }
+        if name.starts_with("user.") {
+            self.check_access(cage_id, node_idx, want)?;
+        }
+        Ok(())
+    }
+
+    fn xattr_list_len<'a>(names: impl Iterator<Item = &'a String>) -> usize {
+        names.map(|name| name.len() + 1).sum()
+    }
+
+    /// setxattr: create or replace an attribute. XATTR_CREATE fails with
+    /// EEXIST if it already exists, XATTR_REPLACE with ENODATA if it doesn't.
+    pub fn setxattr(
+        &mut self,
+        cage_id: u64,
+        target: XattrTarget,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+    ) -> i32 {
+        if flags & !(XATTR_CREATE | XATTR_REPLACE) != 0 || flags == XATTR_CREATE | XATTR_REPLACE {
+            return -22; // EINVAL
+        }
+        if let Err(e) = Self::check_xattr_name(name) {
+            return e;
+        }
+        if value.len() > XATTR_SIZE_MAX {
+            return -7; // E2BIG
+        }
+
+        let node_idx = match self.xattr_node_idx(cage_id, &target) {
+            Ok(idx) => idx,
+            Err(e) => return e,
+        };
+
+        // Like Linux, user.* attributes are only allowed on regular files
+        // and directories.
+        if name.starts_with("user.")
+            && !matches!(
+                self.nodes[node_idx].node_type,
+                NodeType::Reg | NodeType::Dir
+            )
+        {
+            return -1; // EPERM
+        }
+        if let Err(e) = self.check_xattr_access(cage_id, node_idx, name, perm::MAY_WRITE) {
+            return e;
+        }
+
+        let xattrs = &mut self.nodes[node_idx].xattrs;
+        let exists = xattrs.contains_key(name);
+        if exists && flags & XATTR_CREATE != 0 {
+            return -17; // EEXIST
+        }
+        if !exists && flags & XATTR_REPLACE != 0 {
+            return -61; // ENODATA
+        }
+        if !exists && Self::xattr_list_len(xattrs.keys()) + name.len() + 1 > XATTR_LIST_MAX {
+            return -28; // ENOSPC
+        }
+
+        xattrs.insert(name.to_string(), value.to_vec());
+        self.update_ctime(node_idx);
+        0
+    }
+
+    /// getxattr: copy an attribute's value into buf and return its length.
+    /// An empty buf only asks for the length.
+    pub fn getxattr(
+        &mut self,
+        cage_id: u64,
+        target: XattrTarget,
+        name: &str,
+        buf: &mut [u8],
+    ) -> i32 {
+        if let Err(e) = Self::check_xattr_name(name) {
+            return e;
+        }
+
+        let node_idx = match self.xattr_node_idx(cage_id, &target) {
+            Ok(idx) => idx,
+            Err(e) => return e,
+        };
+        if let Err(e) = self.check_xattr_access(cage_id, node_idx, name, perm::MAY_READ) {
+            return e;
+        }
+
+        let Some(value) = self.nodes[node_idx].xattrs.get(name) else {
+            return -61; // ENODATA
+        };
+
+        if buf.is_empty() {
+            return value.len() as i32;
+        }
+        if buf.len() < value.len() {
+            return -34; // ERANGE
+        }
+
+        buf[..value.len()].copy_from_slice(value);
+        value.len() as i32
+    }
+
+    /// listxattr: write the node's attribute names, each NUL-terminated, into
+    /// buf and return the total length. An empty buf only asks for the length.
+    pub fn listxattr(&mut self, cage_id: u64, target: XattrTarget, buf: &mut [u8]) -> i32 {
+        let node_idx = match self.xattr_node_idx(cage_id, &target) {
+            Ok(idx) => idx,
+            Err(e) => return e,
+        };
+
+        let show_trusted = self.is_privileged(cage_id);
+        let names: Vec<&String> = self.nodes[node_idx]
+            .xattrs
+            .keys()
+            .filter(|name| show_trusted || !name.starts_with("trusted."))
+            .collect();
+        let len = Self::xattr_list_len(names.iter().copied());
+
+        if buf.is_empty() {
+            return len as i32;
+        }
+        if buf.len() < len {
+            return -34; // ERANGE
+        }
+
+        let mut pos = 0;
+        for name in names {
+            buf[pos..pos + name.len()].copy_from_slice(name.as_bytes());
+            buf[pos + name.len()] = 0;
+            pos += name.len() + 1;
+        }
+
+        len as i32
+    }
+
+    /// removexattr
+    pub fn removexattr(&mut self, cage_id: u64, target: XattrTarget, name: &str) -> i32 {
+        if let Err(e) = Self::check_xattr_name(name) {
+            return e;
+        }
+
+        let node_idx = match self.xattr_node_idx(cage_id, &target) {
+            Ok(idx) => idx,
+            Err(e) => return e,
+        };
+        if let Err(e) = self.check_xattr_access(cage_id, node_idx, name, perm::MAY_WRITE) {
+            return e;
+        }
+
+        if self.nodes[node_idx].xattrs.remove(name).is_none() {
+            return -61; // ENODATA
+        }
+
+        self.update_ctime(node_idx);
+        0
+    }
+}
@@ -3,7 +3,32 @@
 //! This grate intercepts filesystem syscalls (open, close, read, write, lseek,
 //! fcntl, unlink, pread, pwrite) and handles them with an in-memory filesystem.
 //!
-//! Usage: imfs-grate <cage_binary> [args...]
+//! Usage: imfs-grate [--log] [--namespaces shared|isolated|clone]
+//!                   [--namespace-depth <n>]
+//!                   [--overlay <host_dir> [--overlay-diff <host_dir>]]
+//!                   [--max-size <limits>] [--quota <limits>]
+//!                   [--quota-soft <limits> [--quota-grace <secs>]]
+//!                   [--dev] [--proc] <cage_binary> [args...]
+//!
+//! --namespaces picks what a forked cage sees: the same tree as its parent
+//! (shared, the default), an empty tree of its own (isolated), or a
+//! copy-on-write clone of its parent's tree (clone). Only cages forked
+//! --namespace-depth forks below the initial cage (default 1) get a new
+//! namespace; their descendants share it.
+//!
+//! --overlay layers IMFS over a read-only host directory: files missing from
+//! memory are read from the host, and every change stays in memory. With
+//! --overlay-diff, the changes are written to that host directory at teardown
+//! as an overlay layer, with `.wh.` whiteout files for deletions.
+//!
+//! --max-size caps IMFS as a whole (ENOSPC), and --quota and --quota-soft cap
+//! every cage (EDQUOT), see imfs/quota.rs. Limits are `BYTES[:INODES]`, where
+//! BYTES takes a K, M or G suffix and either part may be empty. A cage may
+//! stay over its soft limit for --quota-grace seconds (default 7 days).
+//!
+//! --dev adds /dev with null, zero, full, random and urandom, and --proc adds
+//! /proc with cpuinfo and each cage's cwd, exe and open fds, see
+//! imfs/devfs.rs and imfs/procfs.rs.
 //!
 //! Environment variables:
 //!   PRELOADS — colon-separated list of host files to load into IMFS at startup.
@@ -19,9 +44,24 @@ use grate_rs::constants::lind::GRATE_MEMORY_FLAG;
 use grate_rs::constants::*;
 use grate_rs::ffi::stat;
 use grate_rs::{GrateBuilder, GrateError, getcageid, make_threei_call};
+use imfs::namespace::NamespaceMode;
+use imfs::perm::Credentials;
+use imfs::quota::{DEFAULT_MAX_BYTES, QuotaConfig};
 use std::ffi::CString;
 
+const SYS_MKNODAT: u64 = 259;
 const SYS_LINKAT: u64 = 265;
+const SYS_FALLOCATE: u64 = 285;
+const SYS_LSETXATTR: u64 = 189;
+const SYS_FSETXATTR: u64 = 190;
+const SYS_GETXATTR: u64 = 191;
+const SYS_LGETXATTR: u64 = 192;
+const SYS_FGETXATTR: u64 = 193;
+const SYS_LLISTXATTR: u64 = 195;
+const SYS_FLISTXATTR: u64 = 196;
+const SYS_REMOVEXATTR: u64 = 197;
+const SYS_LREMOVEXATTR: u64 = 198;
+const SYS_FREMOVEXATTR: u64 = 199;
 const PRELOAD_READ_CHUNK_SIZE: usize = 4096;
 const DUMP_WRITE_CHUNK_SIZE: usize = 1024;
 const S_IFMT: u32 = 0o170000;
@@ -30,41 +70,144 @@ const S_IFREG: u32 = 0o100000;
 struct Config {
     argv: Vec<String>,
     log_enabled: bool,
+    namespace_mode: NamespaceMode,
+    namespace_depth: u32,
+    overlay: Option<String>,
+    overlay_diff: Option<String>,
+    quota: QuotaConfig,
+    dev: bool,
+    proc: bool,
 }
 
-fn parse_argv(args: Vec<String>) -> Config {
+fn parse_argv(args: Vec<String>) -> Result<Config, String> {
     let mut log_enabled = false;
+    let mut namespace_mode = NamespaceMode::Shared;
+    let mut namespace_depth = 1;
+    let mut overlay = None;
+    let mut overlay_diff = None;
+    let mut quota = QuotaConfig::default();
+    let mut dev = false;
+    let mut proc = false;
     let mut i = 0;
 
     while i < args.len() {
         if args[i] == "--log" {
             log_enabled = true;
             i += 1;
+        } else if args[i] == "--dev" {
+            dev = true;
+            i += 1;
+        } else if args[i] == "--proc" {
+            proc = true;
+            i += 1;
+        } else if args[i] == "--namespaces" {
+            if i + 1 >= args.len() {
+                return Err("--namespaces requires an argument".to_string());
+            }
+            namespace_mode = args[i + 1].parse()?;
+            i += 2;
+        } else if args[i] == "--namespace-depth" {
+            if i + 1 >= args.len() {
+                return Err("--namespace-depth requires an argument".to_string());
+            }
+            namespace_depth = match args[i + 1].parse() {
+                Ok(depth) if depth > 0 => depth,
+                _ => return Err(format!("bad namespace depth: {}", args[i + 1])),
+            };
+            i += 2;
+        } else if args[i] == "--overlay" || args[i] == "--overlay-diff" {
+            if i + 1 >= args.len() {
+                return Err(format!("{} requires an argument", args[i]));
+            }
+            if args[i] == "--overlay" {
+                overlay = Some(args[i + 1].clone());
+            } else {
+                overlay_diff = Some(args[i + 1].clone());
+            }
+            i += 2;
+        } else if args[i] == "--max-size"
+            || args[i] == "--quota"
+            || args[i] == "--quota-soft"
+            || args[i] == "--quota-grace"
+        {
+            if i + 1 >= args.len() {
+                return Err(format!("{} requires an argument", args[i]));
+            }
+            let value = &args[i + 1];
+            match args[i].as_str() {
+                "--max-size" => {
+                    quota.global = value.parse()?;
+                    // The address space is the limit either way.
+                    quota.global.bytes = quota.global.bytes.or(Some(DEFAULT_MAX_BYTES));
+                }
+                "--quota" => quota.hard = value.parse()?,
+                "--quota-soft" => quota.soft = value.parse()?,
+                _ => {
+                    quota.grace_secs = value
+                        .parse()
+                        .map_err(|_| format!("bad grace period: {}", value))?
+                }
+            }
+            i += 2;
         } else {
             break;
         }
     }
 
-    Config {
+    if overlay_diff.is_some() && overlay.is_none() {
+        return Err("--overlay-diff requires --overlay".to_string());
+    }
+
+    Ok(Config {
         argv: args[i..].to_vec(),
         log_enabled,
-    }
+        namespace_mode,
+        namespace_depth,
+        overlay,
+        overlay_diff,
+        quota,
+        dev,
+        proc,
+    })
 }
 
 fn main() {
-    let config = parse_argv(std::env::args().skip(1).collect());
+    let config = match parse_argv(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("argument error: {}", err);
+            eprintln!(
+                "Usage: imfs-grate [--log] [--namespaces shared|isolated|clone] \
+                 [--namespace-depth <n>] [--overlay <host_dir> [--overlay-diff <host_dir>]] [--max-size <limits>] \
+                 [--quota <limits>] [--quota-soft <limits> [--quota-grace <secs>]] \
+                 [--dev] [--proc] <program> [args...]"
+            );
+            std::process::exit(1);
+        }
+    };
     logging::init(config.log_enabled);
     let dump_files = std::env::var("DUMPS").ok();
+    let overlay_diff = config.overlay_diff.clone();
 
     // Initialize the in-memory filesystem.
     imfs::init();
+    imfs::with_imfs(|s| s.set_utility_credentials(cage_credentials(getcageid())));
+    imfs::with_imfs(|s| s.set_namespace_mode(config.namespace_mode));
+    imfs::with_imfs(|s| s.set_namespace_depth(config.namespace_depth));
+    imfs::with_imfs(|s| s.set_quota(config.quota));
+    if let Some(lower_dir) = config.overlay.as_deref() {
+        imfs::with_imfs(|s| s.set_overlay(lower_dir));
+    }
+    if config.dev || config.proc {
+        imfs::with_imfs(|s| s.mount_synthetic(config.dev, config.proc));
+    }
 
     // Load files from the host filesystem into IMFS before cage execution.
     if let Ok(preloads) = std::env::var("PRELOADS") {
         load_preloads(&preloads);
     }
 
-    imfs::with_imfs(|s| s.mkdir(0, "/tmp", 0755));
+    imfs::with_imfs(|s| s.mkdir(0, "/tmp", 0o1777));
 
     // Build and run the grate. Registers handlers for all filesystem syscalls,
     // forks a child cage, and waits for it to exit.
@@ -78,12 +221,13 @@ fn main() {
         .register(SYS_DUP, handlers::dup_handler)
         .register(SYS_DUP2, handlers::dup2_handler)
         .register(SYS_DUP3, handlers::dup3_handler)
-        .register(SYS_PIPE, handlers::enosys_handler)
-        .register(SYS_PIPE2, handlers::enosys_handler)
+        .register(SYS_PIPE, handlers::pipe_handler)
+        .register(SYS_PIPE2, handlers::pipe2_handler)
         .register(SYS_READ, handlers::read_handler)
         .register(SYS_WRITE, handlers::write_handler)
         .register(SYS_LSEEK, handlers::lseek_handler)
         .register(SYS_FCNTL, handlers::fcntl_handler)
+        .register(SYS_FLOCK, handlers::flock_handler)
         .register(SYS_GETDENTS, handlers::getdents_handler)
         .register(SYS_UNLINK, handlers::unlink_handler)
         .register(SYS_UNLINKAT, handlers::unlinkat_handler)
@@ -93,6 +237,7 @@ fn main() {
         .register(SYS_RENAMEAT, handlers::renameat_handler)
         .register(SYS_RENAMEAT2, handlers::renameat2_handler)
         .register(SYS_MKNOD, handlers::mknod_handler)
+        .register(SYS_MKNODAT, handlers::mknodat_handler)
         .register(SYS_SYMLINK, handlers::symlink_handler)
         .register(SYS_SYMLINKAT, handlers::symlinkat_handler)
         .register(SYS_READLINK, handlers::readlink_handler)
@@ -104,12 +249,26 @@ fn main() {
         .register(SYS_MKDIR, handlers::mkdir_handler)
         .register(SYS_CLONE, handlers::fork_handler)
         .register(SYS_EXEC, handlers::exec_handler)
+        .register(SYS_EXIT, handlers::exit_handler)
+        .register(SYS_EXIT_GROUP, handlers::exit_group_handler)
         .register(SYS_CHDIR, handlers::chdir_handler)
         .register(SYS_FXSTAT, handlers::fstat_handler)
         .register(SYS_XSTAT, handlers::stat_handler)
         .register(SYS_LSTAT, handlers::lstat_handler)
         .register(SYS_NEWFSTATAT, handlers::fstatat_handler)
-        .register(SYS_STATX, handlers::enosys_handler)
+        .register(SYS_STATX, handlers::statx_handler)
+        .register(SYS_SETXATTR, handlers::setxattr_handler)
+        .register(SYS_LSETXATTR, handlers::lsetxattr_handler)
+        .register(SYS_FSETXATTR, handlers::fsetxattr_handler)
+        .register(SYS_GETXATTR, handlers::getxattr_handler)
+        .register(SYS_LGETXATTR, handlers::lgetxattr_handler)
+        .register(SYS_FGETXATTR, handlers::fgetxattr_handler)
+        .register(SYS_LISTXATTR, handlers::listxattr_handler)
+        .register(SYS_LLISTXATTR, handlers::llistxattr_handler)
+        .register(SYS_FLISTXATTR, handlers::flistxattr_handler)
+        .register(SYS_REMOVEXATTR, handlers::removexattr_handler)
+        .register(SYS_LREMOVEXATTR, handlers::lremovexattr_handler)
+        .register(SYS_FREMOVEXATTR, handlers::fremovexattr_handler)
         .register(SYS_RMDIR, handlers::rmdir_handler)
         .register(SYS_CHMOD, handlers::chmod_handler)
         .register(SYS_FCHMODAT, handlers::fchmodat_handler)
@@ -118,8 +277,10 @@ fn main() {
         .register(SYS_FCHOWNAT, handlers::fchownat_handler)
         .register(SYS_TRUNCATE, handlers::truncate_handler)
         .register(SYS_FTRUNCATE, handlers::ftruncate_handler)
+        .register(SYS_FALLOCATE, handlers::fallocate_handler)
         .register(SYS_FCHDIR, handlers::fchdir_handler)
         .register(SYS_FCHMOD, handlers::fchmod_handler)
+        .register(SYS_UMASK, handlers::umask_handler)
         .register(SYS_READV, handlers::readv_handler)
         .register(SYS_WRITEV, handlers::writev_handler)
         .register(SYS_FSYNC, handlers::fsync_handler)
@@ -129,8 +290,10 @@ fn main() {
         .register(SYS_SYNC_FILE_RANGE, handlers::sync_file_range_handler)
         .register(SYS_UTIMENSAT, handlers::utimensat_handler)
         .preexec(|cageid: i32| {
+            let creds = cage_credentials(cageid as u64);
             imfs::with_imfs(|s| {
                 s.cwd_info.insert(cageid as u64, "/".to_string());
+                s.set_credentials(cageid as u64, creds);
             });
 
             fdtables::init_empty_cage(cageid as u64);
@@ -153,6 +316,9 @@ fn main() {
             if let Some(dumps) = dump_files.as_deref() {
                 dump_outputs(dumps);
             }
+            if let Some(diff_dir) = overlay_diff.as_deref() {
+                dump_overlay_diff(diff_dir);
+            }
             log!("exited: {:?}", result);
         })
         .run(config.argv);
@@ -183,7 +349,7 @@ fn load_preloads(preloads: &str) {
         }
 
         // Read the file from the host filesystem through 3i, bypassing IMFS.
-        let data = match read_preload_file(host_path) {
+        let data = match read_host_file(host_path) {
             Ok(d) => d,
             Err(e) => {
                 log!("failed to read {}: {}", host_path, e);
@@ -299,7 +465,7 @@ fn preload_is_regular_file(path: &str) -> bool {
     ret >= 0 && (st.st_mode & S_IFMT) == S_IFREG
 }
 
-fn read_preload_file(path: &str) -> Result<Vec<u8>, String> {
+fn read_host_file(path: &str) -> Result<Vec<u8>, String> {
     let c_path = CString::new(path).map_err(|_| "path contains interior NUL".to_string())?;
     let this_cage = getcageid();
 
@@ -412,6 +578,64 @@ fn dump_outputs(dumps: &str) {
     }
 }
 
+/// Write the changes made over the --overlay directory to diff_dir, laid
+/// out like an overlayfs upper directory in OCI layer form: changed files
+/// and symlinks at their paths, an empty `.wh.<name>` file for each deleted
+/// host entry, and `.wh..wh..opq` in directories that replaced a deleted one.
+/// Every entry written gets its IMFS mode and owner; its times are those of
+/// the dump, which is what IMFS's utimensat sets them to as well.
+fn dump_overlay_diff(diff_dir: &str) {
+    init_utility_cage();
+
+    let diff = imfs::with_imfs(|state| state.overlay_diff());
+    let diff_dir = diff_dir.trim_end_matches('/');
+    let report = |path: &str, result: Result<(), String>| {
+        if let Err(e) = result {
+            log!("failed to write overlay change {}: {}", path, e);
+        }
+    };
+
+    let _ = mkdir_host(diff_dir);
+    for dir in &diff.dirs {
+        let host_path = format!("{}{}", diff_dir, dir);
+        report(
+            dir,
+            create_host_parent_dirs(&host_path).and_then(|_| mkdir_host(&host_path)),
+        );
+    }
+    for dir in &diff.opaque_dirs {
+        report(
+            dir,
+            create_host_file(&format!("{}{}/.wh..wh..opq", diff_dir, dir)),
+        );
+    }
+    for file in &diff.files {
+        log!("dumping overlay change {}", file);
+        report(file, dump_file(file, &format!("{}{}", diff_dir, file)));
+    }
+    for (link, target) in &diff.symlinks {
+        let host_path = format!("{}{}", diff_dir, link);
+        report(
+            link,
+            create_host_parent_dirs(&host_path).and_then(|_| symlink_host(target, &host_path)),
+        );
+    }
+    for path in &diff.whiteouts {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let host_path = format!("{}{}/.wh.{}", diff_dir, parent, name);
+        report(
+            path,
+            create_host_parent_dirs(&host_path).and_then(|_| create_host_file(&host_path)),
+        );
+    }
+    for meta in &diff.metadata {
+        report(
+            &meta.path,
+            set_host_metadata(&format!("{}{}", diff_dir, meta.path), meta),
+        );
+    }
+}
+
 fn init_utility_cage() {
     // fdtables panics if a cage is initialized twice, so preload and dump share
     // this guard instead of calling init_empty_cage(0) directly.
@@ -541,6 +765,111 @@ fn mkdir_host(path: &str) -> Result<(), String> {
     Ok(())
 }
 
+fn create_host_file(path: &str) -> Result<(), String> {
+    let c_path = CString::new(path).map_err(|_| "path contains interior NUL".to_string())?;
+    let this_cage = getcageid();
+    let fd = raw_threei_syscall(
+        SYS_OPEN,
+        [
+            c_path.as_ptr() as u64,
+            (fs::O_CREAT | fs::O_WRONLY | fs::O_TRUNC) as u64,
+            0o644,
+            0,
+            0,
+            0,
+        ],
+        [
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+        ],
+    );
+    if fd < 0 {
+        return Err(format!("host open failed: {}", fd));
+    }
+
+    let _ = raw_threei_syscall(
+        SYS_CLOSE,
+        [fd as u64, 0, 0, 0, 0, 0],
+        [
+            this_cage, this_cage, this_cage, this_cage, this_cage, this_cage,
+        ],
+    );
+    Ok(())
+}
+
+fn symlink_host(target: &str, path: &str) -> Result<(), String> {
+    let c_target = CString::new(target).map_err(|_| "target contains interior NUL".to_string())?;
+    let c_path = CString::new(path).map_err(|_| "path contains interior NUL".to_string())?;
+    let this_cage = getcageid();
+    let ret = raw_threei_syscall(
+        SYS_SYMLINK,
+        [c_target.as_ptr() as u64, c_path.as_ptr() as u64, 0, 0, 0, 0],
+        [
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+        ],
+    );
+    if ret < 0 {
+        return Err(format!("host symlink failed: {}", ret));
+    }
+    Ok(())
+}
+
+fn set_host_metadata(path: &str, meta: &imfs::overlay::EntryMeta) -> Result<(), String> {
+    let c_path = CString::new(path).map_err(|_| "path contains interior NUL".to_string())?;
+    let this_cage = getcageid();
+    // chmod follows symlinks, and a symlink's own mode is ignored anyway.
+    if !meta.is_symlink {
+        let ret = raw_threei_syscall(
+            SYS_CHMOD,
+            [c_path.as_ptr() as u64, meta.mode as u64, 0, 0, 0, 0],
+            [
+                this_cage | GRATE_MEMORY_FLAG,
+                this_cage,
+                this_cage,
+                this_cage,
+                this_cage,
+                this_cage,
+            ],
+        );
+        if ret < 0 {
+            return Err(format!("host chmod failed: {}", ret));
+        }
+    }
+
+    let ret = raw_threei_syscall(
+        SYS_LCHOWN,
+        [
+            c_path.as_ptr() as u64,
+            meta.uid as u64,
+            meta.gid as u64,
+            0,
+            0,
+            0,
+        ],
+        [
+            this_cage | GRATE_MEMORY_FLAG,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+            this_cage,
+        ],
+    );
+    if ret < 0 {
+        return Err(format!("host lchown failed: {}", ret));
+    }
+    Ok(())
+}
+
 fn write_host_all(fd: i32, mut data: &[u8]) -> Result<(), String> {
     let this_cage = getcageid();
     while !data.is_empty() {
@@ -572,6 +901,44 @@ fn write_host_all(fd: i32, mut data: &[u8]) -> Result<(), String> {
     Ok(())
 }
 
+/// Look up a cage's uid and gid through 3i, for IMFS ownership and
+/// permission checks.
+fn cage_credentials(cage_id: u64) -> Credentials {
+    let this_cage = getcageid();
+    let query = |syscall: u64| {
+        make_threei_call(
+            syscall as u32,
+            0,
+            this_cage,
+            cage_id,
+            0,
+            cage_id,
+            0,
+            cage_id,
+            0,
+            cage_id,
+            0,
+            cage_id,
+            0,
+            cage_id,
+            0,
+            cage_id,
+            0,
+        )
+    };
+
+    match (query(SYS_GETUID), query(SYS_GETGID)) {
+        (Ok(uid), Ok(gid)) if uid >= 0 && gid >= 0 => Credentials {
+            uid: uid as u32,
+            gid: gid as u32,
+        },
+        _ => {
+            log!("could not look up credentials of cage {}", cage_id);
+            Credentials::default()
+        }
+    }
+}
+
 fn raw_threei_syscall(syscall: u64, args: [u64; 6], arg_cages: [u64; 6]) -> i32 {
     let this_cage = getcageid();
     // Utility syscalls target the current grate cage rather than an application
@@ -604,7 +971,10 @@ fn raw_threei_syscall(syscall: u64, args: [u64; 6], arg_cages: [u64; 6]) -> i32
 
 #[cfg(test)]
 mod tests {
-    use super::{imfs_parent_dirs, parse_preload_entry};
+    use super::{
+        DEFAULT_MAX_BYTES, NamespaceMode, QuotaConfig, imfs_parent_dirs, parse_argv,
+        parse_preload_entry,
+    };
 
     /// Split a full PRELOADS value the way load_preloads() does.
     fn parse_preloads(preloads: &str) -> Vec<(&str, &str)> {
@@ -680,4 +1050,108 @@ mod tests {
         );
         assert!(imfs_parent_dirs("/preload/").is_empty());
     }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn namespaces_default_to_shared() {
+        let config = parse_argv(args(&["--log", "prog", "--namespaces"])).unwrap();
+        assert_eq!(config.namespace_mode, NamespaceMode::Shared);
+        assert_eq!(config.argv, args(&["prog", "--namespaces"]));
+    }
+
+    #[test]
+    fn namespaces_flag_selects_the_mode() {
+        let config = parse_argv(args(&["--namespaces", "clone", "prog"])).unwrap();
+        assert_eq!(config.namespace_mode, NamespaceMode::Clone);
+        assert_eq!(config.argv, args(&["prog"]));
+
+        assert!(parse_argv(args(&["--namespaces", "private", "prog"])).is_err());
+        assert!(parse_argv(args(&["--namespaces"])).is_err());
+    }
+
+    #[test]
+    fn namespace_depth_defaults_to_one() {
+        let config = parse_argv(args(&["--namespaces", "clone", "prog"])).unwrap();
+        assert_eq!(config.namespace_depth, 1);
+
+        let config = parse_argv(args(&["--namespace-depth", "2", "prog"])).unwrap();
+        assert_eq!(config.namespace_depth, 2);
+        assert_eq!(config.argv, args(&["prog"]));
+
+        assert!(parse_argv(args(&["--namespace-depth", "0", "prog"])).is_err());
+        assert!(parse_argv(args(&["--namespace-depth", "x", "prog"])).is_err());
+        assert!(parse_argv(args(&["--namespace-depth"])).is_err());
+    }
+
+    #[test]
+    fn overlay_flags_name_host_directories() {
+        let config = parse_argv(args(&[
+            "--overlay",
+            "/toolchain",
+            "--overlay-diff",
+            "/out",
+            "prog",
+        ]))
+        .unwrap();
+        assert_eq!(config.overlay.as_deref(), Some("/toolchain"));
+        assert_eq!(config.overlay_diff.as_deref(), Some("/out"));
+        assert_eq!(config.argv, args(&["prog"]));
+
+        let config = parse_argv(args(&["prog"])).unwrap();
+        assert_eq!(config.overlay, None);
+    }
+
+    #[test]
+    fn overlay_diff_requires_an_overlay() {
+        assert!(parse_argv(args(&["--overlay-diff", "/out", "prog"])).is_err());
+        assert!(parse_argv(args(&["--overlay"])).is_err());
+    }
+
+    #[test]
+    fn quota_flags_parse_byte_and_inode_limits() {
+        let config = parse_argv(args(&[
+            "--max-size",
+            ":5000",
+            "--quota",
+            "64M:100",
+            "--quota-soft",
+            "512k",
+            "--quota-grace",
+            "0",
+            "prog",
+        ]))
+        .unwrap();
+        assert_eq!(config.quota.global.bytes, Some(DEFAULT_MAX_BYTES));
+        assert_eq!(config.quota.global.inodes, Some(5000));
+        assert_eq!(config.quota.hard.bytes, Some(64 << 20));
+        assert_eq!(config.quota.hard.inodes, Some(100));
+        assert_eq!(config.quota.soft.bytes, Some(512 << 10));
+        assert_eq!(config.quota.soft.inodes, None);
+        assert_eq!(config.quota.grace_secs, 0);
+        assert_eq!(config.argv, args(&["prog"]));
+
+        let config = parse_argv(args(&["prog"])).unwrap();
+        assert_eq!(config.quota, QuotaConfig::default());
+    }
+
+    #[test]
+    fn bad_quota_limits_are_rejected() {
+        assert!(parse_argv(args(&["--quota", "10X", "prog"])).is_err());
+        assert!(parse_argv(args(&["--quota", "1M:2k", "prog"])).is_err());
+        assert!(parse_argv(args(&["--quota-grace", "soon", "prog"])).is_err());
+        assert!(parse_argv(args(&["--max-size"])).is_err());
+    }
+
+    #[test]
+    fn synthetic_mount_flags() {
+        let config = parse_argv(args(&["--proc", "--dev", "prog", "--dev"])).unwrap();
+        assert!(config.dev && config.proc);
+        assert_eq!(config.argv, args(&["prog", "--dev"]));
+
+        let config = parse_argv(args(&["prog"])).unwrap();
+        assert!(!config.dev && !config.proc);
+    }
 }
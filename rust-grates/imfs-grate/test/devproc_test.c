@@ -0,0 +1,236 @@
+#define _GNU_SOURCE
+
+/* devproc_test.c — Test binary for the Rust IMFS grate's synthetic /dev and
+ * /proc.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ * `--dev --proc`, so /dev holds null, zero, full, random and urandom, and
+ * /proc holds cpuinfo and a directory per cage.
+ *
+ * Each test prints PASS/FAIL. Exit code 0 if all tests pass, 1 otherwise.
+ */
+#include <sys/stat.h>
+#include <sys/sysmacros.h>
+#include <sys/wait.h>
+#include <dirent.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+/* readlink into buf and terminate it. Returns buf, or "" on error. */
+static const char *link_target(const char *path, char *buf, size_t size) {
+	ssize_t n = readlink(path, buf, size - 1);
+	buf[n < 0 ? 0 : n] = '\0';
+	return buf;
+}
+
+static int dir_has(const char *path, const char *name) {
+	DIR *dir = opendir(path);
+	if (!dir)
+		return 0;
+	struct dirent *entry;
+	int found = 0;
+	while ((entry = readdir(dir)) != NULL)
+		if (strcmp(entry->d_name, name) == 0)
+			found = 1;
+	closedir(dir);
+	return found;
+}
+
+/*  Test 1: The character devices  */
+
+static void test_devices(void) {
+	printf("\n[test_devices]\n");
+
+	char buf[300];
+	struct stat st;
+
+	int fd = open("/dev/null", O_RDWR);
+	CHECK("open /dev/null", fd >= 0);
+	CHECK("writes to /dev/null are accepted", write(fd, "gone", 4) == 4);
+	CHECK("reads from /dev/null are at EOF", read(fd, buf, sizeof(buf)) == 0);
+	close(fd);
+
+	fd = open("/dev/zero", O_RDONLY);
+	memset(buf, 'x', sizeof(buf));
+	CHECK("/dev/zero fills the buffer", read(fd, buf, sizeof(buf)) == sizeof(buf));
+	int zeros = 1;
+	for (size_t i = 0; i < sizeof(buf); i++)
+		zeros &= buf[i] == 0;
+	CHECK("with zeros", zeros);
+	close(fd);
+
+	fd = open("/dev/full", O_WRONLY);
+	errno = 0;
+	CHECK("writes to /dev/full fail with ENOSPC",
+	      write(fd, "x", 1) == -1 && errno == ENOSPC);
+	close(fd);
+
+	fd = open("/dev/urandom", O_RDONLY);
+	memset(buf, 0, sizeof(buf));
+	CHECK("/dev/urandom fills the buffer", read(fd, buf, sizeof(buf)) == sizeof(buf));
+	int nonzero = 0;
+	for (size_t i = 0; i < sizeof(buf); i++)
+		nonzero |= buf[i] != 0;
+	CHECK("with random bytes", nonzero);
+	close(fd);
+
+	CHECK("stat /dev/urandom", stat("/dev/urandom", &st) == 0);
+	CHECK("it is a character device", S_ISCHR(st.st_mode));
+	CHECK("numbered 1:9", major(st.st_rdev) == 1 && minor(st.st_rdev) == 9);
+	CHECK("/dev/shm is a sticky directory",
+	      stat("/dev/shm", &st) == 0 && S_ISDIR(st.st_mode) &&
+		      (st.st_mode & 01777) == 01777);
+	errno = 0;
+	CHECK("mknod cannot add devices",
+	      mknod("/dev/mine", S_IFCHR | 0666, makedev(1, 3)) == -1 &&
+		      errno == EPERM);
+}
+
+/*  Test 2: Redirecting stdout to /dev/null  */
+
+static void test_stdout_redirect(void) {
+	printf("\n[test_stdout_redirect]\n");
+
+	char buf[64];
+	fflush(stdout);
+	int saved = dup(1);
+	int null = open("/dev/null", O_WRONLY);
+	int redirected = dup2(null, 1) == 1;
+	close(null);
+	/* Nothing printed until stdout is back would be seen. */
+	int linked = strcmp(link_target("/proc/self/fd/1", buf, sizeof(buf)), "/dev/null") == 0;
+	int discarded = write(1, "hidden\n", 7) == 7;
+	dup2(saved, 1);
+	close(saved);
+
+	CHECK("dup2 /dev/null onto stdout", redirected);
+	CHECK("/proc/self/fd/1 points to it", linked);
+	CHECK("writes to stdout are discarded", discarded);
+	CHECK("stdout is the host's again",
+	      strcmp(link_target("/proc/self/fd/1", buf, sizeof(buf)), "host:[1]") == 0);
+	CHECK("/dev/stdout links to it",
+	      strcmp(link_target("/dev/stdout", buf, sizeof(buf)), "/proc/self/fd/1") == 0);
+}
+
+/*  Test 3: /proc/self/fd  */
+
+static void test_proc_fds(void) {
+	printf("\n[test_proc_fds]\n");
+
+	char path[64], buf[64], name[16];
+
+	int fd = open("/tmp/tracked", O_CREAT | O_RDWR, 0644);
+	CHECK("create /tmp/tracked", fd >= 0);
+	snprintf(path, sizeof(path), "/proc/self/fd/%d", fd);
+	snprintf(name, sizeof(name), "%d", fd);
+	CHECK("its fd links to its path",
+	      strcmp(link_target(path, buf, sizeof(buf)), "/tmp/tracked") == 0);
+	CHECK("and is listed in /proc/self/fd", dir_has("/proc/self/fd", name));
+	CHECK("so is it in /dev/fd", dir_has("/dev/fd", name));
+
+	CHECK("unlink /tmp/tracked", unlink("/tmp/tracked") == 0);
+	CHECK("the link shows it was deleted",
+	      strcmp(link_target(path, buf, sizeof(buf)), "/tmp/tracked (deleted)") == 0);
+	close(fd);
+	CHECK("closing drops the entry", !dir_has("/proc/self/fd", name));
+
+	int fds[2];
+	CHECK("pipe", pipe(fds) == 0);
+	snprintf(path, sizeof(path), "/proc/self/fd/%d", fds[0]);
+	CHECK("a pipe shows as pipe:[ino]",
+	      strncmp(link_target(path, buf, sizeof(buf)), "pipe:[", 6) == 0);
+	close(fds[0]);
+	close(fds[1]);
+}
+
+/*  Test 4: cwd, exe and cpuinfo  */
+
+static void test_proc_cage(void) {
+	printf("\n[test_proc_cage]\n");
+
+	char buf[256];
+
+	CHECK("chdir /tmp", chdir("/tmp") == 0);
+	CHECK("/proc/self/cwd follows it",
+	      strcmp(link_target("/proc/self/cwd", buf, sizeof(buf)), "/tmp") == 0);
+	chdir("/");
+	CHECK("/proc/self/exe names our program",
+	      strstr(link_target("/proc/self/exe", buf, sizeof(buf)), "devproc_test") != NULL);
+
+	int fd = open("/proc/cpuinfo", O_RDONLY);
+	CHECK("open /proc/cpuinfo", fd >= 0);
+	ssize_t n = read(fd, buf, sizeof(buf) - 1);
+	buf[n < 0 ? 0 : n] = '\0';
+	CHECK("it lists a processor", strncmp(buf, "processor", 9) == 0);
+	close(fd);
+}
+
+/*  Test 5: Every cage has a directory while it runs  */
+
+static void test_proc_cages(void) {
+	printf("\n[test_proc_cages]\n");
+
+	char self[32], path[64];
+	link_target("/proc/self", self, sizeof(self));
+
+	int to_child[2], from_child[2];
+	pipe(to_child);
+	pipe(from_child);
+	pid_t pid = fork();
+	if (pid == 0) {
+		char child[32], c;
+		link_target("/proc/self", child, sizeof(child));
+		write(from_child[1], child, sizeof(child));
+		read(to_child[0], &c, 1);
+		_exit(0);
+	}
+
+	char child[32] = "";
+	CHECK("fork succeeds", pid > 0);
+	CHECK("the child reports its /proc/self",
+	      read(from_child[0], child, sizeof(child)) == sizeof(child));
+	CHECK("which is not ours", child[0] != '\0' && strcmp(child, self) != 0);
+	snprintf(path, sizeof(path), "/proc/%s/fd", child);
+	CHECK("we can see the child's fds", dir_has(path, "0"));
+
+	write(to_child[1], "x", 1);
+	waitpid(pid, NULL, 0);
+	snprintf(path, sizeof(path), "/proc/%s", child);
+	CHECK("the child's directory goes away when it exits",
+	      !dir_has("/proc", child) && access(path, F_OK) == -1);
+	close(to_child[0]);
+	close(to_child[1]);
+	close(from_child[0]);
+	close(from_child[1]);
+}
+
+int main(void) {
+	printf("=== imfs grate /dev and /proc test ===\n");
+
+	test_devices();
+	test_stdout_redirect();
+	test_proc_fds();
+	test_proc_cage();
+	test_proc_cages();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
@@ -11,15 +11,19 @@
  *
  * Each test prints PASS/FAIL. Exit code 0 if all tests pass, 1 otherwise.
  */
+#include <sys/file.h>
 #include <sys/stat.h>
 #include <sys/statfs.h>
 #include <sys/wait.h>
+#include <sys/xattr.h>
+#include <dirent.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
 #include <unistd.h>
 #include <fcntl.h>
 #include <errno.h>
+#include <signal.h>
 
 static int tests_run = 0;
 static int tests_passed = 0;
@@ -463,6 +467,73 @@ static void test_link_rw(void) {
 	CHECK("unlink file2", unlink("file2") == 0);
 }
 
+static void test_unlinked_open_files(void) {
+	printf("\n[test_unlinked_open_files]\n");
+
+	char buf[16];
+	struct stat st, st2;
+
+	int fd = open("/nl_a", O_CREAT | O_RDWR, 0644);
+	CHECK("create /nl_a", fd >= 0 && write(fd, "kept", 4) == 4);
+	CHECK("link /nl_a to /nl_b", link("/nl_a", "/nl_b") == 0);
+	CHECK("both names are the same inode",
+	      stat("/nl_a", &st) == 0 && stat("/nl_b", &st2) == 0 &&
+		      st.st_ino == st2.st_ino && st.st_nlink == 2);
+
+	CHECK("rename between two links of one file is a no-op",
+	      rename("/nl_a", "/nl_b") == 0 && stat("/nl_a", &st) == 0 &&
+		      stat("/nl_b", &st) == 0);
+
+	CHECK("unlink /nl_a", unlink("/nl_a") == 0);
+	CHECK("fstat sees one link left", fstat(fd, &st) == 0 && st.st_nlink == 1);
+	CHECK("unlink /nl_b", unlink("/nl_b") == 0);
+	CHECK("fstat on the unlinked fd reports nlink 0",
+	      fstat(fd, &st) == 0 && st.st_nlink == 0);
+	memset(buf, 0, sizeof(buf));
+	CHECK("data survives until the fd is closed",
+	      pread(fd, buf, sizeof(buf), 0) == 4 && strcmp(buf, "kept") == 0);
+	CHECK("the unlinked file is still writable",
+	      pwrite(fd, "!", 1, 4) == 1 && fstat(fd, &st) == 0 && st.st_size == 5);
+	close(fd);
+
+	fd = open("/nl_old", O_CREAT | O_RDWR, 0644);
+	CHECK("create /nl_old", fd >= 0 && write(fd, "old", 3) == 3);
+	int fd2 = open("/nl_new", O_CREAT | O_WRONLY, 0644);
+	CHECK("create /nl_new", fd2 >= 0 && write(fd2, "new", 3) == 3);
+	close(fd2);
+	CHECK("rename /nl_new over the open /nl_old",
+	      rename("/nl_new", "/nl_old") == 0);
+	memset(buf, 0, sizeof(buf));
+	CHECK("the replaced file keeps its data through the fd",
+	      pread(fd, buf, sizeof(buf), 0) == 3 && strcmp(buf, "old") == 0);
+	CHECK("the replaced file has no links",
+	      fstat(fd, &st) == 0 && st.st_nlink == 0);
+	close(fd);
+	memset(buf, 0, sizeof(buf));
+	fd = open("/nl_old", O_RDONLY);
+	CHECK("the name now has the new data",
+	      fd >= 0 && read(fd, buf, sizeof(buf)) == 3 && strcmp(buf, "new") == 0);
+	close(fd);
+	unlink("/nl_old");
+
+	CHECK("mkdir /nl_dir has 2 links",
+	      mkdir("/nl_dir", 0755) == 0 && stat("/nl_dir", &st) == 0 &&
+		      st.st_nlink == 2);
+	CHECK("a subdirectory adds a link",
+	      mkdir("/nl_dir/sub", 0755) == 0 && stat("/nl_dir", &st) == 0 &&
+		      st.st_nlink == 3);
+	CHECK("moving the subdirectory out drops it again",
+	      rename("/nl_dir/sub", "/nl_sub") == 0 && stat("/nl_dir", &st) == 0 &&
+		      st.st_nlink == 2);
+	fd = open("/nl_sub", O_RDONLY | O_DIRECTORY);
+	CHECK("rmdir a directory that is still open",
+	      fd >= 0 && rmdir("/nl_sub") == 0);
+	CHECK("fstat on the removed directory reports nlink 0",
+	      fstat(fd, &st) == 0 && S_ISDIR(st.st_mode) && st.st_nlink == 0);
+	close(fd);
+	CHECK("rmdir /nl_dir", rmdir("/nl_dir") == 0);
+}
+
 static void test_symlink_readlink_linkat(void) {
 	printf("\n[test_symlink_readlink_linkat]\n");
 
@@ -584,11 +655,11 @@ static void test_at_metadata_syscalls(void) {
 	CHECK("mode after fchmodat is 0600", (st.st_mode & 0777) == 0600);
 
 	CHECK("chown existing path succeeds",
-	      chown("/atdir/file", 123, 456) == 0);
+	      chown("/atdir/file", getuid(), getgid()) == 0);
 	memset(&st, 0, sizeof(st));
 	CHECK("stat after chown succeeds", stat("/atdir/file", &st) == 0);
 	CHECK("stat reports chown owner/group",
-	      st.st_uid == 123 && st.st_gid == 456);
+	      st.st_uid == getuid() && st.st_gid == getgid());
 
 	CHECK("fchmod preserves special mode bits",
 	      chmod("/atdir/file", 04711) == 0);
@@ -644,6 +715,543 @@ static void test_statfs(void) {
 	CHECK("statfs nonexistent path fails", ret != 0);
 }
 
+static int all_zero(const char *buf, size_t len) {
+	for (size_t i = 0; i < len; i++)
+		if (buf[i] != 0)
+			return 0;
+	return 1;
+}
+
+static void test_sparse_fallocate(void) {
+	printf("\n[test_sparse_fallocate]\n");
+
+	const off_t far = 1 << 20;
+	char buf[64];
+	struct stat st;
+
+	int fd = open("/test_sparse", O_CREAT | O_RDWR | O_TRUNC, 0644);
+	CHECK("create /test_sparse", fd >= 0);
+	if (fd < 0)
+		return;
+
+	CHECK("pwrite past a hole", pwrite(fd, "data", 4, far) == 4);
+	CHECK("fstat reports the full size",
+	      fstat(fd, &st) == 0 && st.st_size == far + 4);
+	CHECK("the hole is not counted in st_blocks",
+	      fstat(fd, &st) == 0 && st.st_blocks < far / 512);
+	CHECK("the hole reads as zeros",
+	      pread(fd, buf, sizeof(buf), 0) == sizeof(buf) &&
+		      all_zero(buf, sizeof(buf)));
+	CHECK("SEEK_HOLE at offset 0 stays at 0", lseek(fd, 0, SEEK_HOLE) == 0);
+	off_t data = lseek(fd, 0, SEEK_DATA);
+	CHECK("SEEK_DATA skips the hole", data > 0 && data <= far);
+	CHECK("SEEK_HOLE in the data finds EOF",
+	      lseek(fd, far, SEEK_HOLE) == far + 4);
+	errno = 0;
+	CHECK("SEEK_DATA at EOF fails with ENXIO",
+	      lseek(fd, far + 4, SEEK_DATA) == -1 && errno == ENXIO);
+
+	CHECK("fallocate with KEEP_SIZE succeeds",
+	      fallocate(fd, FALLOC_FL_KEEP_SIZE, far, 8192) == 0);
+	CHECK("KEEP_SIZE leaves the size alone",
+	      fstat(fd, &st) == 0 && st.st_size == far + 4);
+	CHECK("fallocate extends the file",
+	      fallocate(fd, 0, 0, 2 * far) == 0 && fstat(fd, &st) == 0 &&
+		      st.st_size == 2 * far);
+	CHECK("SEEK_DATA finds the allocated start",
+	      lseek(fd, 0, SEEK_DATA) == 0);
+	CHECK("fallocate keeps existing data",
+	      pread(fd, buf, 4, far) == 4 && memcmp(buf, "data", 4) == 0);
+
+	CHECK("punch a hole over the data",
+	      fallocate(fd, FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE, far,
+			4) == 0);
+	CHECK("punched range reads as zeros",
+	      pread(fd, buf, 4, far) == 4 && all_zero(buf, 4));
+	CHECK("punching keeps the size",
+	      fstat(fd, &st) == 0 && st.st_size == 2 * far);
+	errno = 0;
+	CHECK("PUNCH_HOLE without KEEP_SIZE fails with EOPNOTSUPP",
+	      fallocate(fd, FALLOC_FL_PUNCH_HOLE, 0, 4) == -1 &&
+		      errno == EOPNOTSUPP);
+	errno = 0;
+	CHECK("fallocate with a zero length fails with EINVAL",
+	      fallocate(fd, 0, 0, 0) == -1 && errno == EINVAL);
+	close(fd);
+
+	fd = open("/test_sparse", O_RDONLY);
+	errno = 0;
+	CHECK("fallocate on a read-only fd fails with EBADF",
+	      fd >= 0 && fallocate(fd, 0, 0, 4096) == -1 && errno == EBADF);
+	if (fd >= 0)
+		close(fd);
+	unlink("/test_sparse");
+}
+
+static void test_pipe(void) {
+	printf("\n[test_pipe]\n");
+
+	int fds[2];
+	char buf[16] = {0};
+
+	CHECK("pipe succeeds", pipe(fds) == 0);
+	CHECK("write to pipe", write(fds[1], "hello", 5) == 5);
+	CHECK("read from pipe", read(fds[0], buf, sizeof(buf)) == 5);
+	CHECK("pipe data matches", memcmp(buf, "hello", 5) == 0);
+	CHECK("lseek on pipe fails with ESPIPE",
+	      lseek(fds[0], 0, SEEK_SET) == -1 && errno == ESPIPE);
+
+	close(fds[1]);
+	CHECK("read returns EOF once writers are gone",
+	      read(fds[0], buf, sizeof(buf)) == 0);
+	close(fds[0]);
+
+	/* The grate does not raise SIGPIPE; ignore it so this also runs natively. */
+	signal(SIGPIPE, SIG_IGN);
+	CHECK("second pipe succeeds", pipe(fds) == 0);
+	close(fds[0]);
+	CHECK("write with no readers fails with EPIPE",
+	      write(fds[1], "x", 1) == -1 && errno == EPIPE);
+	close(fds[1]);
+}
+
+static void test_pipe2_nonblock(void) {
+	printf("\n[test_pipe2_nonblock]\n");
+
+	int fds[2];
+	char buf[16];
+
+	CHECK("pipe2 O_NONBLOCK|O_CLOEXEC succeeds",
+	      pipe2(fds, O_NONBLOCK | O_CLOEXEC) == 0);
+	CHECK("pipe2 sets close-on-exec", fcntl(fds[0], F_GETFD) & FD_CLOEXEC);
+	CHECK("empty nonblocking read fails with EAGAIN",
+	      read(fds[0], buf, sizeof(buf)) == -1 && errno == EAGAIN);
+
+	static char chunk[4096];
+	int total = 0;
+	ssize_t n;
+	while ((n = write(fds[1], chunk, sizeof(chunk))) > 0)
+		total += n;
+	CHECK("full nonblocking write fails with EAGAIN", n == -1 && errno == EAGAIN);
+	CHECK("pipe holds 64 KiB", total == 65536);
+
+	close(fds[0]);
+	close(fds[1]);
+
+	CHECK("pipe2 rejects unknown flags",
+	      pipe2(fds, O_APPEND) == -1 && errno == EINVAL);
+}
+
+static void test_pipe_fork(void) {
+	printf("\n[test_pipe_fork]\n");
+
+	int fds[2];
+	char buf[32] = {0};
+
+	CHECK("pipe succeeds", pipe(fds) == 0);
+
+	/* Keep buffered output out of the child's copy of stdout. */
+	fflush(stdout);
+	int pid = fork();
+	if (pid == 0) {
+		/* Child: like `producer | consumer`, write through stdout. */
+		close(fds[0]);
+		dup2(fds[1], STDOUT_FILENO);
+		close(fds[1]);
+		write(STDOUT_FILENO, "from child", 10);
+		_exit(0);
+	}
+
+	close(fds[1]);
+	ssize_t total = 0, n;
+	while ((n = read(fds[0], buf + total, sizeof(buf) - 1 - total)) > 0)
+		total += n;
+	wait(NULL);
+
+	CHECK("parent reads child's stdout through the pipe", total == 10);
+	CHECK("pipeline data matches", memcmp(buf, "from child", 10) == 0);
+	CHECK("EOF after child exits", n == 0);
+	close(fds[0]);
+}
+
+static void test_mkfifo(void) {
+	printf("\n[test_mkfifo]\n");
+
+	struct stat st;
+	char buf[16] = {0};
+
+	CHECK("mkfifo succeeds", mkfifo("/test_fifo", 0644) == 0);
+	CHECK("stat reports a FIFO",
+	      stat("/test_fifo", &st) == 0 && S_ISFIFO(st.st_mode));
+	CHECK("nonblocking write-only open without a reader fails with ENXIO",
+	      open("/test_fifo", O_WRONLY | O_NONBLOCK) == -1 && errno == ENXIO);
+
+	fflush(stdout);
+	int pid = fork();
+	if (pid == 0) {
+		int wfd = open("/test_fifo", O_WRONLY);
+		write(wfd, "fifo", 4);
+		close(wfd);
+		_exit(0);
+	}
+
+	/* Blocks until the child opens the write end. */
+	int rfd = open("/test_fifo", O_RDONLY);
+	CHECK("open FIFO for reading", rfd >= 0);
+	ssize_t total = 0, n;
+	while ((n = read(rfd, buf + total, sizeof(buf) - 1 - total)) > 0)
+		total += n;
+	wait(NULL);
+
+	CHECK("read data written through the FIFO",
+	      total == 4 && memcmp(buf, "fifo", 4) == 0);
+	close(rfd);
+	CHECK("unlink FIFO", unlink("/test_fifo") == 0);
+}
+
+static void test_statx(void) {
+	printf("\n[test_statx]\n");
+
+	struct statx stx;
+	struct stat st;
+
+	int fd = open("/test_statx_file", O_CREAT | O_RDWR, 0640);
+	CHECK("create /test_statx_file", fd >= 0);
+	CHECK("write to statx file", write(fd, "statx", 5) == 5);
+
+	memset(&stx, 0, sizeof(stx));
+	int ret = statx(AT_FDCWD, "/test_statx_file", 0, STATX_BASIC_STATS | STATX_BTIME, &stx);
+	CHECK("statx path succeeds", ret == 0);
+	CHECK("statx reports basic stats", (stx.stx_mask & STATX_BASIC_STATS) == STATX_BASIC_STATS);
+	CHECK("statx reports birth time", stx.stx_mask & STATX_BTIME);
+	CHECK("statx size", stx.stx_size == 5);
+	CHECK("statx mode", S_ISREG(stx.stx_mode) && (stx.stx_mode & 0777) == 0640);
+	CHECK("statx matches stat",
+	      stat("/test_statx_file", &st) == 0 && stx.stx_ino == st.st_ino &&
+	      stx.stx_nlink == st.st_nlink);
+
+	memset(&stx, 0, sizeof(stx));
+	CHECK("statx AT_EMPTY_PATH on fd",
+	      statx(fd, "", AT_EMPTY_PATH, STATX_SIZE, &stx) == 0 && stx.stx_size == 5);
+	CHECK("statx empty path without AT_EMPTY_PATH fails with ENOENT",
+	      statx(fd, "", 0, STATX_SIZE, &stx) == -1 && errno == ENOENT);
+
+	CHECK("symlink to statx file", symlink("/test_statx_file", "/test_statx_link") == 0);
+	memset(&stx, 0, sizeof(stx));
+	CHECK("statx AT_SYMLINK_NOFOLLOW reports the link",
+	      statx(AT_FDCWD, "/test_statx_link", AT_SYMLINK_NOFOLLOW, STATX_TYPE, &stx) == 0 &&
+	      S_ISLNK(stx.stx_mode));
+	memset(&stx, 0, sizeof(stx));
+	CHECK("statx follows the link by default",
+	      statx(AT_FDCWD, "/test_statx_link", 0, STATX_TYPE, &stx) == 0 &&
+	      S_ISREG(stx.stx_mode));
+
+	CHECK("statx reserved mask bit fails with EINVAL",
+	      statx(AT_FDCWD, "/test_statx_file", 0, STATX__RESERVED, &stx) == -1 &&
+	      errno == EINVAL);
+	CHECK("statx missing path fails with ENOENT",
+	      statx(AT_FDCWD, "/missing_statx_path", 0, STATX_BASIC_STATS, &stx) == -1 &&
+	      errno == ENOENT);
+
+	close(fd);
+	unlink("/test_statx_link");
+	unlink("/test_statx_file");
+}
+
+static void test_xattr(void) {
+	printf("\n[test_xattr]\n");
+
+	char buf[64];
+	ssize_t n;
+
+	int fd = open("/test_xattr_file", O_CREAT | O_RDWR, 0644);
+	CHECK("create /test_xattr_file", fd >= 0);
+
+	CHECK("setxattr user.tag",
+	      setxattr("/test_xattr_file", "user.tag", "blue", 4, 0) == 0);
+	memset(buf, 0, sizeof(buf));
+	n = getxattr("/test_xattr_file", "user.tag", buf, sizeof(buf));
+	CHECK("getxattr returns the value", n == 4 && memcmp(buf, "blue", 4) == 0);
+	CHECK("getxattr with size 0 returns the length",
+	      getxattr("/test_xattr_file", "user.tag", NULL, 0) == 4);
+	CHECK("getxattr with a short buffer fails with ERANGE",
+	      getxattr("/test_xattr_file", "user.tag", buf, 2) == -1 && errno == ERANGE);
+	CHECK("getxattr missing name fails with ENODATA",
+	      getxattr("/test_xattr_file", "user.missing", buf, sizeof(buf)) == -1 &&
+	      errno == ENODATA);
+
+	CHECK("XATTR_CREATE on an existing name fails with EEXIST",
+	      setxattr("/test_xattr_file", "user.tag", "red", 3, XATTR_CREATE) == -1 &&
+	      errno == EEXIST);
+	CHECK("XATTR_REPLACE on a missing name fails with ENODATA",
+	      setxattr("/test_xattr_file", "user.other", "x", 1, XATTR_REPLACE) == -1 &&
+	      errno == ENODATA);
+	CHECK("fsetxattr replaces the value",
+	      fsetxattr(fd, "user.tag", "red", 3, XATTR_REPLACE) == 0);
+	memset(buf, 0, sizeof(buf));
+	n = fgetxattr(fd, "user.tag", buf, sizeof(buf));
+	CHECK("fgetxattr sees the new value", n == 3 && memcmp(buf, "red", 3) == 0);
+
+	CHECK("setxattr user.cache",
+	      setxattr("/test_xattr_file", "user.cache", "", 0, 0) == 0);
+	CHECK("listxattr with size 0 returns the list length",
+	      listxattr("/test_xattr_file", NULL, 0) == 20);
+	memset(buf, 0, sizeof(buf));
+	n = flistxattr(fd, buf, sizeof(buf));
+	CHECK("flistxattr lists both names",
+	      n == 20 && (memcmp(buf, "user.cache\0user.tag\0", 20) == 0 ||
+			  memcmp(buf, "user.tag\0user.cache\0", 20) == 0));
+	CHECK("listxattr with a short buffer fails with ERANGE",
+	      listxattr("/test_xattr_file", buf, 5) == -1 && errno == ERANGE);
+
+	CHECK("unknown namespace fails with EOPNOTSUPP",
+	      setxattr("/test_xattr_file", "bogus.tag", "x", 1, 0) == -1 &&
+	      errno == EOPNOTSUPP);
+
+	CHECK("symlink to xattr file",
+	      symlink("/test_xattr_file", "/test_xattr_link") == 0);
+	memset(buf, 0, sizeof(buf));
+	CHECK("getxattr follows symlinks",
+	      getxattr("/test_xattr_link", "user.tag", buf, sizeof(buf)) == 3);
+	CHECK("lgetxattr does not follow symlinks",
+	      lgetxattr("/test_xattr_link", "user.tag", buf, sizeof(buf)) == -1 &&
+	      errno == ENODATA);
+	CHECK("lsetxattr user.* on a symlink fails with EPERM",
+	      lsetxattr("/test_xattr_link", "user.tag", "x", 1, 0) == -1 && errno == EPERM);
+
+	CHECK("removexattr user.cache",
+	      removexattr("/test_xattr_file", "user.cache") == 0);
+	CHECK("fremovexattr user.tag", fremovexattr(fd, "user.tag") == 0);
+	CHECK("removexattr of a removed name fails with ENODATA",
+	      removexattr("/test_xattr_file", "user.tag") == -1 && errno == ENODATA);
+	CHECK("list is empty after removal", listxattr("/test_xattr_file", NULL, 0) == 0);
+
+	close(fd);
+	unlink("/test_xattr_link");
+	unlink("/test_xattr_file");
+}
+
+static void test_flock(void) {
+	printf("\n[test_flock]\n");
+
+	int fd1 = open("/test_flock_file", O_CREAT | O_RDWR, 0644);
+	int fd2 = open("/test_flock_file", O_RDWR);
+	CHECK("open two descriptions of one file", fd1 >= 0 && fd2 >= 0);
+
+	CHECK("flock LOCK_EX", flock(fd1, LOCK_EX) == 0);
+	CHECK("conflicting LOCK_NB fails with EWOULDBLOCK",
+	      flock(fd2, LOCK_SH | LOCK_NB) == -1 && errno == EWOULDBLOCK);
+
+	int dupfd = dup(fd1);
+	close(fd1);
+	CHECK("lock survives while a dup of the description is open",
+	      flock(fd2, LOCK_SH | LOCK_NB) == -1 && errno == EWOULDBLOCK);
+	close(dupfd);
+	CHECK("closing the last fd releases the lock",
+	      flock(fd2, LOCK_SH | LOCK_NB) == 0);
+
+	fflush(stdout);
+	int pid = fork();
+	if (pid == 0) {
+		int fd = open("/test_flock_file", O_RDWR);
+		int shared_ok = flock(fd, LOCK_SH | LOCK_NB) == 0;
+		int excl_blocked = flock(fd, LOCK_EX | LOCK_NB) == -1 && errno == EWOULDBLOCK;
+		_exit(shared_ok && excl_blocked ? 0 : 1);
+	}
+	int status;
+	waitpid(pid, &status, 0);
+	CHECK("another cage shares LOCK_SH but cannot take LOCK_EX",
+	      WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	CHECK("flock LOCK_UN", flock(fd2, LOCK_UN) == 0);
+	CHECK("flock rejects a bad operation", flock(fd2, 0) == -1 && errno == EINVAL);
+	close(fd2);
+	unlink("/test_flock_file");
+}
+
+static int set_record_lock(int fd, int cmd, short type, off_t start, off_t len) {
+	struct flock fl = {.l_type = type, .l_whence = SEEK_SET, .l_start = start, .l_len = len};
+	return fcntl(fd, cmd, &fl);
+}
+
+static void test_record_locks(void) {
+	printf("\n[test_record_locks]\n");
+
+	int fd = open("/test_lock_file", O_CREAT | O_RDWR, 0644);
+	CHECK("create /test_lock_file", fd >= 0);
+	CHECK("F_SETLK write lock on bytes 0-9",
+	      set_record_lock(fd, F_SETLK, F_WRLCK, 0, 10) == 0);
+
+	fflush(stdout);
+	int pid = fork();
+	if (pid == 0) {
+		int cfd = open("/test_lock_file", O_RDWR);
+		struct flock fl = {.l_type = F_RDLCK, .l_whence = SEEK_SET, .l_start = 5, .l_len = 1};
+		int getlk_ok = fcntl(cfd, F_GETLK, &fl) == 0 && fl.l_type == F_WRLCK &&
+			       fl.l_start == 0 && fl.l_len == 10;
+		int conflict = set_record_lock(cfd, F_SETLK, F_RDLCK, 0, 5) == -1 &&
+			       (errno == EAGAIN || errno == EACCES);
+		int disjoint = set_record_lock(cfd, F_SETLK, F_WRLCK, 10, 10) == 0;
+		_exit(getlk_ok && conflict && disjoint ? 0 : 1);
+	}
+	int status;
+	waitpid(pid, &status, 0);
+	CHECK("another cage sees the lock and only locks a disjoint range",
+	      WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	CHECK("the exited cage's locks were released",
+	      set_record_lock(fd, F_SETLK, F_WRLCK, 10, 10) == 0);
+
+	struct flock fl = {.l_type = F_WRLCK, .l_whence = SEEK_SET, .l_start = 0, .l_len = 0};
+	CHECK("F_GETLK ignores the caller's own locks",
+	      fcntl(fd, F_GETLK, &fl) == 0 && fl.l_type == F_UNLCK);
+
+	fflush(stdout);
+	pid = fork();
+	if (pid == 0) {
+		int cfd = open("/test_lock_file", O_RDWR);
+		/* Blocks until the parent closes its fd below. */
+		_exit(set_record_lock(cfd, F_SETLKW, F_WRLCK, 0, 0) == 0 ? 0 : 1);
+	}
+	int other = open("/test_lock_file", O_RDONLY);
+	close(other); /* Closing any fd to the file drops the parent's locks. */
+	waitpid(pid, &status, 0);
+	CHECK("F_SETLKW waits until the holder closes the file",
+	      WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	close(fd);
+	unlink("/test_lock_file");
+}
+
+static void test_tmpfile(void) {
+	printf("\n[test_tmpfile]\n");
+
+	char buf[16];
+	struct stat st;
+
+	CHECK("mkdir /tmpfile_dir", mkdir("/tmpfile_dir", 0755) == 0);
+	int fd = open("/tmpfile_dir", O_TMPFILE | O_RDWR, 0644);
+	CHECK("open O_TMPFILE in a directory", fd >= 0);
+	CHECK("write to the unnamed file", write(fd, "atomic", 6) == 6);
+	CHECK("unnamed file has no links",
+	      fstat(fd, &st) == 0 && S_ISREG(st.st_mode) && st.st_nlink == 0);
+
+	CHECK("linkat AT_EMPTY_PATH names the file",
+	      linkat(fd, "", AT_FDCWD, "/tmpfile_dir/final", AT_EMPTY_PATH) == 0);
+	CHECK("named file has one link", fstat(fd, &st) == 0 && st.st_nlink == 1);
+	CHECK("linking it again fails with EEXIST",
+	      linkat(fd, "", AT_FDCWD, "/tmpfile_dir/final", AT_EMPTY_PATH) == -1 &&
+		      errno == EEXIST);
+	close(fd);
+
+	memset(buf, 0, sizeof(buf));
+	fd = open("/tmpfile_dir/final", O_RDONLY);
+	CHECK("the data is readable through the name",
+	      fd >= 0 && read(fd, buf, sizeof(buf)) == 6 && strcmp(buf, "atomic") == 0);
+	close(fd);
+
+	fd = open("/tmpfile_dir", O_TMPFILE | O_WRONLY | O_EXCL, 0600);
+	CHECK("open O_TMPFILE | O_EXCL", fd >= 0);
+	CHECK("an O_EXCL file cannot be linked",
+	      linkat(fd, "", AT_FDCWD, "/tmpfile_dir/excl", AT_EMPTY_PATH) == -1 &&
+		      errno == ENOENT);
+	close(fd);
+
+	fd = open("/tmpfile_dir/final", O_RDWR);
+	CHECK("unlink a file that is still open",
+	      fd >= 0 && unlink("/tmpfile_dir/final") == 0);
+	CHECK("an unlinked file cannot be linked back",
+	      linkat(fd, "", AT_FDCWD, "/tmpfile_dir/back", AT_EMPTY_PATH) == -1 &&
+		      errno == ENOENT);
+	close(fd);
+
+	CHECK("O_TMPFILE needs a writable fd",
+	      open("/tmpfile_dir", O_TMPFILE | O_RDONLY, 0600) == -1 && errno == EINVAL);
+	CHECK("O_TMPFILE on a missing directory fails with ENOENT",
+	      open("/no_such_dir", O_TMPFILE | O_RDWR, 0600) == -1 && errno == ENOENT);
+	CHECK("the directory is empty again", rmdir("/tmpfile_dir") == 0);
+}
+
+/*  Test: Directory offsets stay valid while the directory changes  */
+
+/* Count the entries named "rd<N>" from the stream's current position,
+ * adding each to seen[N]. */
+static void count_listing(DIR *d, int seen[], int n) {
+	struct dirent *ent;
+	while ((ent = readdir(d)) != NULL) {
+		int i;
+		if (sscanf(ent->d_name, "rd%d", &i) == 1 && i >= 0 && i < n)
+			seen[i]++;
+	}
+}
+
+static void test_readdir_offsets(void) {
+	printf("\n[test_readdir_offsets]\n");
+
+	enum { NFILES = 10 };
+	char path[64];
+	int seen[NFILES + 1] = {0};
+	int ok = 1;
+
+	CHECK("mkdir /listing", mkdir("/listing", 0755) == 0);
+	for (int i = 0; i < NFILES; i++) {
+		snprintf(path, sizeof(path), "/listing/rd%d", i);
+		int fd = open(path, O_CREAT | O_WRONLY, 0644);
+		ok = ok && fd >= 0;
+		close(fd);
+	}
+	CHECK("create ten files", ok);
+
+	DIR *d = opendir("/listing");
+	CHECK("opendir /listing", d != NULL);
+	if (d == NULL)
+		return;
+
+	/* Read the first few files, then remember where we are. */
+	int first = -1, read_files = 0;
+	struct dirent *ent;
+	while (read_files < 3 && (ent = readdir(d)) != NULL) {
+		int i;
+		if (sscanf(ent->d_name, "rd%d", &i) == 1) {
+			seen[i]++;
+			if (first < 0)
+				first = i;
+			read_files++;
+		}
+	}
+	long pos = telldir(d);
+	CHECK("telldir after three files", read_files == 3 && pos >= 0);
+
+	/* Remove an entry that was already returned and add a new one. Neither
+	 * may shift the entries that are still to come. */
+	snprintf(path, sizeof(path), "/listing/rd%d", first);
+	CHECK("unlink a file that was already listed", unlink(path) == 0);
+	int fd = open("/listing/rd10", O_CREAT | O_WRONLY, 0644);
+	CHECK("create a file while listing", fd >= 0);
+	close(fd);
+
+	seekdir(d, pos);
+	count_listing(d, seen, NFILES);
+	ok = 1;
+	for (int i = 0; i < NFILES; i++)
+		ok = ok && seen[i] == 1;
+	CHECK("seekdir resumes with no skipped or repeated files", ok);
+
+	memset(seen, 0, sizeof(seen));
+	rewinddir(d);
+	count_listing(d, seen, NFILES + 1);
+	ok = seen[first] == 0 && seen[NFILES] == 1;
+	for (int i = 0; i < NFILES; i++)
+		ok = ok && (i == first || seen[i] == 1);
+	CHECK("rewinddir lists the current files once each", ok);
+	closedir(d);
+
+	for (int i = 0; i <= NFILES; i++) {
+		snprintf(path, sizeof(path), "/listing/rd%d", i);
+		unlink(path);
+	}
+	CHECK("rmdir /listing", rmdir("/listing") == 0);
+}
+
 /*  Main  */
 
 int main(void) {
@@ -663,10 +1271,22 @@ int main(void) {
 	test_fork();
 	test_wrong_write();
 	test_link_rw();
+	test_unlinked_open_files();
 	test_symlink_readlink_linkat();
 	test_at_metadata_syscalls();
 	test_lseek();
 	test_statfs();
+	test_sparse_fallocate();
+	test_statx();
+	test_xattr();
+	test_flock();
+	test_record_locks();
+	test_tmpfile();
+	test_readdir_offsets();
+	test_pipe();
+	test_pipe2_nonblock();
+	test_pipe_fork();
+	test_mkfifo();
 
 	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
 	return (tests_passed == tests_run) ? 0 : 1;
@@ -0,0 +1,235 @@
+#define _GNU_SOURCE
+
+/* namespace_test.c — Test binary for the Rust IMFS grate's per-cage
+ * namespaces.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ * `--namespaces clone` and PRELOADS=/input.txt=preload_hello.txt, so every
+ * cage the test forks starts with a copy-on-write clone of its parent's tree,
+ * and the cages those children fork share their parent's namespace.
+ *
+ * Children report failures through their exit status, since their PASS/FAIL
+ * counts are not shared with the parent.
+ *
+ * Each test prints PASS/FAIL. Exit code 0 if all tests pass, 1 otherwise.
+ */
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+static const char *preload = "hello from the host\n";
+
+static int read_file(const char *path, char *buf, size_t len) {
+	int fd = open(path, O_RDONLY);
+	if (fd < 0)
+		return -1;
+	ssize_t n = read(fd, buf, len - 1);
+	close(fd);
+	if (n < 0)
+		return -1;
+	buf[n] = '\0';
+	return 0;
+}
+
+static int write_file(const char *path, const char *data) {
+	int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+	if (fd < 0)
+		return -1;
+	ssize_t n = write(fd, data, strlen(data));
+	close(fd);
+	return n == (ssize_t)strlen(data) ? 0 : -1;
+}
+
+/* Fork a child running fn and return its exit status, or -1. */
+static int run_child(int (*fn)(void)) {
+	fflush(stdout);
+	int pid = fork();
+	if (pid == 0)
+		_exit(fn());
+
+	int status;
+	if (pid < 0 || waitpid(pid, &status, 0) != pid || !WIFEXITED(status))
+		return -1;
+	return WEXITSTATUS(status);
+}
+
+/*  Test 1: A child sees the parent's tree, including preloaded files  */
+
+static int child_reads_inherited_files(void) {
+	char buf[64];
+
+	if (read_file("/input.txt", buf, sizeof(buf)) != 0 ||
+	    strcmp(buf, preload) != 0)
+		return 1;
+	if (read_file("/ns_dir/parent.txt", buf, sizeof(buf)) != 0 ||
+	    strcmp(buf, "parent") != 0)
+		return 2;
+	return 0;
+}
+
+static void test_clone_inherits_tree(void) {
+	printf("\n[test_clone_inherits_tree]\n");
+
+	CHECK("mkdir /ns_dir", mkdir("/ns_dir", 0755) == 0);
+	CHECK("write /ns_dir/parent.txt",
+	      write_file("/ns_dir/parent.txt", "parent") == 0);
+	CHECK("child reads the preload and the parent's file",
+	      run_child(child_reads_inherited_files) == 0);
+}
+
+/*  Test 2: Changes made by a child stay in its own namespace  */
+
+static int child_modifies_tree(void) {
+	if (write_file("/input.txt", "overwritten by child") != 0)
+		return 1;
+	if (write_file("/ns_dir/child.txt", "child") != 0)
+		return 2;
+	if (unlink("/ns_dir/parent.txt") != 0)
+		return 3;
+	if (mkdir("/child_dir", 0755) != 0)
+		return 4;
+
+	char buf[64];
+	if (read_file("/input.txt", buf, sizeof(buf)) != 0 ||
+	    strcmp(buf, "overwritten by child") != 0)
+		return 5;
+	return 0;
+}
+
+static void test_clone_is_private(void) {
+	printf("\n[test_clone_is_private]\n");
+
+	char buf[64];
+	struct stat st;
+
+	CHECK("child modifies its tree", run_child(child_modifies_tree) == 0);
+
+	CHECK("parent still reads the original preload",
+	      read_file("/input.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, preload) == 0);
+	CHECK("parent still has the file the child unlinked",
+	      read_file("/ns_dir/parent.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, "parent") == 0);
+	errno = 0;
+	CHECK("child's new file is not visible to the parent",
+	      stat("/ns_dir/child.txt", &st) == -1 && errno == ENOENT);
+	errno = 0;
+	CHECK("child's new directory is not visible to the parent",
+	      stat("/child_dir", &st) == -1 && errno == ENOENT);
+}
+
+/*  Test 3: Siblings forked later see the parent's current tree, not each
+ *  other's changes  */
+
+static int child_creates_marker(void) {
+	return write_file("/sibling.txt", "first") == 0 ? 0 : 1;
+}
+
+static int child_checks_marker(void) {
+	struct stat st;
+	if (stat("/sibling.txt", &st) == 0)
+		return 1;
+	char buf[64];
+	if (read_file("/ns_dir/late.txt", buf, sizeof(buf)) != 0 ||
+	    strcmp(buf, "late") != 0)
+		return 2;
+	return 0;
+}
+
+static void test_siblings_are_isolated(void) {
+	printf("\n[test_siblings_are_isolated]\n");
+
+	CHECK("first child writes /sibling.txt",
+	      run_child(child_creates_marker) == 0);
+	CHECK("write /ns_dir/late.txt after the first fork",
+	      write_file("/ns_dir/late.txt", "late") == 0);
+	CHECK("second child sees the parent's later file but not its sibling's",
+	      run_child(child_checks_marker) == 0);
+}
+
+/*  Test 4: An fd opened before fork still refers to the parent's file  */
+
+static int inherited_fd;
+
+static int child_writes_inherited_fd(void) {
+	return write(inherited_fd, "via fd", 6) == 6 ? 0 : 1;
+}
+
+static void test_inherited_fd_is_shared(void) {
+	printf("\n[test_inherited_fd_is_shared]\n");
+
+	char buf[64];
+
+	inherited_fd = open("/ns_dir/fd.txt", O_CREAT | O_RDWR | O_TRUNC, 0644);
+	CHECK("open /ns_dir/fd.txt", inherited_fd >= 0);
+	if (inherited_fd < 0)
+		return;
+
+	CHECK("child writes through the inherited fd",
+	      run_child(child_writes_inherited_fd) == 0);
+	close(inherited_fd);
+
+	CHECK("parent sees the write made through the inherited fd",
+	      read_file("/ns_dir/fd.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, "via fd") == 0);
+}
+
+/*  Test 5: A grandchild shares its parent's namespace  */
+
+static int grandchild_writes_file(void) {
+	return write_file("/subtree.txt", "grandchild") == 0 ? 0 : 1;
+}
+
+static int child_forks_grandchild(void) {
+	char buf[64];
+
+	if (run_child(grandchild_writes_file) != 0)
+		return 1;
+	if (read_file("/subtree.txt", buf, sizeof(buf)) != 0 ||
+	    strcmp(buf, "grandchild") != 0)
+		return 2;
+	return 0;
+}
+
+static void test_subtree_is_shared(void) {
+	printf("\n[test_subtree_is_shared]\n");
+
+	struct stat st;
+
+	CHECK("child sees the file its own child wrote",
+	      run_child(child_forks_grandchild) == 0);
+	errno = 0;
+	CHECK("grandchild's file is not visible to the parent",
+	      stat("/subtree.txt", &st) == -1 && errno == ENOENT);
+}
+
+int main(void) {
+	printf("=== imfs grate namespace test ===\n");
+
+	test_clone_inherits_tree();
+	test_clone_is_private();
+	test_siblings_are_isolated();
+	test_inherited_fd_is_shared();
+	test_subtree_is_shared();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
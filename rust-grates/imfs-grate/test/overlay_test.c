@@ -0,0 +1,184 @@
+#define _GNU_SOURCE
+
+/* overlay_test.c — Test binary for the Rust IMFS grate's overlay mode.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ * `--overlay /`, so IMFS sits on top of the lindfs root, where the
+ * preload_*.txt fixtures are copied. Nothing is preloaded: every fixture is
+ * read from the host on first use, and every change below stays in memory.
+ *
+ * Each test prints PASS/FAIL. Exit code 0 if all tests pass, 1 otherwise.
+ */
+#include <sys/stat.h>
+#include <dirent.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+static int read_file(const char *path, char *buf, size_t len) {
+	int fd = open(path, O_RDONLY);
+	if (fd < 0)
+		return -1;
+	ssize_t n = read(fd, buf, len - 1);
+	close(fd);
+	if (n < 0)
+		return -1;
+	buf[n] = '\0';
+	return 0;
+}
+
+static int append_file(const char *path, const char *data) {
+	int fd = open(path, O_WRONLY | O_APPEND);
+	if (fd < 0)
+		return -1;
+	ssize_t n = write(fd, data, strlen(data));
+	close(fd);
+	return n == (ssize_t)strlen(data) ? 0 : -1;
+}
+
+/* Return 1 if the directory has an entry with this name. */
+static int dir_has_entry(const char *dir, const char *name) {
+	DIR *d = opendir(dir);
+	if (!d)
+		return 0;
+	struct dirent *ent;
+	int found = 0;
+	while ((ent = readdir(d)) != NULL) {
+		if (strcmp(ent->d_name, name) == 0)
+			found = 1;
+	}
+	closedir(d);
+	return found;
+}
+
+/*  Test 1: Host files are visible without preloading  */
+
+static void test_lower_files_visible(void) {
+	printf("\n[test_lower_files_visible]\n");
+
+	char buf[64];
+	struct stat st;
+
+	CHECK("read /preload_hello.txt from the host",
+	      read_file("/preload_hello.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, "hello from the host\n") == 0);
+	CHECK("stat reports the host file's size",
+	      stat("/preload_hello.txt", &st) == 0 && S_ISREG(st.st_mode) &&
+		      st.st_size == 20);
+	CHECK("readdir / lists the host file",
+	      dir_has_entry("/", "preload_hello.txt"));
+
+	int fd = open("/preload_nested.txt", O_RDONLY);
+	CHECK("pread in the middle of a host file",
+	      fd >= 0 && pread(fd, buf, 7, 7) == 7 &&
+		      memcmp(buf, "payload", 7) == 0);
+	close(fd);
+}
+
+/*  Test 2: Writing copies a host file up into memory  */
+
+static void test_copy_up(void) {
+	printf("\n[test_copy_up]\n");
+
+	char buf[64];
+	struct stat st;
+
+	CHECK("append to /preload_rel.txt",
+	      append_file("/preload_rel.txt", "more\n") == 0);
+	CHECK("the copy keeps the host contents before the append",
+	      read_file("/preload_rel.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, "relative payload\nmore\n") == 0);
+
+	int fd = open("/preload_short.txt", O_WRONLY | O_TRUNC);
+	CHECK("open /preload_short.txt with O_TRUNC", fd >= 0);
+	close(fd);
+	CHECK("O_TRUNC empties the copy",
+	      stat("/preload_short.txt", &st) == 0 && st.st_size == 0);
+
+	CHECK("truncate /preload_nested.txt",
+	      truncate("/preload_nested.txt", 6) == 0);
+	CHECK("truncate keeps the start of the host contents",
+	      read_file("/preload_nested.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, "nested") == 0);
+}
+
+/*  Test 3: Unlink and rename hide the host entry  */
+
+static void test_whiteouts(void) {
+	printf("\n[test_whiteouts]\n");
+
+	char buf[64];
+	struct stat st;
+
+	CHECK("unlink /preload_long.txt", unlink("/preload_long.txt") == 0);
+	errno = 0;
+	CHECK("the unlinked host file is gone",
+	      stat("/preload_long.txt", &st) == -1 && errno == ENOENT);
+	CHECK("readdir / no longer lists it",
+	      !dir_has_entry("/", "preload_long.txt"));
+
+	int fd = open("/preload_long.txt", O_CREAT | O_WRONLY, 0644);
+	CHECK("re-create /preload_long.txt",
+	      fd >= 0 && write(fd, "new", 3) == 3);
+	close(fd);
+	CHECK("the new file does not show the host contents",
+	      read_file("/preload_long.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, "new") == 0);
+
+	CHECK("rename /preload_hello.txt to /renamed.txt",
+	      rename("/preload_hello.txt", "/renamed.txt") == 0);
+	CHECK("the renamed file still has the host contents",
+	      read_file("/renamed.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, "hello from the host\n") == 0);
+	errno = 0;
+	CHECK("the old name is gone",
+	      stat("/preload_hello.txt", &st) == -1 && errno == ENOENT);
+}
+
+/*  Test 4: New entries live next to host entries  */
+
+static void test_new_entries(void) {
+	printf("\n[test_new_entries]\n");
+
+	char buf[64];
+
+	CHECK("mkdir /overlay_dir", mkdir("/overlay_dir", 0755) == 0);
+	int fd = open("/overlay_dir/file.txt", O_CREAT | O_WRONLY, 0644);
+	CHECK("create /overlay_dir/file.txt",
+	      fd >= 0 && write(fd, "upper", 5) == 5);
+	close(fd);
+	CHECK("read it back",
+	      read_file("/overlay_dir/file.txt", buf, sizeof(buf)) == 0 &&
+		      strcmp(buf, "upper") == 0);
+	CHECK("readdir / lists new and host entries",
+	      dir_has_entry("/", "overlay_dir") &&
+		      dir_has_entry("/", "preload_rel.txt"));
+}
+
+int main(void) {
+	printf("=== imfs grate overlay test ===\n");
+
+	test_lower_files_visible();
+	test_copy_up();
+	test_whiteouts();
+	test_new_entries();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
@@ -0,0 +1,219 @@
+#define _GNU_SOURCE
+
+/* perm_test.c — Test binary for the Rust IMFS grate's ownership and
+ * permission checks.
+ *
+ * New files are owned by the calling cage and have its umask applied, and
+ * path operations check permissions like the kernel does. Most checks only
+ * fail for a process that is not root, so they are skipped when run as uid 0.
+ *
+ * Each test prints PASS/FAIL. Exit code 0 if all tests pass, 1 otherwise.
+ */
+#include <sys/stat.h>
+#include <sys/xattr.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <errno.h>
+
+static int tests_run = 0;
+static int tests_passed = 0;
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+static int create_file(const char *path, mode_t mode) {
+	int fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, mode);
+	if (fd < 0)
+		return -1;
+	close(fd);
+	return 0;
+}
+
+/* Return 1 (and say so) if checks that only fail for non-root must be
+ * skipped. */
+static int skip_as_root(void) {
+	if (getuid() != 0)
+		return 0;
+	printf("  SKIP: running as root\n");
+	return 1;
+}
+
+/*  Test 1: umask applies to new files and directories  */
+
+static void test_umask(void) {
+	printf("\n[test_umask]\n");
+
+	struct stat st;
+
+	CHECK("default umask is 022", umask(027) == 022);
+	CHECK("create /masked.txt with mode 0666",
+	      create_file("/masked.txt", 0666) == 0);
+	CHECK("umask 027 leaves 0640",
+	      stat("/masked.txt", &st) == 0 && (st.st_mode & 0777) == 0640);
+	CHECK("mkdir /masked_dir with mode 0777",
+	      mkdir("/masked_dir", 0777) == 0);
+	CHECK("umask 027 leaves 0750",
+	      stat("/masked_dir", &st) == 0 && (st.st_mode & 0777) == 0750);
+	CHECK("umask returns the previous mask", umask(022) == 027);
+	CHECK("chmod is not masked",
+	      chmod("/masked.txt", 0666) == 0 && stat("/masked.txt", &st) == 0 &&
+		      (st.st_mode & 0777) == 0666);
+}
+
+/*  Test 2: New nodes are owned by the creating process  */
+
+static void test_ownership(void) {
+	printf("\n[test_ownership]\n");
+
+	struct stat st;
+
+	CHECK("create /owned.txt", create_file("/owned.txt", 0644) == 0);
+	CHECK("new file has our uid and gid",
+	      stat("/owned.txt", &st) == 0 && st.st_uid == getuid() &&
+		      st.st_gid == getgid());
+	CHECK("mkdir /owned_dir", mkdir("/owned_dir", 0755) == 0);
+	CHECK("new directory has our uid and gid",
+	      stat("/owned_dir", &st) == 0 && st.st_uid == getuid() &&
+		      st.st_gid == getgid());
+	CHECK("chown to our own uid and gid",
+	      chown("/owned.txt", getuid(), getgid()) == 0);
+
+	if (skip_as_root())
+		return;
+
+	errno = 0;
+	CHECK("chown to another uid is EPERM",
+	      chown("/owned.txt", getuid() + 1, -1) == -1 && errno == EPERM);
+	errno = 0;
+	CHECK("chown to another group is EPERM",
+	      chown("/owned.txt", -1, getgid() + 1) == -1 && errno == EPERM);
+}
+
+/*  Test 3: Directory write and search permission  */
+
+static void test_directory_permissions(void) {
+	printf("\n[test_directory_permissions]\n");
+
+	struct stat st;
+
+	CHECK("mkdir /locked", mkdir("/locked", 0755) == 0);
+	CHECK("create /locked/file.txt",
+	      create_file("/locked/file.txt", 0644) == 0);
+
+	if (skip_as_root())
+		return;
+
+	CHECK("chmod /locked 0500", chmod("/locked", 0500) == 0);
+	errno = 0;
+	CHECK("create in a read-only directory is EACCES",
+	      create_file("/locked/new.txt", 0644) == -1 && errno == EACCES);
+	errno = 0;
+	CHECK("mkdir in a read-only directory is EACCES",
+	      mkdir("/locked/sub", 0755) == -1 && errno == EACCES);
+	errno = 0;
+	CHECK("unlink in a read-only directory is EACCES",
+	      unlink("/locked/file.txt") == -1 && errno == EACCES);
+	errno = 0;
+	CHECK("rename out of a read-only directory is EACCES",
+	      rename("/locked/file.txt", "/moved.txt") == -1 &&
+		      errno == EACCES);
+	errno = 0;
+	CHECK("access W_OK on the directory is EACCES",
+	      access("/locked", W_OK) == -1 && errno == EACCES);
+
+	CHECK("chmod /locked 0600", chmod("/locked", 0600) == 0);
+	errno = 0;
+	CHECK("stat through a directory without search is EACCES",
+	      stat("/locked/file.txt", &st) == -1 && errno == EACCES);
+	errno = 0;
+	CHECK("chdir into it is EACCES",
+	      chdir("/locked") == -1 && errno == EACCES);
+
+	CHECK("chmod /locked 0755 restores access",
+	      chmod("/locked", 0755) == 0 &&
+		      unlink("/locked/file.txt") == 0);
+}
+
+/*  Test 4: File read and write permission  */
+
+static void test_file_permissions(void) {
+	printf("\n[test_file_permissions]\n");
+
+	CHECK("create /private.txt", create_file("/private.txt", 0644) == 0);
+	errno = 0;
+	CHECK("access X_OK on a file without execute bits is EACCES",
+	      access("/private.txt", X_OK) == -1 && errno == EACCES);
+
+	if (skip_as_root())
+		return;
+
+	CHECK("chmod /private.txt 0400", chmod("/private.txt", 0400) == 0);
+	int fd = open("/private.txt", O_RDONLY);
+	CHECK("open read-only still works", fd >= 0);
+	close(fd);
+	errno = 0;
+	CHECK("open for writing is EACCES",
+	      open("/private.txt", O_WRONLY) == -1 && errno == EACCES);
+	errno = 0;
+	CHECK("truncate is EACCES",
+	      truncate("/private.txt", 0) == -1 && errno == EACCES);
+	errno = 0;
+	CHECK("setting a user xattr needs write permission",
+	      setxattr("/private.txt", "user.tag", "x", 1, 0) == -1 &&
+		      errno == EACCES);
+
+	CHECK("chmod /private.txt 0000", chmod("/private.txt", 0) == 0);
+	errno = 0;
+	CHECK("open for reading is EACCES",
+	      open("/private.txt", O_RDONLY) == -1 && errno == EACCES);
+	errno = 0;
+	CHECK("access R_OK is EACCES",
+	      access("/private.txt", R_OK) == -1 && errno == EACCES);
+	CHECK("the owner can still chmod it back",
+	      chmod("/private.txt", 0644) == 0 &&
+		      access("/private.txt", R_OK | W_OK) == 0);
+
+	errno = 0;
+	CHECK("trusted xattrs need privilege",
+	      setxattr("/private.txt", "trusted.tag", "x", 1, 0) == -1 &&
+		      errno == EPERM);
+}
+
+/*  Test 5: Sticky directories  */
+
+static void test_sticky_directory(void) {
+	printf("\n[test_sticky_directory]\n");
+
+	struct stat st;
+
+	CHECK("/tmp is sticky and world-writable",
+	      stat("/tmp", &st) == 0 && (st.st_mode & 01777) == 01777);
+	CHECK("create /tmp/mine.txt", create_file("/tmp/mine.txt", 0644) == 0);
+	CHECK("rename our own file in /tmp",
+	      rename("/tmp/mine.txt", "/tmp/renamed.txt") == 0);
+	CHECK("unlink our own file in /tmp",
+	      unlink("/tmp/renamed.txt") == 0);
+}
+
+int main(void) {
+	printf("=== imfs grate permission test ===\n");
+
+	test_umask();
+	test_ownership();
+	test_directory_permissions();
+	test_file_permissions();
+	test_sticky_directory();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}
@@ -0,0 +1,177 @@
+#define _GNU_SOURCE
+
+/* quota_test.c — Test binary for the Rust IMFS grate's memory accounting
+ * and quotas.
+ *
+ * The test suite (see test/grates_test.toml) runs the grate with
+ * `--max-size 1M:64 --quota 256K:16 --quota-soft 128K --quota-grace 0`:
+ * IMFS holds at most 1 MiB and 64 nodes, and every cage at most 256 KiB and
+ * 16 nodes. With no grace period, a cage that goes over 128 KiB can finish
+ * that write but not allocate anything more until it is back under.
+ *
+ * Each test prints PASS/FAIL. Exit code 0 if all tests pass, 1 otherwise.
+ */
+#include <sys/stat.h>
+#include <sys/statfs.h>
+#include <sys/wait.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <errno.h>
+
+#define KIB 1024
+#define CHUNK (16 * KIB)
+
+static int tests_run = 0;
+static int tests_passed = 0;
+static char chunk[CHUNK];
+
+#define CHECK(desc, cond)                                                      \
+	do {                                                                   \
+		tests_run++;                                                   \
+		if (cond) {                                                    \
+			printf("  PASS: %s\n", desc);                          \
+			tests_passed++;                                        \
+		} else {                                                       \
+			printf("  FAIL: %s (errno=%d)\n", desc, errno);        \
+		}                                                              \
+	} while (0)
+
+/*  Test 1: statfs reports the global limits  */
+
+static void test_statfs_limits(void) {
+	printf("\n[test_statfs_limits]\n");
+
+	struct statfs sfs;
+	CHECK("statfs / succeeds", statfs("/", &sfs) == 0);
+	CHECK("f_blocks is the 1 MiB limit",
+	      sfs.f_blocks * sfs.f_bsize == 1024 * KIB);
+	CHECK("f_files is the 64 node limit", sfs.f_files == 64);
+	CHECK("f_bavail is capped by our 256 KiB quota",
+	      sfs.f_bavail * sfs.f_bsize <= 256 * KIB);
+}
+
+/*  Test 2: The soft limit with no grace period  */
+
+static void test_soft_limit(void) {
+	printf("\n[test_soft_limit]\n");
+
+	struct statfs before, after;
+	int fd = open("/soft.bin", O_CREAT | O_RDWR | O_TRUNC, 0644);
+	CHECK("create /soft.bin", fd >= 0);
+	if (fd < 0)
+		return;
+
+	statfs("/", &before);
+	size_t total = 0;
+	ssize_t n;
+	errno = 0;
+	while ((n = write(fd, chunk, CHUNK)) == CHUNK)
+		total += CHUNK;
+	CHECK("the write that crosses 128 KiB succeeds", total == 144 * KIB);
+	CHECK("the next one fails with EDQUOT", n == -1 && errno == EDQUOT);
+	statfs("/", &after);
+	CHECK("statfs counts the data as used",
+	      (before.f_bfree - after.f_bfree) * after.f_bsize == 144 * KIB);
+
+	CHECK("overwriting stored data still works",
+	      pwrite(fd, chunk, CHUNK, 0) == CHUNK);
+	CHECK("ftruncate back under the soft limit", ftruncate(fd, 64 * KIB) == 0);
+	CHECK("writing works again", write(fd, chunk, CHUNK) == CHUNK);
+	close(fd);
+}
+
+/*  Test 3: The hard limit and the global limit  */
+
+static void test_hard_limit(void) {
+	printf("\n[test_hard_limit]\n");
+
+	int fd = open("/soft.bin", O_RDWR);
+	CHECK("open /soft.bin", fd >= 0);
+	if (fd < 0)
+		return;
+
+	errno = 0;
+	CHECK("fallocate past the 256 KiB quota fails with EDQUOT",
+	      fallocate(fd, 0, 0, 300 * KIB) == -1 && errno == EDQUOT);
+	errno = 0;
+	CHECK("fallocate past the 1 MiB total fails with ENOSPC",
+	      fallocate(fd, 0, 0, 2048 * KIB) == -1 && errno == ENOSPC);
+	CHECK("a hole costs nothing",
+	      pwrite(fd, "end", 3, 512 * KIB) == 3);
+	close(fd);
+	CHECK("unlink /soft.bin", unlink("/soft.bin") == 0);
+}
+
+/*  Test 4: The node limit  */
+
+static void test_inode_limit(void) {
+	printf("\n[test_inode_limit]\n");
+
+	char path[32];
+	int created = 0;
+	errno = 0;
+	for (;;) {
+		snprintf(path, sizeof(path), "/n%d", created);
+		int fd = open(path, O_CREAT | O_WRONLY, 0644);
+		if (fd < 0)
+			break;
+		close(fd);
+		created++;
+	}
+	CHECK("16 files can be created", created == 16);
+	CHECK("the 17th fails with EDQUOT", errno == EDQUOT);
+	errno = 0;
+	CHECK("so does mkdir", mkdir("/over", 0755) == -1 && errno == EDQUOT);
+	errno = 0;
+	CHECK("and symlink",
+	      symlink("/n0", "/over_link") == -1 && errno == EDQUOT);
+
+	CHECK("unlink /n0 frees a node", unlink("/n0") == 0);
+	CHECK("mkdir then succeeds", mkdir("/over", 0755) == 0);
+}
+
+/*  Test 5: Each cage has a quota of its own  */
+
+static void test_per_cage(void) {
+	printf("\n[test_per_cage]\n");
+
+	pid_t pid = fork();
+	if (pid == 0) {
+		/* We are at our node limit, the child is not. */
+		int fd = open("/child.bin", O_CREAT | O_WRONLY, 0644);
+		if (fd < 0)
+			_exit(1);
+		if (write(fd, chunk, CHUNK) != CHUNK)
+			_exit(2);
+		close(fd);
+		_exit(0);
+	}
+
+	int status = -1;
+	CHECK("fork succeeds", pid > 0);
+	CHECK("the child creates and writes a file",
+	      waitpid(pid, &status, 0) == pid && WIFEXITED(status) &&
+		      WEXITSTATUS(status) == 0);
+
+	struct stat st;
+	CHECK("the file is there", stat("/child.bin", &st) == 0 &&
+					   st.st_size == CHUNK);
+	CHECK("we may remove it", unlink("/child.bin") == 0);
+}
+
+int main(void) {
+	printf("=== imfs grate quota test ===\n");
+
+	memset(chunk, 'q', sizeof(chunk));
+
+	test_statfs_limits();
+	test_soft_limit();
+	test_hard_limit();
+	test_inode_limit();
+	test_per_cage();
+
+	printf("\n=== results: %d/%d passed ===\n", tests_passed, tests_run);
+	return (tests_passed == tests_run) ? 0 : 1;
+}